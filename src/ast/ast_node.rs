@@ -25,6 +25,19 @@ where
     }
 }
 
+impl<C> AstNode<C> for ast::Comment<C>
+where
+    C: fmt::Debug,
+{
+    fn context(&self) -> &C {
+        &self.context
+    }
+
+    fn context_mut(&mut self) -> &mut C {
+        &mut self.context
+    }
+}
+
 impl<C> AstNode<C> for ast::Identifier<C>
 where
     C: fmt::Debug,
@@ -55,6 +68,7 @@ where
             ast::Expression::Lambda(ref v) => v.context(),
             ast::Expression::Select(ref v) => v.context(),
             ast::Expression::Apply(ref v) => v.context(),
+            ast::Expression::Try(ref v) => v.context(),
             ast::Expression::Unknown => panic!("'unknown' AST nodes should not escape the parser"),
         }
     }
@@ -72,6 +86,7 @@ where
             ast::Expression::Lambda(ref mut v) => v.context_mut(),
             ast::Expression::Select(ref mut v) => v.context_mut(),
             ast::Expression::Apply(ref mut v) => v.context_mut(),
+            ast::Expression::Try(ref mut v) => v.context_mut(),
             ast::Expression::Unknown => panic!("'unknown' AST nodes should not escape the parser"),
         }
     }
@@ -239,6 +254,19 @@ where
     }
 }
 
+impl<C> AstNode<C> for ast::Try<C>
+where
+    C: fmt::Debug,
+{
+    fn context(&self) -> &C {
+        &self.context
+    }
+
+    fn context_mut(&mut self) -> &mut C {
+        &mut self.context
+    }
+}
+
 impl<C> AstNode<C> for ast::Parameter<C>
 where
     C: fmt::Debug,
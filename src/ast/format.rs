@@ -0,0 +1,435 @@
+//! Formatting of parsed modules into canonical Tin source code.
+//!
+//! Unlike the `Display` implementations of AST nodes, the formatter preserves comments and blank
+//! lines between definitions and statements.  Records, tuples, applications and lambdas that do
+//! not fit within the line width are broken over several lines, with a trailing comma after the
+//! last element of a broken list.
+use std::cmp;
+
+use crate::ast;
+use crate::ast::pretty;
+use crate::ast::AstNode;
+use crate::parser;
+
+/// The line width that formatted code is kept within where possible.
+const WIDTH: usize = 100;
+
+struct Formatter<'a> {
+    source: &'a str,
+    base: codespan::ByteIndex,
+    comments: &'a [ast::Comment<parser::Context>],
+    next_comment: usize,
+    indent: usize,
+    out: String,
+}
+
+/// Formats the specified module, which was parsed from the specified file.
+pub fn format(module: &ast::Module<parser::Context>, file: &codespan::FileMap) -> String {
+    let mut formatter = Formatter {
+        source: file.src(),
+        base: file.span().start(),
+        comments: &module.comments,
+        next_comment: 0,
+        indent: 0,
+        out: String::new(),
+    };
+
+    let mut previous_end = None;
+    for variable in &module.variables {
+        let span = variable.context.span;
+        if previous_end.is_some() {
+            formatter.separate(previous_end, span.start());
+            formatter.newline();
+        }
+        formatter.comments_before(span.start());
+        formatter.variable(variable);
+        formatter.out.push(';');
+        previous_end = Some(span.end());
+    }
+
+    // The grammar only allows comments in front of other nodes, so all of them should have been
+    // written by now, but make sure that none are lost.
+    let comments = formatter.comments;
+    while let Some(comment) = comments.get(formatter.next_comment) {
+        formatter.newline();
+        formatter.comment(comment);
+    }
+
+    if !formatter.out.is_empty() {
+        formatter.out.push('\n');
+    }
+
+    formatter.out
+}
+
+impl<'a> Formatter<'a> {
+    fn variable(&mut self, variable: &ast::Variable<parser::Context>) {
+        self.out.push_str(&variable.name.value);
+        self.out.push_str(" = ");
+        self.expression(&variable.initializer, pretty::LAMBDA, 1);
+    }
+
+    /// Writes an expression that will be followed by `suffix` characters on the same line.
+    fn expression(
+        &mut self,
+        expression: &ast::Expression<parser::Context>,
+        strength: u8,
+        suffix: usize,
+    ) {
+        let flat = pretty::Nested(expression, strength).to_string();
+        if self.fits(&flat, suffix) && !self.has_comments(expression.context().span) {
+            self.out.push_str(&flat);
+            return;
+        }
+
+        if pretty::binding_strength(expression) < strength {
+            self.out.push('(');
+            self.expression(expression, pretty::LAMBDA, suffix + 1);
+            self.out.push(')');
+            return;
+        }
+
+        match *expression {
+            ast::Expression::Tuple(ref tuple) => {
+                self.list("(", ")", &tuple.fields, |f, field| {
+                    f.expression(field, pretty::LAMBDA, 1)
+                });
+            }
+            ast::Expression::Record(ref record) => {
+                self.list("{", "}", &record.fields, |f, (name, value)| {
+                    f.out.push_str(&name.value);
+                    f.out.push_str(": ");
+                    f.expression(value, pretty::LAMBDA, 1);
+                });
+            }
+            ast::Expression::UnOp(ref un_op) => {
+                self.out.push_str(&format!("{} ", un_op.operator));
+                self.expression(&un_op.operand, pretty::UN_OP, suffix);
+            }
+            ast::Expression::BiOp(ref bi_op) => {
+                let (lhs, rhs) = pretty::operand_strengths(bi_op.operator);
+                let operator = format!(" {} ", bi_op.operator);
+                self.expression(&bi_op.lhs, lhs, operator.len());
+                self.out.push_str(&operator);
+                self.expression(&bi_op.rhs, rhs, suffix);
+            }
+            ast::Expression::Lambda(ref lambda) => self.lambda(lambda, suffix),
+            ast::Expression::Select(ref select) => {
+                let field = &select.field.value;
+                self.expression(&select.record, pretty::PROJECTION, field.len() + 1 + suffix);
+                self.out.push('.');
+                self.out.push_str(field);
+            }
+            ast::Expression::Apply(ref apply) => {
+                self.expression(&apply.function, pretty::PROJECTION, 1);
+                self.list("(", ")", &apply.parameters, |f, parameter| {
+                    f.expression(parameter, pretty::LAMBDA, 1)
+                });
+            }
+            ast::Expression::Try(ref try_) => {
+                self.out.push_str("try {");
+                self.block(&try_.body);
+                self.out
+                    .push_str(&format!(" catch |{}| {{", try_.error.value));
+                self.block(&try_.handler);
+            }
+            ast::Expression::NumberLiteral(_)
+            | ast::Expression::StringLiteral(_)
+            | ast::Expression::Symbol(_)
+            | ast::Expression::Identifier(_)
+            | ast::Expression::Unknown => self.out.push_str(&flat),
+        }
+    }
+
+    /// Writes an indented expression on its own line, followed by a closing brace.
+    fn block(&mut self, expression: &ast::Expression<parser::Context>) {
+        self.indent += pretty::INDENT;
+        self.newline();
+        self.expression(expression, pretty::LAMBDA, 0);
+        self.indent -= pretty::INDENT;
+        self.newline();
+        self.out.push('}');
+    }
+
+    fn lambda(&mut self, lambda: &ast::Lambda<parser::Context>, suffix: usize) {
+        let mut header = String::from("|");
+        for (i, parameter) in lambda.parameters.iter().enumerate() {
+            if i > 0 {
+                header.push_str(", ");
+            }
+            header.push_str(&format!(
+                "{}: {}",
+                parameter.name.value,
+                pretty::Nested(&parameter.signature, pretty::ATOM)
+            ));
+        }
+        header.push_str(&format!(
+            "| -> {}",
+            pretty::Nested(&lambda.signature, pretty::ATOM)
+        ));
+
+        let header_span = codespan::ByteSpan::new(
+            lambda.context.span.start(),
+            lambda.signature.context().span.end(),
+        );
+        let header_suffix = if lambda.result.is_some() { 2 } else { suffix };
+
+        if lambda.parameters.is_empty()
+            || (self.fits(&header, header_suffix) && !self.has_comments(header_span))
+        {
+            self.out.push_str(&header);
+        } else {
+            self.list("|", "|", &lambda.parameters, |f, parameter| {
+                f.comments_before(parameter.context.span.start());
+                f.out.push_str(&parameter.name.value);
+                f.out.push_str(": ");
+                f.expression(&parameter.signature, pretty::ATOM, 1);
+            });
+            self.out.push_str(" -> ");
+            self.expression(&lambda.signature, pretty::ATOM, header_suffix);
+        }
+
+        let result = match lambda.result {
+            Some(ref result) => result,
+            None => return,
+        };
+
+        if lambda.statements.is_empty() {
+            let flat = pretty::Nested(result, pretty::LAMBDA).to_string();
+            let body_span = codespan::ByteSpan::new(header_span.end(), result.context().span.end());
+            if self.fits(&flat, suffix + 4) && !self.has_comments(body_span) {
+                self.out.push_str(" { ");
+                self.out.push_str(&flat);
+                self.out.push_str(" }");
+                return;
+            }
+        }
+
+        self.out.push_str(" {");
+        self.indent += pretty::INDENT;
+
+        let mut previous_end = None;
+        for statement in &lambda.statements {
+            let span = match *statement {
+                ast::Statement::Variable(ref variable) => variable.context.span,
+                ast::Statement::Expression(ref expression) => expression.context().span,
+            };
+            self.separate(previous_end, span.start());
+            self.newline();
+            self.comments_before(span.start());
+            match *statement {
+                ast::Statement::Variable(ref variable) => self.variable(variable),
+                ast::Statement::Expression(ref expression) => {
+                    self.expression(expression, pretty::LAMBDA, 1)
+                }
+            }
+            self.out.push(';');
+            previous_end = Some(span.end());
+        }
+
+        let span = result.context().span;
+        self.separate(previous_end, span.start());
+        self.newline();
+        self.comments_before(span.start());
+        self.expression(result, pretty::LAMBDA, 0);
+
+        self.indent -= pretty::INDENT;
+        self.newline();
+        self.out.push('}');
+    }
+
+    /// Writes a list of items, with each item on a separate line followed by a comma.
+    fn list<A, F>(&mut self, open: &str, close: &str, items: &[A], mut item: F)
+    where
+        F: FnMut(&mut Self, &A),
+    {
+        self.out.push_str(open);
+        if !items.is_empty() {
+            self.indent += pretty::INDENT;
+            for i in items {
+                self.newline();
+                item(self, i);
+                self.out.push(',');
+            }
+            self.indent -= pretty::INDENT;
+            self.newline();
+        }
+        self.out.push_str(close);
+    }
+
+    /// Writes all comments that end before the specified index, each on its own line.
+    fn comments_before(&mut self, index: codespan::ByteIndex) {
+        let comments = self.comments;
+        while let Some(comment) = comments.get(self.next_comment) {
+            if comment.context.span.end() > index {
+                break;
+            }
+            self.comment(comment);
+            self.newline();
+        }
+    }
+
+    fn comment(&mut self, comment: &ast::Comment<parser::Context>) {
+        self.out.push_str("/*");
+        self.out.push_str(&comment.value);
+        self.out.push_str("*/");
+        self.next_comment += 1;
+    }
+
+    /// Writes an empty line if the source had one between the previous node and the next node or
+    /// the comments in front of it.
+    fn separate(&mut self, previous_end: Option<codespan::ByteIndex>, start: codespan::ByteIndex) {
+        if let Some(end) = previous_end {
+            let start = self
+                .comments
+                .get(self.next_comment)
+                .map_or(start, |c| cmp::min(start, c.context.span.start()));
+            let between = &self.source[self.offset(end)..self.offset(start)];
+            if between.matches('\n').count() > 1 {
+                self.trim_end();
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.trim_end();
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push(' ');
+        }
+    }
+
+    fn trim_end(&mut self) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+    }
+
+    /// Whether the specified text fits on the current line, followed by `suffix` characters.
+    fn fits(&self, text: &str, suffix: usize) -> bool {
+        let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        let column = self.out[line_start..].chars().count();
+
+        !text.contains('\n') && column + text.chars().count() + suffix <= WIDTH
+    }
+
+    /// Whether there are comments left to write within the specified span.
+    fn has_comments(&self, span: codespan::ByteSpan) -> bool {
+        self.comments[self.next_comment..]
+            .iter()
+            .any(|c| c.context.span.start() >= span.start() && c.context.span.end() <= span.end())
+    }
+
+    #[cfg_attr(
+        feature = "cargo-clippy",
+        allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)
+    )]
+    fn offset(&self, index: codespan::ByteIndex) -> usize {
+        cmp::min((index - self.base).0 as usize, self.source.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parse;
+
+    use super::*;
+
+    #[test]
+    fn precedence() {
+        let source =
+            "main = || -> i32 { (1i32 + 2i32) * 3i32 - (4i32 - -5i32) <=> (1i32 < 2i32) };\n";
+
+        assert_eq!(source, format_source(source));
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let source = r#"/* The answer */
+answer  =   42u32;
+
+
+/* Adds a number */
+add = |/* the number */ a: u32| -> u32 {
+  /* first */ b = a + answer;   c = b;
+
+  /* then */
+  c
+};
+"#;
+        let expected = r#"/* The answer */
+answer = 42u32;
+
+/* Adds a number */
+add = |
+  /* the number */
+  a: u32,
+| -> u32 {
+  /* first */
+  b = a + answer;
+  c = b;
+
+  /* then */
+  c
+};
+"#;
+
+        assert_eq!(expected, format_source(source));
+        assert_eq!(expected, format_source(expected));
+    }
+
+    #[test]
+    fn line_width() {
+        let source = r#"Person = { name: "Someone with a rather long name", age: 99u32, favorite: :cake, height: 1.9f64, nickname: "Some" };
+describe = |person: Person| -> String { greet(person.name, person.age, person.favorite, person.height, 1u32) };
+short = |a: u32| -> u32 { { x: a }.x };
+"#;
+        let expected = r#"Person = {
+  name: "Someone with a rather long name",
+  age: 99u32,
+  favorite: :cake,
+  height: 1.9f64,
+  nickname: "Some",
+};
+describe = |person: Person| -> String {
+  greet(person.name, person.age, person.favorite, person.height, 1u32)
+};
+short = |a: u32| -> u32 { {x: a}.x };
+"#;
+
+        assert_eq!(expected, format_source(source));
+        assert_eq!(expected, format_source(expected));
+    }
+
+    #[test]
+    fn try_expressions() {
+        let source = r#"safe = |a: u32, b: u32| -> u32 { try {a / b} catch |e| {0u32} };
+message = |a: u32| -> "" { (try { divide(a, 0u32) } catch |error| { error }).message };
+careful = |a: u32| -> u32 { try { compute_something_expensive(a, a + 1u32, a + 2u32, a + 3u32) } catch |error| { fallback_value(a) } };
+"#;
+        let expected = r#"safe = |a: u32, b: u32| -> u32 { try { a / b } catch |e| { 0u32 } };
+message = |a: u32| -> "" { (try { divide(a, 0u32) } catch |error| { error }).message };
+careful = |a: u32| -> u32 {
+  try {
+    compute_something_expensive(a, a + 1u32, a + 2u32, a + 3u32)
+  } catch |error| {
+    fallback_value(a)
+  }
+};
+"#;
+
+        assert_eq!(expected, format_source(source));
+        assert_eq!(expected, format_source(expected));
+    }
+
+    fn format_source(source: &str) -> String {
+        let mut code_map = codespan::CodeMap::new();
+        let file = code_map.add_filemap(
+            codespan::FileName::Virtual("test".into()),
+            source.to_owned(),
+        );
+        let module = ast::Module::parse(file.span(), source).unwrap();
+
+        format(&module, &file)
+    }
+}
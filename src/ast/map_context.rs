@@ -35,8 +35,34 @@ where
             .into_iter()
             .map(|v| v.map_context(mapping))
             .collect();
+        let comments = self
+            .comments
+            .into_iter()
+            .map(|c| c.map_context(mapping))
+            .collect();
 
-        ast::Module { context, variables }
+        ast::Module {
+            context,
+            variables,
+            comments,
+        }
+    }
+}
+
+impl<C1, C2> MapContext<C1, C2> for ast::Comment<C1>
+where
+    C1: fmt::Debug,
+    C2: fmt::Debug,
+{
+    type Output = ast::Comment<C2>;
+
+    fn map_context<F>(self, mapping: &mut F) -> Self::Output
+    where
+        F: FnMut(C1) -> C2,
+    {
+        let context = mapping(self.context);
+        let value = self.value;
+        ast::Comment { context, value }
     }
 }
 
@@ -85,6 +111,7 @@ where
             ast::Expression::Lambda(e) => ast::Expression::Lambda(e.map_context(mapping)),
             ast::Expression::Select(e) => ast::Expression::Select(e.map_context(mapping)),
             ast::Expression::Apply(e) => ast::Expression::Apply(e.map_context(mapping)),
+            ast::Expression::Try(e) => ast::Expression::Try(e.map_context(mapping)),
             ast::Expression::Unknown => ast::Expression::Unknown,
         }
     }
@@ -353,6 +380,30 @@ where
     }
 }
 
+impl<C1, C2> MapContext<C1, C2> for ast::Try<C1>
+where
+    C1: fmt::Debug,
+    C2: fmt::Debug,
+{
+    type Output = ast::Try<C2>;
+
+    fn map_context<F>(self, mapping: &mut F) -> Self::Output
+    where
+        F: FnMut(C1) -> C2,
+    {
+        let context = mapping(self.context);
+        let body = Box::new(self.body.map_context(mapping));
+        let error = self.error.map_context(mapping);
+        let handler = Box::new(self.handler.map_context(mapping));
+        ast::Try {
+            context,
+            body,
+            error,
+            handler,
+        }
+    }
+}
+
 impl<C1, C2> MapContext<C1, C2> for ast::Parameter<C1>
 where
     C1: fmt::Debug,
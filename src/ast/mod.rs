@@ -7,9 +7,12 @@
 //! To generically interact with the context of a node, the [`AstNode`] trait can be used.  All of
 //! the contexts of an entire AST can additionally be transformed using the [`MapContext`] trait.
 mod ast_node;
+mod format;
 mod map_context;
+mod pretty;
 
 pub use self::ast_node::AstNode;
+pub use self::format::format;
 pub use self::map_context::MapContext;
 
 /// Identifies the kind of AST node.
@@ -39,10 +42,14 @@ pub enum Kind {
     Select,
     /// An [`Apply`] AST node.
     Apply,
+    /// A [`Try`] AST node.
+    Try,
     /// A [`Parameter`] AST node.
     Parameter,
     /// A [`Variable`] AST node.
     Variable,
+    /// A [`Comment`] AST node.
+    Comment,
 }
 
 /// A complete Tin module.
@@ -52,6 +59,20 @@ pub struct Module<C> {
     pub context: C,
     /// Variable variables that are part of this module, in declaration order.
     pub variables: Vec<Variable<C>>,
+    /// All of the comments in this module, in source order.
+    ///
+    /// Comments can only precede definitions, lambda parameters, statements and lambda results, so
+    /// they are attached to the nodes that they precede by their location.
+    pub comments: Vec<Comment<C>>,
+}
+
+/// A comment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment<C> {
+    /// This node's AST context.
+    pub context: C,
+    /// The text of the comment, without the surrounding `/*` and `*/`.
+    pub value: String,
 }
 
 /// An identifier.
@@ -88,6 +109,8 @@ pub enum Expression<C> {
     Select(Select<C>),
     /// A function application.
     Apply(Apply<C>),
+    /// An expression that recovers from errors.
+    Try(Try<C>),
 
     /// An unknown expression.
     Unknown,
@@ -349,6 +372,19 @@ pub struct Apply<C> {
     pub parameters: Vec<Expression<C>>,
 }
 
+/// An expression that recovers from errors, like `try { risky(x) } catch |e| { fallback }`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Try<C> {
+    /// This node's AST context.
+    pub context: C,
+    /// The expression to evaluate.
+    pub body: Box<Expression<C>>,
+    /// The name that the caught error is bound to in the handler.
+    pub error: Identifier<C>,
+    /// The expression to evaluate instead if evaluating the body raises an error.
+    pub handler: Box<Expression<C>>,
+}
+
 /// A lambda parameter declaration.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Parameter<C> {
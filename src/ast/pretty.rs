@@ -0,0 +1,346 @@
+//! Pretty-printing of AST nodes back into Tin source code.
+//!
+//! The printed code parses back into an equivalent AST; parentheses are only inserted where they
+//! are needed to preserve the structure of the tree.  Comments are not preserved, and no attempt
+//! is made to keep lines short; see `ast::format` for that.
+use std::fmt;
+
+use crate::ast;
+
+/// The number of spaces per indentation level.
+pub(super) const INDENT: usize = 2;
+
+// Binding strengths of expressions, from weakest to strongest.  They mirror the expression levels
+// of the grammar; lambdas are given the weakest binding strength since their parameter list and
+// optional body would be ambiguous in most other positions.
+pub(super) const LAMBDA: u8 = 0;
+const OR: u8 = 1;
+const XOR: u8 = 2;
+const AND: u8 = 3;
+const CMP: u8 = 4;
+const BOR: u8 = 5;
+const BXOR: u8 = 6;
+const BAND: u8 = 7;
+const SHIFT: u8 = 8;
+const SUM: u8 = 9;
+const FACTOR: u8 = 10;
+pub(super) const UN_OP: u8 = 11;
+pub(super) const PROJECTION: u8 = 12;
+pub(super) const ATOM: u8 = 13;
+
+/// Displays an expression, parenthesized if it binds less strongly than the specified strength.
+pub(super) struct Nested<'a, C>(pub &'a ast::Expression<C>, pub u8);
+
+struct Printer<'a, 'b: 'a> {
+    f: &'a mut fmt::Formatter<'b>,
+    indent: usize,
+}
+
+impl<C> fmt::Display for ast::Module<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut printer = Printer { f, indent: 0 };
+        for variable in &self.variables {
+            printer.variable(variable)?;
+            printer.f.write_str(";\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl<C> fmt::Display for ast::Expression<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer { f, indent: 0 }.expression(self, LAMBDA)
+    }
+}
+
+impl<'a, C> fmt::Display for Nested<'a, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer { f, indent: 0 }.expression(self.0, self.1)
+    }
+}
+
+impl fmt::Display for ast::UnOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ast::UnOperator::Not => "!",
+            ast::UnOperator::BNot => "~!",
+            ast::UnOperator::Cl0 => "#^0",
+            ast::UnOperator::Cl1 => "#^1",
+            ast::UnOperator::Cls => "#^-",
+            ast::UnOperator::Ct0 => "#$0",
+            ast::UnOperator::Ct1 => "#$1",
+            ast::UnOperator::C0 => "#0",
+            ast::UnOperator::C1 => "#1",
+            ast::UnOperator::Sqrt => "^/",
+        })
+    }
+}
+
+impl fmt::Display for ast::BiOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ast::BiOperator::Eq => "==",
+            ast::BiOperator::Ne => "!=",
+            ast::BiOperator::Lt => "<",
+            ast::BiOperator::Ge => ">=",
+            ast::BiOperator::Gt => ">",
+            ast::BiOperator::Le => "<=",
+            ast::BiOperator::Cmp => "<=>",
+            ast::BiOperator::Add => "+",
+            ast::BiOperator::Sub => "-",
+            ast::BiOperator::Mul => "*",
+            ast::BiOperator::Div => "/",
+            ast::BiOperator::Rem => "%",
+            ast::BiOperator::And => "&",
+            ast::BiOperator::BAnd => "~&",
+            ast::BiOperator::Or => "|",
+            ast::BiOperator::BOr => "~|",
+            ast::BiOperator::Xor => "^",
+            ast::BiOperator::BXor => "~^",
+            ast::BiOperator::AndNot => "&!",
+            ast::BiOperator::BAndNot => "~&!",
+            ast::BiOperator::OrNot => "|!",
+            ast::BiOperator::BOrNot => "~|!",
+            ast::BiOperator::XorNot => "^!",
+            ast::BiOperator::BXorNot => "~^!",
+            ast::BiOperator::RotL => "<-<",
+            ast::BiOperator::RotR => ">->",
+            ast::BiOperator::ShL => "<<",
+            ast::BiOperator::ShR => ">>",
+        })
+    }
+}
+
+impl fmt::Display for ast::NumberValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ast::NumberValue::U8(n) => write!(f, "{}u8", n),
+            ast::NumberValue::U16(n) => write!(f, "{}u16", n),
+            ast::NumberValue::U32(n) => write!(f, "{}u32", n),
+            ast::NumberValue::U64(n) => write!(f, "{}u64", n),
+            ast::NumberValue::I8(n) => write!(f, "{}i8", n),
+            ast::NumberValue::I16(n) => write!(f, "{}i16", n),
+            ast::NumberValue::I32(n) => write!(f, "{}i32", n),
+            ast::NumberValue::I64(n) => write!(f, "{}i64", n),
+            ast::NumberValue::F32(n) => write!(f, "{}f32", n),
+            ast::NumberValue::F64(n) => write!(f, "{}f64", n),
+            ast::NumberValue::Invalid => f.write_str("?"),
+        }
+    }
+}
+
+impl fmt::Display for ast::StringValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::fmt::Write;
+
+        match *self {
+            ast::StringValue::String(ref s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\u{0008}' => f.write_str("\\b")?,
+                        '\u{000C}' => f.write_str("\\f")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if c.is_control() => write!(f, "\\u{{{:x}}}", u32::from(c))?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            ast::StringValue::Invalid => f.write_str("\"?\""),
+        }
+    }
+}
+
+impl<'a, 'b> Printer<'a, 'b> {
+    fn variable<C>(&mut self, variable: &ast::Variable<C>) -> fmt::Result {
+        write!(self.f, "{} = ", variable.name.value)?;
+        self.expression(&variable.initializer, LAMBDA)
+    }
+
+    fn expression<C>(&mut self, expression: &ast::Expression<C>, strength: u8) -> fmt::Result {
+        let parenthesize = binding_strength(expression) < strength;
+        if parenthesize {
+            self.f.write_str("(")?;
+        }
+
+        match *expression {
+            ast::Expression::NumberLiteral(ref number) => write!(self.f, "{}", number.value)?,
+            ast::Expression::StringLiteral(ref string) => write!(self.f, "{}", string.value)?,
+            ast::Expression::Symbol(ref symbol) => write!(self.f, ":{}", symbol.label)?,
+            ast::Expression::Identifier(ref identifier) => self.f.write_str(&identifier.value)?,
+            ast::Expression::Tuple(ref tuple) => self.tuple(tuple)?,
+            ast::Expression::Record(ref record) => self.record(record)?,
+            ast::Expression::UnOp(ref un_op) => {
+                write!(self.f, "{} ", un_op.operator)?;
+                self.expression(&un_op.operand, UN_OP)?;
+            }
+            ast::Expression::BiOp(ref bi_op) => {
+                let (lhs, rhs) = operand_strengths(bi_op.operator);
+                self.expression(&bi_op.lhs, lhs)?;
+                write!(self.f, " {} ", bi_op.operator)?;
+                self.expression(&bi_op.rhs, rhs)?;
+            }
+            ast::Expression::Lambda(ref lambda) => self.lambda(lambda)?,
+            ast::Expression::Select(ref select) => {
+                self.expression(&select.record, PROJECTION)?;
+                write!(self.f, ".{}", select.field.value)?;
+            }
+            ast::Expression::Apply(ref apply) => {
+                self.expression(&apply.function, PROJECTION)?;
+                self.f.write_str("(")?;
+                for (i, parameter) in apply.parameters.iter().enumerate() {
+                    if i > 0 {
+                        self.f.write_str(", ")?;
+                    }
+                    self.expression(parameter, LAMBDA)?;
+                }
+                self.f.write_str(")")?;
+            }
+            ast::Expression::Try(ref try_) => {
+                self.f.write_str("try { ")?;
+                self.expression(&try_.body, LAMBDA)?;
+                write!(self.f, " }} catch |{}| {{ ", try_.error.value)?;
+                self.expression(&try_.handler, LAMBDA)?;
+                self.f.write_str(" }")?;
+            }
+            ast::Expression::Unknown => self.f.write_str("?")?,
+        }
+
+        if parenthesize {
+            self.f.write_str(")")?;
+        }
+        Ok(())
+    }
+
+    fn tuple<C>(&mut self, tuple: &ast::Tuple<C>) -> fmt::Result {
+        self.f.write_str("(")?;
+        for (i, field) in tuple.fields.iter().enumerate() {
+            if i > 0 {
+                self.f.write_str(", ")?;
+            }
+            self.expression(field, LAMBDA)?;
+        }
+        // A parenthesized expression and a tuple with one field can only be told apart by a
+        // trailing comma.
+        if tuple.fields.len() == 1 {
+            self.f.write_str(",")?;
+        }
+        self.f.write_str(")")
+    }
+
+    fn record<C>(&mut self, record: &ast::Record<C>) -> fmt::Result {
+        self.f.write_str("{")?;
+        for (i, (name, value)) in record.fields.iter().enumerate() {
+            if i > 0 {
+                self.f.write_str(", ")?;
+            }
+            write!(self.f, "{}: ", name.value)?;
+            self.expression(value, LAMBDA)?;
+        }
+        self.f.write_str("}")
+    }
+
+    fn lambda<C>(&mut self, lambda: &ast::Lambda<C>) -> fmt::Result {
+        self.f.write_str("|")?;
+        for (i, parameter) in lambda.parameters.iter().enumerate() {
+            if i > 0 {
+                self.f.write_str(", ")?;
+            }
+            write!(self.f, "{}: ", parameter.name.value)?;
+            self.expression(&parameter.signature, ATOM)?;
+        }
+        self.f.write_str("| -> ")?;
+        self.expression(&lambda.signature, ATOM)?;
+
+        if let Some(ref result) = lambda.result {
+            if lambda.statements.is_empty() {
+                self.f.write_str(" { ")?;
+                self.expression(result, LAMBDA)?;
+                self.f.write_str(" }")?;
+            } else {
+                self.f.write_str(" {\n")?;
+                self.indent += INDENT;
+                for statement in &lambda.statements {
+                    self.write_indent()?;
+                    match *statement {
+                        ast::Statement::Variable(ref variable) => self.variable(variable)?,
+                        ast::Statement::Expression(ref expression) => {
+                            self.expression(expression, LAMBDA)?
+                        }
+                    }
+                    self.f.write_str(";\n")?;
+                }
+                self.write_indent()?;
+                self.expression(result, LAMBDA)?;
+                self.f.write_str("\n")?;
+                self.indent -= INDENT;
+                self.write_indent()?;
+                self.f.write_str("}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_indent(&mut self) -> fmt::Result {
+        write!(self.f, "{:1$}", "", self.indent)
+    }
+}
+
+pub(super) fn binding_strength<C>(expression: &ast::Expression<C>) -> u8 {
+    match *expression {
+        ast::Expression::Lambda(_) => LAMBDA,
+        ast::Expression::BiOp(ref bi_op) => bi_operator_strength(bi_op.operator),
+        // A selection or application after a try expression would be easy to mistake for a part of
+        // its handler, so try expressions are parenthesized there even though they need not be.
+        ast::Expression::UnOp(_) | ast::Expression::Try(_) => UN_OP,
+        ast::Expression::Select(_) | ast::Expression::Apply(_) => PROJECTION,
+        ast::Expression::NumberLiteral(_)
+        | ast::Expression::StringLiteral(_)
+        | ast::Expression::Symbol(_)
+        | ast::Expression::Identifier(_)
+        | ast::Expression::Tuple(_)
+        | ast::Expression::Record(_)
+        | ast::Expression::Unknown => ATOM,
+    }
+}
+
+/// The binding strengths that the left-hand and right-hand operands of an operator need.
+pub(super) fn operand_strengths(operator: ast::BiOperator) -> (u8, u8) {
+    let strength = bi_operator_strength(operator);
+    // Comparisons are not associative, so both sides need to bind more strongly.
+    if strength == CMP {
+        (BOR, BOR)
+    } else {
+        (strength, strength + 1)
+    }
+}
+
+fn bi_operator_strength(operator: ast::BiOperator) -> u8 {
+    match operator {
+        ast::BiOperator::Or | ast::BiOperator::OrNot => OR,
+        ast::BiOperator::Xor | ast::BiOperator::XorNot => XOR,
+        ast::BiOperator::And | ast::BiOperator::AndNot => AND,
+        ast::BiOperator::Eq
+        | ast::BiOperator::Ne
+        | ast::BiOperator::Lt
+        | ast::BiOperator::Ge
+        | ast::BiOperator::Gt
+        | ast::BiOperator::Le
+        | ast::BiOperator::Cmp => CMP,
+        ast::BiOperator::BOr | ast::BiOperator::BOrNot => BOR,
+        ast::BiOperator::BXor | ast::BiOperator::BXorNot => BXOR,
+        ast::BiOperator::BAnd | ast::BiOperator::BAndNot => BAND,
+        ast::BiOperator::RotL
+        | ast::BiOperator::RotR
+        | ast::BiOperator::ShL
+        | ast::BiOperator::ShR => SHIFT,
+        ast::BiOperator::Add | ast::BiOperator::Sub => SUM,
+        ast::BiOperator::Mul | ast::BiOperator::Div | ast::BiOperator::Rem => FACTOR,
+    }
+}
@@ -10,9 +10,11 @@ use std::fs;
 use std::io;
 use std::path;
 use std::process;
+use std::str;
 use std::sync::atomic;
 
 static REPORTED_DIAGNOSTICS: atomic::AtomicBool = atomic::AtomicBool::new(false);
+static JSON_DIAGNOSTICS: atomic::AtomicBool = atomic::AtomicBool::new(false);
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "tin")]
@@ -20,6 +22,101 @@ struct Options {
     /// Source file to compile (that contains a main function); will use stdin if omitted.
     #[structopt(name = "SOURCE", parse(from_os_str))]
     source: Option<path::PathBuf>,
+    /// Writes compiler output instead of running the code, as `KIND` or `KIND=PATH`.
+    ///
+    /// `KIND` is one of `clif` (the Cranelift IR of every function), `ir-dot` (the IR as a
+    /// GraphViz graph), `layouts` (the memory layout of each top-level definition) or `types`
+    /// (the inferred type of each top-level definition).  The output is written to `PATH`, or to
+    /// stdout if omitted.  Can be specified several times.
+    #[structopt(long = "emit", raw(number_of_values = "1"))]
+    emit: Vec<Emit>,
+    /// How to report errors; either `human` (the default) or `json`.
+    ///
+    /// With `json`, every diagnostic is written to stderr as a JSON object on a separate line.
+    #[structopt(
+        long = "error-format",
+        default_value = "human",
+        raw(possible_values = "&[\"human\", \"json\"]", global = "true")
+    )]
+    error_format: String,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Compiles a source file ahead of time into a relocatable object file.
+    ///
+    /// Public top-level functions are exported as `tin_<name>`; link the object together with the
+    /// `tin-runtime` library.  With `--target wasm32`, a standalone WebAssembly module is written
+    /// instead, which imports the builtin functions from the host.
+    #[structopt(name = "build")]
+    Build {
+        /// Source file to compile; will use stdin if omitted.
+        #[structopt(name = "SOURCE", parse(from_os_str))]
+        source: Option<path::PathBuf>,
+        /// The object file or WebAssembly module to write.
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: path::PathBuf,
+        /// The target to compile for; either `native` (the default) or `wasm32`.
+        #[structopt(
+            long = "target",
+            default_value = "native",
+            raw(possible_values = "&[\"native\", \"wasm32\"]")
+        )]
+        target: String,
+    },
+    /// Serves the Language Server Protocol over stdin and stdout.
+    ///
+    /// Open documents are checked as they change; the server provides diagnostics, the types of
+    /// expressions on hover, go to definition and the top-level definitions as document symbols.
+    #[structopt(name = "lsp")]
+    Lsp,
+    /// Starts an interactive session for evaluating expressions and adding definitions.
+    ///
+    /// Each input is either a definition, which is added to the session, or an expression, which
+    /// is evaluated and printed along with its type.  Type `:help` for a list of commands.
+    #[structopt(name = "repl")]
+    Repl,
+    /// Runs the tests in source files.
+    ///
+    /// A test is a top-level function named `test_*` that takes no parameters.  It passes when it
+    /// returns `:t` or zero, and fails when it returns anything else or raises a runtime error.
+    #[structopt(name = "test")]
+    Test {
+        /// Source files to test; will use stdin if omitted.
+        #[structopt(name = "FILES", parse(from_os_str))]
+        files: Vec<path::PathBuf>,
+        /// Only runs the tests whose names contain this string.
+        #[structopt(short = "f", long = "filter")]
+        filter: Option<String>,
+    },
+    /// Formats source files in the canonical style.
+    ///
+    /// Files are rewritten in place; if no files are given, stdin is formatted to stdout.
+    #[structopt(name = "fmt")]
+    Fmt {
+        /// Source files to format; will use stdin if omitted.
+        #[structopt(name = "FILES", parse(from_os_str))]
+        files: Vec<path::PathBuf>,
+        /// Only checks whether the sources are formatted, and fails if any of them is not.
+        #[structopt(long = "check")]
+        check: bool,
+    },
+}
+
+#[derive(Debug)]
+struct Emit {
+    kind: EmitKind,
+    path: Option<path::PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum EmitKind {
+    Clif,
+    IrDot,
+    Layouts,
+    Types,
 }
 
 fn main() {
@@ -38,29 +135,30 @@ fn main() {
 }
 
 fn run() -> Result<i32, failure::Error> {
-    use std::io::Read;
     use structopt::StructOpt;
 
     pretty_env_logger::init_timed();
 
     let options = Options::from_args();
+    JSON_DIAGNOSTICS.store(options.error_format == "json", atomic::Ordering::Relaxed);
 
-    let mut source = String::new();
-    let file_name;
-    if let Some(path) = options.source {
-        let mut file = fs::File::open(&path)?;
-        file_name = codespan::FileName::Real(path);
-        file.read_to_string(&mut source)?;
-    } else {
-        let stdin = io::stdin();
-        file_name = codespan::FileName::Virtual("stdin".into());
-        let mut stdin = stdin.lock();
-        stdin.read_to_string(&mut source)?;
+    match options.command {
+        Some(Command::Build {
+            source,
+            output,
+            target,
+        }) => build(source, &output, &target),
+        Some(Command::Lsp) => lsp(),
+        Some(Command::Repl) => repl(),
+        Some(Command::Test { files, filter }) => test(&files, filter.as_ref().map(String::as_str)),
+        Some(Command::Fmt { files, check }) => fmt(&files, check),
+        None if !options.emit.is_empty() => emit(options.source, &options.emit),
+        None => execute(options.source),
     }
+}
 
-    let mut tin = tin::Tin::new();
-    tin.load(file_name, &source)
-        .map_err(|e| report_diagnostics(tin.codemap(), e))?;
+fn execute(source: Option<path::PathBuf>) -> Result<i32, failure::Error> {
+    let mut tin = load(source)?;
 
     let mut module = tin
         .compile()
@@ -70,15 +168,247 @@ fn run() -> Result<i32, failure::Error> {
         .function::<tin::module::Function0<i32>>("main")
         .ok_or_else(|| failure::err_msg("missing a main function"))?;
 
-    let result = entrypoint.call()?;
+    let result = entrypoint
+        .call()
+        .map_err(|e| report_diagnostics(tin.codemap(), e.into()))?;
+
+    Ok(result)
+}
+
+fn build(
+    source: Option<path::PathBuf>,
+    output: &path::Path,
+    target: &str,
+) -> Result<i32, failure::Error> {
+    let mut tin = load(source)?;
+
+    let object = if target == "wasm32" {
+        tin.compile_wasm()
+    } else {
+        let name = output.to_string_lossy().into_owned();
+        tin.compile_object(&name)
+    }
+    .map_err(|e| report_diagnostics(tin.codemap(), e))?;
+
+    fs::write(output, object)?;
+
+    Ok(0)
+}
+
+fn emit(source: Option<path::PathBuf>, emits: &[Emit]) -> Result<i32, failure::Error> {
+    use std::io::Write;
+
+    let mut tin = load(source)?;
+
+    for emit in emits {
+        let mut output = Vec::new();
+
+        match emit.kind {
+            EmitKind::Clif => {
+                let clif = tin
+                    .compile_clif()
+                    .map_err(|e| report_diagnostics(tin.codemap(), e))?;
+                output.write_all(clif.as_bytes())?;
+            }
+            EmitKind::IrDot => {
+                dot::render(&tin.graph(), &mut output)?;
+            }
+            EmitKind::Layouts => {
+                let layouts = tin
+                    .layouts()
+                    .map_err(|e| report_diagnostics(tin.codemap(), e))?;
+                for (name, layout) in layouts {
+                    writeln!(output, "{}: {}", name, layout)?;
+                }
+            }
+            EmitKind::Types => {
+                let exports = tin
+                    .exports()
+                    .map_err(|e| report_diagnostics(tin.codemap(), e))?;
+                for export in exports {
+                    writeln!(output, "{}: {}", export.name(), export.ty())?;
+                }
+            }
+        }
+
+        if let Some(ref path) = emit.path {
+            fs::write(path, output)?;
+        } else {
+            io::stdout().write_all(&output)?;
+        }
+    }
+
+    Ok(0)
+}
+
+fn lsp() -> Result<i32, failure::Error> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    let code = tin::lsp::serve(stdin.lock(), stdout.lock())?;
+
+    Ok(code)
+}
+
+fn repl() -> Result<i32, failure::Error> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    tin::repl::run(stdin.lock(), stdout.lock())?;
+
+    Ok(0)
+}
+
+fn test(files: &[path::PathBuf], filter: Option<&str>) -> Result<i32, failure::Error> {
+    let sources = if files.is_empty() {
+        vec![None]
+    } else {
+        files.iter().cloned().map(Some).collect()
+    };
+
+    let mut passed = 0;
+    let mut filtered = 0;
+    let mut broken = 0;
+    let mut failures = Vec::new();
+
+    for source in sources {
+        let file = source
+            .as_ref()
+            .map_or_else(|| "stdin".to_owned(), |p| p.display().to_string());
+
+        let mut module = match load(source).and_then(|mut tin| {
+            tin.compile()
+                .map_err(|e| report_diagnostics(tin.codemap(), e).into())
+        }) {
+            Ok(module) => module,
+            Err(error) => {
+                error!("could not compile {}: {}", file, error);
+                broken += 1;
+                continue;
+            }
+        };
+
+        for test in tin::testing::discover(&module) {
+            if filter.map_or(false, |f| !test.name().contains(f)) {
+                filtered += 1;
+                continue;
+            }
+
+            let name = format!("{}::{}", file, test);
+            match tin::testing::run(&mut module, &test) {
+                Ok(()) => {
+                    println!("test {} ... ok", name);
+                    passed += 1;
+                }
+                Err(failure) => {
+                    println!("test {} ... FAILED", name);
+                    failures.push((name, failure));
+                }
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        println!();
+        println!("failures:");
+        for (name, failure) in &failures {
+            println!();
+            println!("---- {} ----", name);
+            println!("{}", failure);
+        }
+    }
+
+    let ok = failures.is_empty() && broken == 0;
+    print!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out",
+        if ok { "ok" } else { "FAILED" },
+        passed,
+        failures.len(),
+        filtered
+    );
+    if broken > 0 {
+        print!("; {} could not be compiled", broken);
+    }
+    println!();
+
+    Ok(if ok { 0 } else { 1 })
+}
+
+fn fmt(files: &[path::PathBuf], check: bool) -> Result<i32, failure::Error> {
+    use std::io::Read;
+    use std::io::Write;
+
+    let mut tin = tin::Tin::new();
+
+    if files.is_empty() {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        let formatted = tin
+            .format(codespan::FileName::Virtual("stdin".into()), &source)
+            .map_err(|e| report_diagnostics(tin.codemap(), e))?;
+
+        if check {
+            return Ok(if formatted == source { 0 } else { 1 });
+        }
+
+        io::stdout().write_all(formatted.as_bytes())?;
+        return Ok(0);
+    }
+
+    let mut result = 0;
+    for path in files {
+        let source = fs::read_to_string(path)?;
+        let formatted = tin
+            .format(codespan::FileName::Real(path.clone()), &source)
+            .map_err(|e| report_diagnostics(tin.codemap(), e))?;
+
+        if formatted != source {
+            if check {
+                println!("{}", path.display());
+                result = 1;
+            } else {
+                fs::write(path, formatted)?;
+            }
+        }
+    }
 
     Ok(result)
 }
 
+fn load(source: Option<path::PathBuf>) -> Result<tin::Tin, failure::Error> {
+    use std::io::Read;
+
+    let mut source_code = String::new();
+    let file_name;
+    if let Some(path) = source {
+        let mut file = fs::File::open(&path)?;
+        file_name = codespan::FileName::Real(path);
+        file.read_to_string(&mut source_code)?;
+    } else {
+        let stdin = io::stdin();
+        file_name = codespan::FileName::Virtual("stdin".into());
+        let mut stdin = stdin.lock();
+        stdin.read_to_string(&mut source_code)?;
+    }
+
+    let mut tin = tin::Tin::new();
+    tin.load(file_name, &source_code)
+        .map_err(|e| report_diagnostics(tin.codemap(), e))?;
+
+    Ok(tin)
+}
+
 fn report_diagnostics(codemap: &codespan::CodeMap, error: tin::Error) -> tin::Error {
     use codespan_reporting::termcolor;
     use tin::diagnostic::Diagnostics;
 
+    if JSON_DIAGNOSTICS.load(atomic::Ordering::Relaxed) {
+        let stderr = io::stderr();
+        tin::diagnostic::emit_json(stderr.lock(), codemap, &error).unwrap();
+        REPORTED_DIAGNOSTICS.store(true, atomic::Ordering::Relaxed);
+        return error;
+    }
+
     let mut builder = tin::diagnostic::DiagnosticsBuilder::new();
     error.to_diagnostics(&mut builder);
     let diagnostics = builder.build();
@@ -96,3 +426,29 @@ fn report_diagnostics(codemap: &codespan::CodeMap, error: tin::Error) -> tin::Er
 
     error
 }
+
+impl str::FromStr for Emit {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, path) = match s.find('=') {
+            Some(idx) => (&s[..idx], Some(path::PathBuf::from(&s[idx + 1..]))),
+            None => (s, None),
+        };
+
+        let kind = match kind {
+            "clif" => EmitKind::Clif,
+            "ir-dot" => EmitKind::IrDot,
+            "layouts" => EmitKind::Layouts,
+            "types" => EmitKind::Types,
+            other => {
+                return Err(failure::err_msg(format!(
+                    "unknown output kind {:?}; expected one of clif, ir-dot, layouts or types",
+                    other
+                )))
+            }
+        };
+
+        Ok(Emit { kind, path })
+    }
+}
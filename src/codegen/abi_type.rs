@@ -28,7 +28,8 @@ impl AbiType {
             ty::Type::String | ty::Type::Tuple(_) | ty::Type::Record(_) | ty::Type::Function(_) => {
                 AbiType::Ptr
             }
-            ty::Type::Symbol(_) => AbiType::Scalar(types::I8),
+            // Values of the never type don't exist, but they still need a placeholder
+            ty::Type::Symbol(_) | ty::Type::Never => AbiType::Scalar(types::I8),
             ty::Type::Union(ty::Union { ref alternatives }) => {
                 let n = alternatives.len();
 
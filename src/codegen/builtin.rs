@@ -1,12 +1,6 @@
-use std::alloc;
-use std::num;
-use std::slice;
-use std::str;
-
 use cranelift::prelude::types;
 
 use crate::codegen::abi_type;
-use crate::module;
 
 #[derive(Debug)]
 pub struct Builtin {
@@ -32,8 +26,8 @@ macro_rules! builtins {
 macro_rules! builtin {
     ($builtin:ident, $params:expr, $returns:expr) => {
         Builtin {
-            symbol: concat!("@builtin:", stringify!($builtin)),
-            ptr: $builtin as *const u8,
+            symbol: concat!("tin_builtin_", stringify!($builtin)),
+            ptr: tin_runtime::builtin::$builtin as *const u8,
             signature: abi_type::AbiSignature {
                 params: $params,
                 returns: $returns,
@@ -43,58 +37,18 @@ macro_rules! builtin {
 }
 
 builtins! {
-    (ALLOC, alloc, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr], &[abi_type::AbiType::Ptr]),
-    (DEALLOC, dealloc, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr], &[]),
+    (ALLOC, alloc, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr], &[abi_type::AbiType::Ptr]),
+    (DEALLOC, dealloc, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr], &[]),
+    (ENTER_CALL, enter_call, &[abi_type::AbiType::Ptr], &[abi_type::AbiType::Ptr]),
+    (EXIT_CALL, exit_call, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr], &[]),
+    (CONSUME_FUEL, consume_fuel, &[abi_type::AbiType::Ptr], &[abi_type::AbiType::Scalar(types::I8)]),
+    (TAKE_INTERRUPT, take_interrupt, &[abi_type::AbiType::Ptr], &[abi_type::AbiType::Scalar(types::I8)]),
     (ERROR, error, &[abi_type::AbiType::Scalar(types::I32)], &[abi_type::AbiType::Ptr]),
-    (UNWIND_FRAME, unwind_frame, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Scalar(types::I32), abi_type::AbiType::Scalar(types::I32)], &[]),
-}
-
-unsafe extern "C" fn alloc(size: usize, align: num::NonZeroUsize) -> *mut u8 {
-    let layout = alloc::Layout::from_size_align_unchecked(size, align.get());
-    let ptr = alloc::alloc(layout);
-    debug!("alloc size={:?} align={:?} ptr={:?}", size, align, ptr);
-    ptr
-}
-
-unsafe extern "C" fn dealloc(ptr: *mut u8, size: usize, align: num::NonZeroUsize) {
-    let layout = alloc::Layout::from_size_align_unchecked(size, align.get());
-    debug!("dealloc ptr={:?} size={:?} align={:?}", ptr, size, align);
-    alloc::dealloc(ptr, layout);
-}
-
-unsafe extern "C" fn error(kind: u32) -> *mut module::Error {
-    use num_traits::cast::FromPrimitive;
-
-    let kind = module::ErrorKind::from_u32(kind).unwrap_or(module::ErrorKind::Unknown);
-
-    debug!("error kind={:?}", kind);
-
-    let error = module::Error::new(kind);
-
-    Box::into_raw(Box::new(error))
-}
-
-unsafe extern "C" fn unwind_frame(
-    error: *mut module::Error,
-    name_data: *const u8,
-    name_len: usize,
-    path_data: *const u8,
-    path_len: usize,
-    line: u32,
-    col: u32,
-) {
-    let error = error.as_mut().unwrap();
-
-    let name = str::from_utf8_unchecked(slice::from_raw_parts(name_data, name_len));
-    let path = str::from_utf8_unchecked(slice::from_raw_parts(path_data, path_len));
-    let location = module::Point::new(path.to_owned(), line, col);
-
-    debug!(
-        "unwind_frame error={:?} name={:?} location={:?}",
-        error, name, location
-    );
-
-    let frame = module::Frame::new(name.to_owned(), location);
-
-    error.push_frame(frame);
+    (USER_ERROR, user_error, &[abi_type::AbiType::Ptr], &[abi_type::AbiType::Ptr]),
+    (ASSERTION_ERROR, assertion_error, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Scalar(types::I32), abi_type::AbiType::Scalar(types::I64), abi_type::AbiType::Scalar(types::I64)], &[abi_type::AbiType::Ptr]),
+    (REM_F32, rem_f32, &[abi_type::AbiType::Scalar(types::F32), abi_type::AbiType::Scalar(types::F32)], &[abi_type::AbiType::Scalar(types::F32)]),
+    (REM_F64, rem_f64, &[abi_type::AbiType::Scalar(types::F64), abi_type::AbiType::Scalar(types::F64)], &[abi_type::AbiType::Scalar(types::F64)]),
+    (RECOVERABLE, recoverable, &[abi_type::AbiType::Ptr], &[abi_type::AbiType::Scalar(types::I8)]),
+    (CATCH_ERROR, catch_error, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr], &[abi_type::AbiType::Ptr]),
+    (UNWIND_FRAME, unwind_frame, &[abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Ptr, abi_type::AbiType::Scalar(types::I32), abi_type::AbiType::Scalar(types::I32), abi_type::AbiType::Scalar(types::I32), abi_type::AbiType::Scalar(types::I32)], &[]),
 }
@@ -1,22 +1,48 @@
 use std::fmt;
+use std::marker;
 
 use cranelift::prelude::*;
 
 use crate::ir::component::layout;
 use crate::value;
 
-pub struct Translator<'a> {
-    storage: &'a mut Vec<u8>,
+/// The initial contents of a data object, along with the data objects that it points to.
+#[derive(Debug)]
+pub struct Data {
+    pub contents: Vec<u8>,
+    pub pointers: Vec<(usize, Data)>,
+}
+
+/// Translates values into data objects, using the byte order `E` of the target.
+pub struct Translator<E> {
+    data: Data,
     ptr_type: Type,
+    byte_order: marker::PhantomData<E>,
 }
 
-impl<'a> Translator<'a> {
-    pub fn new(storage: &'a mut Vec<u8>, ptr_type: Type) -> Self {
-        Translator { storage, ptr_type }
+impl<E> Translator<E>
+where
+    E: byteorder::ByteOrder,
+{
+    pub fn new(ptr_type: Type) -> Self {
+        let contents = Vec::new();
+        let pointers = Vec::new();
+        let data = Data { contents, pointers };
+        let byte_order = marker::PhantomData;
+
+        Translator {
+            data,
+            ptr_type,
+            byte_order,
+        }
+    }
+
+    pub fn into_data(self) -> Data {
+        self.data
     }
 
     pub fn store_value(&mut self, layout: &layout::Layout, value: &value::Value) {
-        self.storage.reserve(layout.size);
+        self.data.contents.reserve(layout.size);
         match value.case() {
             value::Case::Number(v) => self.store_number(layout, *v),
             value::Case::String(v) => self.store_string(layout, v),
@@ -30,40 +56,16 @@ impl<'a> Translator<'a> {
         use byteorder::WriteBytesExt;
 
         match number {
-            value::Number::U8(v) => self.storage.write_u8(v).unwrap(),
-            value::Number::U16(v) => self
-                .storage
-                .write_u16::<byteorder::NativeEndian>(v)
-                .unwrap(),
-            value::Number::U32(v) => self
-                .storage
-                .write_u32::<byteorder::NativeEndian>(v)
-                .unwrap(),
-            value::Number::U64(v) => self
-                .storage
-                .write_u64::<byteorder::NativeEndian>(v)
-                .unwrap(),
-            value::Number::I8(v) => self.storage.write_i8(v).unwrap(),
-            value::Number::I16(v) => self
-                .storage
-                .write_i16::<byteorder::NativeEndian>(v)
-                .unwrap(),
-            value::Number::I32(v) => self
-                .storage
-                .write_i32::<byteorder::NativeEndian>(v)
-                .unwrap(),
-            value::Number::I64(v) => self
-                .storage
-                .write_i64::<byteorder::NativeEndian>(v)
-                .unwrap(),
-            value::Number::F32(v) => self
-                .storage
-                .write_f32::<byteorder::NativeEndian>(v)
-                .unwrap(),
-            value::Number::F64(v) => self
-                .storage
-                .write_f64::<byteorder::NativeEndian>(v)
-                .unwrap(),
+            value::Number::U8(v) => self.data.contents.write_u8(v).unwrap(),
+            value::Number::U16(v) => self.data.contents.write_u16::<E>(v).unwrap(),
+            value::Number::U32(v) => self.data.contents.write_u32::<E>(v).unwrap(),
+            value::Number::U64(v) => self.data.contents.write_u64::<E>(v).unwrap(),
+            value::Number::I8(v) => self.data.contents.write_i8(v).unwrap(),
+            value::Number::I16(v) => self.data.contents.write_i16::<E>(v).unwrap(),
+            value::Number::I32(v) => self.data.contents.write_i32::<E>(v).unwrap(),
+            value::Number::I64(v) => self.data.contents.write_i64::<E>(v).unwrap(),
+            value::Number::F32(v) => self.data.contents.write_f32::<E>(v).unwrap(),
+            value::Number::F64(v) => self.data.contents.write_f64::<E>(v).unwrap(),
         }
     }
 
@@ -74,64 +76,63 @@ impl<'a> Translator<'a> {
 
         let len = string.len();
         match self.ptr_type.bits() {
-            8 => self.storage.write_u8(len as u8).unwrap(),
-            16 => self
-                .storage
-                .write_u16::<byteorder::NativeEndian>(len as u16)
-                .unwrap(),
-            32 => self
-                .storage
-                .write_u32::<byteorder::NativeEndian>(len as u32)
-                .unwrap(),
-            64 => self
-                .storage
-                .write_u64::<byteorder::NativeEndian>(len as u64)
-                .unwrap(),
+            8 => self.data.contents.write_u8(len as u8).unwrap(),
+            16 => self.data.contents.write_u16::<E>(len as u16).unwrap(),
+            32 => self.data.contents.write_u32::<E>(len as u32).unwrap(),
+            64 => self.data.contents.write_u64::<E>(len as u64).unwrap(),
             _ => unimplemented!(),
         }
 
-        self.storage.write_all(string.as_bytes()).unwrap();
+        self.data.contents.write_all(string.as_bytes()).unwrap();
     }
 
     pub fn store_tuple(&mut self, layout: &layout::Layout, tuple: &value::Tuple) {
-        use std::io::Write;
-
         let unnamed_fields = &layout.unnamed_fields;
-        let mut pos = 0;
         assert_eq!(tuple.fields.len(), unnamed_fields.len());
 
+        let start = self.data.contents.len();
         for (value, offset_layout) in tuple.fields.iter().zip(unnamed_fields.iter()) {
-            assert!(offset_layout.offset >= pos);
-            while pos < offset_layout.offset {
-                self.storage.write_all(&[0_u8]).unwrap();
-                pos += 1;
-            }
-            self.store_value(&offset_layout.layout, value);
-            pos += offset_layout.layout.size;
+            self.store_field(start, offset_layout, value);
         }
+        self.pad_to(start + layout.size);
     }
 
     pub fn store_record(&mut self, layout: &layout::Layout, record: &value::Record) {
-        use std::io::Write;
-
         let named_fields = &layout.named_fields;
-        let mut pos = 0;
         assert_eq!(record.fields.len(), named_fields.len());
 
-        for ((_, value), named_field) in record.fields.iter().zip(named_fields.iter()) {
-            let offset_layout = &named_field.offset_layout;
-            assert!(offset_layout.offset >= pos);
-            while pos < offset_layout.offset {
-                self.storage.write_all(&[0_u8]).unwrap();
-                pos += 1;
+        let start = self.data.contents.len();
+        for named_field in named_fields {
+            let value = &record.fields[&named_field.field];
+            self.store_field(start, &named_field.offset_layout, value);
+        }
+        self.pad_to(start + layout.size);
+    }
+
+    fn store_field(&mut self, start: usize, offset_layout: &layout::Offset, value: &value::Value) {
+        let offset = start + offset_layout.offset;
+        self.pad_to(offset);
+
+        match value.case() {
+            value::Case::String(_) | value::Case::Tuple(_) | value::Case::Record(_) => {
+                let mut translator = Translator::<E>::new(self.ptr_type);
+                translator.store_value(&offset_layout.layout, value);
+                self.data.pointers.push((offset, translator.into_data()));
+                self.pad_to(offset + self.ptr_type.bytes() as usize);
+            }
+            value::Case::Number(_) | value::Case::Symbol(_) => {
+                self.store_value(&offset_layout.layout, value)
             }
-            self.store_value(&offset_layout.layout, value);
-            pos += offset_layout.layout.size;
         }
     }
+
+    fn pad_to(&mut self, offset: usize) {
+        assert!(offset >= self.data.contents.len());
+        self.data.contents.resize(offset, 0);
+    }
 }
 
-impl<'a> fmt::Debug for Translator<'a> {
+impl<E> fmt::Debug for Translator<E> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Translator").finish()
     }
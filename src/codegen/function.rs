@@ -1,17 +1,19 @@
 use std::collections;
 use std::fmt;
+use std::mem;
 
 use specs;
 
 use cranelift::prelude::*;
 use cranelift_module;
-use cranelift_simplejit;
+use tin_runtime;
 
 use crate::codegen::abi_type;
 use crate::codegen::builtin;
 use crate::codegen::util;
 use crate::ir::component::constexpr;
 use crate::ir::component::element;
+use crate::ir::component::escape;
 use crate::ir::component::layout;
 use crate::ir::component::location;
 use crate::ir::component::symbol;
@@ -19,14 +21,16 @@ use crate::ir::component::ty;
 use crate::module;
 use crate::value;
 
-pub struct Translator<'a, 'f>
+pub struct Translator<'a, 'f, B>
 where
     'f: 'a,
+    B: cranelift_module::Backend + 'a,
 {
-    module: &'a mut cranelift_module::Module<cranelift_simplejit::SimpleJITBackend>,
+    module: &'a mut cranelift_module::Module<B>,
     builder: &'a mut FunctionBuilder<'f>,
     constexprs: &'a specs::ReadStorage<'a, constexpr::Constexpr>,
     elements: &'a specs::ReadStorage<'a, element::Element>,
+    escapes: &'a specs::ReadStorage<'a, escape::Escape>,
     layouts: &'a specs::ReadStorage<'a, layout::Layout>,
     locations: &'a specs::ReadStorage<'a, location::Location>,
     symbols: &'a specs::ReadStorage<'a, symbol::Symbol>,
@@ -37,15 +41,20 @@ where
     variables: &'a collections::HashMap<specs::Entity, Variable>,
     defined_strings: &'a mut collections::HashMap<String, cranelift_module::DataId>,
     codemap: &'a codespan::CodeMap,
+    caught_errors: collections::HashMap<specs::Entity, Value>,
 }
 
-impl<'a, 'f> Translator<'a, 'f> {
+impl<'a, 'f, B> Translator<'a, 'f, B>
+where
+    B: cranelift_module::Backend,
+{
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
     pub fn new(
-        module: &'a mut cranelift_module::Module<cranelift_simplejit::SimpleJITBackend>,
+        module: &'a mut cranelift_module::Module<B>,
         builder: &'a mut FunctionBuilder<'f>,
         constexprs: &'a specs::ReadStorage<'a, constexpr::Constexpr>,
         elements: &'a specs::ReadStorage<'a, element::Element>,
+        escapes: &'a specs::ReadStorage<'a, escape::Escape>,
         layouts: &'a specs::ReadStorage<'a, layout::Layout>,
         locations: &'a specs::ReadStorage<'a, location::Location>,
         symbols: &'a specs::ReadStorage<'a, symbol::Symbol>,
@@ -62,6 +71,7 @@ impl<'a, 'f> Translator<'a, 'f> {
             builder,
             constexprs,
             elements,
+            escapes,
             layouts,
             locations,
             symbols,
@@ -72,6 +82,7 @@ impl<'a, 'f> Translator<'a, 'f> {
             variables,
             defined_strings,
             codemap,
+            caught_errors: collections::HashMap::new(),
         }
     }
 
@@ -92,6 +103,8 @@ impl<'a, 'f> Translator<'a, 'f> {
     pub fn eval_element(&mut self, entity: specs::Entity, element: &element::Element) -> Value {
         if let Some(constexpr) = self.constexprs.get(entity) {
             self.eval_constexpr(entity, constexpr)
+        } else if self.types.get(entity) == Some(&ty::Type::Never) {
+            self.eval_never(entity, element)
         } else {
             match *element {
                 element::Element::Number(ref v) => self.eval_number_value(entity, v),
@@ -107,6 +120,10 @@ impl<'a, 'f> Translator<'a, 'f> {
                 element::Element::Variable(ref v) => self.eval_variable(entity, v),
                 element::Element::Select(ref v) => self.eval_select(entity, v),
                 element::Element::Apply(ref v) => self.eval_apply(entity, v),
+                element::Element::Fail(_) => unreachable!(),
+                element::Element::Assert(ref v) => self.eval_assert(entity, v),
+                element::Element::Try(ref v) => self.eval_try(entity, v),
+                element::Element::CaughtError(ref v) => self.eval_caught_error(entity, v),
                 element::Element::Parameter(ref v) => self.eval_parameter(entity, v),
                 element::Element::Capture(ref v) => self.eval_capture(entity, v),
                 element::Element::Closure(ref v) => self.eval_closure(entity, v),
@@ -115,6 +132,135 @@ impl<'a, 'f> Translator<'a, 'f> {
         }
     }
 
+    /// Evaluates an element that never produces a value, because it fails or because evaluating
+    /// one of its operands does.
+    ///
+    /// The operands are evaluated up to and including the first one that never produces a value.
+    /// Any code after that is unreachable, so a placeholder value is returned.
+    fn eval_never(&mut self, entity: specs::Entity, element: &element::Element) -> Value {
+        let operands = match *element {
+            element::Element::Fail(ref v) => return self.eval_fail(entity, v),
+            // Both the body and the handler never produce a value
+            element::Element::Try(ref v) => return self.eval_try(entity, v),
+            element::Element::Tuple(ref v) => v.fields.clone(),
+            element::Element::Record(ref v) => {
+                let mut names = v.fields.keys().collect::<Vec<_>>();
+                names.sort();
+                names.into_iter().map(|name| v.fields[name]).collect()
+            }
+            element::Element::UnOp(ref v) => vec![v.operand],
+            element::Element::BiOp(ref v) => vec![v.lhs, v.rhs],
+            element::Element::Select(ref v) => vec![v.record],
+            element::Element::Apply(ref v) => v.parameters.clone(),
+            element::Element::Assert(ref v) => vec![v.condition, v.message],
+            // Anything else refers to a variable that was already defined by code that failed
+            _ => Vec::new(),
+        };
+
+        for operand in operands {
+            self.eval_element(operand, self.elements.get(operand).unwrap());
+            if self.types.get(operand) == Some(&ty::Type::Never) {
+                break;
+            }
+        }
+
+        self.placeholder(
+            abi_type::AbiType::from_ir_type(&ty::Type::Never).into_specific(self.ptr_type),
+        )
+    }
+
+    /// Raises a user generated error with the message of the specified `fail` element, by
+    /// unwinding the current function directly.
+    pub fn eval_fail(&mut self, entity: specs::Entity, fail: &element::Fail) -> Value {
+        let message = self.eval_element(fail.message, self.elements.get(fail.message).unwrap());
+
+        if self.types.get(fail.message) != Some(&ty::Type::Never) {
+            let error = self.builtin_user_error(message);
+
+            let location = self.locations.get(entity).unwrap().0;
+            let (filename, filename_len, line, col, start, end) = self.immediate_location(location);
+
+            self.builder.ins().jump(
+                self.error_unwind_ebb,
+                &[error, filename, filename_len, line, col, start, end],
+            );
+
+            let unreachable_ebb = self.builder.create_ebb();
+            self.builder.switch_to_block(unreachable_ebb);
+            self.builder.seal_block(unreachable_ebb);
+        }
+
+        self.placeholder(
+            abi_type::AbiType::from_ir_type(&ty::Type::Never).into_specific(self.ptr_type),
+        )
+    }
+
+    /// Raises a user generated error if the condition of the specified `assert` element does not
+    /// hold, by unwinding the current function directly.
+    pub fn eval_assert(&mut self, entity: specs::Entity, assert: &element::Assert) -> Value {
+        // The operands of a comparison are only evaluated once, and are shown if it fails
+        let (condition, operands) = match assert.operands[..] {
+            [lhs, rhs] => {
+                let operator = match *self.elements.get(assert.condition).unwrap() {
+                    element::Element::BiOp(ref v) => v.operator,
+                    _ => unreachable!(),
+                };
+                let lhs_value = self.eval_element(lhs, self.elements.get(lhs).unwrap());
+                let rhs_value = self.eval_element(rhs, self.elements.get(rhs).unwrap());
+                let condition = self.compare(lhs, operator, lhs_value, rhs_value);
+                (condition, Some((lhs, lhs_value, rhs_value)))
+            }
+            _ => {
+                let condition = self.eval_element(
+                    assert.condition,
+                    self.elements.get(assert.condition).unwrap(),
+                );
+                (condition, None)
+            }
+        };
+        let message = self.eval_element(assert.message, self.elements.get(assert.message).unwrap());
+
+        let fail_ebb = self.builder.create_ebb();
+        let done_ebb = self.builder.create_ebb();
+        self.builder.ins().brz(condition, fail_ebb, &[]);
+        self.builder.ins().jump(done_ebb, &[]);
+
+        self.builder.switch_to_block(fail_ebb);
+        self.builder.seal_block(fail_ebb);
+
+        let error = self.builtin_assertion_error(message, &assert.source, operands);
+
+        let location = self.locations.get(entity).unwrap().0;
+        let (filename, filename_len, line, col, start, end) = self.immediate_location(location);
+
+        self.builder.ins().jump(
+            self.error_unwind_ebb,
+            &[error, filename, filename_len, line, col, start, end],
+        );
+
+        self.builder.switch_to_block(done_ebb);
+        self.builder.seal_block(done_ebb);
+
+        // The resulting `:t` symbol takes up no space
+        self.placeholder(
+            abi_type::AbiType::from_ir_type(self.types.get(entity).unwrap())
+                .into_specific(self.ptr_type),
+        )
+    }
+
+    /// Creates a value of the specified type, for code that is known to be unreachable.
+    pub fn placeholder(&mut self, ty: Type) -> Value {
+        if ty.is_bool() {
+            self.builder.ins().bconst(ty, false)
+        } else if ty == types::F32 {
+            self.builder.ins().f32const(Ieee32::with_float(0.0))
+        } else if ty == types::F64 {
+            self.builder.ins().f64const(Ieee64::with_float(0.0))
+        } else {
+            self.builder.ins().iconst(ty, 0)
+        }
+    }
+
     fn eval_constexpr(&mut self, entity: specs::Entity, constexpr: &constexpr::Constexpr) -> Value {
         if let value::Case::Number(ref n) = *constexpr.value.case() {
             match *n {
@@ -136,6 +282,8 @@ impl<'a, 'f> Translator<'a, 'f> {
                 value::Number::F32(ref v) => self.builder.ins().f32const(Ieee32::with_float(*v)),
                 value::Number::F64(ref v) => self.builder.ins().f64const(Ieee64::with_float(*v)),
             }
+        } else if let value::Case::Symbol(ref symbol) = *constexpr.value.case() {
+            self.eval_symbol_constexpr(entity, symbol)
         } else {
             let ty = self.types.get(entity).unwrap();
             let abi_type = abi_type::AbiType::from_ir_type(ty).into_specific(self.ptr_type);
@@ -145,7 +293,7 @@ impl<'a, 'f> Translator<'a, 'f> {
             let data_id = self
                 .module
                 .declare_data(
-                    &entity.id().to_string(),
+                    &util::constexpr_data_name(entity),
                     cranelift_module::Linkage::Local,
                     false,
                 )
@@ -157,6 +305,26 @@ impl<'a, 'f> Translator<'a, 'f> {
         }
     }
 
+    /// Produces a constant symbol, which is represented by its index among the alternatives of its
+    /// type.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+    fn eval_symbol_constexpr(&mut self, entity: specs::Entity, symbol: &value::Symbol) -> Value {
+        let ty = self.types.get(entity).unwrap();
+        let index = match *ty {
+            ty::Type::Union(ref union) => union
+                .alternatives
+                .iter()
+                .position(|alternative| alternative.label == symbol.label)
+                .unwrap(),
+            _ => 0,
+        };
+
+        match abi_type::AbiType::from_ir_type(ty).into_specific(self.ptr_type) {
+            types::B1 => self.builder.ins().bconst(types::B1, index != 0),
+            abi_type => self.builder.ins().iconst(abi_type, index as i64),
+        }
+    }
+
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
     pub fn eval_number_value(
         &mut self,
@@ -200,12 +368,7 @@ impl<'a, 'f> Translator<'a, 'f> {
     )]
     pub fn eval_tuple(&mut self, entity: specs::Entity, tuple: &element::Tuple) -> Value {
         let layout = self.layouts.get(entity).unwrap();
-        let alloc_size = self.builder.ins().iconst(self.ptr_type, layout.size as i64);
-        let alloc_align = self
-            .builder
-            .ins()
-            .iconst(self.ptr_type, layout.alignment as i64);
-        let result = self.builtin_alloc(alloc_size, alloc_align);
+        let result = self.alloc_composite(entity, layout);
 
         let mut mem_flags = MemFlags::new();
         mem_flags.set_notrap();
@@ -223,22 +386,7 @@ impl<'a, 'f> Translator<'a, 'f> {
 
     pub fn eval_record(&mut self, entity: specs::Entity, record: &element::Record) -> Value {
         let layout = self.layouts.get(entity).unwrap();
-
-        #[cfg_attr(
-            feature = "cargo-clippy",
-            allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)
-        )]
-        let alloc_size = self.builder.ins().iconst(self.ptr_type, layout.size as i64);
-
-        #[cfg_attr(
-            feature = "cargo-clippy",
-            allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)
-        )]
-        let alloc_align = self
-            .builder
-            .ins()
-            .iconst(self.ptr_type, layout.alignment as i64);
-        let result = self.builtin_alloc(alloc_size, alloc_align);
+        let result = self.alloc_composite(entity, layout);
 
         let mut mem_flags = MemFlags::new();
         mem_flags.set_notrap();
@@ -259,6 +407,29 @@ impl<'a, 'f> Translator<'a, 'f> {
         result
     }
 
+    /// Allocates memory for a composite value, on the stack if the value does not escape the
+    /// current function, and on the heap otherwise.
+    #[cfg_attr(
+        feature = "cargo-clippy",
+        allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)
+    )]
+    fn alloc_composite(&mut self, entity: specs::Entity, layout: &layout::Layout) -> Value {
+        if self.escapes.get(entity) == Some(&escape::Escape::Local) {
+            let slot = self.builder.create_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                layout.size as u32,
+            ));
+            self.builder.ins().stack_addr(self.ptr_type, slot, 0)
+        } else {
+            let alloc_size = self.builder.ins().iconst(self.ptr_type, layout.size as i64);
+            let alloc_align = self
+                .builder
+                .ins()
+                .iconst(self.ptr_type, layout.alignment as i64);
+            self.builtin_alloc(entity, alloc_size, alloc_align)
+        }
+    }
+
     pub fn eval_un_op(&mut self, _entity: specs::Entity, un_op: &element::UnOp) -> Value {
         let element::UnOp { operator, operand } = un_op;
         let operand = *operand;
@@ -269,23 +440,74 @@ impl<'a, 'f> Translator<'a, 'f> {
             element::UnOperator::Not | element::UnOperator::BNot => {
                 self.builder.ins().bnot(operand_value)
             }
-            element::UnOperator::Cl0 => self.builder.ins().clz(operand_value),
+            element::UnOperator::Sqrt => self.builder.ins().sqrt(operand_value),
+            _ => self.count_bits(*operator, operand_value),
+        }
+    }
+
+    /// Counts bits of an integral value using a counting operator, which always yields an `u32`.
+    ///
+    /// Narrow values are extended to 32 bits first, and the count is corrected for the bits that
+    /// were added.
+    fn count_bits(&mut self, operator: element::UnOperator, value: Value) -> Value {
+        let bits = self.builder.func.dfg.value_type(value).bits();
+        let width = bits.max(32);
+        let excess = i64::from(width - bits);
+        let mask = if bits < 32 { (1_i64 << bits) - 1 } else { -1 };
+
+        let value = if bits >= 32 {
+            value
+        } else if operator == element::UnOperator::Cls {
+            self.builder.ins().sextend(types::I32, value)
+        } else {
+            self.builder.ins().uextend(types::I32, value)
+        };
+
+        let count = match operator {
+            element::UnOperator::Cl0 => {
+                let zeros = self.builder.ins().clz(value);
+                self.builder.ins().iadd_imm(zeros, -excess)
+            }
             element::UnOperator::Cl1 => {
-                let inverted = self.builder.ins().bnot(operand_value);
-                self.builder.ins().clz(inverted)
+                let inverted = self.builder.ins().bxor_imm(value, mask);
+                let zeros = self.builder.ins().clz(inverted);
+                self.builder.ins().iadd_imm(zeros, -excess)
+            }
+            element::UnOperator::Cls => {
+                // Flip all bits if the value is negative, so that the leading bits that are
+                // equal to the sign bit become zeros; the sign bit itself is not counted
+                let sign = self.builder.ins().sshr_imm(value, i64::from(width - 1));
+                let flipped = self.builder.ins().bxor(value, sign);
+                let zeros = self.builder.ins().clz(flipped);
+                self.builder.ins().iadd_imm(zeros, -excess - 1)
+            }
+            element::UnOperator::Ct0 => {
+                // Stop counting at the width of the type
+                let bounded = if bits < 32 {
+                    self.builder.ins().bor_imm(value, 1_i64 << bits)
+                } else {
+                    value
+                };
+                self.builder.ins().ctz(bounded)
             }
-            element::UnOperator::Cls => self.builder.ins().cls(operand_value),
-            element::UnOperator::Ct0 => self.builder.ins().ctz(operand_value),
             element::UnOperator::Ct1 => {
-                let inverted = self.builder.ins().bnot(operand_value);
+                let inverted = self.builder.ins().bnot(value);
                 self.builder.ins().ctz(inverted)
             }
             element::UnOperator::C0 => {
-                let inverted = self.builder.ins().bnot(operand_value);
+                let inverted = self.builder.ins().bxor_imm(value, mask);
                 self.builder.ins().popcnt(inverted)
             }
-            element::UnOperator::C1 => self.builder.ins().popcnt(operand_value),
-            element::UnOperator::Sqrt => self.builder.ins().sqrt(operand_value),
+            element::UnOperator::C1 => self.builder.ins().popcnt(value),
+            element::UnOperator::Not | element::UnOperator::BNot | element::UnOperator::Sqrt => {
+                unreachable!()
+            }
+        };
+
+        if bits > 32 {
+            self.builder.ins().ireduce(types::I32, count)
+        } else {
+            count
         }
     }
 
@@ -299,12 +521,12 @@ impl<'a, 'f> Translator<'a, 'f> {
         let rhs_value = self.eval_element(rhs, self.elements.get(rhs).unwrap());
 
         match operator {
-            element::BiOperator::Eq => unimplemented!(),
-            element::BiOperator::Ne => unimplemented!(),
-            element::BiOperator::Lt => unimplemented!(),
-            element::BiOperator::Ge => unimplemented!(),
-            element::BiOperator::Gt => unimplemented!(),
-            element::BiOperator::Le => unimplemented!(),
+            element::BiOperator::Eq
+            | element::BiOperator::Ne
+            | element::BiOperator::Lt
+            | element::BiOperator::Ge
+            | element::BiOperator::Gt
+            | element::BiOperator::Le => self.compare(lhs, *operator, lhs_value, rhs_value),
             element::BiOperator::Cmp => unimplemented!(),
             element::BiOperator::Add => match self.types.get(lhs).unwrap().scalar_class() {
                 ty::class::Scalar::Integral(_) => self.builder.ins().iadd(lhs_value, rhs_value),
@@ -328,7 +550,11 @@ impl<'a, 'f> Translator<'a, 'f> {
                 }
                 ty::class::Scalar::Integral(ty::class::IntegralScalar::Signed) => {
                     self.error_if_zero(entity, rhs_value, module::ErrorKind::IntegerDivisonByZero);
-                    self.builder.ins().sdiv(lhs_value, rhs_value)
+                    // Dividing the smallest value by -1 overflows, and wraps around to itself
+                    let negated = self.builder.ins().ineg(lhs_value);
+                    let (is_minus_one, divisor) = self.non_overflowing_divisor(rhs_value);
+                    let quotient = self.builder.ins().sdiv(lhs_value, divisor);
+                    self.builder.ins().select(is_minus_one, negated, quotient)
                 }
                 ty::class::Scalar::Fractional => self.builder.ins().fdiv(lhs_value, rhs_value),
                 _ => unreachable!(),
@@ -340,8 +566,11 @@ impl<'a, 'f> Translator<'a, 'f> {
                 }
                 ty::class::Scalar::Integral(ty::class::IntegralScalar::Signed) => {
                     self.error_if_zero(entity, rhs_value, module::ErrorKind::IntegerDivisonByZero);
-                    self.builder.ins().srem(lhs_value, rhs_value)
+                    // The remainder of dividing by 1 is the same as that of dividing by -1
+                    let (_, divisor) = self.non_overflowing_divisor(rhs_value);
+                    self.builder.ins().srem(lhs_value, divisor)
                 }
+                ty::class::Scalar::Fractional => self.builtin_rem(lhs_value, rhs_value),
                 _ => unreachable!(),
             },
             element::BiOperator::And | element::BiOperator::BAnd => {
@@ -362,18 +591,91 @@ impl<'a, 'f> Translator<'a, 'f> {
             element::BiOperator::XorNot | element::BiOperator::BXorNot => {
                 self.builder.ins().bxor_not(lhs_value, rhs_value)
             }
-            element::BiOperator::RotL => self.builder.ins().rotl(lhs_value, rhs_value),
-            element::BiOperator::RotR => self.builder.ins().rotr(lhs_value, rhs_value),
-            element::BiOperator::ShL => self.builder.ins().ishl(lhs_value, rhs_value),
-            element::BiOperator::ShR => match self.types.get(lhs).unwrap().scalar_class() {
-                ty::class::Scalar::Integral(ty::class::IntegralScalar::Unsigned) => {
-                    self.builder.ins().ushr(lhs_value, rhs_value)
-                }
-                ty::class::Scalar::Integral(ty::class::IntegralScalar::Signed) => {
-                    self.builder.ins().sshr(lhs_value, rhs_value)
+            element::BiOperator::RotL => {
+                let amount = self.shift_amount(lhs_value, rhs_value);
+                self.builder.ins().rotl(lhs_value, amount)
+            }
+            element::BiOperator::RotR => {
+                let amount = self.shift_amount(lhs_value, rhs_value);
+                self.builder.ins().rotr(lhs_value, amount)
+            }
+            element::BiOperator::ShL => {
+                let amount = self.shift_amount(lhs_value, rhs_value);
+                self.builder.ins().ishl(lhs_value, amount)
+            }
+            element::BiOperator::ShR => {
+                let amount = self.shift_amount(lhs_value, rhs_value);
+                match self.types.get(lhs).unwrap().scalar_class() {
+                    ty::class::Scalar::Integral(ty::class::IntegralScalar::Unsigned) => {
+                        self.builder.ins().ushr(lhs_value, amount)
+                    }
+                    ty::class::Scalar::Integral(ty::class::IntegralScalar::Signed) => {
+                        self.builder.ins().sshr(lhs_value, amount)
+                    }
+                    _ => unreachable!(),
                 }
-                _ => unreachable!(),
-            },
+            }
+        }
+    }
+
+    /// Replaces a divisor of -1 with 1, since signed division traps if it overflows.
+    ///
+    /// Returns whether the divisor was -1, and the divisor to use.
+    fn non_overflowing_divisor(&mut self, divisor: Value) -> (Value, Value) {
+        let ty = self.builder.func.dfg.value_type(divisor);
+        let is_minus_one = self.builder.ins().icmp_imm(IntCC::Equal, divisor, -1_i64);
+        let one = self.builder.ins().iconst(ty, 1);
+        let divisor = self.builder.ins().select(is_minus_one, one, divisor);
+        (is_minus_one, divisor)
+    }
+
+    /// Takes a shift amount modulo the width of the shifted value, which is how the interpreter
+    /// and the WebAssembly backend treat amounts that are too large.
+    fn shift_amount(&mut self, value: Value, amount: Value) -> Value {
+        let bits = self.builder.func.dfg.value_type(value).bits();
+        self.builder.ins().band_imm(amount, i64::from(bits - 1))
+    }
+
+    /// Compares two numbers of the same type as the `lhs` entity using a comparison operator.
+    pub fn compare(
+        &mut self,
+        lhs: specs::Entity,
+        operator: element::BiOperator,
+        lhs_value: Value,
+        rhs_value: Value,
+    ) -> Value {
+        match self.types.get(lhs).unwrap().scalar_class() {
+            ty::class::Scalar::Integral(signedness) => {
+                let signed = signedness == ty::class::IntegralScalar::Signed;
+                let cond = match operator {
+                    element::BiOperator::Eq => IntCC::Equal,
+                    element::BiOperator::Ne => IntCC::NotEqual,
+                    element::BiOperator::Lt if signed => IntCC::SignedLessThan,
+                    element::BiOperator::Lt => IntCC::UnsignedLessThan,
+                    element::BiOperator::Ge if signed => IntCC::SignedGreaterThanOrEqual,
+                    element::BiOperator::Ge => IntCC::UnsignedGreaterThanOrEqual,
+                    element::BiOperator::Gt if signed => IntCC::SignedGreaterThan,
+                    element::BiOperator::Gt => IntCC::UnsignedGreaterThan,
+                    element::BiOperator::Le if signed => IntCC::SignedLessThanOrEqual,
+                    element::BiOperator::Le => IntCC::UnsignedLessThanOrEqual,
+                    _ => unreachable!(),
+                };
+                self.builder.ins().icmp(cond, lhs_value, rhs_value)
+            }
+            ty::class::Scalar::Fractional => {
+                let cond = match operator {
+                    element::BiOperator::Eq => FloatCC::Equal,
+                    element::BiOperator::Ne => FloatCC::NotEqual,
+                    element::BiOperator::Lt => FloatCC::LessThan,
+                    element::BiOperator::Ge => FloatCC::GreaterThanOrEqual,
+                    element::BiOperator::Gt => FloatCC::GreaterThan,
+                    element::BiOperator::Le => FloatCC::LessThanOrEqual,
+                    _ => unreachable!(),
+                };
+                self.builder.ins().fcmp(cond, lhs_value, rhs_value)
+            }
+            // Rejected by `Codegen::check_supported`
+            _ => unreachable!(),
         }
     }
 
@@ -416,6 +718,119 @@ impl<'a, 'f> Translator<'a, 'f> {
             .load(field_abi_type, mem_flags, record, field_offset)
     }
 
+    /// Evaluates the body of a `try` expression, and evaluates the handler instead if the body
+    /// raises an error that can be recovered from.
+    ///
+    /// While the body is evaluated, errors are thrown and unwound to blocks local to the `try`
+    /// expression.  Errors that can't be recovered from continue to unwind the current function.
+    pub fn eval_try(&mut self, entity: specs::Entity, try_: &element::Try) -> Value {
+        let result_type = abi_type::AbiType::from_ir_type(self.types.get(entity).unwrap())
+            .into_specific(self.ptr_type);
+
+        let throw_ebb = self.builder.create_ebb();
+        let catch_ebb = self.builder.create_ebb();
+        let done_ebb = self.builder.create_ebb();
+        let result = self.builder.append_ebb_param(done_ebb, result_type);
+
+        let outer_throw_ebb = mem::replace(&mut self.error_throw_ebb, throw_ebb);
+        let outer_unwind_ebb = mem::replace(&mut self.error_unwind_ebb, catch_ebb);
+        let body = self.eval_branch(try_.body, result_type);
+        self.error_throw_ebb = outer_throw_ebb;
+        self.error_unwind_ebb = outer_unwind_ebb;
+        self.builder.ins().jump(done_ebb, &[body]);
+
+        let kind = self.builder.append_ebb_param(throw_ebb, types::I32);
+        let location = self.append_location_params(throw_ebb);
+        self.builder.switch_to_block(throw_ebb);
+        self.builder.seal_block(throw_ebb);
+
+        let error = self.builtin_error(kind);
+        let mut args = vec![error];
+        args.extend(location);
+        self.builder.ins().jump(catch_ebb, &args);
+
+        let error = self.builder.append_ebb_param(catch_ebb, self.ptr_type);
+        let location = self.append_location_params(catch_ebb);
+        self.builder.switch_to_block(catch_ebb);
+        self.builder.seal_block(catch_ebb);
+
+        let mut args = vec![error];
+        args.extend(location);
+        let recoverable = self.builtin_recoverable(error);
+        self.builder
+            .ins()
+            .brz(recoverable, self.error_unwind_ebb, &args);
+
+        let message = self.builtin_catch_error(try_.error, error);
+        let layout = self.layouts.get(try_.error).unwrap();
+        let record = self.alloc_composite(try_.error, layout);
+
+        let mut mem_flags = MemFlags::new();
+        mem_flags.set_notrap();
+        mem_flags.set_aligned();
+
+        // The kind is a symbol, which takes up no space
+        #[cfg_attr(
+            feature = "cargo-clippy",
+            allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)
+        )]
+        let message_offset = layout
+            .named_fields
+            .iter()
+            .find(|f| f.field == "message")
+            .unwrap()
+            .offset_layout
+            .offset as i32;
+        self.builder
+            .ins()
+            .store(mem_flags, message, record, message_offset);
+
+        self.caught_errors.insert(try_.error, record);
+        let handler = self.eval_branch(try_.handler, result_type);
+        self.builder.ins().jump(done_ebb, &[handler]);
+
+        self.builder.switch_to_block(done_ebb);
+        self.builder.seal_block(done_ebb);
+
+        result
+    }
+
+    pub fn eval_caught_error(
+        &mut self,
+        entity: specs::Entity,
+        _caught_error: &element::CaughtError,
+    ) -> Value {
+        self.caught_errors[&entity]
+    }
+
+    /// Evaluates one of the branches of a conditional element, using a placeholder of the
+    /// specified type as the value if the branch never produces a value.
+    fn eval_branch(&mut self, entity: specs::Entity, ty: Type) -> Value {
+        let value = self.eval_element(entity, self.elements.get(entity).unwrap());
+
+        if self.types.get(entity) == Some(&ty::Type::Never) {
+            self.placeholder(ty)
+        } else {
+            value
+        }
+    }
+
+    /// Appends the parameters that describe the location of an error to the specified EBB.
+    fn append_location_params(&mut self, ebb: Ebb) -> Vec<Value> {
+        let ptr_type = self.ptr_type;
+        [
+            ptr_type,
+            ptr_type,
+            types::I32,
+            types::I32,
+            types::I32,
+            types::I32,
+        ]
+        .iter()
+        .map(|ty| self.builder.append_ebb_param(ebb, *ty))
+        .collect()
+    }
+
     pub fn eval_parameter(
         &mut self,
         entity: specs::Entity,
@@ -463,12 +878,12 @@ impl<'a, 'f> Translator<'a, 'f> {
         let error = results[1];
 
         let location = self.locations.get(entity).unwrap().0;
-        let (filename, filename_len, line, col) = self.immediate_location(location);
+        let (filename, filename_len, line, col, start, end) = self.immediate_location(location);
 
         self.builder.ins().brnz(
             error,
             self.error_unwind_ebb,
-            &[error, filename, filename_len, line, col],
+            &[error, filename, filename_len, line, col, start, end],
         );
         result
     }
@@ -485,7 +900,52 @@ impl<'a, 'f> Translator<'a, 'f> {
         unimplemented!()
     }
 
+    /// Consumes one unit of fuel unless fuel metering is disabled, throwing an `OutOfFuel` error
+    /// if there is no fuel left.
+    pub fn consume_fuel(&mut self, entity: specs::Entity) {
+        let local_callee = self.declare_builtin(&builtin::CONSUME_FUEL);
+        let state = self.runtime_state();
+
+        let call = self.builder.ins().call(local_callee, &[state]);
+        let has_fuel = self.builder.inst_results(call)[0];
+
+        self.error_if_zero(entity, has_fuel, module::ErrorKind::OutOfFuel);
+    }
+
+    /// Checks whether the running call has been interrupted, and if so throws an `Interrupted`
+    /// error after clearing the interrupt flag.
+    pub fn check_interrupt(&mut self, entity: specs::Entity) {
+        let local_callee = self.declare_builtin(&builtin::TAKE_INTERRUPT);
+        let state = self.runtime_state();
+
+        let call = self.builder.ins().call(local_callee, &[state]);
+        let interrupted = self.builder.inst_results(call)[0];
+
+        let interrupted_ebb = self.builder.create_ebb();
+        let running_ebb = self.builder.create_ebb();
+
+        self.builder.ins().brnz(interrupted, interrupted_ebb, &[]);
+        self.builder.ins().jump(running_ebb, &[]);
+
+        self.builder.switch_to_block(interrupted_ebb);
+        self.builder.seal_block(interrupted_ebb);
+        self.error_throw(entity, module::ErrorKind::Interrupted);
+
+        self.builder.switch_to_block(running_ebb);
+        self.builder.seal_block(running_ebb);
+    }
+
+    pub fn error_throw(&mut self, entity: specs::Entity, kind: module::ErrorKind) {
+        let args = self.error_args(entity, kind);
+        self.builder.ins().jump(self.error_throw_ebb, &args);
+    }
+
     pub fn error_if_zero(&mut self, entity: specs::Entity, value: Value, kind: module::ErrorKind) {
+        let args = self.error_args(entity, kind);
+        self.builder.ins().brz(value, self.error_throw_ebb, &args);
+    }
+
+    fn error_args(&mut self, entity: specs::Entity, kind: module::ErrorKind) -> [Value; 7] {
         use num_traits::cast::ToPrimitive;
 
         let kind = self
@@ -495,19 +955,15 @@ impl<'a, 'f> Translator<'a, 'f> {
 
         let location = self.locations.get(entity).unwrap().0;
 
-        let (filename, filename_len, line, col) = self.immediate_location(location);
+        let (filename, filename_len, line, col, start, end) = self.immediate_location(location);
 
-        self.builder.ins().brz(
-            value,
-            self.error_throw_ebb,
-            &[kind, filename, filename_len, line, col],
-        );
+        [kind, filename, filename_len, line, col, start, end]
     }
 
     pub fn immediate_location(
         &mut self,
         location: codespan::ByteSpan,
-    ) -> (Value, Value, Value, Value) {
+    ) -> (Value, Value, Value, Value, Value, Value) {
         let filemap = self.codemap.find_file(location.start()).unwrap();
         let (line, col) = filemap.location(location.start()).unwrap();
         let filename = filemap.name().to_string();
@@ -531,8 +987,16 @@ impl<'a, 'f> Translator<'a, 'f> {
             .iconst(self.ptr_type, filename_len as i64);
         let line = self.builder.ins().iconst(types::I32, i64::from(line.0));
         let col = self.builder.ins().iconst(types::I32, i64::from(col.0));
+        let start = self
+            .builder
+            .ins()
+            .iconst(types::I32, i64::from(location.start().0));
+        let end = self
+            .builder
+            .ins()
+            .iconst(types::I32, i64::from(location.end().0));
 
-        (filename, filename_len, line, col)
+        (filename, filename_len, line, col, start, end)
     }
 
     fn get_symbol(&self, entity: specs::Entity) -> Option<&symbol::Symbol> {
@@ -550,12 +1014,18 @@ impl<'a, 'f> Translator<'a, 'f> {
         }
     }
 
-    pub fn builtin_alloc(&mut self, size: Value, align: Value) -> Value {
+    /// Allocates memory through the module's runtime state, throwing an `OutOfMemory` error if
+    /// the memory limit would be exceeded.
+    pub fn builtin_alloc(&mut self, entity: specs::Entity, size: Value, align: Value) -> Value {
         let local_callee = self.declare_builtin(&builtin::ALLOC);
+        let state = self.runtime_state();
 
-        let call = self.builder.ins().call(local_callee, &[size, align]);
+        let call = self.builder.ins().call(local_callee, &[state, size, align]);
+        let result = self.builder.inst_results(call)[0];
 
-        self.builder.inst_results(call)[0]
+        self.error_if_zero(entity, result, module::ErrorKind::OutOfMemory);
+
+        result
     }
 
     pub fn builtin_error(&mut self, kind: Value) -> Value {
@@ -566,6 +1036,128 @@ impl<'a, 'f> Translator<'a, 'f> {
         self.builder.inst_results(call)[0]
     }
 
+    pub fn builtin_user_error(&mut self, message: Value) -> Value {
+        let local_callee = self.declare_builtin(&builtin::USER_ERROR);
+
+        let call = self.builder.ins().call(local_callee, &[message]);
+
+        self.builder.inst_results(call)[0]
+    }
+
+    /// Creates a user generated error for a failed assertion of the specified source code.
+    ///
+    /// If the assertion is a comparison, `operands` has the entity of the left-hand side operand,
+    /// which determines the type of both operands, and the values of the operands.
+    pub fn builtin_assertion_error(
+        &mut self,
+        message: Value,
+        source: &str,
+        operands: Option<(specs::Entity, Value, Value)>,
+    ) -> Value {
+        use num_traits::cast::ToPrimitive;
+
+        let local_callee = self.declare_builtin(&builtin::ASSERTION_ERROR);
+
+        let source_len = source.len();
+        let source_data_id = util::define_string(
+            self.module,
+            self.defined_strings,
+            &format!("assertion:{}", source),
+            source.to_owned(),
+        );
+        let source_global_value = self
+            .module
+            .declare_data_in_func(source_data_id, self.builder.func);
+        let source = self
+            .builder
+            .ins()
+            .global_value(self.ptr_type, source_global_value);
+        let source_len = self.builder.ins().iconst(self.ptr_type, source_len as i64);
+
+        let (operand_type, lhs, rhs) = match operands {
+            Some((entity, lhs, rhs)) => {
+                let number = match *self.types.get(entity).unwrap() {
+                    ty::Type::Number(number) => number,
+                    _ => unreachable!(),
+                };
+                let lhs = self.operand_bits(number, lhs);
+                let rhs = self.operand_bits(number, rhs);
+                (operand_type(number), lhs, rhs)
+            }
+            None => {
+                let zero = self.builder.ins().iconst(types::I64, 0);
+                (tin_runtime::builtin::OperandType::None, zero, zero)
+            }
+        };
+        let operand_type = self
+            .builder
+            .ins()
+            .iconst(types::I32, i64::from(operand_type.to_u32().unwrap()));
+
+        let call = self.builder.ins().call(
+            local_callee,
+            &[message, source, source_len, operand_type, lhs, rhs],
+        );
+
+        self.builder.inst_results(call)[0]
+    }
+
+    /// Converts a number to the 64 bits that `builtin_assertion_error` passes operands as.
+    fn operand_bits(&mut self, number: ty::Number, value: Value) -> Value {
+        match number {
+            ty::Number::U8 | ty::Number::U16 | ty::Number::U32 => {
+                self.builder.ins().uextend(types::I64, value)
+            }
+            ty::Number::I8 | ty::Number::I16 | ty::Number::I32 => {
+                self.builder.ins().sextend(types::I64, value)
+            }
+            ty::Number::U64 | ty::Number::I64 => value,
+            ty::Number::F32 => {
+                let bits = self.builder.ins().bitcast(types::I32, value);
+                self.builder.ins().uextend(types::I64, bits)
+            }
+            ty::Number::F64 => self.builder.ins().bitcast(types::I64, value),
+        }
+    }
+
+    /// Whether the specified error can be recovered from, as a non-zero `I8`.
+    /// Computes the remainder of dividing two fractional numbers, which Cranelift has no
+    /// instruction for.
+    pub fn builtin_rem(&mut self, lhs: Value, rhs: Value) -> Value {
+        let builtin = if self.builder.func.dfg.value_type(lhs) == types::F32 {
+            &builtin::REM_F32
+        } else {
+            &builtin::REM_F64
+        };
+        let local_callee = self.declare_builtin(builtin);
+
+        let call = self.builder.ins().call(local_callee, &[lhs, rhs]);
+
+        self.builder.inst_results(call)[0]
+    }
+
+    pub fn builtin_recoverable(&mut self, error: Value) -> Value {
+        let local_callee = self.declare_builtin(&builtin::RECOVERABLE);
+
+        let call = self.builder.ins().call(local_callee, &[error]);
+
+        self.builder.inst_results(call)[0]
+    }
+
+    /// Takes ownership of a caught error, returning its message as a string, and throws an
+    /// `OutOfMemory` error if the message can't be allocated.
+    pub fn builtin_catch_error(&mut self, entity: specs::Entity, error: Value) -> Value {
+        let local_callee = self.declare_builtin(&builtin::CATCH_ERROR);
+        let state = self.runtime_state();
+
+        let call = self.builder.ins().call(local_callee, &[state, error]);
+        let result = self.builder.inst_results(call)[0];
+
+        self.error_if_zero(entity, result, module::ErrorKind::OutOfMemory);
+
+        result
+    }
+
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
     pub fn builtin_unwind_frame(
         &mut self,
@@ -576,46 +1168,60 @@ impl<'a, 'f> Translator<'a, 'f> {
         filename_len: Value,
         line: Value,
         col: Value,
+        start: Value,
+        end: Value,
     ) {
         let local_callee = self.declare_builtin(&builtin::UNWIND_FRAME);
 
         self.builder.ins().call(
             local_callee,
-            &[error, name, name_len, filename, filename_len, line, col],
+            &[
+                error,
+                name,
+                name_len,
+                filename,
+                filename_len,
+                line,
+                col,
+                start,
+                end,
+            ],
         );
     }
 
-    fn declare_builtin(&mut self, builtin: &builtin::Builtin) -> cranelift::codegen::ir::FuncRef {
-        let mut signature = self.module.make_signature();
-
-        for param in builtin.signature.params {
-            signature
-                .params
-                .push(AbiParam::new(param.into_specific(self.ptr_type)));
-        }
-
-        for ret in builtin.signature.returns {
-            signature
-                .returns
-                .push(AbiParam::new(ret.into_specific(self.ptr_type)));
-        }
-
-        let callee = self
-            .module
-            .declare_function(
-                builtin.symbol,
-                cranelift_module::Linkage::Import,
-                &signature,
-            )
-            .unwrap();
+    fn runtime_state(&mut self) -> Value {
+        let global_value = util::declare_runtime_state(self.module, self.builder.func);
+        self.builder.ins().global_value(self.ptr_type, global_value)
+    }
 
-        self.module
-            .declare_func_in_func(callee, &mut self.builder.func)
+    fn declare_builtin(&mut self, builtin: &builtin::Builtin) -> cranelift::codegen::ir::FuncRef {
+        util::declare_builtin(self.module, self.builder.func, self.ptr_type, builtin)
     }
 }
 
-impl<'a, 'f> fmt::Debug for Translator<'a, 'f> {
+impl<'a, 'f, B> fmt::Debug for Translator<'a, 'f, B>
+where
+    B: cranelift_module::Backend,
+{
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Translator").finish()
     }
 }
+
+/// The type that the runtime formats the operands of a failed assertion with.
+fn operand_type(number: ty::Number) -> tin_runtime::builtin::OperandType {
+    use tin_runtime::builtin::OperandType;
+
+    match number {
+        ty::Number::U8 => OperandType::U8,
+        ty::Number::U16 => OperandType::U16,
+        ty::Number::U32 => OperandType::U32,
+        ty::Number::U64 => OperandType::U64,
+        ty::Number::I8 => OperandType::I8,
+        ty::Number::I16 => OperandType::I16,
+        ty::Number::I32 => OperandType::I32,
+        ty::Number::I64 => OperandType::I64,
+        ty::Number::F32 => OperandType::F32,
+        ty::Number::F64 => OperandType::F64,
+    }
+}
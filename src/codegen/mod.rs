@@ -1,15 +1,22 @@
 //! A JIT compiler implementation based on the IR.
 use std::collections;
 use std::fmt;
+use std::sync;
 
 use cranelift::codegen;
+use cranelift_faerie;
 use cranelift_module;
+use cranelift_native;
 use cranelift_simplejit;
+use failure;
 use specs;
+use tin_runtime;
 
+use crate::error;
 use crate::ir;
 use crate::ir::component::constexpr;
 use crate::ir::component::element;
+use crate::ir::component::escape;
 use crate::ir::component::layout;
 use crate::ir::component::location;
 use crate::ir::component::symbol;
@@ -24,13 +31,17 @@ mod data;
 mod function;
 #[cfg(test)]
 mod tests;
+mod unsupported;
 mod util;
+mod wasm;
 
 /// A codegen system, that can be used for JIT compilation.
 pub struct Codegen<'a> {
+    ir: &'a ir::Ir,
     entities: specs::Entities<'a>,
     constexprs: specs::ReadStorage<'a, constexpr::Constexpr>,
     elements: specs::ReadStorage<'a, element::Element>,
+    escapes: specs::ReadStorage<'a, escape::Escape>,
     layouts: specs::ReadStorage<'a, layout::Layout>,
     locations: specs::ReadStorage<'a, location::Location>,
     symbols: specs::ReadStorage<'a, symbol::Symbol>,
@@ -44,15 +55,18 @@ impl<'a> Codegen<'a> {
         let entities = ir.world.entities();
         let constexprs = ir.world.read_storage();
         let elements = ir.world.read_storage();
+        let escapes = ir.world.read_storage();
         let layouts = ir.world.read_storage();
         let locations = ir.world.read_storage();
         let symbols = ir.world.read_storage();
         let types = ir.world.read_storage();
 
         Codegen {
+            ir,
             entities,
             constexprs,
             elements,
+            escapes,
             layouts,
             locations,
             symbols,
@@ -62,7 +76,98 @@ impl<'a> Codegen<'a> {
     }
 
     /// Compiles the captured IR into a module.
-    pub fn compile(&self) -> module::Module {
+    ///
+    /// Returns an error if the IR uses a feature that the Cranelift backend does not support.
+    pub fn compile(&self) -> error::Result<module::Module> {
+        self.check_supported(unsupported::Backend::Cranelift)?;
+
+        let state = sync::Arc::new(tin_runtime::State::new());
+
+        let mut builder = cranelift_simplejit::SimpleJITBuilder::new();
+
+        builder.symbols(builtin::BUILTINS.iter().map(|b| (b.symbol, b.ptr)));
+        builder.symbol(tin_runtime::state::STATE_SYMBOL, state.as_ptr());
+
+        let mut module: cranelift_module::Module<cranelift_simplejit::SimpleJITBackend> =
+            cranelift_module::Module::new(builder);
+
+        let function_ids = self.translate(&mut module, None);
+
+        module.finalize_definitions();
+
+        Ok(module::Module::new(
+            module,
+            state,
+            function_ids,
+            self.ir.exports(),
+            self.ir.constants(),
+        ))
+    }
+
+    /// Compiles the captured IR into a relocatable object file for the host architecture.
+    ///
+    /// Public top-level functions are exported as C-callable symbols named `tin_<name>`.  The
+    /// object refers to the builtin functions and the runtime state provided by the
+    /// `tin-runtime` library, which must be linked into the final program.
+    pub fn compile_object(&self, name: &str) -> Result<Vec<u8>, failure::Error> {
+        self.check_supported(unsupported::Backend::Cranelift)?;
+
+        let mut module = object_module(name)?;
+
+        self.translate(&mut module, None);
+
+        module.finalize_definitions();
+
+        module.finish().emit()
+    }
+
+    /// Renders the Cranelift IR of every function, as it would be compiled for the host.
+    ///
+    /// The functions are sorted by name, and each function is preceded by a comment with its name.
+    pub fn compile_clif(&self) -> Result<String, failure::Error> {
+        use std::fmt::Write;
+
+        self.check_supported(unsupported::Backend::Cranelift)?;
+
+        let mut module = object_module("clif")?;
+        let mut functions = Vec::new();
+
+        self.translate(&mut module, Some(&mut functions));
+
+        functions.sort();
+
+        let mut result = String::new();
+        for (name, function) in functions {
+            writeln!(result, "; {}", name)?;
+            writeln!(result, "{}", function)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Compiles the captured IR into a standalone WebAssembly module.
+    ///
+    /// The IR has to be laid out for a 4-byte pointer size.  Public top-level functions are
+    /// exported as `tin_<name>`, and the builtin functions are imported from the host.  Returns an
+    /// error if the IR uses a feature that the WebAssembly backend does not support.
+    pub fn compile_wasm(&self) -> error::Result<Vec<u8>> {
+        self.check_supported(unsupported::Backend::Wasm)?;
+
+        Ok(wasm::compile(self))
+    }
+
+    /// Translates all functions and constants into the specified module.
+    ///
+    /// Returns the IDs of the public functions, by name.  This is shared by all backends.  If
+    /// `clif` is specified, the rendered Cranelift IR of each function is recorded in it.
+    fn translate<B>(
+        &self,
+        module: &mut cranelift_module::Module<B>,
+        mut clif: Option<&mut Vec<(String, String)>>,
+    ) -> collections::HashMap<String, cranelift_module::FuncId>
+    where
+        B: cranelift_module::Backend,
+    {
         use crate::best_iter::BestIteratorCollect;
         use crate::best_iter::BestIteratorMap;
         use crate::best_iter::BestJoin;
@@ -72,37 +177,31 @@ impl<'a> Codegen<'a> {
             ref entities,
             ref constexprs,
             ref elements,
+            ref escapes,
             ref layouts,
             ref locations,
             ref symbols,
             ref types,
             ref codemap,
+            ..
         } = *self;
 
-        let mut builder = cranelift_simplejit::SimpleJITBuilder::new();
-
-        builder.symbols(builtin::BUILTINS.iter().map(|b| (b.symbol, b.ptr)));
-
-        let mut module: cranelift_module::Module<cranelift_simplejit::SimpleJITBackend> =
-            cranelift_module::Module::new(builder);
         let ptr_type = module.target_config().pointer_type();
 
-        let data_ctxs: Vec<_> = (entities, layouts, constexprs)
+        let data_objects: Vec<_> = (entities, layouts, constexprs)
             .best_join()
             .best_map(|(entity, layout, constexpr)| {
-                let mut ctx = cranelift_module::DataContext::new();
-                let mut data = Vec::new();
-                data::Translator::new(&mut data, ptr_type).store_value(layout, &constexpr.value);
-                ctx.define(data.into_boxed_slice());
-                (entity, ctx)
+                let mut translator = data::Translator::<byteorder::NativeEndian>::new(ptr_type);
+                translator.store_value(layout, &constexpr.value);
+                (entity, translator.into_data())
             })
             .best_collect();
 
         let mut defined_strings = collections::HashMap::new();
 
-        let function_ctxs = (elements, symbols, types)
+        let function_ctxs = (entities, elements, symbols, types)
             .join()
-            .flat_map(|(el, sy, ty)| {
+            .flat_map(|(entity, el, sy, ty)| {
                 if let Some((closure, ty)) = as_closure(el, ty) {
                     let mut ctx: codegen::Context = module.make_context();
                     let mut builder_context = FunctionBuilderContext::new();
@@ -125,7 +224,7 @@ impl<'a> Codegen<'a> {
                         let name = sy.to_string();
                         let name_len = name.len();
                         let name_data_id = util::define_string(
-                            &mut module,
+                            module,
                             &mut defined_strings,
                             &format!("funcname:{}", sy),
                             name,
@@ -154,10 +253,11 @@ impl<'a> Codegen<'a> {
 
                         let result = {
                             let mut translation_ctx = function::Translator::new(
-                                &mut module,
+                                module,
                                 &mut builder,
                                 constexprs,
                                 elements,
+                                escapes,
                                 layouts,
                                 locations,
                                 symbols,
@@ -170,15 +270,24 @@ impl<'a> Codegen<'a> {
                                 codemap,
                             );
 
+                            translation_ctx.check_interrupt(entity);
+                            translation_ctx.consume_fuel(entity);
+
                             for stmt in &closure.statements {
                                 translation_ctx
                                     .exec_element(*stmt, self.elements.get(*stmt).unwrap());
                             }
 
-                            translation_ctx.eval_element(
+                            let result = translation_ctx.eval_element(
                                 closure.result,
                                 self.elements.get(closure.result).unwrap(),
-                            )
+                            );
+
+                            if types.get(closure.result) == Some(&ty::Type::Never) {
+                                translation_ctx.placeholder(ret_type)
+                            } else {
+                                result
+                            }
                         };
 
                         let null_error = builder.ins().iconst(ptr_type, 0);
@@ -190,15 +299,18 @@ impl<'a> Codegen<'a> {
                             builder.append_ebb_param(error_throw_ebb, ptr_type);
                         let error_line = builder.append_ebb_param(error_throw_ebb, types::I32);
                         let error_col = builder.append_ebb_param(error_throw_ebb, types::I32);
+                        let error_start = builder.append_ebb_param(error_throw_ebb, types::I32);
+                        let error_end = builder.append_ebb_param(error_throw_ebb, types::I32);
                         builder.switch_to_block(error_throw_ebb);
                         builder.seal_block(error_throw_ebb);
 
                         let error = {
                             let mut translation_ctx = function::Translator::new(
-                                &mut module,
+                                module,
                                 &mut builder,
                                 constexprs,
                                 elements,
+                                escapes,
                                 layouts,
                                 locations,
                                 symbols,
@@ -220,6 +332,8 @@ impl<'a> Codegen<'a> {
                                 error_filename_len,
                                 error_line,
                                 error_col,
+                                error_start,
+                                error_end,
                             ],
                         );
 
@@ -229,6 +343,8 @@ impl<'a> Codegen<'a> {
                             builder.append_ebb_param(error_unwind_ebb, ptr_type);
                         let error_line = builder.append_ebb_param(error_unwind_ebb, types::I32);
                         let error_col = builder.append_ebb_param(error_unwind_ebb, types::I32);
+                        let error_start = builder.append_ebb_param(error_unwind_ebb, types::I32);
+                        let error_end = builder.append_ebb_param(error_unwind_ebb, types::I32);
                         builder.switch_to_block(error_unwind_ebb);
                         builder.seal_block(error_unwind_ebb);
 
@@ -237,10 +353,11 @@ impl<'a> Codegen<'a> {
 
                         {
                             let mut translation_ctx = function::Translator::new(
-                                &mut module,
+                                module,
                                 &mut builder,
                                 constexprs,
                                 elements,
+                                escapes,
                                 layouts,
                                 locations,
                                 symbols,
@@ -260,6 +377,8 @@ impl<'a> Codegen<'a> {
                                 error_filename_len,
                                 error_line,
                                 error_col,
+                                error_start,
+                                error_end,
                             );
                         };
 
@@ -330,11 +449,30 @@ impl<'a> Codegen<'a> {
                             let local_callee =
                                 module.declare_func_in_func(callee, &mut builder.func);
 
+                            let state_global_value =
+                                util::declare_runtime_state(module, builder.func);
+                            let enter_call = util::declare_builtin(
+                                module,
+                                builder.func,
+                                ptr_type,
+                                &builtin::ENTER_CALL,
+                            );
+                            let exit_call = util::declare_builtin(
+                                module,
+                                builder.func,
+                                ptr_type,
+                                &builtin::EXIT_CALL,
+                            );
+
                             let (error_out_ptr, parameter_values) =
                                 builder.ebb_params(entry_ebb).split_last().unwrap();
                             let error_out_ptr = *error_out_ptr;
                             let parameter_values = parameter_values.to_vec();
 
+                            let state = builder.ins().global_value(ptr_type, state_global_value);
+                            let call = builder.ins().call(enter_call, &[state]);
+                            let frame = builder.inst_results(call)[0];
+
                             let call = builder.ins().call(local_callee, &parameter_values);
 
                             let results = builder.inst_results(call);
@@ -344,6 +482,14 @@ impl<'a> Codegen<'a> {
                             builder
                                 .ins()
                                 .brnz(error, error_ebb, &[error, error_out_ptr]);
+
+                            // Everything allocated by the call is released once it returns,
+                            // except for what the pointer that the host receives refers to.
+                            let retain = match abi_type::AbiType::from_ir_type(&ty.result) {
+                                abi_type::AbiType::Ptr => result,
+                                abi_type::AbiType::Scalar(_) => builder.ins().iconst(ptr_type, 0),
+                            };
+                            builder.ins().call(exit_call, &[state, frame, retain]);
                             builder.ins().return_(&[result]);
 
                             let error = builder.append_ebb_param(error_ebb, ptr_type);
@@ -351,6 +497,9 @@ impl<'a> Codegen<'a> {
                             builder.switch_to_block(error_ebb);
                             builder.seal_block(error_ebb);
 
+                            let retain = builder.ins().iconst(ptr_type, 0);
+                            builder.ins().call(exit_call, &[state, frame, retain]);
+
                             let null_result = if ret_type.is_int() {
                                 builder.ins().iconst(ret_type, 0)
                             } else if ret_type == types::F32 {
@@ -382,38 +531,36 @@ impl<'a> Codegen<'a> {
             .collect::<Vec<_>>();
 
         let mut declared_functions = Vec::new();
-        let mut declared_data = Vec::new();
         let mut function_ids = collections::HashMap::new();
 
-        for (entity, ctx) in data_ctxs {
-            let data_name = entity.id().to_string();
-            let data_id = module
-                .declare_data(&data_name, cranelift_module::Linkage::Local, false)
-                .unwrap();
-            declared_data.push((data_id, ctx));
+        for (entity, data) in data_objects {
+            util::define_data(module, &util::constexpr_data_name(entity), data);
         }
 
         for (sy, ctx) in function_ctxs {
-            let fn_name = sy.to_string();
-            let fn_id = module
-                .declare_function(
-                    &fn_name,
-                    if sy.is_public() {
-                        cranelift_module::Linkage::Export
-                    } else {
-                        cranelift_module::Linkage::Local
-                    },
-                    &ctx.func.signature,
-                )
-                .unwrap();
-            declared_functions.push((fn_id, ctx));
-            if sy.is_public() {
-                function_ids.insert(fn_name["public:".len()..].to_owned(), fn_id);
+            let (fn_id, name) = if sy.is_public() {
+                let name = sy.to_string()["public:".len()..].to_owned();
+                let public_name = util::public_function_name(&name);
+                let fn_id = module
+                    .declare_function(
+                        &public_name,
+                        cranelift_module::Linkage::Export,
+                        &ctx.func.signature,
+                    )
+                    .unwrap();
+                function_ids.insert(name, fn_id);
+                (fn_id, public_name)
+            } else {
+                let name = sy.to_string();
+                let fn_id = module
+                    .declare_function(&name, cranelift_module::Linkage::Local, &ctx.func.signature)
+                    .unwrap();
+                (fn_id, name)
+            };
+            if let Some(ref mut clif) = clif {
+                clif.push((name, ctx.func.display(None).to_string()));
             }
-        }
-
-        for (id, data_ctx) in declared_data {
-            module.define_data(id, &data_ctx).unwrap();
+            declared_functions.push((fn_id, ctx));
         }
 
         for (id, mut function_ctx) in declared_functions {
@@ -421,12 +568,29 @@ impl<'a> Codegen<'a> {
             module.clear_context(&mut function_ctx);
         }
 
-        module.finalize_definitions();
-
-        module::Module::new(module, function_ids)
+        function_ids
     }
 }
 
+/// Creates a module that emits a relocatable object file for the host architecture.
+fn object_module(
+    name: &str,
+) -> Result<cranelift_module::Module<cranelift_faerie::FaerieBackend>, failure::Error> {
+    let mut flag_builder = settings::builder();
+    flag_builder.enable("is_pic")?;
+    let isa_builder = cranelift_native::builder().map_err(failure::err_msg)?;
+    let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+
+    let builder = cranelift_faerie::FaerieBuilder::new(
+        isa,
+        name.to_owned(),
+        cranelift_faerie::FaerieTrapCollection::Disabled,
+        cranelift_module::default_libcall_names(),
+    )?;
+
+    Ok(cranelift_module::Module::new(builder))
+}
+
 impl<'a> fmt::Debug for Codegen<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Codegen").finish()
@@ -5,6 +5,7 @@ use super::*;
 use crate::ast;
 use crate::ir;
 use crate::test_util;
+use crate::value;
 
 #[test]
 fn immediate() -> Result<(), failure::Error> {
@@ -344,20 +345,573 @@ main = || -> f32 { a = 2f32; b = 1f32; a - b };
     Ok(())
 }
 
+#[test]
+fn exports() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Int = 0u32;
+double = |a: u32| -> u32 { b = a; a + b };
+"#;
+
+    let module = compile_module("exports", source)?;
+
+    let exports = module.exports();
+    assert_eq!(2, exports.len());
+
+    assert_eq!("Int", exports[0].name());
+    assert_eq!(module::ExportKind::Constant, exports[0].kind());
+    assert_eq!(&ty::Type::Number(ty::Number::U32), exports[0].ty());
+
+    assert_eq!("double", exports[1].name());
+    assert_eq!(module::ExportKind::Function, exports[1].kind());
+    assert_eq!(
+        &ty::Type::Function(ty::Function {
+            parameters: vec![ty::Type::Number(ty::Number::U32)],
+            result: Box::new(ty::Type::Number(ty::Number::U32)),
+        }),
+        exports[1].ty()
+    );
+    Ok(())
+}
+
+#[test]
+fn constant() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+answer = 42u32;
+alice = { name: "Alice", age: 42u32 };
+double = |a: u32| -> u32 { a + a };
+"#;
+
+    let module = compile_module("constant", source)?;
+
+    assert_eq!(Some(value::Value::from(42_u32)), module.constant("answer"));
+
+    let mut fields = collections::HashMap::new();
+    fields.insert("name".to_owned(), value::Value::string("Alice"));
+    fields.insert("age".to_owned(), value::Value::from(42_u32));
+    assert_eq!(
+        Some(value::Value::record(value::Record { fields })),
+        module.constant("alice")
+    );
+
+    assert_eq!(None, module.constant("double"));
+    assert_eq!(None, module.constant("missing"));
+    Ok(())
+}
+
+#[test]
+fn constant_data() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Person = { name: "", age: 0u32 };
+alice = { name: "Alice", age: 42u32 };
+age = |person: Person| -> u32 { person.age };
+main = || -> u32 { age(alice) };
+"#;
+
+    let mut module = compile_module("constant_data", source)?;
+
+    let main = module.function::<module::Function0<u32>>("main").unwrap();
+
+    let result = main.call();
+    assert_eq!(Ok(42), result);
+    Ok(())
+}
+
+#[test]
+fn out_of_fuel() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+forever = |a: u32| -> u32 { forever(a) };
+"#;
+
+    let mut module = compile_module("out_of_fuel", source)?;
+    module.set_fuel(Some(100));
+
+    let forever = module
+        .function::<module::Function1<u32, u32>>("forever")
+        .unwrap();
+
+    let error = forever.call(1).unwrap_err();
+    assert_eq!(module::ErrorKind::OutOfFuel, error.kind());
+    assert_eq!(Some(0), module.fuel());
+    Ok(())
+}
+
+#[test]
+fn remaining_fuel() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+double = |a: u32| -> u32 { a + a };
+main = || -> u32 { double(double(21u32)) / 2u32 };
+"#;
+
+    let mut module = compile_module("remaining_fuel", source)?;
+    assert_eq!(None, module.fuel());
+    module.set_fuel(Some(10));
+
+    let main = module.function::<module::Function0<u32>>("main").unwrap();
+
+    let result = main.call();
+    assert_eq!(Ok(42), result);
+    assert_eq!(Some(7), module.fuel());
+
+    module.set_fuel(None);
+    assert_eq!(None, module.fuel());
+    Ok(())
+}
+
+#[test]
+fn user_error() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+fail_with = |reason: ""| -> u32 { fail(reason) };
+main = || -> u32 { fail_with("boom") + 1u32 };
+"#;
+
+    let mut module = compile_module("user_error", source)?;
+
+    let main = module.function::<module::Function0<u32>>("main").unwrap();
+
+    let error = main.call().unwrap_err();
+    assert_eq!(module::ErrorKind::UserGenerated, error.kind());
+    assert_eq!(Some("boom"), error.message());
+    let frames = error.backtrace().frames();
+    assert_eq!(2, frames.len());
+    assert_eq!("fail_with", frames[0].name());
+    assert_eq!("main", frames[1].name());
+    Ok(())
+}
+
+#[test]
+fn try_catch() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+div = |a: u32, b: u32| -> u32 { a / b };
+safe_div = |a: u32, b: u32| -> u32 { try { div(a, b) } catch |e| { 0u32 } };
+local_div = |a: u32, b: u32| -> u32 { try { a / b } catch |e| { 1u32 } };
+checked_div = |a: u32, b: u32| -> u32 { try { div(a, b) } catch |e| { fail(e.message) } };
+forever = |a: u32| -> u32 { forever(a) };
+careful = |a: u32| -> u32 { try { forever(a) } catch |e| { 0u32 } };
+"#;
+
+    let mut module = compile_module("try_catch", source)?;
+
+    let safe_div = module
+        .function::<module::Function2<u32, u32, u32>>("safe_div")
+        .unwrap();
+    assert_eq!(Ok(3), safe_div.call(6, 2));
+    assert_eq!(Ok(0), safe_div.call(6, 0));
+
+    let local_div = module
+        .function::<module::Function2<u32, u32, u32>>("local_div")
+        .unwrap();
+    assert_eq!(Ok(3), local_div.call(6, 2));
+    assert_eq!(Ok(1), local_div.call(6, 0));
+
+    let checked_div = module
+        .function::<module::Function2<u32, u32, u32>>("checked_div")
+        .unwrap();
+    let error = checked_div.call(6, 0).unwrap_err();
+    assert_eq!(module::ErrorKind::UserGenerated, error.kind());
+    assert_eq!(Some("integer division by zero"), error.message());
+    assert_eq!(1, error.backtrace().frames().len());
+    assert_eq!("checked_div", error.backtrace().frames()[0].name());
+
+    module.set_fuel(Some(100));
+    let careful = module
+        .function::<module::Function1<u32, u32>>("careful")
+        .unwrap();
+    let error = careful.call(1).unwrap_err();
+    assert_eq!(module::ErrorKind::OutOfFuel, error.kind());
+    Ok(())
+}
+
+#[test]
+fn assertions() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+check_port = |port: u32| -> u32 { assert(port < 65536u32, "port out of range"); port };
+open_port = |port: u32| -> u32 { check_port(port) + 1u32 };
+check_distinct = |a: i8, b: i8| -> i8 { assert(!(a == b), "values must differ"); a - b };
+check_ratio = |a: f64, b: f64| -> f64 { assert(a / b <= 1f64, "ratio too large"); a / b };
+"#;
+
+    let mut module = compile_module("assertions", source)?;
+
+    let open_port = module
+        .function::<module::Function1<u32, u32>>("open_port")
+        .unwrap();
+    assert_eq!(Ok(81), open_port.call(80));
+
+    let error = open_port.call(70000).unwrap_err();
+    assert_eq!(module::ErrorKind::UserGenerated, error.kind());
+    assert_eq!(
+        Some("assertion `port < 65536u32` failed: port out of range (left: 70000u32, right: 65536u32)"),
+        error.message()
+    );
+    let frames = error.backtrace().frames();
+    assert_eq!(2, frames.len());
+    assert_eq!("check_port", frames[0].name());
+    assert_eq!("open_port", frames[1].name());
+
+    let check_distinct = module
+        .function::<module::Function2<i8, i8, i8>>("check_distinct")
+        .unwrap();
+    assert_eq!(Ok(-3), check_distinct.call(-1, 2));
+    assert_eq!(
+        Some("assertion `!(a == b)` failed: values must differ"),
+        check_distinct.call(-1, -1).unwrap_err().message()
+    );
+
+    let check_ratio = module
+        .function::<module::Function2<f64, f64, f64>>("check_ratio")
+        .unwrap();
+    assert_eq!(Ok(0.5), check_ratio.call(1.0, 2.0));
+    assert_eq!(
+        Some("assertion `a / b <= 1f64` failed: ratio too large (left: 1.5f64, right: 1f64)"),
+        check_ratio.call(3.0, 2.0).unwrap_err().message()
+    );
+    Ok(())
+}
+
+#[test]
+fn interrupted() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+double = |a: u32| -> u32 { a + a };
+main = || -> u32 { double(21u32) };
+"#;
+
+    let mut module = compile_module("interrupted", source)?;
+    let interrupt_handle = module.interrupt_handle();
+
+    let main = module.function::<module::Function0<u32>>("main").unwrap();
+
+    interrupt_handle.interrupt();
+    let error = main.call().unwrap_err();
+    assert_eq!(module::ErrorKind::Interrupted, error.kind());
+    assert_eq!(1, error.backtrace().frames().len());
+    assert_eq!("main", error.backtrace().frames()[0].name());
+
+    let result = main.call();
+    assert_eq!(Ok(42), result);
+    Ok(())
+}
+
+#[test]
+fn memory_limit() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Pair = { a: 0u32, b: 0u32 };
+second = |r: Pair| -> u32 { r.b };
+main = |a: u32| -> u32 { second({ a: a, b: a + 1u32 }) };
+"#;
+
+    let mut module = compile_module("memory_limit", source)?;
+    assert_eq!(None, module.memory_limit());
+
+    let main = module
+        .function::<module::Function1<u32, u32>>("main")
+        .unwrap();
+
+    let result = main.call(1);
+    assert_eq!(Ok(2), result);
+
+    let stats = module.memory_stats();
+    assert_eq!(8, stats.bytes_allocated());
+    assert_eq!(0, stats.live_bytes());
+    assert_eq!(8, stats.peak_bytes());
+    assert_eq!(0, stats.live_allocations());
+
+    module.set_memory_limit(Some(4));
+    let error = main.call(1).unwrap_err();
+    assert_eq!(module::ErrorKind::OutOfMemory, error.kind());
+    assert_eq!(0, module.memory_stats().live_bytes());
+
+    module.reset_memory_stats();
+    assert_eq!(0, module.memory_stats().bytes_allocated());
+    assert_eq!(0, module.memory_stats().peak_bytes());
+    Ok(())
+}
+
+#[test]
+fn reclaim_memory() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Pair = { first: 0u32, second: 0u32 };
+pair = |a: u32| -> Pair { { first: a, second: a + 1u32 } };
+first = |a: u32| -> u32 { p = pair(a); p.first };
+fail = |a: u32| -> u32 { p = pair(a); a / 0u32 };
+next = |a: u32| -> Pair { p = pair(a); pair(p.second) };
+"#;
+
+    let mut module = compile_module("reclaim_memory", source)?;
+
+    let first = module
+        .function::<module::Function1<u32, u32>>("first")
+        .unwrap();
+    assert_eq!(Ok(1), first.call(1));
+    assert_eq!(0, module.memory_stats().live_allocations());
+
+    let fail = module
+        .function::<module::Function1<u32, u32>>("fail")
+        .unwrap();
+    assert!(fail.call(1).is_err());
+    assert_eq!(0, module.memory_stats().live_allocations());
+
+    let pair = module
+        .function::<module::Function1<*const u8, u32>>("pair")
+        .unwrap();
+    let owned = module.take_ownership(pair.call(1)?);
+    assert!(!owned.as_ptr().is_null());
+    assert_eq!(1, module.memory_stats().live_allocations());
+
+    drop(owned);
+    assert_eq!(0, module.memory_stats().live_allocations());
+
+    // Only what the returned pointer refers to outlives the call
+    let next = module
+        .function::<module::Function1<*const u8, u32>>("next")
+        .unwrap();
+    let owned = module.take_ownership(next.call(1)?);
+    assert_eq!(1, module.memory_stats().live_allocations());
+
+    drop(owned);
+    assert_eq!(0, module.memory_stats().live_allocations());
+    Ok(())
+}
+
+#[test]
+fn concurrent_calls() -> Result<(), failure::Error> {
+    use std::thread;
+
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Pair = { first: 0u32, second: 0u32 };
+pair = |a: u32| -> Pair { { first: a, second: a + 1u32 } };
+sum = |a: u32| -> u32 { p = pair(a); q = pair(p.second); p.first + q.second };
+"#;
+
+    let mut module = compile_module("concurrent_calls", source)?;
+
+    let threads = (0..4u32)
+        .map(|t| {
+            let sum = module
+                .function::<module::Function1<u32, u32>>("sum")
+                .unwrap();
+            thread::spawn(move || (t * 1000..(t + 1) * 1000).all(|a| sum.call(a) == Ok(2 * a + 2)))
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        assert!(thread.join().unwrap());
+    }
+    assert_eq!(0, module.memory_stats().live_allocations());
+    Ok(())
+}
+
+#[test]
+fn stack_allocation() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+main = |a: u32| -> u32 { r = { a: a, b: a + 1u32 }; t = (r.b, a); r.a + r.b };
+"#;
+
+    let mut module = compile_module("stack_allocation", source)?;
+    module.set_memory_limit(Some(0));
+
+    let main = module
+        .function::<module::Function1<u32, u32>>("main")
+        .unwrap();
+
+    let result = main.call(1);
+    assert_eq!(Ok(3), result);
+    assert_eq!(0, module.memory_stats().bytes_allocated());
+    Ok(())
+}
+
+#[test]
+fn object_file() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Pair = { a: 0u32, b: 0u32 };
+second = |r: Pair| -> u32 { r.b };
+main = |a: u32| -> u32 { second({ a: a, b: a + 1u32 }) };
+"#;
+
+    let (ir, codemap) = check_module("object_file", source)?;
+    let object = Codegen::new(&ir, &codemap).compile_object("object_file.o")?;
+
+    let contains = |needle: &[u8]| object.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"tin_main"));
+    assert!(contains(b"tin_second"));
+    assert!(contains(b"tin_builtin_alloc"));
+    assert!(contains(b"tin_runtime_state"));
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn linked_object_file() -> Result<(), failure::Error> {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Pair = { a: 0u32, b: 0u32 };
+second = |r: Pair| -> u32 { r.b };
+main = |a: u32| -> u32 { second({ a: a, b: a + 1u32 }) };
+broken = |a: u32| -> u32 { a / (a - a) };
+"#;
+
+    let program = r#"
+#include <stdint.h>
+#include <stdio.h>
+
+uint32_t tin_main(uint32_t a, void **error);
+uint32_t tin_broken(uint32_t a, void **error);
+uint32_t tin_error_kind(const void *error);
+void tin_error_free(void *error);
+
+int main(void) {
+    void *error = NULL;
+    uint32_t result = tin_main(41, &error);
+    if (error != NULL) {
+        return 1;
+    }
+    printf("%u\n", result);
+
+    tin_broken(41, &error);
+    if (error == NULL) {
+        return 1;
+    }
+    printf("%u\n", tin_error_kind(error));
+    tin_error_free(error);
+    return 0;
+}
+"#;
+
+    let (ir, codemap) = check_module("linked_object_file", source)?;
+    let object = Codegen::new(&ir, &codemap).compile_object("linked_object_file.o")?;
+
+    let dir = env::temp_dir().join(format!("tin-linked-object-file-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("object.o"), &object)?;
+    fs::write(dir.join("main.c"), program)?;
+
+    let status = process::Command::new("cc")
+        .arg(dir.join("main.c"))
+        .arg(dir.join("object.o"))
+        .arg(runtime_library()?)
+        .args(&[
+            "-lpthread",
+            "-ldl",
+            "-lrt",
+            "-lutil",
+            "-lm",
+            "-lgcc_s",
+            "-lc",
+        ])
+        .arg("-o")
+        .arg(dir.join("program"))
+        .status()?;
+    assert!(status.success());
+
+    let output = process::Command::new(dir.join("program")).output()?;
+    fs::remove_dir_all(&dir)?;
+
+    assert!(output.status.success());
+    // The second line is `ErrorKind::IntegerDivisonByZero`
+    assert_eq!("42\n5\n", String::from_utf8(output.stdout)?);
+    Ok(())
+}
+
+#[test]
+fn clif() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+second = |a: u32| -> u32 { a + 1u32 };
+main = |a: u32| -> u32 { second(a) };
+"#;
+
+    let (ir, codemap) = check_module("clif", source)?;
+    let clif = Codegen::new(&ir, &codemap).compile_clif()?;
+
+    let main = clif.find("; tin_main\n").unwrap();
+    let second = clif.find("; tin_second\n").unwrap();
+    assert!(main < second);
+    assert!(clif.contains("function "));
+    assert!(clif.contains("iadd"));
+    Ok(())
+}
+
+/// Finds the static library of the runtime that was built together with this test binary.
+#[cfg(target_os = "linux")]
+fn runtime_library() -> Result<std::path::PathBuf, failure::Error> {
+    use std::env;
+    use std::fs;
+
+    let exe = env::current_exe()?;
+    let mut libraries = fs::read_dir(exe.parent().unwrap())?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>, failure::Error>>()?
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| {
+                    name.starts_with("libtin_runtime-") && name.ends_with(".a")
+                })
+        })
+        .collect::<Vec<_>>();
+    // Stale builds of the runtime may still be around, so prefer the most recent one
+    libraries.sort_by_key(|path| fs::metadata(path).and_then(|m| m.modified()).ok());
+
+    libraries
+        .pop()
+        .ok_or_else(|| failure::err_msg("the static library of tin-runtime has not been built"))
+}
+
 fn compile_module(name: &'static str, source: &str) -> Result<module::Module, failure::Error> {
+    let (ir, codemap) = check_module(name, source)?;
+    let compiler = Codegen::new(&ir, &codemap);
+    let module = compiler.compile()?;
+
+    Ok(module)
+}
+
+fn check_module(
+    name: &'static str,
+    source: &str,
+) -> Result<(ir::Ir, codespan::CodeMap), failure::Error> {
     use crate::parser::Parse;
 
     let mut codemap = codespan::CodeMap::new();
-    let span = codemap
-        .add_filemap(codespan::FileName::Virtual(name.into()), source.to_owned())
-        .span();
-    let ast_module = ast::Module::parse(span, source)?;
+    let file = codemap.add_filemap(codespan::FileName::Virtual(name.into()), source.to_owned());
+    let ast_module = ast::Module::parse(file.span(), source)?;
     let mut ir = ir::Ir::new();
-    ir.load(&ast_module)?;
+    ir.load(&ast_module, &file)?;
     ir.check_types()?;
     test_util::render_graph(&format!(concat!(module_path!(), "::{}"), name), &ir)?;
-    let compiler = Codegen::new(&ir, &codemap);
-    let module = compiler.compile();
 
-    Ok(module)
+    Ok((ir, codemap))
 }
@@ -0,0 +1,135 @@
+//! Detection of code that a backend cannot translate yet.
+//!
+//! The translators assume that every element they encounter is supported, so the code that will
+//! be translated is checked up front, and the first unsupported feature is reported as an error
+//! instead of aborting the translation.
+use std::fmt;
+
+use specs;
+
+use crate::codegen::Codegen;
+use crate::error;
+use crate::ir::component::element;
+use crate::ir::component::ty;
+
+/// A backend that translates the IR.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Backend {
+    /// The Cranelift backend, used for JIT compilation and object files.
+    Cranelift,
+    /// The WebAssembly backend.
+    Wasm,
+}
+
+impl<'a> Codegen<'a> {
+    /// Checks that the specified backend can translate all functions, returning an error for the
+    /// first element that it cannot translate.
+    pub(super) fn check_supported(&self, backend: Backend) -> error::Result<()> {
+        use specs::Join;
+
+        for (_, element) in (&self.entities, &self.elements).join() {
+            if let element::Element::Closure(ref closure) = *element {
+                for statement in &closure.statements {
+                    match *self.elements.get(*statement).unwrap() {
+                        element::Element::Variable(ref v) => {
+                            self.check_element(backend, v.initializer)?
+                        }
+                        _ => self.check_element(backend, *statement)?,
+                    }
+                }
+                self.check_element(backend, closure.result)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_element(&self, backend: Backend, entity: specs::Entity) -> error::Result<()> {
+        if self.constexprs.get(entity).is_some() {
+            return Ok(());
+        }
+
+        let element = self.elements.get(entity).unwrap();
+
+        if let Some(feature) = self.unsupported_feature(backend, element) {
+            return Err(error::Error::Unsupported {
+                feature: feature.to_owned(),
+                backend: backend.to_string(),
+                location: self.locations.get(entity).unwrap().0,
+            });
+        }
+
+        let operands = match *element {
+            element::Element::Tuple(ref v) => v.fields.clone(),
+            element::Element::Record(ref v) => v.fields.values().cloned().collect(),
+            element::Element::UnOp(ref v) => vec![v.operand],
+            element::Element::BiOp(ref v) => vec![v.lhs, v.rhs],
+            element::Element::Select(ref v) => vec![v.record],
+            element::Element::Apply(ref v) => v.parameters.clone(),
+            element::Element::Fail(ref v) => vec![v.message],
+            element::Element::Assert(ref v) => vec![v.condition, v.message],
+            element::Element::Try(ref v) => vec![v.body, v.handler],
+            // Variables are checked where they are defined
+            _ => Vec::new(),
+        };
+
+        for operand in operands {
+            self.check_element(backend, operand)?;
+        }
+
+        Ok(())
+    }
+
+    /// Describes the feature used by the specified non-constant element, if the backend does not
+    /// support it.
+    fn unsupported_feature(
+        &self,
+        backend: Backend,
+        element: &element::Element,
+    ) -> Option<&'static str> {
+        match *element {
+            element::Element::Closure(_) => Some("nested functions"),
+            element::Element::Module(_) => Some("modules"),
+            element::Element::Symbol(_) => Some("non-constant symbols"),
+            element::Element::String(_) if backend == Backend::Wasm => Some("non-constant strings"),
+            element::Element::BiOp(ref v) if is_comparison(v.operator) => match backend {
+                Backend::Cranelift => match self.types.get(v.lhs) {
+                    Some(ty::Type::Number(_)) | Some(ty::Type::Never) => None,
+                    _ => Some("comparisons of values other than numbers"),
+                },
+                // TODO: lower comparisons
+                Backend::Wasm => Some("comparisons"),
+            },
+            // TODO: conditions need a representation for bools
+            element::Element::Assert(_) if backend == Backend::Wasm => Some("assertions"),
+            element::Element::Try(_) | element::Element::CaughtError(_)
+                if backend == Backend::Wasm =>
+            {
+                Some("`try` expressions")
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Backend::Cranelift => f.write_str("Cranelift"),
+            Backend::Wasm => f.write_str("WebAssembly"),
+        }
+    }
+}
+
+fn is_comparison(operator: element::BiOperator) -> bool {
+    match operator {
+        element::BiOperator::Eq
+        | element::BiOperator::Ne
+        | element::BiOperator::Lt
+        | element::BiOperator::Ge
+        | element::BiOperator::Gt
+        | element::BiOperator::Le
+        | element::BiOperator::Cmp => true,
+        _ => false,
+    }
+}
@@ -1,11 +1,21 @@
 use std::collections;
 
-pub fn define_string(
-    module: &mut cranelift_module::Module<cranelift_simplejit::SimpleJITBackend>,
+use cranelift::prelude::*;
+use specs;
+use tin_runtime;
+
+use crate::codegen::builtin;
+use crate::codegen::data;
+
+pub fn define_string<B>(
+    module: &mut cranelift_module::Module<B>,
     defined_strings: &mut collections::HashMap<String, cranelift_module::DataId>,
     name: &str,
     string: String,
-) -> cranelift_module::DataId {
+) -> cranelift_module::DataId
+where
+    B: cranelift_module::Backend,
+{
     *defined_strings.entry(name.to_owned()).or_insert_with(|| {
         let data_id = module
             .declare_data(&name, cranelift_module::Linkage::Local, false)
@@ -18,3 +28,100 @@ pub fn define_string(
         data_id
     })
 }
+
+/// Declares the runtime state of the module being compiled, so that it can be accessed from the
+/// specified function.
+pub fn declare_runtime_state<B>(
+    module: &mut cranelift_module::Module<B>,
+    func: &mut cranelift::codegen::ir::Function,
+) -> cranelift::codegen::ir::GlobalValue
+where
+    B: cranelift_module::Backend,
+{
+    let data_id = module
+        .declare_data(
+            tin_runtime::state::STATE_SYMBOL,
+            cranelift_module::Linkage::Import,
+            true,
+        )
+        .unwrap();
+    module.declare_data_in_func(data_id, func)
+}
+
+/// Declares a builtin function, so that it can be called from the specified function.
+pub fn declare_builtin<B>(
+    module: &mut cranelift_module::Module<B>,
+    func: &mut cranelift::codegen::ir::Function,
+    ptr_type: Type,
+    builtin: &builtin::Builtin,
+) -> cranelift::codegen::ir::FuncRef
+where
+    B: cranelift_module::Backend,
+{
+    let mut signature = module.make_signature();
+
+    for param in builtin.signature.params {
+        signature
+            .params
+            .push(AbiParam::new(param.into_specific(ptr_type)));
+    }
+
+    for ret in builtin.signature.returns {
+        signature
+            .returns
+            .push(AbiParam::new(ret.into_specific(ptr_type)));
+    }
+
+    let callee = module
+        .declare_function(
+            builtin.symbol,
+            cranelift_module::Linkage::Import,
+            &signature,
+        )
+        .unwrap();
+
+    module.declare_func_in_func(callee, func)
+}
+
+/// The symbol name under which a public top-level function is exported.
+///
+/// These names are valid C identifiers, so that ahead-of-time compiled functions can be called
+/// from C.
+pub fn public_function_name(name: &str) -> String {
+    format!("tin_{}", name)
+}
+
+/// The name of the data object holding the compile-time constant value of an entity.
+///
+/// Both the data objects defined up-front and the references to them from function bodies use
+/// this name, so that there is only one copy of each constant.
+pub fn constexpr_data_name(entity: specs::Entity) -> String {
+    format!("constexpr:{}", entity.id())
+}
+
+pub fn define_data<B>(
+    module: &mut cranelift_module::Module<B>,
+    name: &str,
+    data: data::Data,
+) -> cranelift_module::DataId
+where
+    B: cranelift_module::Backend,
+{
+    let data_id = module
+        .declare_data(name, cranelift_module::Linkage::Local, false)
+        .unwrap();
+
+    let mut ctx = cranelift_module::DataContext::new();
+    for (idx, (offset, pointee)) in data.pointers.into_iter().enumerate() {
+        let pointee_id = define_data(module, &format!("{}.{}", name, idx), pointee);
+        let pointee_global_value = module.declare_data_in_data(pointee_id, &mut ctx);
+
+        #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+        let offset = offset as u32;
+        ctx.write_data_addr(offset, pointee_global_value, 0);
+    }
+    ctx.define(data.contents.into_boxed_slice());
+    module.define_data(data_id, &ctx).unwrap();
+
+    data_id
+}
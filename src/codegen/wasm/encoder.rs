@@ -0,0 +1,457 @@
+//! An encoder for the binary WebAssembly module format.
+//!
+//! Only the parts of the format that are needed by the translator are supported.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ValueType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IntType {
+    I32,
+    I64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FloatType {
+    F32,
+    F64,
+}
+
+/// Integer operators, in opcode order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IntOp {
+    Clz,
+    Ctz,
+    Popcnt,
+    Add,
+    Sub,
+    Mul,
+    DivS,
+    DivU,
+    RemS,
+    RemU,
+    And,
+    Or,
+    Xor,
+    Shl,
+    ShrS,
+    ShrU,
+    Rotl,
+    Rotr,
+}
+
+/// Floating point operators, in opcode order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FloatOp {
+    Sqrt,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// The different ways of transferring a value between the stack and linear memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+    I32,
+    I64,
+    F32,
+    F64,
+    I32S8,
+    I32U8,
+    I32S16,
+    I32U16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// Starts a block without a result.
+    Block,
+    /// Starts a conditional block without a result.
+    If,
+    End,
+    Br(u32),
+    Return,
+    Call(u32),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    Load(Access, u32),
+    Store(Access, u32),
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64),
+    Eqz(IntType),
+    Int(IntType, IntOp),
+    Float(FloatType, FloatOp),
+    I32WrapI64,
+    I64ExtendI32U,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FunctionType {
+    pub params: Vec<ValueType>,
+    pub results: Vec<ValueType>,
+}
+
+/// The body of a function, consisting of its locals and its instructions.
+#[derive(Debug)]
+pub struct Function {
+    type_index: u32,
+    param_count: u32,
+    locals: Vec<ValueType>,
+    code: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct Module {
+    types: Vec<FunctionType>,
+    imports: Vec<(String, String, u32)>,
+    functions: Vec<Function>,
+    memory_pages: u32,
+    globals: Vec<(ValueType, bool, Instruction)>,
+    exports: Vec<(String, ExportKind, u32)>,
+    data: Vec<(u32, Vec<u8>)>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExportKind {
+    Function,
+    Memory,
+    Global,
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+impl Module {
+    pub fn new() -> Self {
+        Module::default()
+    }
+
+    /// Returns the index of the specified function type, adding it if it is not yet known.
+    pub fn type_index(&mut self, ty: FunctionType) -> u32 {
+        if let Some(index) = self.types.iter().position(|t| *t == ty) {
+            index as u32
+        } else {
+            self.types.push(ty);
+            self.types.len() as u32 - 1
+        }
+    }
+
+    /// Imports a function, returning its function index.
+    ///
+    /// All imports have to be added before any functions are defined.
+    pub fn import_function(&mut self, module: &str, field: &str, ty: FunctionType) -> u32 {
+        assert!(self.functions.is_empty());
+        let type_index = self.type_index(ty);
+        self.imports
+            .push((module.to_owned(), field.to_owned(), type_index));
+        self.imports.len() as u32 - 1
+    }
+
+    /// The function index that the next defined function will get.
+    pub fn next_function_index(&self) -> u32 {
+        (self.imports.len() + self.functions.len()) as u32
+    }
+
+    pub fn define_function(&mut self, function: Function) -> u32 {
+        self.functions.push(function);
+        self.next_function_index() - 1
+    }
+
+    pub fn set_memory_pages(&mut self, pages: u32) {
+        self.memory_pages = pages;
+    }
+
+    /// Adds a global with a constant initializer, returning its global index.
+    pub fn add_global(&mut self, ty: ValueType, mutable: bool, init: Instruction) -> u32 {
+        self.globals.push((ty, mutable, init));
+        self.globals.len() as u32 - 1
+    }
+
+    pub fn export(&mut self, name: &str, kind: ExportKind, index: u32) {
+        self.exports.push((name.to_owned(), kind, index));
+    }
+
+    pub fn add_data(&mut self, offset: u32, bytes: Vec<u8>) {
+        self.data.push((offset, bytes));
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = b"\0asm".to_vec();
+        out.extend_from_slice(&[1, 0, 0, 0]);
+
+        section(&mut out, 1, &self.types, |buf, ty| {
+            buf.push(0x60);
+            vec(buf, &ty.params, |buf, t| buf.push(value_type(*t)));
+            vec(buf, &ty.results, |buf, t| buf.push(value_type(*t)));
+        });
+        section(
+            &mut out,
+            2,
+            &self.imports,
+            |buf, (module, field, type_index)| {
+                name(buf, module);
+                name(buf, field);
+                buf.push(0x00);
+                unsigned(buf, u64::from(*type_index));
+            },
+        );
+        section(&mut out, 3, &self.functions, |buf, function| {
+            unsigned(buf, u64::from(function.type_index));
+        });
+        section(&mut out, 5, &[self.memory_pages], |buf, pages| {
+            buf.push(0x00);
+            unsigned(buf, u64::from(*pages));
+        });
+        section(&mut out, 6, &self.globals, |buf, (ty, mutable, init)| {
+            buf.push(value_type(*ty));
+            buf.push(if *mutable { 1 } else { 0 });
+            instruction(buf, *init);
+            instruction(buf, Instruction::End);
+        });
+        section(
+            &mut out,
+            7,
+            &self.exports,
+            |buf, (export_name, kind, index)| {
+                name(buf, export_name);
+                buf.push(match kind {
+                    ExportKind::Function => 0x00,
+                    ExportKind::Memory => 0x02,
+                    ExportKind::Global => 0x03,
+                });
+                unsigned(buf, u64::from(*index));
+            },
+        );
+        section(&mut out, 10, &self.functions, |buf, function| {
+            let mut body = Vec::new();
+            let mut runs: Vec<(u32, ValueType)> = Vec::new();
+            for local in &function.locals {
+                match runs.last_mut() {
+                    Some((count, ty)) if ty == local => *count += 1,
+                    _ => runs.push((1, *local)),
+                }
+            }
+            vec(&mut body, &runs, |buf, (count, ty)| {
+                unsigned(buf, u64::from(*count));
+                buf.push(value_type(*ty));
+            });
+            body.extend_from_slice(&function.code);
+            instruction(&mut body, Instruction::End);
+
+            unsigned(buf, body.len() as u64);
+            buf.extend_from_slice(&body);
+        });
+        section(&mut out, 11, &self.data, |buf, (offset, bytes)| {
+            #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+            let offset = *offset as i32;
+            unsigned(buf, 0);
+            instruction(buf, Instruction::I32Const(offset));
+            instruction(buf, Instruction::End);
+            unsigned(buf, bytes.len() as u64);
+            buf.extend_from_slice(bytes);
+        });
+
+        out
+    }
+}
+
+impl Function {
+    /// Creates a function of the specified type, whose first locals are its parameters.
+    pub fn new(type_index: u32, param_count: u32) -> Self {
+        let locals = Vec::new();
+        let code = Vec::new();
+
+        Function {
+            type_index,
+            param_count,
+            locals,
+            code,
+        }
+    }
+
+    /// Declares a new local beyond the parameters, returning its local index.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    pub fn add_local(&mut self, ty: ValueType) -> u32 {
+        self.locals.push(ty);
+        self.param_count + self.locals.len() as u32 - 1
+    }
+
+    pub fn push(&mut self, instruction: Instruction) {
+        self::instruction(&mut self.code, instruction);
+    }
+}
+
+fn section<A, F>(out: &mut Vec<u8>, id: u8, items: &[A], encode: F)
+where
+    F: FnMut(&mut Vec<u8>, &A),
+{
+    if items.is_empty() {
+        return;
+    }
+
+    let mut contents = Vec::new();
+    vec(&mut contents, items, encode);
+
+    out.push(id);
+    unsigned(out, contents.len() as u64);
+    out.extend_from_slice(&contents);
+}
+
+fn vec<A, F>(out: &mut Vec<u8>, items: &[A], mut encode: F)
+where
+    F: FnMut(&mut Vec<u8>, &A),
+{
+    unsigned(out, items.len() as u64);
+    for item in items {
+        encode(out, item);
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn value_type(ty: ValueType) -> u8 {
+    match ty {
+        ValueType::I32 => 0x7f,
+        ValueType::I64 => 0x7e,
+        ValueType::F32 => 0x7d,
+        ValueType::F64 => 0x7c,
+    }
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn instruction(out: &mut Vec<u8>, instruction: Instruction) {
+    match instruction {
+        Instruction::Block => out.extend_from_slice(&[0x02, 0x40]),
+        Instruction::If => out.extend_from_slice(&[0x04, 0x40]),
+        Instruction::End => out.push(0x0b),
+        Instruction::Br(depth) => {
+            out.push(0x0c);
+            unsigned(out, u64::from(depth));
+        }
+        Instruction::Return => out.push(0x0f),
+        Instruction::Call(index) => {
+            out.push(0x10);
+            unsigned(out, u64::from(index));
+        }
+        Instruction::Drop => out.push(0x1a),
+        Instruction::LocalGet(index) => {
+            out.push(0x20);
+            unsigned(out, u64::from(index));
+        }
+        Instruction::LocalSet(index) => {
+            out.push(0x21);
+            unsigned(out, u64::from(index));
+        }
+        Instruction::LocalTee(index) => {
+            out.push(0x22);
+            unsigned(out, u64::from(index));
+        }
+        Instruction::GlobalGet(index) => {
+            out.push(0x23);
+            unsigned(out, u64::from(index));
+        }
+        Instruction::GlobalSet(index) => {
+            out.push(0x24);
+            unsigned(out, u64::from(index));
+        }
+        Instruction::Load(access, offset) => {
+            let (opcode, align) = match access {
+                Access::I32 => (0x28, 2),
+                Access::I64 => (0x29, 3),
+                Access::F32 => (0x2a, 2),
+                Access::F64 => (0x2b, 3),
+                Access::I32S8 => (0x2c, 0),
+                Access::I32U8 => (0x2d, 0),
+                Access::I32S16 => (0x2e, 1),
+                Access::I32U16 => (0x2f, 1),
+            };
+            out.push(opcode);
+            unsigned(out, align);
+            unsigned(out, u64::from(offset));
+        }
+        Instruction::Store(access, offset) => {
+            let (opcode, align) = match access {
+                Access::I32 => (0x36, 2),
+                Access::I64 => (0x37, 3),
+                Access::F32 => (0x38, 2),
+                Access::F64 => (0x39, 3),
+                Access::I32S8 | Access::I32U8 => (0x3a, 0),
+                Access::I32S16 | Access::I32U16 => (0x3b, 1),
+            };
+            out.push(opcode);
+            unsigned(out, align);
+            unsigned(out, u64::from(offset));
+        }
+        Instruction::I32Const(value) => {
+            out.push(0x41);
+            signed(out, i64::from(value));
+        }
+        Instruction::I64Const(value) => {
+            out.push(0x42);
+            signed(out, value);
+        }
+        Instruction::F32Const(value) => {
+            out.push(0x43);
+            out.extend_from_slice(&value.to_bits().to_le_bytes());
+        }
+        Instruction::F64Const(value) => {
+            out.push(0x44);
+            out.extend_from_slice(&value.to_bits().to_le_bytes());
+        }
+        Instruction::Eqz(IntType::I32) => out.push(0x45),
+        Instruction::Eqz(IntType::I64) => out.push(0x50),
+        Instruction::Int(ty, op) => {
+            let base = match ty {
+                IntType::I32 => 0x67,
+                IntType::I64 => 0x79,
+            };
+            out.push(base + op as u8);
+        }
+        Instruction::Float(ty, op) => {
+            let base = match ty {
+                FloatType::F32 => 0x91,
+                FloatType::F64 => 0x9f,
+            };
+            out.push(base + op as u8);
+        }
+        Instruction::I32WrapI64 => out.push(0xa7),
+        Instruction::I64ExtendI32U => out.push(0xad),
+    }
+}
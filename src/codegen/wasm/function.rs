@@ -0,0 +1,965 @@
+use std::collections;
+use std::fmt;
+
+use specs;
+
+use crate::codegen::builtin;
+use crate::codegen::wasm;
+use crate::codegen::wasm::encoder;
+use crate::codegen::wasm::encoder::Instruction;
+use crate::codegen::Codegen;
+use crate::ir::component::element;
+use crate::ir::component::ty;
+use crate::module;
+use crate::value;
+
+/// Translates a single function into WebAssembly instructions.
+///
+/// Every function body is wrapped in two blocks; branching out of the inner block throws the error
+/// whose kind is stored in the error locals, and branching out of the outer block unwinds the error
+/// stored in the error global.  `depth` tracks how many blocks have been entered within the inner
+/// block, so that the right branch depth can be used from anywhere.
+pub struct Translator<'a, 'c>
+where
+    'c: 'a,
+{
+    codegen: &'a Codegen<'c>,
+    statics: &'a mut wasm::Statics,
+    builtins: &'a collections::HashMap<&'static str, u32>,
+    functions: &'a collections::HashMap<String, u32>,
+    constants: &'a collections::HashMap<specs::Entity, u32>,
+    error_global: u32,
+    function: encoder::Function,
+    locals: collections::HashMap<specs::Entity, u32>,
+    error_locals: ErrorLocals,
+    depth: u32,
+}
+
+/// The locals that describe an error that is being thrown or unwound.
+#[derive(Clone, Copy, Debug)]
+struct ErrorLocals {
+    kind: u32,
+    filename: u32,
+    filename_len: u32,
+    line: u32,
+    col: u32,
+    start: u32,
+    end: u32,
+}
+
+/// The representation of a number type in WebAssembly.
+#[derive(Clone, Copy, Debug)]
+enum Repr {
+    /// An integer computed in the specified type; `bits` is the width of the Tin type, which is
+    /// smaller than that of the WebAssembly type for 8- and 16-bit numbers.
+    ///
+    /// Narrow values are always kept zero- or sign-extended according to their signedness.
+    Int {
+        ty: encoder::IntType,
+        bits: u32,
+        signed: bool,
+    },
+    Float(encoder::FloatType),
+}
+
+/// The WebAssembly function type corresponding to the specified function type.
+pub fn function_type(ty: &ty::Function) -> encoder::FunctionType {
+    encoder::FunctionType {
+        params: ty.parameters.iter().map(value_type).collect(),
+        results: vec![value_type(&ty.result)],
+    }
+}
+
+/// The WebAssembly type that values of the specified type are passed around as.
+pub fn value_type(ty: &ty::Type) -> encoder::ValueType {
+    match *ty {
+        ty::Type::Number(ty::Number::U64) | ty::Type::Number(ty::Number::I64) => {
+            encoder::ValueType::I64
+        }
+        ty::Type::Number(ty::Number::F32) => encoder::ValueType::F32,
+        ty::Type::Number(ty::Number::F64) => encoder::ValueType::F64,
+        _ => encoder::ValueType::I32,
+    }
+}
+
+/// How values of the specified type are stored in linear memory, or `None` if they take up no
+/// space.
+fn access(ty: &ty::Type) -> Option<encoder::Access> {
+    match *ty {
+        ty::Type::Number(n) => Some(match n {
+            ty::Number::U8 => encoder::Access::I32U8,
+            ty::Number::U16 => encoder::Access::I32U16,
+            ty::Number::U32 | ty::Number::I32 => encoder::Access::I32,
+            ty::Number::U64 | ty::Number::I64 => encoder::Access::I64,
+            ty::Number::I8 => encoder::Access::I32S8,
+            ty::Number::I16 => encoder::Access::I32S16,
+            ty::Number::F32 => encoder::Access::F32,
+            ty::Number::F64 => encoder::Access::F64,
+        }),
+        ty::Type::String | ty::Type::Tuple(_) | ty::Type::Record(_) | ty::Type::Function(_) => {
+            Some(encoder::Access::I32)
+        }
+        ty::Type::Symbol(_) | ty::Type::Union(_) | ty::Type::Never => None,
+    }
+}
+
+impl Repr {
+    fn of(number_type: &ty::Type) -> Repr {
+        let n = match *number_type {
+            ty::Type::Number(n) => n,
+            _ => unreachable!(),
+        };
+
+        let (ty, bits, signed) = match n {
+            ty::Number::U8 => (encoder::IntType::I32, 8, false),
+            ty::Number::U16 => (encoder::IntType::I32, 16, false),
+            ty::Number::U32 => (encoder::IntType::I32, 32, false),
+            ty::Number::U64 => (encoder::IntType::I64, 64, false),
+            ty::Number::I8 => (encoder::IntType::I32, 8, true),
+            ty::Number::I16 => (encoder::IntType::I32, 16, true),
+            ty::Number::I32 => (encoder::IntType::I32, 32, true),
+            ty::Number::I64 => (encoder::IntType::I64, 64, true),
+            ty::Number::F32 => return Repr::Float(encoder::FloatType::F32),
+            ty::Number::F64 => return Repr::Float(encoder::FloatType::F64),
+        };
+
+        Repr::Int { ty, bits, signed }
+    }
+}
+
+impl<'a, 'c> Translator<'a, 'c> {
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    pub fn new(
+        codegen: &'a Codegen<'c>,
+        statics: &'a mut wasm::Statics,
+        builtins: &'a collections::HashMap<&'static str, u32>,
+        functions: &'a collections::HashMap<String, u32>,
+        constants: &'a collections::HashMap<specs::Entity, u32>,
+        error_global: u32,
+        type_index: u32,
+        ty: &ty::Function,
+    ) -> Self {
+        #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+        let mut function = encoder::Function::new(type_index, ty.parameters.len() as u32);
+        let locals = collections::HashMap::new();
+        let error_locals = ErrorLocals {
+            kind: function.add_local(encoder::ValueType::I32),
+            filename: function.add_local(encoder::ValueType::I32),
+            filename_len: function.add_local(encoder::ValueType::I32),
+            line: function.add_local(encoder::ValueType::I32),
+            col: function.add_local(encoder::ValueType::I32),
+            start: function.add_local(encoder::ValueType::I32),
+            end: function.add_local(encoder::ValueType::I32),
+        };
+        let depth = 0;
+
+        Translator {
+            codegen,
+            statics,
+            builtins,
+            functions,
+            constants,
+            error_global,
+            function,
+            locals,
+            error_locals,
+            depth,
+        }
+    }
+
+    pub fn into_function(self) -> encoder::Function {
+        self.function
+    }
+
+    /// Translates the body of a closure, which returns its result and leaves the error global
+    /// untouched if it succeeds.
+    ///
+    /// If it fails, it returns a zero value and stores the error in the error global after adding
+    /// a frame for itself.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    pub fn translate_closure(
+        &mut self,
+        entity: specs::Entity,
+        closure: &element::Closure,
+        name: &str,
+    ) {
+        for (i, parameter) in closure.parameters.iter().enumerate() {
+            self.locals.insert(*parameter, i as u32);
+        }
+
+        for statement in &closure.statements {
+            if let element::Element::Variable(_) = *self.codegen.elements.get(*statement).unwrap() {
+                let ty = value_type(self.codegen.types.get(*statement).unwrap());
+                let local = self.function.add_local(ty);
+                self.locals.insert(*statement, local);
+            }
+        }
+
+        // Unwind block
+        self.push(Instruction::Block);
+        // Throw block
+        self.push(Instruction::Block);
+
+        self.check_interrupt(entity);
+        self.consume_fuel(entity);
+
+        for statement in &closure.statements {
+            self.exec_element(*statement, self.codegen.elements.get(*statement).unwrap());
+        }
+
+        self.eval_element(
+            closure.result,
+            self.codegen.elements.get(closure.result).unwrap(),
+        );
+        self.push(Instruction::Return);
+        self.push(Instruction::End);
+
+        let error_locals = self.error_locals;
+        self.push(Instruction::LocalGet(error_locals.kind));
+        self.call_builtin(&builtin::ERROR);
+        self.push(Instruction::GlobalSet(self.error_global));
+        self.push(Instruction::End);
+
+        let (name, name_len) = self.statics.string(name);
+        self.push(Instruction::GlobalGet(self.error_global));
+        self.push_address(name);
+        self.push_address(name_len);
+        self.push(Instruction::LocalGet(error_locals.filename));
+        self.push(Instruction::LocalGet(error_locals.filename_len));
+        self.push(Instruction::LocalGet(error_locals.line));
+        self.push(Instruction::LocalGet(error_locals.col));
+        self.push(Instruction::LocalGet(error_locals.start));
+        self.push(Instruction::LocalGet(error_locals.end));
+        self.call_builtin(&builtin::UNWIND_FRAME);
+
+        // The result itself might never produce a value, so use the declared type instead
+        let result_type = self.codegen.types.get(closure.signature).unwrap();
+        self.push_zero(value_type(result_type));
+    }
+
+    /// Translates an exported wrapper around the specified function.
+    ///
+    /// Everything allocated by the call is released once it returns, unless the host receives a
+    /// pointer that might refer to it.  The error of the call (or zero) is stored in the runtime
+    /// state, and the error global is cleared for the next call.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    pub fn translate_public_wrapper(&mut self, callee: u32, ty: &ty::Function) {
+        let mark = self.function.add_local(encoder::ValueType::I32);
+        let result = self.function.add_local(value_type(&ty.result));
+
+        self.push_address(wasm::STATE_ADDRESS);
+        self.call_builtin(&builtin::ENTER_CALL);
+        self.push(Instruction::LocalSet(mark));
+
+        for i in 0..ty.parameters.len() {
+            self.push(Instruction::LocalGet(i as u32));
+        }
+        self.push(Instruction::Call(callee));
+        self.push(Instruction::LocalSet(result));
+
+        self.push_address(wasm::STATE_ADDRESS);
+        self.push(Instruction::LocalGet(mark));
+        match *ty.result {
+            ty::Type::String | ty::Type::Tuple(_) | ty::Type::Record(_) | ty::Type::Function(_) => {
+                self.push(Instruction::LocalGet(result))
+            }
+            _ => self.push(Instruction::I32Const(0)),
+        }
+        self.call_builtin(&builtin::EXIT_CALL);
+
+        self.push_address(wasm::STATE_ADDRESS);
+        self.push(Instruction::GlobalGet(self.error_global));
+        self.push(Instruction::Store(encoder::Access::I32, wasm::ERROR_OFFSET));
+        self.push(Instruction::I32Const(0));
+        self.push(Instruction::GlobalSet(self.error_global));
+
+        self.push(Instruction::LocalGet(result));
+    }
+
+    fn exec_element(&mut self, entity: specs::Entity, element: &element::Element) {
+        if let element::Element::Variable(ref v) = element {
+            self.exec_variable(entity, v);
+        } else {
+            self.eval_element(entity, element);
+            self.push(Instruction::Drop);
+        }
+    }
+
+    fn exec_variable(&mut self, entity: specs::Entity, variable: &element::Variable) {
+        let initializer_element = self.codegen.elements.get(variable.initializer).unwrap();
+        self.eval_element(variable.initializer, initializer_element);
+        self.push(Instruction::LocalSet(self.locals[&entity]));
+    }
+
+    fn eval_element(&mut self, entity: specs::Entity, element: &element::Element) {
+        if let Some(constexpr) = self.codegen.constexprs.get(entity) {
+            self.eval_constexpr(entity, &constexpr.value)
+        } else if self.codegen.types.get(entity) == Some(&ty::Type::Never) {
+            self.eval_never(entity, element)
+        } else {
+            match *element {
+                element::Element::Number(ref v) => self.eval_number_value(v),
+                // TODO: non-constant strings and symbols; rejected by `Codegen::check_supported`
+                element::Element::String(_) | element::Element::Symbol(_) => unreachable!(),
+                element::Element::Tuple(ref v) => self.eval_tuple(entity, v),
+                element::Element::Record(ref v) => self.eval_record(entity, v),
+                element::Element::UnOp(ref v) => self.eval_un_op(v),
+                element::Element::BiOp(ref v) => self.eval_bi_op(entity, v),
+                element::Element::Variable(_)
+                | element::Element::Parameter(_)
+                | element::Element::Capture(_) => {
+                    self.push(Instruction::LocalGet(self.locals[&entity]))
+                }
+                element::Element::Select(ref v) => self.eval_select(v),
+                element::Element::Apply(ref v) => self.eval_apply(entity, v),
+                element::Element::Fail(_) => unreachable!(),
+                // The following are rejected by `Codegen::check_supported`
+                // TODO: conditions need a representation for bools
+                element::Element::Assert(_) => unreachable!(),
+                // TODO: catching errors needs branch targets that depend on the enclosing `try`
+                element::Element::Try(_) | element::Element::CaughtError(_) => unreachable!(),
+                element::Element::Closure(_) | element::Element::Module(_) => unreachable!(),
+            }
+        }
+    }
+
+    /// Evaluates an element that never produces a value, because it fails or because evaluating
+    /// one of its operands does.
+    ///
+    /// The operands are evaluated up to and including the first one that never produces a value,
+    /// which branches away; the operand stack is polymorphic after that, so nothing more is pushed.
+    fn eval_never(&mut self, entity: specs::Entity, element: &element::Element) {
+        let operands = match *element {
+            element::Element::Fail(ref v) => return self.eval_fail(entity, v),
+            element::Element::Tuple(ref v) => v.fields.clone(),
+            element::Element::Record(ref v) => {
+                let mut names = v.fields.keys().collect::<Vec<_>>();
+                names.sort();
+                names.into_iter().map(|name| v.fields[name]).collect()
+            }
+            element::Element::UnOp(ref v) => vec![v.operand],
+            element::Element::BiOp(ref v) => vec![v.lhs, v.rhs],
+            element::Element::Select(ref v) => vec![v.record],
+            element::Element::Apply(ref v) => v.parameters.clone(),
+            element::Element::Assert(ref v) => vec![v.condition, v.message],
+            // Anything else refers to a variable that was already defined by code that failed
+            _ => Vec::new(),
+        };
+
+        for operand in operands {
+            self.eval_element(operand, self.codegen.elements.get(operand).unwrap());
+            if self.codegen.types.get(operand) == Some(&ty::Type::Never) {
+                break;
+            }
+        }
+    }
+
+    /// Raises a user generated error with the message of the specified `fail` element, by
+    /// unwinding the current function directly.
+    fn eval_fail(&mut self, entity: specs::Entity, fail: &element::Fail) {
+        self.eval_element(
+            fail.message,
+            self.codegen.elements.get(fail.message).unwrap(),
+        );
+
+        if self.codegen.types.get(fail.message) != Some(&ty::Type::Never) {
+            self.call_builtin(&builtin::USER_ERROR);
+            self.push(Instruction::GlobalSet(self.error_global));
+            self.set_error_location(entity);
+            let unwind = self.depth + 1;
+            self.push(Instruction::Br(unwind));
+        }
+    }
+
+    fn eval_constexpr(&mut self, entity: specs::Entity, value: &value::Value) {
+        if let value::Case::Number(n) = *value.case() {
+            self.push(match n {
+                value::Number::U8(v) => Instruction::I32Const(i32::from(v)),
+                value::Number::U16(v) => Instruction::I32Const(i32::from(v)),
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+                value::Number::U32(v) => Instruction::I32Const(v as i32),
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+                value::Number::U64(v) => Instruction::I64Const(v as i64),
+                value::Number::I8(v) => Instruction::I32Const(i32::from(v)),
+                value::Number::I16(v) => Instruction::I32Const(i32::from(v)),
+                value::Number::I32(v) => Instruction::I32Const(v),
+                value::Number::I64(v) => Instruction::I64Const(v),
+                value::Number::F32(v) => Instruction::F32Const(v),
+                value::Number::F64(v) => Instruction::F64Const(v),
+            });
+        } else {
+            let address = self.constants[&entity];
+            self.push_address(address);
+        }
+    }
+
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+    fn eval_number_value(&mut self, number_value: &element::Number) {
+        self.push(match *number_value {
+            element::Number::U8(v) => Instruction::I32Const(i32::from(v)),
+            element::Number::U16(v) => Instruction::I32Const(i32::from(v)),
+            element::Number::U32(v) => Instruction::I32Const(v as i32),
+            element::Number::U64(v) => Instruction::I64Const(v as i64),
+            element::Number::I8(v) => Instruction::I32Const(i32::from(v)),
+            element::Number::I16(v) => Instruction::I32Const(i32::from(v)),
+            element::Number::I32(v) => Instruction::I32Const(v),
+            element::Number::I64(v) => Instruction::I64Const(v),
+            element::Number::F32(v) => Instruction::F32Const(v),
+            element::Number::F64(v) => Instruction::F64Const(v),
+        });
+    }
+
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    fn eval_tuple(&mut self, entity: specs::Entity, tuple: &element::Tuple) {
+        let layout = self.codegen.layouts.get(entity).unwrap();
+        let result = self.alloc_composite(entity, layout.size, layout.alignment);
+
+        for (field, offset_layout) in tuple.fields.iter().zip(layout.unnamed_fields.iter()) {
+            self.store_field(result, *field, offset_layout.offset as u32);
+        }
+
+        self.push(Instruction::LocalGet(result));
+    }
+
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    fn eval_record(&mut self, entity: specs::Entity, record: &element::Record) {
+        let layout = self.codegen.layouts.get(entity).unwrap();
+        let result = self.alloc_composite(entity, layout.size, layout.alignment);
+
+        for named_field in &layout.named_fields {
+            let field = record.fields[&named_field.field];
+            self.store_field(result, field, named_field.offset_layout.offset as u32);
+        }
+
+        self.push(Instruction::LocalGet(result));
+    }
+
+    /// Allocates memory for a composite value through the host, returning the local that holds
+    /// its address.
+    ///
+    /// There is no stack in linear memory, so values that do not escape are allocated in the
+    /// same way; they are released when the exported function that was called returns.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    fn alloc_composite(&mut self, entity: specs::Entity, size: usize, alignment: usize) -> u32 {
+        let result = self.function.add_local(encoder::ValueType::I32);
+
+        self.push_address(wasm::STATE_ADDRESS);
+        self.push_address(size as u32);
+        self.push_address(alignment as u32);
+        self.call_builtin(&builtin::ALLOC);
+        self.push(Instruction::LocalTee(result));
+        self.error_if_zero(
+            entity,
+            encoder::IntType::I32,
+            module::ErrorKind::OutOfMemory,
+        );
+
+        result
+    }
+
+    fn store_field(&mut self, base: u32, field: specs::Entity, offset: u32) {
+        let element = self.codegen.elements.get(field).unwrap();
+        match access(self.codegen.types.get(field).unwrap()) {
+            Some(access) => {
+                self.push(Instruction::LocalGet(base));
+                self.eval_element(field, element);
+                self.push(Instruction::Store(access, offset));
+            }
+            None => {
+                self.eval_element(field, element);
+                self.push(Instruction::Drop);
+            }
+        }
+    }
+
+    fn eval_un_op(&mut self, un_op: &element::UnOp) {
+        let element::UnOp { operator, operand } = *un_op;
+
+        self.eval_element(operand, self.codegen.elements.get(operand).unwrap());
+
+        if operator == element::UnOperator::Not {
+            self.push(Instruction::Eqz(encoder::IntType::I32));
+            return;
+        }
+
+        let (ty, bits, signed) = match Repr::of(self.codegen.types.get(operand).unwrap()) {
+            Repr::Int { ty, bits, signed } => (ty, bits, signed),
+            Repr::Float(ty) => {
+                assert_eq!(element::UnOperator::Sqrt, operator);
+                self.push(Instruction::Float(ty, encoder::FloatOp::Sqrt));
+                return;
+            }
+        };
+        let narrow = bits < 32;
+        let mask = (1_i64 << bits) - 1;
+
+        match operator {
+            element::UnOperator::BNot => {
+                self.push_int(ty, -1);
+                self.push(Instruction::Int(ty, encoder::IntOp::Xor));
+                self.normalize(ty, bits, signed);
+            }
+            element::UnOperator::Cl0 | element::UnOperator::Cl1 => {
+                if operator == element::UnOperator::Cl1 {
+                    self.push_int(ty, if narrow { mask } else { -1 });
+                    self.push(Instruction::Int(ty, encoder::IntOp::Xor));
+                }
+                if narrow {
+                    self.push_int(ty, mask);
+                    self.push(Instruction::Int(ty, encoder::IntOp::And));
+                    self.push(Instruction::Int(ty, encoder::IntOp::Clz));
+                    self.push_int(ty, i64::from(32 - bits));
+                    self.push(Instruction::Int(ty, encoder::IntOp::Sub));
+                } else {
+                    self.push(Instruction::Int(ty, encoder::IntOp::Clz));
+                }
+            }
+            element::UnOperator::Ct0 | element::UnOperator::Ct1 => {
+                if operator == element::UnOperator::Ct1 {
+                    self.push_int(ty, -1);
+                    self.push(Instruction::Int(ty, encoder::IntOp::Xor));
+                }
+                if narrow {
+                    // Stop counting at the width of the type
+                    self.push_int(ty, 1 << bits);
+                    self.push(Instruction::Int(ty, encoder::IntOp::Or));
+                }
+                self.push(Instruction::Int(ty, encoder::IntOp::Ctz));
+            }
+            element::UnOperator::C0 | element::UnOperator::C1 => {
+                if operator == element::UnOperator::C0 {
+                    self.push_int(ty, -1);
+                    self.push(Instruction::Int(ty, encoder::IntOp::Xor));
+                }
+                if narrow {
+                    self.push_int(ty, mask);
+                    self.push(Instruction::Int(ty, encoder::IntOp::And));
+                }
+                self.push(Instruction::Int(ty, encoder::IntOp::Popcnt));
+            }
+            element::UnOperator::Cls => {
+                // Sign-extend narrow values to the full width, then count the leading bits that
+                // are equal to the sign bit, excluding the sign bit itself
+                let full_bits = if narrow { 32 } else { bits };
+                if narrow {
+                    self.push_int(ty, i64::from(32 - bits));
+                    self.push(Instruction::Int(ty, encoder::IntOp::Shl));
+                    self.push_int(ty, i64::from(32 - bits));
+                    self.push(Instruction::Int(ty, encoder::IntOp::ShrS));
+                }
+                let value = self.function.add_local(int_value_type(ty));
+                self.push(Instruction::LocalTee(value));
+                self.push(Instruction::LocalGet(value));
+                self.push_int(ty, i64::from(full_bits - 1));
+                self.push(Instruction::Int(ty, encoder::IntOp::ShrS));
+                self.push(Instruction::Int(ty, encoder::IntOp::Xor));
+                self.push(Instruction::Int(ty, encoder::IntOp::Clz));
+                self.push_int(ty, i64::from(full_bits - bits + 1));
+                self.push(Instruction::Int(ty, encoder::IntOp::Sub));
+            }
+            element::UnOperator::Not | element::UnOperator::Sqrt => unreachable!(),
+        }
+
+        // All counts are `u32`s
+        if ty == encoder::IntType::I64 && operator != element::UnOperator::BNot {
+            self.push(Instruction::I32WrapI64);
+        }
+    }
+
+    fn eval_bi_op(&mut self, entity: specs::Entity, bi_op: &element::BiOp) {
+        let element::BiOp { lhs, operator, rhs } = *bi_op;
+
+        // TODO: support lazy evaluation
+        self.eval_element(lhs, self.codegen.elements.get(lhs).unwrap());
+        self.eval_element(rhs, self.codegen.elements.get(rhs).unwrap());
+
+        match operator {
+            // TODO: comparisons; rejected by `Codegen::check_supported`
+            element::BiOperator::Eq
+            | element::BiOperator::Ne
+            | element::BiOperator::Lt
+            | element::BiOperator::Ge
+            | element::BiOperator::Gt
+            | element::BiOperator::Le
+            | element::BiOperator::Cmp => unreachable!(),
+            element::BiOperator::And => {
+                self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::And))
+            }
+            element::BiOperator::Or => {
+                self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::Or))
+            }
+            element::BiOperator::Xor => {
+                self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::Xor))
+            }
+            element::BiOperator::AndNot => {
+                self.push(Instruction::Eqz(encoder::IntType::I32));
+                self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::And));
+            }
+            element::BiOperator::OrNot => {
+                self.push(Instruction::Eqz(encoder::IntType::I32));
+                self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::Or));
+            }
+            element::BiOperator::XorNot => {
+                self.push(Instruction::Eqz(encoder::IntType::I32));
+                self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::Xor));
+            }
+            _ => match Repr::of(self.codegen.types.get(lhs).unwrap()) {
+                Repr::Int { ty, bits, signed } => {
+                    self.eval_int_bi_op(entity, operator, ty, bits, signed)
+                }
+                Repr::Float(encoder::FloatType::F32) if operator == element::BiOperator::Rem => {
+                    self.call_builtin(&builtin::REM_F32)
+                }
+                Repr::Float(encoder::FloatType::F64) if operator == element::BiOperator::Rem => {
+                    self.call_builtin(&builtin::REM_F64)
+                }
+                Repr::Float(ty) => self.push(Instruction::Float(
+                    ty,
+                    match operator {
+                        element::BiOperator::Add => encoder::FloatOp::Add,
+                        element::BiOperator::Sub => encoder::FloatOp::Sub,
+                        element::BiOperator::Mul => encoder::FloatOp::Mul,
+                        element::BiOperator::Div => encoder::FloatOp::Div,
+                        _ => unreachable!(),
+                    },
+                )),
+            },
+        }
+    }
+
+    /// Applies an integer operator to the two operands on the stack.
+    fn eval_int_bi_op(
+        &mut self,
+        entity: specs::Entity,
+        operator: element::BiOperator,
+        ty: encoder::IntType,
+        bits: u32,
+        signed: bool,
+    ) {
+        let narrow = bits < 32;
+
+        match operator {
+            element::BiOperator::Add => self.push(Instruction::Int(ty, encoder::IntOp::Add)),
+            element::BiOperator::Sub => self.push(Instruction::Int(ty, encoder::IntOp::Sub)),
+            element::BiOperator::Mul => self.push(Instruction::Int(ty, encoder::IntOp::Mul)),
+            element::BiOperator::Div | element::BiOperator::Rem => {
+                let rhs = self.function.add_local(int_value_type(ty));
+                self.push(Instruction::LocalTee(rhs));
+                self.error_if_zero(entity, ty, module::ErrorKind::IntegerDivisonByZero);
+                self.push(Instruction::LocalGet(rhs));
+                self.push(Instruction::Int(
+                    ty,
+                    match (operator, signed) {
+                        (element::BiOperator::Div, false) => encoder::IntOp::DivU,
+                        (element::BiOperator::Div, true) => encoder::IntOp::DivS,
+                        (_, false) => encoder::IntOp::RemU,
+                        (_, true) => encoder::IntOp::RemS,
+                    },
+                ));
+            }
+            element::BiOperator::BAnd => self.push(Instruction::Int(ty, encoder::IntOp::And)),
+            element::BiOperator::BOr => self.push(Instruction::Int(ty, encoder::IntOp::Or)),
+            element::BiOperator::BXor => self.push(Instruction::Int(ty, encoder::IntOp::Xor)),
+            element::BiOperator::BAndNot
+            | element::BiOperator::BOrNot
+            | element::BiOperator::BXorNot => {
+                self.push_int(ty, -1);
+                self.push(Instruction::Int(ty, encoder::IntOp::Xor));
+                self.push(Instruction::Int(
+                    ty,
+                    match operator {
+                        element::BiOperator::BAndNot => encoder::IntOp::And,
+                        element::BiOperator::BOrNot => encoder::IntOp::Or,
+                        _ => encoder::IntOp::Xor,
+                    },
+                ));
+            }
+            element::BiOperator::ShL
+            | element::BiOperator::ShR
+            | element::BiOperator::RotL
+            | element::BiOperator::RotR => {
+                // The shift amount is always a `u32`, and is taken modulo the width of the type
+                if narrow {
+                    self.push_int(encoder::IntType::I32, i64::from(bits - 1));
+                    self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::And));
+                } else if ty == encoder::IntType::I64 {
+                    self.push(Instruction::I64ExtendI32U);
+                }
+
+                match operator {
+                    element::BiOperator::ShL => {
+                        self.push(Instruction::Int(ty, encoder::IntOp::Shl))
+                    }
+                    element::BiOperator::ShR if signed => {
+                        self.push(Instruction::Int(ty, encoder::IntOp::ShrS))
+                    }
+                    element::BiOperator::ShR => {
+                        self.push(Instruction::Int(ty, encoder::IntOp::ShrU))
+                    }
+                    element::BiOperator::RotL if !narrow => {
+                        self.push(Instruction::Int(ty, encoder::IntOp::Rotl))
+                    }
+                    element::BiOperator::RotR if !narrow => {
+                        self.push(Instruction::Int(ty, encoder::IntOp::Rotr))
+                    }
+                    _ => self.rotate_narrow(operator, bits),
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        self.normalize(ty, bits, signed);
+    }
+
+    /// Rotates a narrow value by an amount that is smaller than its width, by combining two
+    /// shifts of the zero-extended value.
+    fn rotate_narrow(&mut self, operator: element::BiOperator, bits: u32) {
+        let (first, second) = if operator == element::BiOperator::RotL {
+            (encoder::IntOp::Shl, encoder::IntOp::ShrU)
+        } else {
+            (encoder::IntOp::ShrU, encoder::IntOp::Shl)
+        };
+        let amount = self.function.add_local(encoder::ValueType::I32);
+        let value = self.function.add_local(encoder::ValueType::I32);
+
+        self.push(Instruction::LocalSet(amount));
+        self.push_int(encoder::IntType::I32, (1 << bits) - 1);
+        self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::And));
+        self.push(Instruction::LocalSet(value));
+
+        self.push(Instruction::LocalGet(value));
+        self.push(Instruction::LocalGet(amount));
+        self.push(Instruction::Int(encoder::IntType::I32, first));
+
+        self.push(Instruction::LocalGet(value));
+        self.push_int(encoder::IntType::I32, i64::from(bits));
+        self.push(Instruction::LocalGet(amount));
+        self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::Sub));
+        self.push_int(encoder::IntType::I32, i64::from(bits - 1));
+        self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::And));
+        self.push(Instruction::Int(encoder::IntType::I32, second));
+
+        self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::Or));
+    }
+
+    /// Brings a narrow integer back into its canonical zero- or sign-extended form.
+    fn normalize(&mut self, ty: encoder::IntType, bits: u32, signed: bool) {
+        if bits >= 32 {
+            return;
+        }
+
+        if signed {
+            self.push_int(ty, i64::from(32 - bits));
+            self.push(Instruction::Int(ty, encoder::IntOp::Shl));
+            self.push_int(ty, i64::from(32 - bits));
+            self.push(Instruction::Int(ty, encoder::IntOp::ShrS));
+        } else {
+            self.push_int(ty, (1 << bits) - 1);
+            self.push(Instruction::Int(ty, encoder::IntOp::And));
+        }
+    }
+
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    fn eval_select(&mut self, select: &element::Select) {
+        let record_layout = self.codegen.layouts.get(select.record).unwrap();
+        let record_type = match self.codegen.types.get(select.record).unwrap() {
+            ty::Type::Record(r) => r,
+            _ => unreachable!(),
+        };
+
+        let field_type = &record_type.fields[&select.field];
+        let field_offset = record_layout
+            .named_fields
+            .iter()
+            .find(|f| *f.field == select.field)
+            .unwrap()
+            .offset_layout
+            .offset as u32;
+
+        self.eval_element(
+            select.record,
+            self.codegen.elements.get(select.record).unwrap(),
+        );
+
+        if let Some(access) = access(field_type) {
+            self.push(Instruction::Load(access, field_offset));
+        } else {
+            self.push(Instruction::Drop);
+            self.push_zero(value_type(field_type));
+        }
+    }
+
+    fn eval_apply(&mut self, entity: specs::Entity, apply: &element::Apply) {
+        let name = self.get_symbol(apply.function).unwrap().to_string();
+        let callee = self.functions[&name];
+
+        for parameter in &apply.parameters {
+            self.eval_element(*parameter, self.codegen.elements.get(*parameter).unwrap());
+        }
+
+        self.push(Instruction::Call(callee));
+
+        self.push(Instruction::GlobalGet(self.error_global));
+        self.push(Instruction::If);
+        self.depth += 1;
+        self.set_error_location(entity);
+        let unwind = self.depth + 1;
+        self.push(Instruction::Br(unwind));
+        self.depth -= 1;
+        self.push(Instruction::End);
+    }
+
+    /// Consumes one unit of fuel, throwing an `OutOfFuel` error if there is no fuel left.
+    ///
+    /// Nothing is consumed if the fuel is `u32::MAX`, which disables fuel metering.
+    fn consume_fuel(&mut self, entity: specs::Entity) {
+        let fuel = self.function.add_local(encoder::ValueType::I32);
+
+        self.push_address(wasm::STATE_ADDRESS);
+        self.push(Instruction::Load(encoder::Access::I32, wasm::FUEL_OFFSET));
+        self.push(Instruction::LocalTee(fuel));
+        self.error_if_zero(entity, encoder::IntType::I32, module::ErrorKind::OutOfFuel);
+
+        // Adding one only yields zero for `u32::MAX`
+        self.push(Instruction::LocalGet(fuel));
+        self.push(Instruction::I32Const(1));
+        self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::Add));
+        self.push(Instruction::If);
+        self.depth += 1;
+
+        self.push_address(wasm::STATE_ADDRESS);
+        self.push(Instruction::LocalGet(fuel));
+        self.push(Instruction::I32Const(1));
+        self.push(Instruction::Int(encoder::IntType::I32, encoder::IntOp::Sub));
+        self.push(Instruction::Store(encoder::Access::I32, wasm::FUEL_OFFSET));
+
+        self.depth -= 1;
+        self.push(Instruction::End);
+    }
+
+    /// Checks whether the running call has been interrupted, and if so throws an `Interrupted`
+    /// error after clearing the interrupt flag.
+    fn check_interrupt(&mut self, entity: specs::Entity) {
+        self.push_address(wasm::STATE_ADDRESS);
+        self.push(Instruction::Load(
+            encoder::Access::I32U8,
+            wasm::INTERRUPTED_OFFSET,
+        ));
+        self.push(Instruction::If);
+        self.depth += 1;
+
+        self.push_address(wasm::STATE_ADDRESS);
+        self.push(Instruction::I32Const(0));
+        self.push(Instruction::Store(
+            encoder::Access::I32U8,
+            wasm::INTERRUPTED_OFFSET,
+        ));
+        self.error_throw(entity, module::ErrorKind::Interrupted);
+
+        self.depth -= 1;
+        self.push(Instruction::End);
+    }
+
+    fn error_throw(&mut self, entity: specs::Entity, kind: module::ErrorKind) {
+        use num_traits::cast::ToPrimitive;
+
+        #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+        let kind = kind.to_u32().unwrap() as i32;
+        self.push(Instruction::I32Const(kind));
+        self.push(Instruction::LocalSet(self.error_locals.kind));
+        self.set_error_location(entity);
+        self.push(Instruction::Br(self.depth));
+    }
+
+    /// Throws an error if the integer on the stack is zero, consuming it.
+    fn error_if_zero(
+        &mut self,
+        entity: specs::Entity,
+        ty: encoder::IntType,
+        kind: module::ErrorKind,
+    ) {
+        self.push(Instruction::Eqz(ty));
+        self.push(Instruction::If);
+        self.depth += 1;
+        self.error_throw(entity, kind);
+        self.depth -= 1;
+        self.push(Instruction::End);
+    }
+
+    fn set_error_location(&mut self, entity: specs::Entity) {
+        let location = self.codegen.locations.get(entity).unwrap().0;
+        let filemap = self.codegen.codemap.find_file(location.start()).unwrap();
+        let (line, col) = filemap.location(location.start()).unwrap();
+        let (filename, filename_len) = self.statics.string(&filemap.name().to_string());
+
+        let error_locals = self.error_locals;
+        self.push_address(filename);
+        self.push(Instruction::LocalSet(error_locals.filename));
+        self.push_address(filename_len);
+        self.push(Instruction::LocalSet(error_locals.filename_len));
+        self.push_address(line.0);
+        self.push(Instruction::LocalSet(error_locals.line));
+        self.push_address(col.0);
+        self.push(Instruction::LocalSet(error_locals.col));
+        self.push_address(location.start().0);
+        self.push(Instruction::LocalSet(error_locals.start));
+        self.push_address(location.end().0);
+        self.push(Instruction::LocalSet(error_locals.end));
+    }
+
+    fn get_symbol(&self, entity: specs::Entity) -> Option<String> {
+        if let Some(symbol) = self.codegen.symbols.get(entity) {
+            Some(symbol.to_string())
+        } else if let Some(element::Element::Capture(element::Capture { captured, .. })) =
+            self.codegen.elements.get(entity)
+        {
+            self.get_symbol(*captured)
+        } else {
+            None
+        }
+    }
+
+    fn call_builtin(&mut self, builtin: &builtin::Builtin) {
+        self.push(Instruction::Call(self.builtins[builtin.symbol]));
+    }
+
+    /// Pushes an unsigned 32-bit value, such as an address or a size.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+    fn push_address(&mut self, value: u32) {
+        self.push(Instruction::I32Const(value as i32));
+    }
+
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    fn push_int(&mut self, ty: encoder::IntType, value: i64) {
+        self.push(match ty {
+            encoder::IntType::I32 => Instruction::I32Const(value as i32),
+            encoder::IntType::I64 => Instruction::I64Const(value),
+        });
+    }
+
+    fn push_zero(&mut self, ty: encoder::ValueType) {
+        self.push(match ty {
+            encoder::ValueType::I32 => Instruction::I32Const(0),
+            encoder::ValueType::I64 => Instruction::I64Const(0),
+            encoder::ValueType::F32 => Instruction::F32Const(0.0),
+            encoder::ValueType::F64 => Instruction::F64Const(0.0),
+        });
+    }
+
+    fn push(&mut self, instruction: Instruction) {
+        self.function.push(instruction);
+    }
+}
+
+impl<'a, 'c> fmt::Debug for Translator<'a, 'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Translator").finish()
+    }
+}
+
+fn int_value_type(ty: encoder::IntType) -> encoder::ValueType {
+    match ty {
+        encoder::IntType::I32 => encoder::ValueType::I32,
+        encoder::IntType::I64 => encoder::ValueType::I64,
+    }
+}
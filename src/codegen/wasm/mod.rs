@@ -0,0 +1,275 @@
+//! A WebAssembly backend, that translates the IR into a standalone `.wasm` module.
+//!
+//! The IR has to be laid out for a 4-byte pointer size before it can be translated.  The module
+//! imports the builtin functions from the host under the `env` module, and exports:
+//!
+//!   * Every public top-level function `foo` as `tin_foo`, taking and returning the same values
+//!     as the Tin function.
+//!   * Its linear memory as `memory`.
+//!   * The address of the runtime state as the global `tin_state`.
+//!   * The address where the host may start allocating memory as the global `tin_heap_base`.
+//!
+//! The runtime state is a small block in linear memory; see the `*_OFFSET` constants for its
+//! layout.  Builtins receive the address of the state wherever their native counterparts would
+//! receive a pointer to `tin_runtime::State`.
+use std::collections;
+
+use byteorder;
+use cranelift::prelude::types;
+use specs;
+
+use crate::codegen::abi_type;
+use crate::codegen::builtin;
+use crate::codegen::data;
+use crate::codegen::util;
+use crate::codegen::Codegen;
+use crate::ir::component::element;
+use crate::ir::component::ty;
+use crate::value;
+
+mod encoder;
+mod function;
+#[cfg(test)]
+mod tests;
+
+/// The module name under which builtin functions are imported.
+pub const IMPORT_MODULE: &str = "env";
+
+/// The address of the runtime state in linear memory.
+///
+/// The bytes before it are left unused, so that a null pointer never refers to valid data.
+pub const STATE_ADDRESS: u32 = 8;
+
+/// The offset of the remaining fuel (a `u32`) within the runtime state.
+///
+/// The fuel starts out at `u32::MAX`, which disables fuel metering.
+pub const FUEL_OFFSET: u32 = 0;
+
+/// The offset of the interrupt flag (a `u8`) within the runtime state.
+pub const INTERRUPTED_OFFSET: u32 = 4;
+
+/// The offset of the error (a `u32`) within the runtime state.
+///
+/// Exported functions store the error returned by the builtins here, or zero if the call
+/// succeeded.
+pub const ERROR_OFFSET: u32 = 8;
+
+const STATE_SIZE: u32 = 12;
+
+const PAGE_SIZE: u32 = 65536;
+
+/// Static data in linear memory, starting at the runtime state.
+#[derive(Debug)]
+pub struct Statics {
+    contents: Vec<u8>,
+    strings: collections::HashMap<String, u32>,
+}
+
+/// Translates all functions and constants into a WebAssembly module.
+pub fn compile(codegen: &Codegen) -> Vec<u8> {
+    use specs::Join;
+
+    let mut module = encoder::Module::new();
+
+    let builtins = builtin::BUILTINS
+        .iter()
+        .map(|builtin| {
+            let ty = encoder::FunctionType {
+                params: builtin
+                    .signature
+                    .params
+                    .iter()
+                    .map(abi_value_type)
+                    .collect(),
+                results: builtin
+                    .signature
+                    .returns
+                    .iter()
+                    .map(abi_value_type)
+                    .collect(),
+            };
+            let index = module.import_function(IMPORT_MODULE, builtin.symbol, ty);
+            (builtin.symbol, index)
+        })
+        .collect::<collections::HashMap<_, _>>();
+
+    let error_global = module.add_global(
+        encoder::ValueType::I32,
+        true,
+        encoder::Instruction::I32Const(0),
+    );
+
+    let mut statics = Statics::new();
+
+    let constants = (&codegen.entities, &codegen.layouts, &codegen.constexprs)
+        .join()
+        .filter(|(_, _, constexpr)| match constexpr.value.case() {
+            value::Case::Number(_) => false,
+            _ => true,
+        })
+        .map(|(entity, layout, constexpr)| {
+            let mut translator = data::Translator::<byteorder::LittleEndian>::new(types::I32);
+            translator.store_value(layout, &constexpr.value);
+            (entity, statics.place(translator.into_data()))
+        })
+        .collect::<collections::HashMap<_, _>>();
+
+    let closures = (
+        &codegen.entities,
+        &codegen.elements,
+        &codegen.symbols,
+        &codegen.types,
+    )
+        .join()
+        .filter_map(|(entity, element, symbol, ty)| match (element, ty) {
+            (element::Element::Closure(closure), ty::Type::Function(function)) => {
+                Some((entity, closure, symbol, function))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let first_index = module.next_function_index();
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    let functions = closures
+        .iter()
+        .enumerate()
+        .map(|(i, (_, _, symbol, _))| (symbol.to_string(), first_index + i as u32))
+        .collect::<collections::HashMap<_, _>>();
+
+    for (entity, closure, symbol, ty) in &closures {
+        let type_index = module.type_index(function::function_type(ty));
+        let mut translator = function::Translator::new(
+            codegen,
+            &mut statics,
+            &builtins,
+            &functions,
+            &constants,
+            error_global,
+            type_index,
+            ty,
+        );
+        translator.translate_closure(*entity, closure, &symbol.to_string());
+        module.define_function(translator.into_function());
+    }
+
+    for (_, _, symbol, ty) in &closures {
+        if symbol.is_top_level() {
+            let name = symbol.to_string();
+            let type_index = module.type_index(function::function_type(ty));
+            let mut translator = function::Translator::new(
+                codegen,
+                &mut statics,
+                &builtins,
+                &functions,
+                &constants,
+                error_global,
+                type_index,
+                ty,
+            );
+            translator.translate_public_wrapper(functions[&name], ty);
+            let index = module.define_function(translator.into_function());
+            module.export(
+                &util::public_function_name(&name),
+                encoder::ExportKind::Function,
+                index,
+            );
+        }
+    }
+
+    let heap_base = statics.end();
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+    let state_global = module.add_global(
+        encoder::ValueType::I32,
+        false,
+        encoder::Instruction::I32Const(STATE_ADDRESS as i32),
+    );
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_wrap))]
+    let heap_base_global = module.add_global(
+        encoder::ValueType::I32,
+        false,
+        encoder::Instruction::I32Const(heap_base as i32),
+    );
+
+    module.set_memory_pages(heap_base / PAGE_SIZE + 1);
+    module.export("memory", encoder::ExportKind::Memory, 0);
+    module.export("tin_state", encoder::ExportKind::Global, state_global);
+    module.export(
+        "tin_heap_base",
+        encoder::ExportKind::Global,
+        heap_base_global,
+    );
+    module.add_data(STATE_ADDRESS, statics.contents);
+
+    module.encode()
+}
+
+/// The WebAssembly type of a builtin parameter or result, where pointers are 32 bits wide.
+fn abi_value_type(abi_type: &abi_type::AbiType) -> encoder::ValueType {
+    match *abi_type {
+        abi_type::AbiType::Scalar(types::I64) => encoder::ValueType::I64,
+        abi_type::AbiType::Scalar(types::F32) => encoder::ValueType::F32,
+        abi_type::AbiType::Scalar(types::F64) => encoder::ValueType::F64,
+        _ => encoder::ValueType::I32,
+    }
+}
+
+impl Statics {
+    fn new() -> Self {
+        use byteorder::WriteBytesExt;
+
+        let mut contents = Vec::new();
+        contents
+            .write_u32::<byteorder::LittleEndian>(u32::max_value())
+            .unwrap();
+        contents.resize(STATE_SIZE as usize, 0);
+        let strings = collections::HashMap::new();
+
+        Statics { contents, strings }
+    }
+
+    /// The first address after all static data, suitably aligned for any value.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    fn end(&mut self) -> u32 {
+        self.align(8);
+        STATE_ADDRESS + self.contents.len() as u32
+    }
+
+    /// Places the specified data object and everything it points to, returning its address.
+    fn place(&mut self, data: data::Data) -> u32 {
+        use byteorder::ByteOrder;
+
+        let address = self.end();
+        let start = self.contents.len();
+        self.contents.extend_from_slice(&data.contents);
+
+        for (offset, pointee) in data.pointers {
+            let pointee_address = self.place(pointee);
+            byteorder::LittleEndian::write_u32(
+                &mut self.contents[start + offset..start + offset + 4],
+                pointee_address,
+            );
+        }
+
+        address
+    }
+
+    /// Places the bytes of the specified string, returning its address and length.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+    fn string(&mut self, string: &str) -> (u32, u32) {
+        let contents = &mut self.contents;
+        let address = *self.strings.entry(string.to_owned()).or_insert_with(|| {
+            let address = STATE_ADDRESS + contents.len() as u32;
+            contents.extend_from_slice(string.as_bytes());
+            address
+        });
+
+        (address, string.len() as u32)
+    }
+
+    fn align(&mut self, alignment: usize) {
+        let len = self.contents.len();
+        self.contents
+            .resize((len + alignment - 1) / alignment * alignment, 0);
+    }
+}
@@ -0,0 +1,472 @@
+use env_logger;
+use failure;
+use wasmi;
+
+use super::*;
+use crate::ast;
+use crate::ir;
+use crate::module;
+use crate::test_util;
+
+/// A host for the builtin functions, with a bump allocator for the linear memory.
+#[derive(Debug)]
+struct Host {
+    memory: wasmi::MemoryRef,
+    next: u32,
+    errors: Vec<(module::ErrorKind, Vec<String>)>,
+}
+
+#[derive(Debug)]
+struct Resolver;
+
+#[derive(Debug)]
+struct Instance {
+    instance: wasmi::ModuleRef,
+    host: Host,
+    heap_base: u32,
+}
+
+type CallResult = Result<Option<wasmi::RuntimeValue>, (module::ErrorKind, Vec<String>)>;
+
+#[test]
+fn immediate() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+main = || -> u32 { 42u32 };
+"#;
+
+    let mut instance = compile_module("immediate", source)?;
+
+    let result = instance.call("main", &[]);
+    assert_eq!(Ok(Some(wasmi::RuntimeValue::I32(42))), result);
+    Ok(())
+}
+
+#[test]
+fn apply() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+other = |x: u32| -> u32 { x };
+main = |y: u32| -> u32 { a = other(y); other(other(a)) };
+"#;
+
+    let mut instance = compile_module("apply", source)?;
+
+    let result = instance.call("main", &[wasmi::RuntimeValue::I32(7)]);
+    assert_eq!(Ok(Some(wasmi::RuntimeValue::I32(7))), result);
+    Ok(())
+}
+
+#[test]
+fn record() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+main = |b: u32| -> u32 { a = { x: 1u32, y: b, z: 3u32}; a.y };
+"#;
+
+    let mut instance = compile_module("record", source)?;
+
+    let result = instance.call("main", &[wasmi::RuntimeValue::I32(2)]);
+    assert_eq!(Ok(Some(wasmi::RuntimeValue::I32(2))), result);
+    assert_eq!(instance.heap_base, instance.host.next);
+    Ok(())
+}
+
+#[test]
+fn constant_data() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Person = { name: "", age: 0u32 };
+alice = { name: "Alice", age: 42u32 };
+age = |person: Person| -> u32 { person.age };
+main = || -> u32 { age(alice) };
+"#;
+
+    let mut instance = compile_module("constant_data", source)?;
+
+    let result = instance.call("main", &[]);
+    assert_eq!(Ok(Some(wasmi::RuntimeValue::I32(42))), result);
+    Ok(())
+}
+
+#[test]
+fn operators_f64() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+main = |a: f64, b: f64| -> f64 { (a * 24f64 + b * 3f64) / 10f64 };
+"#;
+
+    let mut instance = compile_module("operators_f64", source)?;
+
+    let result = instance.call(
+        "main",
+        &[
+            wasmi::RuntimeValue::from(1.0_f64),
+            wasmi::RuntimeValue::from(2.0_f64),
+        ],
+    );
+    assert_eq!(
+        Ok(Some(3.0)),
+        result.map(|r| r.and_then(|v| v.try_into::<f64>()))
+    );
+    Ok(())
+}
+
+#[test]
+fn operators_narrow() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+add_u8 = |a: u8, b: u8| -> u8 { a + b };
+add_i8 = |a: i8, b: i8| -> i8 { a + b };
+rotl_u8 = |a: u8, b: u32| -> u8 { a <-< b };
+rotr_u16 = |a: u16, b: u32| -> u16 { a >-> b };
+shr_i8 = |a: i8, b: u32| -> i8 { a >> b };
+bnot_u8 = |a: u8| -> u8 { ~!a };
+cl0_u8 = |a: u8| -> u32 { #^0 a };
+cls_i16 = |a: i16| -> u32 { #^- a };
+ct0_u64 = |a: u64| -> u32 { #$0 a };
+c0_u16 = |a: u16| -> u32 { #0 a };
+"#;
+
+    let mut instance = compile_module("operators_narrow", source)?;
+
+    let w32 = wasmi::RuntimeValue::I32;
+    let w64 = wasmi::RuntimeValue::I64;
+    let cases = vec![
+        ("add_u8", vec![w32(200), w32(100)], 44),
+        ("add_i8", vec![w32(100), w32(100)], -112),
+        ("rotl_u8", vec![w32(0b1000_0001), w32(1)], 0b11),
+        ("rotr_u16", vec![w32(1), w32(1)], 0x8000),
+        ("shr_i8", vec![w32(-128), w32(3)], -16),
+        ("bnot_u8", vec![w32(0x0f)], 0xf0),
+        ("cl0_u8", vec![w32(1)], 7),
+        ("cls_i16", vec![w32(-1)], 15),
+        ("ct0_u64", vec![w64(0)], 64),
+        ("c0_u16", vec![w32(0xff)], 8),
+    ];
+
+    for (name, args, expected) in cases {
+        let result = instance.call(name, &args);
+        assert_eq!(Ok(Some(w32(expected))), result, "{}", name);
+    }
+    Ok(())
+}
+
+#[test]
+fn division_by_zero() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+div = |a: u32, b: u32| -> u32 { a / b };
+main = |a: u32| -> u32 { div(a, 0u32) };
+"#;
+
+    let mut instance = compile_module("division_by_zero", source)?;
+
+    let result = instance.call("main", &[wasmi::RuntimeValue::I32(1)]);
+    assert_eq!(
+        Err((
+            module::ErrorKind::IntegerDivisonByZero,
+            vec!["div".to_owned(), "main".to_owned()]
+        )),
+        result
+    );
+
+    // The error is cleared for the next call
+    let result = instance.call(
+        "div",
+        &[wasmi::RuntimeValue::I32(4), wasmi::RuntimeValue::I32(2)],
+    );
+    assert_eq!(Ok(Some(wasmi::RuntimeValue::I32(2))), result);
+    Ok(())
+}
+
+#[test]
+fn user_error() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+fail_with = |reason: ""| -> u32 { fail(reason) };
+main = |a: u32| -> u32 { fail_with("boom") + a };
+"#;
+
+    let mut instance = compile_module("user_error", source)?;
+
+    let result = instance.call("main", &[wasmi::RuntimeValue::I32(1)]);
+    assert_eq!(
+        Err((
+            module::ErrorKind::UserGenerated,
+            vec!["fail_with".to_owned(), "main".to_owned()]
+        )),
+        result
+    );
+    Ok(())
+}
+
+#[test]
+fn out_of_fuel() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+forever = |a: u32| -> u32 { forever(a) };
+"#;
+
+    let mut instance = compile_module("out_of_fuel", source)?;
+    instance.host.write_u32(STATE_ADDRESS + FUEL_OFFSET, 100);
+
+    let (kind, _) = instance
+        .call("forever", &[wasmi::RuntimeValue::I32(1)])
+        .unwrap_err();
+    assert_eq!(module::ErrorKind::OutOfFuel, kind);
+    assert_eq!(0, instance.host.read_u32(STATE_ADDRESS + FUEL_OFFSET));
+    Ok(())
+}
+
+#[test]
+fn unlimited_fuel() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+double = |a: u32| -> u32 { a + a };
+main = |a: u32| -> u32 { double(a) };
+"#;
+
+    let mut instance = compile_module("unlimited_fuel", source)?;
+
+    let result = instance.call("main", &[wasmi::RuntimeValue::I32(21)]);
+    assert_eq!(Ok(Some(wasmi::RuntimeValue::I32(42))), result);
+    assert_eq!(
+        u32::max_value(),
+        instance.host.read_u32(STATE_ADDRESS + FUEL_OFFSET)
+    );
+    Ok(())
+}
+
+#[test]
+fn layouts_for_native_target() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Person = { name: "", age: 0u32 };
+alice = { name: "Alice", age: 42u32 };
+age = |person: Person| -> u32 { person.age };
+main = || -> u32 { age(alice) };
+"#;
+
+    let (mut ir, codemap) = check_module("layouts_for_native_target", source)?;
+    Codegen::new(&ir, &codemap).compile_wasm()?;
+
+    // Switching back to the native target must not reuse the 4-byte pointer layouts
+    ir.check_types()?;
+    let mut module = Codegen::new(&ir, &codemap).compile()?;
+    let main = module.function::<module::Function0<u32>>("main").unwrap();
+
+    let result = main.call();
+    assert_eq!(Ok(42), result);
+    Ok(())
+}
+
+#[test]
+fn unsupported() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let cases = [
+        (
+            "main = |a: u32| -> u32 { double = |x: u32| -> u32 { x * 2u32 }; double(a) };",
+            "nested functions",
+            "|x: u32| -> u32 { x * 2u32 }",
+        ),
+        (
+            "main = |a: u32| -> u32 { same = a == 1u32; a };",
+            "comparisons",
+            "a == 1u32",
+        ),
+        (
+            "main = |a: u32| -> u32 { try { a / a } catch |e| { 0u32 } };",
+            "`try` expressions",
+            "try { a / a } catch |e| { 0u32 }",
+        ),
+        (
+            r#"main = |a: u32| -> u32 { ok = assert(a == 1u32, "a must be one"); a };"#,
+            "assertions",
+            r#"assert(a == 1u32, "a must be one")"#,
+        ),
+    ];
+
+    for (source, feature, snippet) in &cases {
+        let (ir, codemap) = check_module("unsupported", source)?;
+
+        match Codegen::new(&ir, &codemap).compile_wasm() {
+            Err(crate::error::Error::Unsupported {
+                feature: ref actual,
+                ref backend,
+                location,
+            }) => {
+                assert_eq!(feature, actual);
+                assert_eq!("WebAssembly", backend);
+                let filemap = codemap.find_file(location.start()).unwrap();
+                assert_eq!(snippet, &filemap.src_slice(location).unwrap());
+            }
+            other => panic!("expected an unsupported feature error, got {:?}", other),
+        }
+    }
+    Ok(())
+}
+
+impl Instance {
+    fn new(wasm: &[u8]) -> Result<Self, failure::Error> {
+        let module = wasmi::Module::from_buffer(wasm)?;
+        let imports = wasmi::ImportsBuilder::new().with_resolver(IMPORT_MODULE, &Resolver);
+        let instance = wasmi::ModuleInstance::new(&module, &imports)?.assert_no_start();
+
+        let memory = instance
+            .export_by_name("memory")
+            .and_then(|e| e.as_memory().cloned())
+            .unwrap();
+        let heap_base = instance
+            .export_by_name("tin_heap_base")
+            .and_then(|e| e.as_global().cloned())
+            .and_then(|g| g.get().try_into::<u32>())
+            .unwrap();
+        let host = Host {
+            memory,
+            next: heap_base,
+            errors: Vec::new(),
+        };
+
+        Ok(Instance {
+            instance,
+            host,
+            heap_base,
+        })
+    }
+
+    fn call(&mut self, name: &str, args: &[wasmi::RuntimeValue]) -> CallResult {
+        let result = self
+            .instance
+            .invoke_export(&util::public_function_name(name), args, &mut self.host)
+            .unwrap();
+
+        match self.host.read_u32(STATE_ADDRESS + ERROR_OFFSET) {
+            0 => Ok(result),
+            error => Err(self.host.errors[error as usize - 1].clone()),
+        }
+    }
+}
+
+impl Host {
+    fn read_u32(&self, address: u32) -> u32 {
+        use byteorder::ByteOrder;
+
+        byteorder::LittleEndian::read_u32(&self.memory.get(address, 4).unwrap())
+    }
+
+    fn write_u32(&self, address: u32, value: u32) {
+        use byteorder::ByteOrder;
+
+        let mut bytes = [0; 4];
+        byteorder::LittleEndian::write_u32(&mut bytes, value);
+        self.memory.set(address, &bytes).unwrap();
+    }
+
+    fn read_string(&self, address: u32, len: u32) -> String {
+        String::from_utf8(self.memory.get(address, len as usize).unwrap()).unwrap()
+    }
+}
+
+impl wasmi::ModuleImportResolver for Resolver {
+    fn resolve_func(
+        &self,
+        field_name: &str,
+        signature: &wasmi::Signature,
+    ) -> Result<wasmi::FuncRef, wasmi::Error> {
+        let index = builtin::BUILTINS
+            .iter()
+            .position(|b| b.symbol == field_name)
+            .ok_or_else(|| wasmi::Error::Instantiation(format!("unknown {}", field_name)))?;
+
+        Ok(wasmi::FuncInstance::alloc_host(signature.clone(), index))
+    }
+}
+
+impl wasmi::Externals for Host {
+    #[cfg_attr(
+        feature = "cargo-clippy",
+        allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)
+    )]
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: wasmi::RuntimeArgs,
+    ) -> Result<Option<wasmi::RuntimeValue>, wasmi::Trap> {
+        use num_traits::cast::FromPrimitive;
+
+        let symbol = builtin::BUILTINS[index].symbol;
+        let result = if symbol == builtin::ALLOC.symbol {
+            let size: u32 = args.nth_checked(1)?;
+            let align: u32 = args.nth_checked(2)?;
+            let address = (self.next + align - 1) / align * align;
+            self.next = address + size;
+            Some(address)
+        } else if symbol == builtin::ENTER_CALL.symbol {
+            Some(self.next)
+        } else if symbol == builtin::EXIT_CALL.symbol {
+            let mark: u32 = args.nth_checked(1)?;
+            let retain: u32 = args.nth_checked(2)?;
+            if retain == 0 {
+                self.next = mark;
+            }
+            None
+        } else if symbol == builtin::ERROR.symbol {
+            let kind: u32 = args.nth_checked(0)?;
+            self.errors
+                .push((module::ErrorKind::from_u32(kind).unwrap(), Vec::new()));
+            Some(self.errors.len() as u32)
+        } else if symbol == builtin::USER_ERROR.symbol {
+            self.errors
+                .push((module::ErrorKind::UserGenerated, Vec::new()));
+            Some(self.errors.len() as u32)
+        } else if symbol == builtin::UNWIND_FRAME.symbol {
+            let error: u32 = args.nth_checked(0)?;
+            let name: u32 = args.nth_checked(1)?;
+            let name_len: u32 = args.nth_checked(2)?;
+            let name = self.read_string(name, name_len);
+            self.errors[error as usize - 1].1.push(name);
+            None
+        } else {
+            None
+        };
+
+        Ok(result.map(|v| wasmi::RuntimeValue::I32(v as i32)))
+    }
+}
+
+fn compile_module(name: &'static str, source: &str) -> Result<Instance, failure::Error> {
+    let (ir, codemap) = check_module(name, source)?;
+    let wasm = Codegen::new(&ir, &codemap).compile_wasm()?;
+
+    Instance::new(&wasm)
+}
+
+fn check_module(
+    name: &'static str,
+    source: &str,
+) -> Result<(ir::Ir, codespan::CodeMap), failure::Error> {
+    use crate::parser::Parse;
+
+    let mut codemap = codespan::CodeMap::new();
+    let file = codemap.add_filemap(codespan::FileName::Virtual(name.into()), source.to_owned());
+    let ast_module = ast::Module::parse(file.span(), source)?;
+    let mut ir = ir::Ir::new();
+    ir.load(&ast_module, &file)?;
+    ir.check_types_for_ptr_size(4)?;
+    test_util::render_graph(&format!(concat!(module_path!(), "::{}"), name), &ir)?;
+
+    Ok((ir, codemap))
+}
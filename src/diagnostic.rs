@@ -37,6 +37,8 @@
 use std::io;
 use std::mem;
 
+use serde_json;
+
 /// A trait for types that have the ability to emit diagnostic information.
 pub trait Diagnostics {
     /// Emits diagnostics from an instance of this type.
@@ -87,6 +89,133 @@ where
     emit(stderr, code_map, diagnostics)
 }
 
+/// Writes the specified [`Diagnostics`] as JSON, with one object per line.
+///
+/// Every object has a `severity` (`"bug"`, `"error"`, `"warning"`, `"note"` or `"help"`), a
+/// `message`, a `code` (which may be `null`) and a list of `labels`.  Each label has a `file`, a
+/// byte `span` relative to the start of the file, the 1-based `line` and `column` of the start of
+/// the span, a `style` (`"primary"` or `"secondary"`) and a `message` (which may be `null`).
+///
+/// # Examples
+///
+/// ```
+/// # extern crate failure;
+/// # extern crate tin;
+/// # fn main() -> Result<(), failure::Error> {
+/// let mut tin = tin::Tin::new();
+/// let error = tin.load("main.tn", "main = || -> i32 { a };").unwrap_err();
+///
+/// let mut output = Vec::new();
+/// tin::diagnostic::emit_json(&mut output, tin.codemap(), &error)?;
+///
+/// assert_eq!(
+///     concat!(
+///         r#"{"code":null,"labels":[{"column":20,"file":"main.tn","line":1,"message":null,"#,
+///         r#""span":{"end":20,"start":19},"style":"primary"}],"#,
+///         r#""message":"undefined reference to `a`","severity":"error"}"#,
+///         "\n",
+///     ),
+///     String::from_utf8(output)?
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub fn emit_json<W, D>(
+    mut writer: W,
+    code_map: &codespan::CodeMap,
+    diagnostics: &D,
+) -> io::Result<()>
+where
+    D: Diagnostics,
+    W: io::Write,
+{
+    for diagnostic in to_json(code_map, diagnostics) {
+        serde_json::to_writer(&mut writer, &diagnostic)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Converts the specified [`Diagnostics`] into JSON values, in the format used by [`emit_json`].
+pub fn to_json<D>(code_map: &codespan::CodeMap, diagnostics: &D) -> Vec<serde_json::Value>
+where
+    D: Diagnostics,
+{
+    let mut builder = DiagnosticsBuilder::new();
+    diagnostics.to_diagnostics(&mut builder);
+
+    builder
+        .result
+        .iter()
+        .map(|diagnostic| {
+            let severity = match diagnostic.severity {
+                codespan_reporting::Severity::Bug => "bug",
+                codespan_reporting::Severity::Error => "error",
+                codespan_reporting::Severity::Warning => "warning",
+                codespan_reporting::Severity::Note => "note",
+                codespan_reporting::Severity::Help => "help",
+            };
+            let labels = diagnostic
+                .labels
+                .iter()
+                .map(|label| label_to_json(code_map, label))
+                .collect::<Vec<_>>();
+
+            json!({
+                "severity": severity,
+                "message": diagnostic.message,
+                "code": diagnostic.code,
+                "labels": labels,
+            })
+        })
+        .collect()
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_sign_loss))]
+fn label_to_json(
+    code_map: &codespan::CodeMap,
+    label: &codespan_reporting::Label,
+) -> serde_json::Value {
+    let style = match label.style {
+        codespan_reporting::LabelStyle::Primary => "primary",
+        codespan_reporting::LabelStyle::Secondary => "secondary",
+    };
+
+    match code_map.find_file(label.span.start()) {
+        Some(file) => {
+            let base = file.span().start();
+            let (line, column) = file
+                .location(label.span.start())
+                .map(|(line, column)| (line.0 + 1, column.0 + 1))
+                .unwrap_or((0, 0));
+            let name = match *file.name() {
+                codespan::FileName::Real(ref path) => path.display().to_string(),
+                codespan::FileName::Virtual(ref name) => name.to_string(),
+            };
+
+            json!({
+                "file": name,
+                "span": {
+                    "start": (label.span.start() - base).0 as u64,
+                    "end": (label.span.end() - base).0 as u64,
+                },
+                "line": line,
+                "column": column,
+                "style": style,
+                "message": label.message,
+            })
+        }
+        None => json!({
+            "file": null,
+            "span": null,
+            "line": null,
+            "column": null,
+            "style": style,
+            "message": label.message,
+        }),
+    }
+}
+
 /// Emits the specified [`Diagnostics`] to an output that potentially supports color.
 ///
 /// Use `termcolor::NoColor::new(...)` to wrap an ordinary `Write` if color is not desired.  This is
@@ -164,3 +293,80 @@ impl DiagnosticsBuilder {
         self.result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Tin;
+
+    use super::*;
+
+    #[test]
+    fn json_labels() {
+        let mut tin = Tin::new();
+        tin.load("main.tn", "\nmain = || -> i32 { 42f32 };\n")
+            .unwrap();
+        let error = tin.compile().unwrap_err();
+
+        let diagnostics = to_json(tin.codemap(), &error);
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(json!("error"), diagnostics[0]["severity"]);
+        assert_eq!(json!("type error"), diagnostics[0]["message"]);
+        assert_eq!(
+            json!({
+                "file": "main.tn",
+                "span": { "start": 20, "end": 25 },
+                "line": 2,
+                "column": 20,
+                "style": "primary",
+                "message": "expected `i32` but got `f32`",
+            }),
+            diagnostics[0]["labels"][1]
+        );
+        assert_eq!(json!("secondary"), diagnostics[0]["labels"][2]["style"]);
+        assert_eq!(json!(2), diagnostics[0]["labels"][2]["line"]);
+        assert_eq!(json!(14), diagnostics[0]["labels"][2]["column"]);
+    }
+
+    #[test]
+    fn json_runtime_error() {
+        let mut tin = Tin::new();
+        tin.load(
+            "main.tn",
+            "div = |a: i32, b: i32| -> i32 { a / b };\nmain = || -> i32 { div(1i32, 0i32) };\n",
+        )
+        .unwrap();
+        let mut module = tin.compile().unwrap();
+        let main = module
+            .function::<crate::module::Function0<i32>>("main")
+            .unwrap();
+        let error = crate::Error::from(main.call().unwrap_err());
+
+        let mut output = Vec::new();
+        emit_json(&mut output, tin.codemap(), &error).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(1, output.lines().count());
+        let diagnostic = serde_json::from_str::<serde_json::Value>(&output).unwrap();
+        assert_eq!(json!("error"), diagnostic["severity"]);
+        assert_eq!(json!("integer division by zero"), diagnostic["message"]);
+        assert_eq!(
+            json!({
+                "file": "main.tn",
+                "span": { "start": 32, "end": 37 },
+                "line": 1,
+                "column": 33,
+                "style": "primary",
+                "message": "raised in `div`",
+            }),
+            diagnostic["labels"][0]
+        );
+        assert_eq!(json!("secondary"), diagnostic["labels"][1]["style"]);
+        assert_eq!(json!(2), diagnostic["labels"][1]["line"]);
+        assert_eq!(json!(20), diagnostic["labels"][1]["column"]);
+        assert_eq!(
+            json!("called from `main`"),
+            diagnostic["labels"][1]["message"]
+        );
+    }
+}
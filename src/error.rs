@@ -4,11 +4,16 @@ use std::result;
 use crate::diagnostic;
 use crate::interpreter;
 use crate::ir;
+use crate::module;
 use crate::parser;
+use crate::ty;
 
 /// An error that occurs while interacting with Tin.
 #[derive(Clone, Debug, Fail, PartialEq)]
 pub enum Error {
+    /// Generating machine code failed.
+    #[fail(display = "codegen error: {}", _0)]
+    Codegen(String),
     /// Interpreting the code failed.
     ///
     /// This can happen either during an interpreter run, or during compiler constant evaluation
@@ -21,6 +26,22 @@ pub enum Error {
     /// Parsing the source code failed.
     #[fail(display = "parser error")]
     Parser(#[cause] parser::Error),
+    /// Running compiled code failed.
+    #[fail(display = "runtime error")]
+    Runtime(#[cause] module::Error),
+    /// The code uses a feature that the targeted backend cannot translate yet.
+    #[fail(display = "{} are not supported by the {} backend", feature, backend)]
+    Unsupported {
+        /// A description of the unsupported feature.
+        feature: String,
+        /// The name of the backend.
+        backend: String,
+        /// The location of the code that uses the feature.
+        location: codespan::ByteSpan,
+    },
+    /// A value of the specified type cannot be represented on the host.
+    #[fail(display = "values of type `{}` cannot be represented as a `Value`", _0)]
+    UnrepresentableValue(ty::Type),
 }
 
 /// A convenience result wrapper for the [`Error`] type.
@@ -32,6 +53,26 @@ impl diagnostic::Diagnostics for Error {
             Error::Parser(ref e) => e.to_diagnostics(builder),
             Error::Interpreter(ref e) => e.to_diagnostics(builder),
             Error::Ir(ref e) => e.to_diagnostics(builder),
+            Error::Runtime(ref e) => e.to_diagnostics(builder),
+            Error::Unsupported { location, .. } => {
+                builder.add_label(codespan_reporting::Label {
+                    span: location,
+                    message: None,
+                    style: codespan_reporting::LabelStyle::Primary,
+                });
+                builder.add_diagnostic(
+                    codespan_reporting::Severity::Error,
+                    None,
+                    &self.to_string(),
+                );
+            }
+            Error::Codegen(_) | Error::UnrepresentableValue(_) => {
+                builder.add_diagnostic(
+                    codespan_reporting::Severity::Error,
+                    None,
+                    &self.to_string(),
+                );
+            }
         }
     }
 }
@@ -48,6 +89,12 @@ impl From<ir::error::Error> for Error {
     }
 }
 
+impl From<module::Error> for Error {
+    fn from(err: module::Error) -> Self {
+        Error::Runtime(err)
+    }
+}
+
 impl From<parser::Error> for Error {
     fn from(err: parser::Error) -> Self {
         Error::Parser(err)
@@ -0,0 +1,662 @@
+//! Differential testing of constant evaluation against compiled code.
+//!
+//! The interpreter (used for constant evaluation) and the code generator implement the semantics of
+//! every operator separately.  This module generates random well-typed number expressions, and
+//! comparisons between them, and evaluates each of them twice:
+//!
+//!   * once with only literal operands, so that it is folded by constant evaluation, and
+//!   * once with every literal passed through an identity function, so that nothing can be folded
+//!     and the expression is instead evaluated by compiled code.
+//!
+//! If the two results differ, the expression is shrunk as far as possible while still causing a
+//! disagreement, and reported as a small Tin program.
+use std::fmt;
+use std::panic;
+
+use crate::ast;
+use crate::error;
+use crate::fuzz;
+use crate::interpreter;
+use crate::ir;
+use crate::module;
+use crate::ty;
+use crate::value;
+use crate::Tin;
+
+/// The maximum depth of generated expressions.
+const MAX_DEPTH: usize = 5;
+
+/// A disagreement between constant evaluation and compiled code.
+#[derive(Clone, Debug)]
+pub struct Disagreement {
+    expression: Expression,
+    constant: Outcome,
+    compiled: Outcome,
+}
+
+/// The outcome of evaluating an expression.
+///
+/// Only values and runtime errors are legitimate outcomes; two evaluations agree if they produce
+/// equal values or errors of the same kind.  Rejected expressions and panics always count as
+/// disagreements, since the generated expressions are well-typed and should never cause either.
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    /// The expression evaluated to a value.
+    Value(value::Value),
+    /// Evaluating the expression failed with a runtime error of the specified kind.
+    Error(module::ErrorKind),
+    /// The expression was rejected before it could be evaluated.
+    Rejected(String),
+    /// Evaluating the expression caused a panic.
+    Panic(String),
+}
+
+/// A well-typed number expression, or a comparison of two number expressions at the top level.
+#[derive(Clone, Debug)]
+enum Expression {
+    Literal(value::Number),
+    UnOp {
+        ty: ty::Number,
+        operator: ast::UnOperator,
+        operand: Box<Expression>,
+    },
+    BiOp {
+        ty: ty::Number,
+        lhs: Box<Expression>,
+        operator: ast::BiOperator,
+        rhs: Box<Expression>,
+    },
+    Comparison {
+        lhs: Box<Expression>,
+        operator: ast::BiOperator,
+        rhs: Box<Expression>,
+    },
+}
+
+/// Whether to render expressions for constant evaluation or for compiled code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    Constant,
+    Compiled,
+}
+
+/// Generates a random expression and checks that constant evaluation and compiled code agree on
+/// its value.
+///
+/// Returns the minimised disagreement if they don't.
+pub fn check(entropy: &mut fuzz::Entropy) -> Option<Disagreement> {
+    let ty = *entropy.choose(fuzz::NUMBERS);
+    let expression = if entropy.chance(0x40) {
+        let operator = *entropy.choose(fuzz::COMPARISON_BI_OPERATORS);
+        let lhs = Expression::generate(entropy, ty, MAX_DEPTH - 1);
+        let rhs = Expression::generate(entropy, ty, MAX_DEPTH - 1);
+        Expression::comparison(lhs, operator, rhs)
+    } else {
+        Expression::generate(entropy, ty, MAX_DEPTH)
+    };
+    check_expression(expression).map(Disagreement::minimise)
+}
+
+fn check_expression(expression: Expression) -> Option<Disagreement> {
+    let constant = evaluate(&expression.render(Mode::Constant));
+    let compiled = evaluate(&expression.render(Mode::Compiled));
+
+    if constant.agrees_with(&compiled) {
+        None
+    } else {
+        Some(Disagreement {
+            expression,
+            constant,
+            compiled,
+        })
+    }
+}
+
+fn evaluate(source: &str) -> Outcome {
+    let result = panic::catch_unwind(|| {
+        let mut tin = Tin::new();
+        tin.load("prelude.tn", &prelude())?;
+        tin.eval(source)
+    });
+
+    match result {
+        Ok(Ok(value)) => Outcome::Value(value),
+        Ok(Err(error)) => match runtime_error_kind(&error) {
+            Some(kind) => Outcome::Error(kind),
+            None => Outcome::Rejected(error.to_string()),
+        },
+        Err(payload) => Outcome::Panic(
+            payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| (*s).to_owned()))
+                .unwrap_or_else(|| "unknown panic".to_owned()),
+        ),
+    }
+}
+
+/// The kind of runtime error that caused an evaluation to fail, either during constant evaluation
+/// or in compiled code.
+fn runtime_error_kind(error: &error::Error) -> Option<module::ErrorKind> {
+    match *error {
+        error::Error::Runtime(ref error) => Some(error.kind()),
+        error::Error::Interpreter(ref error) => interpreter_error_kind(error),
+        error::Error::Ir(ref error) => ir_error_kind(error),
+        _ => None,
+    }
+}
+
+fn ir_error_kind(error: &ir::error::Error) -> Option<module::ErrorKind> {
+    match *error {
+        ir::error::Error::Constexpr(_, ref error) => match *error {
+            ir::component::constexpr::error::Error::Evaluation(ref error) => {
+                interpreter_error_kind(error)
+            }
+        },
+        // All errors must agree, since there is a single expression that can fail
+        ir::error::Error::Multiple { ref errors } => {
+            let mut kinds = errors.iter().map(ir_error_kind);
+            let first = kinds.next()??;
+            if kinds.all(|kind| kind == Some(first)) {
+                Some(first)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn interpreter_error_kind(error: &interpreter::error::Error) -> Option<module::ErrorKind> {
+    match *error {
+        interpreter::error::Error::EvaluationError(ref error) => Some(error.kind()),
+        interpreter::error::Error::RuntimeTypeConflict(_) => None,
+    }
+}
+
+/// Identity functions for every number type, which hide values from constant evaluation.
+fn prelude() -> String {
+    fuzz::NUMBERS
+        .iter()
+        .map(|ty| format!("{} = |x: {}| -> {} {{ x }};\n", identity(*ty), ty, ty))
+        .collect()
+}
+
+fn identity(ty: ty::Number) -> String {
+    format!("id_{}", ty)
+}
+
+impl Disagreement {
+    /// The minimised program that demonstrates this disagreement.
+    ///
+    /// The `constant` definition is evaluated at compile time, and the `compiled` function should
+    /// return the same value when called.
+    pub fn program(&self) -> String {
+        format!(
+            "{}constant = {};\ncompiled = || -> {} {{ {} }};\n",
+            prelude(),
+            self.expression.render(Mode::Constant),
+            self.expression.signature(),
+            self.expression.render(Mode::Compiled)
+        )
+    }
+
+    /// The outcome of constant evaluation.
+    pub fn constant(&self) -> &Outcome {
+        &self.constant
+    }
+
+    /// The outcome of running compiled code.
+    pub fn compiled(&self) -> &Outcome {
+        &self.compiled
+    }
+
+    /// Repeatedly shrinks the expression for as long as the disagreement persists.
+    fn minimise(self) -> Self {
+        let mut current = self;
+
+        'shrink: loop {
+            for candidate in current.expression.shrink() {
+                if let Some(disagreement) = check_expression(candidate) {
+                    current = disagreement;
+                    continue 'shrink;
+                }
+            }
+            return current;
+        }
+    }
+}
+
+impl fmt::Display for Disagreement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "constant evaluation: {}", self.constant)?;
+        writeln!(f, "compiled code:       {}", self.compiled)?;
+        write!(f, "{}", self.program())
+    }
+}
+
+impl Outcome {
+    fn agrees_with(&self, other: &Outcome) -> bool {
+        match (self, other) {
+            (Outcome::Value(a), Outcome::Value(b)) => match (a.case(), b.case()) {
+                (
+                    value::Case::Number(value::Number::F32(a)),
+                    value::Case::Number(value::Number::F32(b)),
+                ) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+                (
+                    value::Case::Number(value::Number::F64(a)),
+                    value::Case::Number(value::Number::F64(b)),
+                ) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+                _ => a == b,
+            },
+            (Outcome::Error(a), Outcome::Error(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Value(ref value) => write!(f, "value {:?}", value.case()),
+            Outcome::Error(kind) => write!(f, "error: {}", kind),
+            Outcome::Rejected(ref error) => write!(f, "rejected: {}", error),
+            Outcome::Panic(ref message) => write!(f, "panic: {}", message),
+        }
+    }
+}
+
+impl Expression {
+    fn generate(entropy: &mut fuzz::Entropy, ty: ty::Number, depth: usize) -> Self {
+        // A zero byte always means a leaf, so that generation terminates once fuzzer input runs
+        // out.
+        if depth == 0 || entropy.byte() < 0x40 {
+            return Expression::Literal(fuzz::literal(entropy, ty));
+        }
+
+        let depth = depth - 1;
+
+        match entropy.below(3) {
+            0 if fuzz::is_integral(ty) => {
+                let operator = *entropy.choose(fuzz::INTEGRAL_UN_OPERATORS);
+                Expression::un_op(ty, operator, Expression::generate(entropy, ty, depth))
+            }
+            0 => {
+                let operator = *entropy.choose(fuzz::FRACTIONAL_UN_OPERATORS);
+                Expression::un_op(ty, operator, Expression::generate(entropy, ty, depth))
+            }
+            1 if fuzz::is_integral(ty) && entropy.chance(0x80) => {
+                let operator = *entropy.choose(fuzz::SHIFT_BI_OPERATORS);
+                let lhs = Expression::generate(entropy, ty, depth);
+                let rhs = Expression::generate(entropy, ty::Number::U32, depth);
+                Expression::bi_op(ty, lhs, operator, rhs)
+            }
+            1 if fuzz::is_integral(ty) => {
+                let operator = *entropy.choose(fuzz::BITWISE_BI_OPERATORS);
+                let lhs = Expression::generate(entropy, ty, depth);
+                let rhs = Expression::generate(entropy, ty, depth);
+                Expression::bi_op(ty, lhs, operator, rhs)
+            }
+            _ if ty == ty::Number::U32 && entropy.chance(0x40) => {
+                let operator = *entropy.choose(fuzz::COUNT_UN_OPERATORS);
+                let operand_ty = *entropy.choose(fuzz::INTEGRALS);
+                let operand = Expression::generate(entropy, operand_ty, depth);
+                Expression::un_op(ty, operator, operand)
+            }
+            _ => {
+                let operator = *entropy.choose(fuzz::ARITHMETIC_BI_OPERATORS);
+                let lhs = Expression::generate(entropy, ty, depth);
+                let rhs = Expression::generate(entropy, ty, depth);
+                Expression::bi_op(ty, lhs, operator, rhs)
+            }
+        }
+    }
+
+    fn un_op(ty: ty::Number, operator: ast::UnOperator, operand: Expression) -> Self {
+        let operand = Box::new(operand);
+        Expression::UnOp {
+            ty,
+            operator,
+            operand,
+        }
+    }
+
+    fn bi_op(ty: ty::Number, lhs: Expression, operator: ast::BiOperator, rhs: Expression) -> Self {
+        let lhs = Box::new(lhs);
+        let rhs = Box::new(rhs);
+        Expression::BiOp {
+            ty,
+            lhs,
+            operator,
+            rhs,
+        }
+    }
+
+    fn comparison(lhs: Expression, operator: ast::BiOperator, rhs: Expression) -> Self {
+        let lhs = Box::new(lhs);
+        let rhs = Box::new(rhs);
+        Expression::Comparison { lhs, operator, rhs }
+    }
+
+    /// The type of the numbers in this expression; for a comparison, the type of its operands.
+    fn ty(&self) -> ty::Number {
+        match *self {
+            Expression::Literal(number) => number_type(number),
+            Expression::UnOp { ty, .. } | Expression::BiOp { ty, .. } => ty,
+            Expression::Comparison { ref lhs, .. } => lhs.ty(),
+        }
+    }
+
+    /// The result type of this expression, as it would appear in a function signature.
+    fn signature(&self) -> String {
+        match *self {
+            // Comparisons produce `:f | :t`, which is most easily spelled as another comparison
+            Expression::Comparison { .. } => {
+                let zero = render_number(fuzz::zero(self.ty()));
+                format!("({} == {})", zero, zero)
+            }
+            _ => self.ty().to_string(),
+        }
+    }
+
+    fn render(&self, mode: Mode) -> String {
+        match *self {
+            Expression::Literal(number) => match mode {
+                Mode::Constant => render_number(number),
+                Mode::Compiled => format!(
+                    "{}({})",
+                    identity(number_type(number)),
+                    render_number(number)
+                ),
+            },
+            Expression::UnOp {
+                operator,
+                ref operand,
+                ..
+            } => format!("{} ({})", operator, operand.render(mode)),
+            Expression::BiOp {
+                ref lhs,
+                operator,
+                ref rhs,
+                ..
+            }
+            | Expression::Comparison {
+                ref lhs,
+                operator,
+                ref rhs,
+            } => format!("({} {} {})", lhs.render(mode), operator, rhs.render(mode)),
+        }
+    }
+
+    /// Returns simpler variants of this expression, simplest first.
+    ///
+    /// Every variant is either made up of fewer nodes, or has a literal that is closer to zero, so
+    /// repeated shrinking always terminates.
+    fn shrink(&self) -> Vec<Expression> {
+        let ty = self.ty();
+        let mut candidates = Vec::new();
+
+        match *self {
+            Expression::Literal(number) => {
+                candidates.extend(shrink_number(number).into_iter().map(Expression::Literal));
+            }
+            Expression::UnOp {
+                operator,
+                ref operand,
+                ..
+            } => {
+                candidates.push(Expression::Literal(fuzz::zero(ty)));
+                if operand.ty() == ty {
+                    candidates.push((**operand).clone());
+                }
+                candidates.extend(
+                    operand
+                        .shrink()
+                        .into_iter()
+                        .map(|operand| Expression::un_op(ty, operator, operand)),
+                );
+            }
+            Expression::BiOp {
+                ref lhs,
+                operator,
+                ref rhs,
+                ..
+            } => {
+                candidates.push(Expression::Literal(fuzz::zero(ty)));
+                if lhs.ty() == ty {
+                    candidates.push((**lhs).clone());
+                }
+                if rhs.ty() == ty {
+                    candidates.push((**rhs).clone());
+                }
+                candidates.extend(
+                    lhs.shrink()
+                        .into_iter()
+                        .map(|lhs| Expression::bi_op(ty, lhs, operator, (**rhs).clone())),
+                );
+                candidates.extend(
+                    rhs.shrink()
+                        .into_iter()
+                        .map(|rhs| Expression::bi_op(ty, (**lhs).clone(), operator, rhs)),
+                );
+            }
+            Expression::Comparison {
+                ref lhs,
+                operator,
+                ref rhs,
+            } => {
+                // The operands are number expressions on their own, and might disagree already
+                candidates.push((**lhs).clone());
+                candidates.push((**rhs).clone());
+                candidates.extend(
+                    lhs.shrink()
+                        .into_iter()
+                        .map(|lhs| Expression::comparison(lhs, operator, (**rhs).clone())),
+                );
+                candidates.extend(
+                    rhs.shrink()
+                        .into_iter()
+                        .map(|rhs| Expression::comparison((**lhs).clone(), operator, rhs)),
+                );
+            }
+        }
+
+        candidates
+    }
+}
+
+fn number_type(number: value::Number) -> ty::Number {
+    match number {
+        value::Number::U8(_) => ty::Number::U8,
+        value::Number::U16(_) => ty::Number::U16,
+        value::Number::U32(_) => ty::Number::U32,
+        value::Number::U64(_) => ty::Number::U64,
+        value::Number::I8(_) => ty::Number::I8,
+        value::Number::I16(_) => ty::Number::I16,
+        value::Number::I32(_) => ty::Number::I32,
+        value::Number::I64(_) => ty::Number::I64,
+        value::Number::F32(_) => ty::Number::F32,
+        value::Number::F64(_) => ty::Number::F64,
+    }
+}
+
+fn render_number(number: value::Number) -> String {
+    match number {
+        value::Number::U8(n) => format!("{}u8", n),
+        value::Number::U16(n) => format!("{}u16", n),
+        value::Number::U32(n) => format!("{}u32", n),
+        value::Number::U64(n) => format!("{}u64", n),
+        value::Number::I8(n) => format!("{}i8", n),
+        value::Number::I16(n) => format!("{}i16", n),
+        value::Number::I32(n) => format!("{}i32", n),
+        value::Number::I64(n) => format!("{}i64", n),
+        value::Number::F32(n) => format!("{}f32", n),
+        value::Number::F64(n) => format!("{}f64", n),
+    }
+}
+
+/// Returns numbers that are closer to zero than the specified number.
+#[allow(clippy::float_cmp)]
+fn shrink_number(number: value::Number) -> Vec<value::Number> {
+    macro_rules! shrink_integral {
+        ($case:ident, $n:expr) => {{
+            let mut candidates = Vec::new();
+            if $n != 0 {
+                candidates.push(value::Number::$case(0));
+            }
+            if $n != 0 && $n != 1 {
+                candidates.push(value::Number::$case(1));
+            }
+            if $n / 2 != 0 && $n / 2 != 1 {
+                candidates.push(value::Number::$case($n / 2));
+            }
+            candidates
+        }};
+    }
+
+    macro_rules! shrink_fractional {
+        ($case:ident, $n:expr) => {{
+            let mut candidates = Vec::new();
+            if $n.to_bits() != 0 {
+                candidates.push(value::Number::$case(0.0));
+            }
+            if $n.to_bits() != 0 && $n != 1.0 {
+                candidates.push(value::Number::$case(1.0));
+            }
+            if $n.trunc() != $n && $n.trunc() != 0.0 && $n.trunc() != 1.0 {
+                candidates.push(value::Number::$case($n.trunc()));
+            }
+            candidates
+        }};
+    }
+
+    match number {
+        value::Number::U8(n) => shrink_integral!(U8, n),
+        value::Number::U16(n) => shrink_integral!(U16, n),
+        value::Number::U32(n) => shrink_integral!(U32, n),
+        value::Number::U64(n) => shrink_integral!(U64, n),
+        value::Number::I8(n) => shrink_integral!(I8, n),
+        value::Number::I16(n) => shrink_integral!(I16, n),
+        value::Number::I32(n) => shrink_integral!(I32, n),
+        value::Number::I64(n) => shrink_integral!(I64, n),
+        value::Number::F32(n) => shrink_fractional!(F32, n),
+        value::Number::F64(n) => shrink_fractional!(F64, n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agreement() {
+        let expression = Expression::bi_op(
+            ty::Number::U32,
+            Expression::Literal(value::Number::U32(40)),
+            ast::BiOperator::Add,
+            Expression::Literal(value::Number::U32(2)),
+        );
+
+        assert!(check_expression(expression).is_none());
+    }
+
+    #[test]
+    fn fractional_remainder() {
+        let expression = Expression::bi_op(
+            ty::Number::F64,
+            Expression::bi_op(
+                ty::Number::F64,
+                Expression::Literal(value::Number::F64(7.5)),
+                ast::BiOperator::Rem,
+                Expression::Literal(value::Number::F64(2.0)),
+            ),
+            ast::BiOperator::Add,
+            Expression::Literal(value::Number::F64(3.0)),
+        );
+
+        assert!(check_expression(expression).is_none());
+    }
+
+    #[test]
+    fn overflowing_division() {
+        let expression = Expression::bi_op(
+            ty::Number::I32,
+            Expression::Literal(value::Number::I32(i32::min_value())),
+            ast::BiOperator::Div,
+            Expression::Literal(value::Number::I32(-1)),
+        );
+
+        assert!(check_expression(expression).is_none());
+    }
+
+    #[test]
+    fn nan_comparisons() {
+        for &ty in &[ty::Number::F32, ty::Number::F64] {
+            let nan = || {
+                Expression::bi_op(
+                    ty,
+                    Expression::Literal(fuzz::zero(ty)),
+                    ast::BiOperator::Div,
+                    Expression::Literal(fuzz::zero(ty)),
+                )
+            };
+
+            for &operator in fuzz::COMPARISON_BI_OPERATORS {
+                let zero = Expression::Literal(fuzz::zero(ty));
+                for (lhs, rhs) in vec![(nan(), nan()), (nan(), zero)] {
+                    let expression = Expression::comparison(lhs, operator, rhs);
+
+                    // NaN is unordered, so only `!=` holds
+                    match evaluate(&expression.render(Mode::Constant)) {
+                        Outcome::Value(ref value) => {
+                            assert_eq!(value::Value::from(operator == ast::BiOperator::Ne), *value)
+                        }
+                        ref outcome => panic!("unexpected outcome: {}", outcome),
+                    }
+                    assert!(check_expression(expression).is_none());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn error_kinds() {
+        let expression = Expression::bi_op(
+            ty::Number::U8,
+            Expression::Literal(value::Number::U8(1)),
+            ast::BiOperator::Rem,
+            Expression::Literal(value::Number::U8(0)),
+        );
+
+        assert!(check_expression(expression).is_none());
+
+        let division_by_zero = Outcome::Error(module::ErrorKind::IntegerDivisonByZero);
+        let overflow = Outcome::Error(module::ErrorKind::IntegerOverflow);
+        let panic = Outcome::Panic("oops".to_owned());
+        let rejected = Outcome::Rejected("type error".to_owned());
+
+        assert!(division_by_zero.agrees_with(&division_by_zero));
+        assert!(!division_by_zero.agrees_with(&overflow));
+        assert!(!panic.agrees_with(&panic));
+        assert!(!rejected.agrees_with(&rejected));
+    }
+
+    #[test]
+    fn random_expressions() {
+        let mut disagreements = Vec::new();
+
+        // Only a small number of seeds, to keep the test fast; fuzzing explores further
+        for seed in 0..100 {
+            let mut entropy = fuzz::Entropy::from_seed(seed);
+            if let Some(disagreement) = check(&mut entropy) {
+                disagreements.push(disagreement.to_string());
+            }
+        }
+
+        assert!(
+            disagreements.is_empty(),
+            "found disagreements:\n{}",
+            disagreements.join("\n")
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Tools for randomised testing of Tin itself.
+//!
+//! This module is only available with the `fuzzing` feature, and is used by the `tin-fuzz-target`
+//! crate as well as by the test suite.
+use crate::ast;
+use crate::ty;
+use crate::value;
+
+pub mod differential;
+pub mod program;
+
+const NUMBERS: &[ty::Number] = &[
+    ty::Number::U8,
+    ty::Number::U16,
+    ty::Number::U32,
+    ty::Number::U64,
+    ty::Number::I8,
+    ty::Number::I16,
+    ty::Number::I32,
+    ty::Number::I64,
+    ty::Number::F32,
+    ty::Number::F64,
+];
+
+const INTEGRALS: &[ty::Number] = &[
+    ty::Number::U8,
+    ty::Number::U16,
+    ty::Number::U32,
+    ty::Number::U64,
+    ty::Number::I8,
+    ty::Number::I16,
+    ty::Number::I32,
+    ty::Number::I64,
+];
+
+/// Unary operators that map an integral number to a number of the same type.
+const INTEGRAL_UN_OPERATORS: &[ast::UnOperator] = &[ast::UnOperator::BNot];
+/// Unary operators that map an integral number to an `u32` bit count.
+const COUNT_UN_OPERATORS: &[ast::UnOperator] = &[
+    ast::UnOperator::Cl0,
+    ast::UnOperator::Cl1,
+    ast::UnOperator::Cls,
+    ast::UnOperator::Ct0,
+    ast::UnOperator::Ct1,
+    ast::UnOperator::C0,
+    ast::UnOperator::C1,
+];
+/// Unary operators that map a fractional number to a number of the same type.
+const FRACTIONAL_UN_OPERATORS: &[ast::UnOperator] = &[ast::UnOperator::Sqrt];
+/// Binary operators that take two numbers of the same type and produce that type.
+const ARITHMETIC_BI_OPERATORS: &[ast::BiOperator] = &[
+    ast::BiOperator::Add,
+    ast::BiOperator::Sub,
+    ast::BiOperator::Mul,
+    ast::BiOperator::Div,
+    ast::BiOperator::Rem,
+];
+/// Binary operators that take two integral numbers of the same type and produce that type.
+const BITWISE_BI_OPERATORS: &[ast::BiOperator] = &[
+    ast::BiOperator::BAnd,
+    ast::BiOperator::BAndNot,
+    ast::BiOperator::BOr,
+    ast::BiOperator::BOrNot,
+    ast::BiOperator::BXor,
+    ast::BiOperator::BXorNot,
+];
+/// Binary operators that compare two numbers of the same type, and produce `:f` or `:t`.
+const COMPARISON_BI_OPERATORS: &[ast::BiOperator] = &[
+    ast::BiOperator::Eq,
+    ast::BiOperator::Ne,
+    ast::BiOperator::Lt,
+    ast::BiOperator::Ge,
+    ast::BiOperator::Gt,
+    ast::BiOperator::Le,
+];
+/// Binary operators that take an integral number and an `u32` amount, and produce the former type.
+const SHIFT_BI_OPERATORS: &[ast::BiOperator] = &[
+    ast::BiOperator::RotL,
+    ast::BiOperator::RotR,
+    ast::BiOperator::ShL,
+    ast::BiOperator::ShR,
+];
+
+/// A source of random decisions.
+///
+/// Entropy can either come from a fuzzer, in which case the same input always results in the same
+/// decisions, or from a seeded pseudo-random number generator.  Once the bytes from a fuzzer run
+/// out, all further decisions pick the first alternative, so that generation always terminates.
+#[derive(Clone, Copy, Debug)]
+pub struct Entropy<'a> {
+    source: Source<'a>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Source<'a> {
+    Bytes(&'a [u8]),
+    Seed(u64),
+}
+
+impl<'a> Entropy<'a> {
+    /// Creates entropy that is read from the specified bytes, for example fuzzer input.
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        let source = Source::Bytes(bytes);
+        Entropy { source }
+    }
+
+    /// Creates entropy that is generated from the specified seed.
+    pub fn from_seed(seed: u64) -> Self {
+        // The xorshift generator gets stuck at zero, so avoid that state.
+        let source = Source::Seed(seed | 1);
+        Entropy { source }
+    }
+
+    /// Returns the next random byte.
+    pub fn byte(&mut self) -> u8 {
+        match self.source {
+            Source::Bytes(ref mut bytes) => {
+                if let Some((first, rest)) = bytes.split_first() {
+                    *bytes = rest;
+                    *first
+                } else {
+                    0
+                }
+            }
+            Source::Seed(ref mut state) => {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                #[allow(clippy::cast_possible_truncation)]
+                let byte = (*state >> 24) as u8;
+                byte
+            }
+        }
+    }
+
+    /// Returns the next random 64-bit number.
+    pub fn u64(&mut self) -> u64 {
+        (0..8).fold(0, |acc, _| (acc << 8) | u64::from(self.byte()))
+    }
+
+    /// Returns a random number that is less than `bound`, which must not be zero.
+    pub fn below(&mut self, bound: usize) -> usize {
+        if bound <= 0x100 {
+            usize::from(self.byte()) % bound
+        } else {
+            #[allow(clippy::cast_possible_truncation)]
+            let value = self.u64() as usize;
+            value % bound
+        }
+    }
+
+    /// Returns `true` with a probability of roughly `numerator / 256`.
+    pub fn chance(&mut self, numerator: u8) -> bool {
+        self.byte() < numerator
+    }
+
+    /// Picks a random element from the specified non-empty slice.
+    pub fn choose<'b, A>(&mut self, items: &'b [A]) -> &'b A {
+        &items[self.below(items.len())]
+    }
+}
+
+fn is_integral(ty: ty::Number) -> bool {
+    INTEGRALS.contains(&ty)
+}
+
+fn zero(ty: ty::Number) -> value::Number {
+    integral(ty, 0)
+}
+
+/// Creates a number of the specified type from a bit pattern, truncating it as necessary.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_precision_loss
+)]
+fn integral(ty: ty::Number, bits: u64) -> value::Number {
+    match ty {
+        ty::Number::U8 => value::Number::U8(bits as u8),
+        ty::Number::U16 => value::Number::U16(bits as u16),
+        ty::Number::U32 => value::Number::U32(bits as u32),
+        ty::Number::U64 => value::Number::U64(bits),
+        ty::Number::I8 => value::Number::I8(bits as i8),
+        ty::Number::I16 => value::Number::I16(bits as i16),
+        ty::Number::I32 => value::Number::I32(bits as i32),
+        ty::Number::I64 => value::Number::I64(bits as i64),
+        ty::Number::F32 => value::Number::F32(bits as i64 as f32),
+        ty::Number::F64 => value::Number::F64(bits as i64 as f64),
+    }
+}
+
+/// Generates a literal, with a bias towards edge cases like zero, one and the extreme values.
+fn literal(entropy: &mut Entropy, ty: ty::Number) -> value::Number {
+    match entropy.below(8) {
+        0 => zero(ty),
+        1 => integral(ty, 1),
+        2 => integral(ty, u64::max_value()),
+        3 => extreme(ty),
+        4 if ty == ty::Number::F32 => value::Number::F32(f32::from(entropy.byte()) / 16.0),
+        4 if ty == ty::Number::F64 => value::Number::F64(f64::from(entropy.byte()) / 16.0),
+        4 | 5 => integral(ty, u64::from(entropy.byte())),
+        _ => integral(ty, entropy.u64()),
+    }
+}
+
+/// The largest unsigned or smallest signed value of the specified type.
+fn extreme(ty: ty::Number) -> value::Number {
+    match ty {
+        ty::Number::U8 => value::Number::U8(u8::max_value()),
+        ty::Number::U16 => value::Number::U16(u16::max_value()),
+        ty::Number::U32 => value::Number::U32(u32::max_value()),
+        ty::Number::U64 => value::Number::U64(u64::max_value()),
+        ty::Number::I8 => value::Number::I8(i8::min_value()),
+        ty::Number::I16 => value::Number::I16(i16::min_value()),
+        ty::Number::I32 => value::Number::I32(i32::min_value()),
+        ty::Number::I64 => value::Number::I64(i64::min_value()),
+        ty::Number::F32 => value::Number::F32(std::f32::MAX),
+        ty::Number::F64 => value::Number::F64(std::f64::MAX),
+    }
+}
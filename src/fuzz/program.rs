@@ -0,0 +1,495 @@
+//! Generation of random Tin programs that are well-typed by construction.
+//!
+//! Random bytes almost never make it past the parser, and random token streams almost never make
+//! it past the type checker.  This module instead builds an AST from random decisions, only ever
+//! picking constructs that will type check, so that the later stages of the compiler (like layout
+//! inference and code generation) get to see many different programs.
+use std::collections;
+
+use crate::ast;
+use crate::fuzz;
+use crate::ty;
+use crate::value;
+
+/// The maximum depth of generated expressions.
+const MAX_DEPTH: usize = 4;
+/// The maximum depth of generated tuple and record types.
+const MAX_TYPE_DEPTH: usize = 2;
+/// The maximum depth of functions defined within other functions.
+const MAX_NESTING: usize = 2;
+const MAX_DEFINITIONS: usize = 6;
+const MAX_STATEMENTS: usize = 4;
+const MAX_PARAMETERS: usize = 3;
+const MAX_TUPLE_FIELDS: usize = 3;
+const RECORD_FIELDS: &[&str] = &["a", "b", "c", "d"];
+
+/// Generates the source code of a random well-typed module.
+///
+/// The module always ends with a `main` function that takes no parameters and returns an `i32`.
+pub fn generate(entropy: &mut fuzz::Entropy) -> String {
+    module(entropy).to_string()
+}
+
+/// Generates a random well-typed module.
+pub(crate) fn module(entropy: &mut fuzz::Entropy) -> ast::Module<()> {
+    Generator {
+        entropy,
+        next_name: 0,
+    }
+    .module()
+}
+
+struct Generator<'a, 'b: 'a> {
+    entropy: &'a mut fuzz::Entropy<'b>,
+    next_name: usize,
+}
+
+/// The definitions that can be referred to from an expression.
+///
+/// Functions are only ever applied directly by name, and are never passed around as values.
+#[derive(Clone, Debug, Default)]
+struct Scope {
+    values: Vec<(String, ty::Type)>,
+    functions: Vec<(String, ty::Function)>,
+}
+
+impl<'a, 'b> Generator<'a, 'b> {
+    fn module(&mut self) -> ast::Module<()> {
+        let mut scope = Scope::default();
+        let mut variables = Vec::new();
+
+        for _ in 0..self.entropy.below(MAX_DEFINITIONS) {
+            if self.entropy.chance(0x80) {
+                // Top-level constants may only refer to other constants, so that they can be
+                // evaluated at compile time.
+                let constants = Scope {
+                    values: scope.values.clone(),
+                    functions: Vec::new(),
+                };
+                let ty = self.value_type(MAX_TYPE_DEPTH);
+                let initializer = self.expression(&constants, &ty, MAX_DEPTH);
+                let name = self.fresh_name("constant");
+                scope.values.push((name.clone(), ty));
+                variables.push(variable(name, initializer));
+            } else {
+                let (initializer, function) = self.function(&scope, MAX_NESTING);
+                let name = self.fresh_name("function");
+                scope.functions.push((name.clone(), function));
+                variables.push(variable(name, initializer));
+            }
+        }
+
+        let main = self.lambda(
+            &scope,
+            Vec::new(),
+            &ty::Type::Number(ty::Number::I32),
+            MAX_NESTING,
+        );
+        variables.push(variable("main".to_owned(), main));
+
+        ast::Module {
+            context: (),
+            variables,
+            comments: Vec::new(),
+        }
+    }
+
+    fn function(&mut self, scope: &Scope, nesting: usize) -> (ast::Expression<()>, ty::Function) {
+        let parameters = (0..self.entropy.below(MAX_PARAMETERS + 1))
+            .map(|_| (self.fresh_name("param"), self.value_type(MAX_TYPE_DEPTH)))
+            .collect::<Vec<_>>();
+        let result = self.value_type(MAX_TYPE_DEPTH);
+
+        let function = ty::Function {
+            parameters: parameters.iter().map(|(_, ty)| ty.clone()).collect(),
+            result: Box::new(result.clone()),
+        };
+        let lambda = self.lambda(scope, parameters, &result, nesting);
+
+        (lambda, function)
+    }
+
+    fn lambda(
+        &mut self,
+        scope: &Scope,
+        parameters: Vec<(String, ty::Type)>,
+        result: &ty::Type,
+        nesting: usize,
+    ) -> ast::Expression<()> {
+        let mut scope = scope.clone();
+        scope.values.extend(parameters.iter().cloned());
+
+        let mut statements = Vec::new();
+        for _ in 0..self.entropy.below(MAX_STATEMENTS + 1) {
+            if nesting == 0 || self.entropy.chance(0xc0) {
+                let ty = self.value_type(MAX_TYPE_DEPTH);
+                let initializer = self.expression(&scope, &ty, MAX_DEPTH);
+                let name = self.fresh_name("value");
+                scope.values.push((name.clone(), ty));
+                statements.push(ast::Statement::Variable(variable(name, initializer)));
+            } else {
+                // Local functions capture the parameters and variables that were defined so far.
+                let (initializer, function) = self.function(&scope, nesting - 1);
+                let name = self.fresh_name("function");
+                scope.functions.push((name.clone(), function));
+                statements.push(ast::Statement::Variable(variable(name, initializer)));
+            }
+        }
+
+        let result_expression = self.expression(&scope, result, MAX_DEPTH);
+
+        let parameters = parameters
+            .into_iter()
+            .map(|(name, ty)| ast::Parameter {
+                context: (),
+                name: identifier(name),
+                signature: signature(&ty),
+            })
+            .collect();
+
+        ast::Expression::Lambda(ast::Lambda {
+            context: (),
+            parameters,
+            signature: Box::new(signature(result)),
+            statements,
+            result: Some(Box::new(result_expression)),
+        })
+    }
+
+    fn value_type(&mut self, depth: usize) -> ty::Type {
+        match if depth == 0 { 0 } else { self.entropy.below(4) } {
+            0 | 1 => ty::Type::Number(*self.entropy.choose(fuzz::NUMBERS)),
+            2 => {
+                let fields = (0..self.entropy.below(MAX_TUPLE_FIELDS + 1))
+                    .map(|_| self.value_type(depth - 1))
+                    .collect();
+                ty::Type::Tuple(ty::Tuple { fields })
+            }
+            _ => {
+                let mut fields = collections::HashMap::new();
+                for name in RECORD_FIELDS {
+                    if self.entropy.chance(0x80) {
+                        fields.insert((*name).to_owned(), self.value_type(depth - 1));
+                    }
+                }
+                ty::Type::Record(ty::Record { fields })
+            }
+        }
+    }
+
+    fn expression(&mut self, scope: &Scope, ty: &ty::Type, depth: usize) -> ast::Expression<()> {
+        if depth > 0 {
+            let expression = match self.entropy.below(4) {
+                1 => self.reference(scope, ty),
+                2 => self.apply(scope, ty, depth - 1),
+                3 => self.select(scope, ty),
+                _ => None,
+            };
+
+            if let Some(expression) = expression {
+                return expression;
+            }
+        }
+
+        match *ty {
+            ty::Type::Number(number) => self.number(scope, number, depth),
+            ty::Type::Tuple(ref tuple) => ast::Expression::Tuple(ast::Tuple {
+                context: (),
+                fields: tuple
+                    .fields
+                    .iter()
+                    .map(|field| self.expression(scope, field, depth.saturating_sub(1)))
+                    .collect(),
+            }),
+            ty::Type::Record(ref record) => {
+                let mut names = record.fields.keys().collect::<Vec<_>>();
+                names.sort_unstable();
+                ast::Expression::Record(ast::Record {
+                    context: (),
+                    fields: names
+                        .into_iter()
+                        .map(|name| {
+                            let value = self.expression(
+                                scope,
+                                &record.fields[name],
+                                depth.saturating_sub(1),
+                            );
+                            (identifier(name.clone()), value)
+                        })
+                        .collect(),
+                })
+            }
+            _ => unreachable!("only numbers, tuples and records are generated as values"),
+        }
+    }
+
+    fn reference(&mut self, scope: &Scope, ty: &ty::Type) -> Option<ast::Expression<()>> {
+        let candidates = scope
+            .values
+            .iter()
+            .filter(|(_, t)| t == ty)
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            None
+        } else {
+            let (name, _) = self.entropy.choose(&candidates);
+            Some(ast::Expression::Identifier(identifier(name.clone())))
+        }
+    }
+
+    fn apply(&mut self, scope: &Scope, ty: &ty::Type, depth: usize) -> Option<ast::Expression<()>> {
+        let candidates = scope
+            .functions
+            .iter()
+            .filter(|(_, f)| *f.result == *ty)
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            None
+        } else {
+            let (name, function) = self.entropy.choose(&candidates);
+            let parameters = function
+                .parameters
+                .iter()
+                .map(|parameter| self.expression(scope, parameter, depth))
+                .collect();
+
+            Some(ast::Expression::Apply(ast::Apply {
+                context: (),
+                function: Box::new(ast::Expression::Identifier(identifier(name.clone()))),
+                parameters,
+            }))
+        }
+    }
+
+    fn select(&mut self, scope: &Scope, ty: &ty::Type) -> Option<ast::Expression<()>> {
+        let candidates = scope
+            .values
+            .iter()
+            .filter_map(|(name, t)| match *t {
+                ty::Type::Record(ref record) => Some((name, record)),
+                _ => None,
+            })
+            .flat_map(|(name, record)| {
+                let mut fields = record
+                    .fields
+                    .iter()
+                    .filter(|(_, t)| *t == ty)
+                    .map(|(field, _)| (name, field))
+                    .collect::<Vec<_>>();
+                fields.sort_unstable();
+                fields
+            })
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            None
+        } else {
+            let (name, field) = self.entropy.choose(&candidates);
+            Some(ast::Expression::Select(ast::Select {
+                context: (),
+                record: Box::new(ast::Expression::Identifier(identifier((*name).clone()))),
+                field: identifier((*field).clone()),
+            }))
+        }
+    }
+
+    fn number(&mut self, scope: &Scope, ty: ty::Number, depth: usize) -> ast::Expression<()> {
+        // A zero byte always means a literal, so that generation terminates once fuzzer input runs
+        // out.
+        if depth == 0 || self.entropy.byte() < 0x40 {
+            let value = number_value(fuzz::literal(self.entropy, ty));
+            return ast::Expression::NumberLiteral(ast::NumberLiteral { context: (), value });
+        }
+
+        let depth = depth - 1;
+        let operand =
+            |generator: &mut Self, ty| generator.expression(scope, &ty::Type::Number(ty), depth);
+
+        match self.entropy.below(3) {
+            0 if fuzz::is_integral(ty) => {
+                let operator = *self.entropy.choose(fuzz::INTEGRAL_UN_OPERATORS);
+                un_op(operator, operand(self, ty))
+            }
+            0 => {
+                let operator = *self.entropy.choose(fuzz::FRACTIONAL_UN_OPERATORS);
+                un_op(operator, operand(self, ty))
+            }
+            1 if fuzz::is_integral(ty) && self.entropy.chance(0x80) => {
+                let operator = *self.entropy.choose(fuzz::SHIFT_BI_OPERATORS);
+                let lhs = operand(self, ty);
+                let rhs = operand(self, ty::Number::U32);
+                bi_op(lhs, operator, rhs)
+            }
+            1 if fuzz::is_integral(ty) => {
+                let operator = *self.entropy.choose(fuzz::BITWISE_BI_OPERATORS);
+                let lhs = operand(self, ty);
+                let rhs = operand(self, ty);
+                bi_op(lhs, operator, rhs)
+            }
+            _ if ty == ty::Number::U32 && self.entropy.chance(0x40) => {
+                let operator = *self.entropy.choose(fuzz::COUNT_UN_OPERATORS);
+                let operand_ty = *self.entropy.choose(fuzz::INTEGRALS);
+                un_op(operator, operand(self, operand_ty))
+            }
+            _ => {
+                let operator = *self.entropy.choose(fuzz::ARITHMETIC_BI_OPERATORS);
+                let lhs = operand(self, ty);
+                let rhs = operand(self, ty);
+                bi_op(lhs, operator, rhs)
+            }
+        }
+    }
+
+    fn fresh_name(&mut self, prefix: &str) -> String {
+        let name = format!("{}{}", prefix, self.next_name);
+        self.next_name += 1;
+        name
+    }
+}
+
+/// Creates an expression that evaluates to a value of the specified type, for use as a signature.
+fn signature(ty: &ty::Type) -> ast::Expression<()> {
+    match *ty {
+        ty::Type::Number(number) => ast::Expression::NumberLiteral(ast::NumberLiteral {
+            context: (),
+            value: number_value(fuzz::zero(number)),
+        }),
+        ty::Type::Tuple(ref tuple) => ast::Expression::Tuple(ast::Tuple {
+            context: (),
+            fields: tuple.fields.iter().map(signature).collect(),
+        }),
+        ty::Type::Record(ref record) => {
+            let mut fields = record.fields.iter().collect::<Vec<_>>();
+            fields.sort_unstable_by_key(|(name, _)| *name);
+            ast::Expression::Record(ast::Record {
+                context: (),
+                fields: fields
+                    .into_iter()
+                    .map(|(name, ty)| (identifier(name.clone()), signature(ty)))
+                    .collect(),
+            })
+        }
+        _ => unreachable!("only numbers, tuples and records are generated as values"),
+    }
+}
+
+fn variable(name: String, initializer: ast::Expression<()>) -> ast::Variable<()> {
+    ast::Variable {
+        context: (),
+        name: identifier(name),
+        initializer,
+    }
+}
+
+fn identifier(value: String) -> ast::Identifier<()> {
+    ast::Identifier { context: (), value }
+}
+
+fn un_op(operator: ast::UnOperator, operand: ast::Expression<()>) -> ast::Expression<()> {
+    ast::Expression::UnOp(ast::UnOp {
+        context: (),
+        operator,
+        operand: Box::new(operand),
+    })
+}
+
+fn bi_op(
+    lhs: ast::Expression<()>,
+    operator: ast::BiOperator,
+    rhs: ast::Expression<()>,
+) -> ast::Expression<()> {
+    ast::Expression::BiOp(ast::BiOp {
+        context: (),
+        lhs: Box::new(lhs),
+        operator,
+        rhs: Box::new(rhs),
+    })
+}
+
+fn number_value(number: value::Number) -> ast::NumberValue {
+    match number {
+        value::Number::U8(n) => ast::NumberValue::U8(n),
+        value::Number::U16(n) => ast::NumberValue::U16(n),
+        value::Number::U32(n) => ast::NumberValue::U32(n),
+        value::Number::U64(n) => ast::NumberValue::U64(n),
+        value::Number::I8(n) => ast::NumberValue::I8(n),
+        value::Number::I16(n) => ast::NumberValue::I16(n),
+        value::Number::I32(n) => ast::NumberValue::I32(n),
+        value::Number::I64(n) => ast::NumberValue::I64(n),
+        value::Number::F32(n) => ast::NumberValue::F32(n),
+        value::Number::F64(n) => ast::NumberValue::F64(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::sync;
+
+    use crate::ast;
+    use crate::ast::MapContext;
+    use crate::fuzz;
+    use crate::ir;
+    use crate::parser;
+
+    #[test]
+    fn round_trip() {
+        for seed in 0..100 {
+            let expected = super::module(&mut fuzz::Entropy::from_seed(seed));
+            let (actual, _) = parse(&expected.to_string());
+            let actual = actual.map_context(&mut |_| ());
+            assert_eq!(expected, actual, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn well_typed() {
+        for seed in 0..100 {
+            let (module, file) = parse(&super::generate(&mut fuzz::Entropy::from_seed(seed)));
+            let mut ir = ir::Ir::new();
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                ir.load(&module, &file)?;
+                ir.check_types()
+            }));
+
+            // Evaluating generated constants might fail, for example on division by zero, but
+            // there must never be any type errors or undefined references.
+            match result {
+                Ok(Err(ref error)) if is_ill_typed(error) => {
+                    panic!("seed {} generated an ill-typed module:\n{}", seed, module)
+                }
+                Ok(_) => {}
+                Err(_) => panic!("seed {} panicked while checking types:\n{}", seed, module),
+            }
+        }
+    }
+
+    /// Whether an error means that a module is ill-typed, as opposed to evaluating one of its
+    /// constants having failed.
+    fn is_ill_typed(error: &ir::error::Error) -> bool {
+        match *error {
+            ir::error::Error::Type(..) | ir::error::Error::UndefinedReference { .. } => true,
+            ir::error::Error::Multiple { ref errors } => errors.iter().any(is_ill_typed),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn exhausted() {
+        let module = super::generate(&mut fuzz::Entropy::from_bytes(&[]));
+        assert_eq!("main = || -> 0i32 { 0i32 };\n", module);
+    }
+
+    fn parse(source: &str) -> (ast::Module<parser::Context>, sync::Arc<codespan::FileMap>) {
+        use crate::parser::Parse;
+
+        let mut codemap = codespan::CodeMap::new();
+        let file = codemap.add_filemap(
+            codespan::FileName::Virtual("generated".into()),
+            source.to_owned(),
+        );
+        (ast::Module::parse(file.span(), source).unwrap(), file)
+    }
+}
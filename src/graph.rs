@@ -3,6 +3,7 @@
 //! Use this module to diagnose errors encountered in a piece of code.  The graph representation
 //! aims to provide all of the information available to the Tin compiler.
 use std::borrow;
+use std::collections;
 use std::fmt;
 
 use dot;
@@ -21,6 +22,7 @@ pub struct Graph<'a> {
     layouts: specs::ReadStorage<'a, layout::Layout>,
     symbols: specs::ReadStorage<'a, symbol::Symbol>,
     types: specs::ReadStorage<'a, ty::Type>,
+    included: Option<collections::HashSet<specs::Entity>>,
 }
 
 /// A node in the IR graph.
@@ -43,6 +45,12 @@ enum Label<'a> {
     SelectField(&'a str),
     AppliedFunction,
     AppliedParameter(usize),
+    FailMessage,
+    AssertCondition,
+    AssertMessage,
+    TryBody,
+    TryError,
+    TryHandler,
     ParameterSignature,
     ClosureCaptureDefinition(&'a str),
     ClosureCaptureUsage(&'a str),
@@ -65,6 +73,7 @@ impl<'a> Graph<'a> {
         let layouts = world.read_storage();
         let symbols = world.read_storage();
         let types = world.read_storage();
+        let included = None;
 
         Graph {
             entities,
@@ -72,8 +81,28 @@ impl<'a> Graph<'a> {
             layouts,
             symbols,
             types,
+            included,
         }
     }
+
+    /// Creates a graph of only the top-level definition with the specified name, and everything
+    /// that it refers to.
+    ///
+    /// Returns `None` if there is no such definition.
+    pub(crate) fn of_definition(ir: &'a ir::Ir, name: &str) -> Option<Graph<'a>> {
+        let included = ir.definition_entities(name)?;
+
+        Some(Graph {
+            included: Some(included),
+            ..Graph::new(ir)
+        })
+    }
+
+    fn includes(&self, entity: specs::Entity) -> bool {
+        self.included
+            .as_ref()
+            .map_or(true, |included| included.contains(&entity))
+    }
 }
 
 impl<'a> dot::GraphWalk<'a, Node, Edge<'a>> for Graph<'a> {
@@ -84,7 +113,7 @@ impl<'a> dot::GraphWalk<'a, Node, Edge<'a>> for Graph<'a> {
             self.entities
                 .join()
                 .filter_map(|e| {
-                    if self.elements.contains(e) {
+                    if self.elements.contains(e) && self.includes(e) {
                         Some(Node(e))
                     } else {
                         None
@@ -99,7 +128,7 @@ impl<'a> dot::GraphWalk<'a, Node, Edge<'a>> for Graph<'a> {
 
         let mut edges = Vec::new();
 
-        for entity in self.entities.join() {
+        for entity in self.entities.join().filter(|&e| self.includes(e)) {
             if let Some(element) = self.elements.get(entity) {
                 match element {
                     element::Element::Number(_)
@@ -172,6 +201,46 @@ impl<'a> dot::GraphWalk<'a, Node, Edge<'a>> for Graph<'a> {
                             });
                         }
                     }
+                    element::Element::Fail(element::Fail { message }) => edges.push(Edge {
+                        source: Node(entity),
+                        target: Node(*message),
+                        label: Label::FailMessage,
+                    }),
+                    element::Element::Assert(element::Assert {
+                        condition, message, ..
+                    }) => {
+                        edges.push(Edge {
+                            source: Node(entity),
+                            target: Node(*condition),
+                            label: Label::AssertCondition,
+                        });
+                        edges.push(Edge {
+                            source: Node(entity),
+                            target: Node(*message),
+                            label: Label::AssertMessage,
+                        });
+                    }
+                    element::Element::Try(element::Try {
+                        body,
+                        error,
+                        handler,
+                    }) => {
+                        edges.push(Edge {
+                            source: Node(entity),
+                            target: Node(*body),
+                            label: Label::TryBody,
+                        });
+                        edges.push(Edge {
+                            source: Node(entity),
+                            target: Node(*error),
+                            label: Label::TryError,
+                        });
+                        edges.push(Edge {
+                            source: Node(entity),
+                            target: Node(*handler),
+                            label: Label::TryHandler,
+                        });
+                    }
                     element::Element::Parameter(element::Parameter { signature, .. }) => {
                         edges.push(Edge {
                             source: Node(entity),
@@ -295,6 +364,14 @@ impl<'a> dot::Labeller<'a, Node, Edge<'a>> for Graph<'a> {
                 element::Element::Apply(element::Apply { parameters, .. }) => {
                     write!(result, "apply <br/> <b>{:?}</b> params", parameters.len()).unwrap()
                 }
+                element::Element::Fail(element::Fail { .. }) => write!(result, "fail").unwrap(),
+                element::Element::Assert(element::Assert { .. }) => {
+                    write!(result, "assert").unwrap()
+                }
+                element::Element::Try(element::Try { .. }) => write!(result, "try").unwrap(),
+                element::Element::CaughtError(element::CaughtError { name }) => {
+                    write!(result, "caught error <b>{:?}</b>", name).unwrap()
+                }
                 element::Element::Parameter(element::Parameter { name, .. }) => {
                     write!(result, "param <b>{:?}</b>", name).unwrap()
                 }
@@ -359,6 +436,12 @@ impl<'a> dot::Labeller<'a, Node, Edge<'a>> for Graph<'a> {
             Label::AppliedParameter(idx) => {
                 dot::LabelText::HtmlStr(format!("apply param <b>{}</b>", idx).into())
             }
+            Label::FailMessage => dot::LabelText::LabelStr("message".into()),
+            Label::AssertCondition => dot::LabelText::LabelStr("condition".into()),
+            Label::AssertMessage => dot::LabelText::LabelStr("message".into()),
+            Label::TryBody => dot::LabelText::LabelStr("body".into()),
+            Label::TryError => dot::LabelText::LabelStr("error".into()),
+            Label::TryHandler => dot::LabelText::LabelStr("handler".into()),
             Label::ParameterSignature => dot::LabelText::LabelStr("param sig".into()),
             Label::ClosureCaptureDefinition(ref name) => {
                 dot::LabelText::HtmlStr(format!("capture definition <b>{}</b>", name).into())
@@ -391,6 +474,11 @@ impl<'a> dot::Labeller<'a, Node, Edge<'a>> for Graph<'a> {
             | Label::SelectField(_)
             | Label::AppliedFunction
             | Label::AppliedParameter(_)
+            | Label::FailMessage
+            | Label::AssertCondition
+            | Label::AssertMessage
+            | Label::TryBody
+            | Label::TryHandler
             | Label::ClosureCaptureUsage(_)
             | Label::ClosureParameter(_)
             | Label::ClosureResult
@@ -399,7 +487,9 @@ impl<'a> dot::Labeller<'a, Node, Edge<'a>> for Graph<'a> {
             | Label::BiLhs
             | Label::BiRhs => dot::Style::None,
             Label::ParameterSignature | Label::ClosureSignature => dot::Style::Dotted,
-            Label::ClosureCaptureDefinition(_) | Label::ClosureStatement(_) => dot::Style::Dashed,
+            Label::TryError | Label::ClosureCaptureDefinition(_) | Label::ClosureStatement(_) => {
+                dot::Style::Dashed
+            }
         }
     }
 }
@@ -0,0 +1,223 @@
+//! Definitions for interpreted modules.
+//!
+//! An interpreted module has the same API as a compiled [`module::Module`], but runs code by
+//! walking the intermediate representation instead of generating machine code.  This makes it
+//! usable on platforms where executable memory is forbidden, and avoids the cost of code
+//! generation for code that only runs a few times.
+use std::collections;
+use std::fmt;
+use std::marker;
+use std::sync;
+
+use tin_runtime;
+
+use crate::interpreter::program;
+use crate::ir;
+use crate::module;
+use crate::value;
+
+/// An interpreted module, the result of an invocation of `Tin::interpret`.
+pub struct Module {
+    program: sync::Arc<program::Program>,
+    state: sync::Arc<tin_runtime::State>,
+    functions: collections::HashMap<String, program::Closure>,
+    exports: Vec<module::Export>,
+    constants: collections::HashMap<String, value::Value>,
+}
+
+/// A function of an interpreted [`Module`], before it has been given a signature.
+#[derive(Clone, Debug)]
+pub struct Callable {
+    program: sync::Arc<program::Program>,
+    state: sync::Arc<tin_runtime::State>,
+    closure: program::Closure,
+}
+
+/// A function that is exported from an interpreted [`Module`].
+pub trait Function: Sized {
+    /// Creates a new function from a callable.
+    ///
+    /// Returns `None` if the callable takes a different number of parameters than this function.
+    fn from_callable(callable: Callable) -> Option<Self>;
+}
+
+macro_rules! define_function {
+    ($name:ident, $doc:expr, $arity:expr, $ret:ident) => {
+        define_function!($name, $doc, $arity, $ret,);
+    };
+    ($name:ident, $doc:expr, $arity:expr, $ret:ident, $($argn:ident: $argt:ident),*) => {
+        #[doc=$doc]
+        pub struct $name<$ret, $($argt),*>(Callable, marker::PhantomData<fn($($argt),*) -> $ret>);
+
+        impl<$ret, $($argt),*> $name<$ret, $($argt),*>
+        where
+            $ret: value::FromValue,
+            $($argt: Into<value::Value>,)*
+        {
+            /// Call the underlying function.
+            ///
+            /// If the result can not be converted into the return type of this function, an
+            /// `ErrorKind::Unknown` error is returned.
+            pub fn call(&self, $($argn: $argt),*) -> Result<$ret, module::Error> {
+                let result = self.0.call(vec![$($argn.into()),*])?;
+                <$ret as value::FromValue>::from_value(&result)
+                    .ok_or_else(|| module::Error::new(module::ErrorKind::Unknown))
+            }
+        }
+
+        impl<$ret, $($argt),*> Function for $name<$ret, $($argt),*> {
+            fn from_callable(callable: Callable) -> Option<Self> {
+                if callable.arity() == $arity {
+                    Some($name(callable, marker::PhantomData))
+                } else {
+                    None
+                }
+            }
+        }
+
+        impl<$ret, $($argt),*> fmt::Debug for $name<$ret, $($argt),*> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_struct(stringify!($name)).finish()
+            }
+        }
+    };
+}
+
+define_function!(Function0, "A function taking 0 arguments", 0, R);
+define_function!(Function1, "A function taking 1 arguments", 1, R, a1: A1);
+define_function!(
+    Function2,
+    "A function taking 2 arguments",
+    2,
+    R,
+    a1: A1,
+    a2: A2
+);
+define_function!(
+    Function3,
+    "A function taking 3 arguments",
+    3,
+    R,
+    a1: A1,
+    a2: A2,
+    a3: A3
+);
+define_function!(
+    Function4,
+    "A function taking 4 arguments",
+    4,
+    R,
+    a1: A1,
+    a2: A2,
+    a3: A3,
+    a4: A4
+);
+define_function!(
+    Function5,
+    "A function taking 5 arguments",
+    5,
+    R,
+    a1: A1,
+    a2: A2,
+    a3: A3,
+    a4: A4,
+    a5: A5
+);
+define_function!(
+    Function6,
+    "A function taking 6 arguments",
+    6,
+    R,
+    a1: A1,
+    a2: A2,
+    a3: A3,
+    a4: A4,
+    a5: A5,
+    a6: A6
+);
+
+impl Module {
+    pub(crate) fn new(ir: &ir::Ir, codemap: &codespan::CodeMap) -> Self {
+        let program = program::Program::new(ir, codemap);
+        let functions = program.functions();
+
+        Module {
+            program: sync::Arc::new(program),
+            state: sync::Arc::new(tin_runtime::State::new()),
+            functions,
+            exports: ir.exports(),
+            constants: ir.constants(),
+        }
+    }
+
+    /// Returns all of the public top-level definitions of this module, sorted by name.
+    pub fn exports(&self) -> &[module::Export] {
+        &self.exports
+    }
+
+    /// Fetches the value of the specified top-level constant.
+    ///
+    /// Returns `None` if there is no top-level definition with the specified name, or if the
+    /// definition does not have a value that is known at compile time.
+    pub fn constant(&self, name: &str) -> Option<value::Value> {
+        self.constants.get(name).cloned()
+    }
+
+    /// Sets the amount of fuel available to subsequent calls into this module.
+    ///
+    /// Fuel is metered exactly like for compiled modules; see `module::Module::set_fuel`.
+    pub fn set_fuel(&self, fuel: Option<usize>) {
+        self.state.set_fuel(fuel);
+    }
+
+    /// The amount of fuel that remains, or `None` if fuel metering is disabled.
+    pub fn fuel(&self) -> Option<usize> {
+        self.state.fuel()
+    }
+
+    /// Returns a handle that can be used to interrupt a running call into this module.
+    pub fn interrupt_handle(&self) -> module::InterruptHandle {
+        module::InterruptHandle::new(self.state.clone())
+    }
+
+    /// Fetches the specified function with the specified signature.
+    ///
+    /// Returns `None` if there is no such function, or if it takes a different number of
+    /// parameters than the signature.
+    pub fn function<F>(&self, name: &str) -> Option<F>
+    where
+        F: Function,
+    {
+        let callable = Callable {
+            program: self.program.clone(),
+            state: self.state.clone(),
+            closure: self.functions.get(name)?.clone(),
+        };
+        F::from_callable(callable)
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Module").finish()
+    }
+}
+
+impl Callable {
+    /// The number of parameters that this callable takes.
+    pub fn arity(&self) -> usize {
+        self.program.arity(&self.closure)
+    }
+
+    /// Calls the underlying function with the specified arguments.
+    ///
+    /// Functions can not be passed to or returned from the host, so if the function returns
+    /// another function, an `ErrorKind::Unknown` error is returned.
+    pub fn call(&self, arguments: Vec<value::Value>) -> Result<value::Value, module::Error> {
+        let arguments = arguments.into_iter().map(program::Value::Data).collect();
+        match self.program.call(&self.state, &self.closure, arguments)? {
+            program::Value::Data(value) => Ok(value),
+            program::Value::Closure(_) => Err(module::Error::new(module::ErrorKind::Unknown)),
+        }
+    }
+}
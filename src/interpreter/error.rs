@@ -15,13 +15,7 @@ impl diagnostic::Diagnostics for Error {
             Error::RuntimeTypeConflict(ref cause) => {
                 builder.add_diagnostic(codespan_reporting::Severity::Bug, None, cause);
             }
-            Error::EvaluationError(ref cause) => {
-                builder.add_diagnostic(
-                    codespan_reporting::Severity::Error,
-                    None,
-                    &cause.to_string(),
-                );
-            }
+            Error::EvaluationError(ref cause) => cause.to_diagnostics(builder),
         }
     }
 }
@@ -10,6 +10,9 @@ use crate::value;
 pub mod error;
 #[macro_use]
 mod macros;
+pub mod program;
+#[cfg(test)]
+mod tests;
 
 pub fn eval<'a, F>(
     element: &element::Element,
@@ -52,10 +55,92 @@ where
                 ))),
             }))
         }
-        _ => Ok(None), // TODO
+        element::Element::Fail(element::Fail { message }) => {
+            transpose(lookup(*message).map(|message| match message.case() {
+                value::Case::String(s) => Err(error::Error::EvaluationError(
+                    module::Error::with_message(module::ErrorKind::UserGenerated, s.clone()),
+                )),
+                other => Err(error::Error::RuntimeTypeConflict(format!(
+                    "not a string: {:?}",
+                    other
+                ))),
+            }))
+        }
+        element::Element::Assert(element::Assert {
+            condition,
+            message,
+            ref source,
+            ref operands,
+        }) => {
+            let operands = operands
+                .iter()
+                .map(|operand| lookup(*operand))
+                .collect::<Option<Vec<_>>>();
+            match (lookup(*condition), lookup(*message), operands) {
+                (Some(condition), Some(message), Some(operands)) => {
+                    eval_assert(condition, message, source, &operands).map(Some)
+                }
+                _ => Ok(None),
+            }
+        }
+        // These need an environment of bindings, see `program::Program`
+        _ => Ok(None),
     }
 }
 
+/// The value that a `try` expression binds a caught error to; a record of the kind of the error as
+/// a symbol, and its message.
+///
+/// Errors without a message get a description of their kind as the message instead.
+pub fn caught_error(error: &module::Error) -> value::Value {
+    let message = error
+        .message()
+        .map_or_else(|| error.kind().to_string(), str::to_owned);
+
+    let mut fields = collections::HashMap::new();
+    fields.insert(
+        "kind".to_owned(),
+        value::Value::symbol(error.kind().label()),
+    );
+    fields.insert("message".to_owned(), value::Value::string(message));
+    value::Value::record(value::Record { fields })
+}
+
+/// Checks the condition of an assertion, raising an error that shows the source of the condition
+/// and the values of its operands if it does not hold.
+fn eval_assert(
+    condition: &value::Value,
+    message: &value::Value,
+    source: &str,
+    operands: &[&value::Value],
+) -> Result<value::Value, error::Error> {
+    let message = match message.case() {
+        value::Case::String(s) => s,
+        other => {
+            return Err(error::Error::RuntimeTypeConflict(format!(
+                "not a string: {:?}",
+                other
+            )));
+        }
+    };
+
+    if to_bool(condition)? {
+        return Ok(value::TRUE.clone());
+    }
+
+    let message = match operands {
+        [lhs, rhs] => format!(
+            "assertion `{}` failed: {} (left: {}, right: {})",
+            source, message, lhs, rhs
+        ),
+        _ => format!("assertion `{}` failed: {}", source, message),
+    };
+    Err(error::Error::EvaluationError(module::Error::with_message(
+        module::ErrorKind::UserGenerated,
+        message,
+    )))
+}
+
 fn eval_number(number: &element::Number) -> value::Number {
     match *number {
         element::Number::U8(n) => value::Number::U8(n),
@@ -84,7 +169,15 @@ fn eval_un_op(
         element::UnOperator::Cl1 => {
             match_integral_value!("#^1", (operand), |v| Ok((!v).leading_zeros().into()))
         }
-        element::UnOperator::Cls => unimplemented!(),
+        element::UnOperator::Cls => match_integral_value!("#^-", (operand), |v| {
+            // The leading bits that are equal to the sign bit, excluding the sign bit itself
+            let sign_bits = if v.leading_zeros() == 0 {
+                (!v).leading_zeros()
+            } else {
+                v.leading_zeros()
+            };
+            Ok((sign_bits - 1).into())
+        }),
         element::UnOperator::Ct0 => {
             match_integral_value!("#$0", (operand), |v| Ok(v.trailing_zeros().into()))
         }
@@ -114,7 +207,8 @@ fn eval_bi_op(
             Ok((cmp_value(lhs, rhs)?.map_or(false, |o| o == cmp::Ordering::Equal)).into())
         }
         element::BiOperator::Ne => {
-            Ok((cmp_value(lhs, rhs)?.map_or(false, |o| o != cmp::Ordering::Equal)).into())
+            // Unordered values, like NaN, are never equal
+            Ok((cmp_value(lhs, rhs)?.map_or(true, |o| o != cmp::Ordering::Equal)).into())
         }
         element::BiOperator::Lt => {
             Ok((cmp_value(lhs, rhs)?.map_or(false, |o| o == cmp::Ordering::Less)).into())
@@ -184,10 +278,10 @@ fn eval_bi_op(
             match_integral_value!(">->", (lhs), |l| Ok((l.rotate_right(to_u32(rhs)?)).into()))
         }
         element::BiOperator::ShL => {
-            match_integral_value!("<<", (lhs), |l| Ok((l << to_u32(rhs)?).into()))
+            match_integral_value!("<<", (lhs), |l| Ok((l.wrapping_shl(to_u32(rhs)?)).into()))
         }
         element::BiOperator::ShR => {
-            match_integral_value!(">>", (lhs), |l| Ok((l >> to_u32(rhs)?).into()))
+            match_integral_value!(">>", (lhs), |l| Ok((l.wrapping_shr(to_u32(rhs)?)).into()))
         }
     }
 }
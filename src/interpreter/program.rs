@@ -0,0 +1,424 @@
+//! Running complete programs by walking the IR, without generating any code.
+use std::collections;
+use std::sync;
+
+use specs;
+use tin_runtime;
+
+use crate::interpreter;
+use crate::interpreter::error;
+use crate::ir;
+use crate::ir::component::constexpr;
+use crate::ir::component::element;
+use crate::ir::component::location;
+use crate::ir::component::symbol;
+use crate::module;
+use crate::value;
+
+/// The parts of the IR that are needed to run a program, detached from the IR world.
+#[derive(Debug)]
+pub struct Program {
+    constexprs: collections::HashMap<specs::Entity, value::Value>,
+    elements: collections::HashMap<specs::Entity, element::Element>,
+    locations: collections::HashMap<specs::Entity, module::Point>,
+    symbols: collections::HashMap<specs::Entity, symbol::Symbol>,
+}
+
+/// A value at runtime; unlike a `value::Value`, this can also be a function.
+#[derive(Clone, Debug)]
+pub enum Value {
+    /// A plain data value.
+    Data(value::Value),
+    /// A function value.
+    Closure(Closure),
+}
+
+/// A function along with the environment that it was created in.
+#[derive(Clone, Debug)]
+pub struct Closure {
+    entity: specs::Entity,
+    environment: sync::Arc<Environment>,
+}
+
+/// Bindings of parameters, variables and captured values within a function call.
+///
+/// Captures are resolved through the parent environment, which is the environment that the called
+/// closure was created in.
+#[derive(Debug, Default)]
+struct Environment {
+    bindings: collections::HashMap<specs::Entity, Value>,
+    parent: Option<sync::Arc<Environment>>,
+}
+
+/// An error that was raised while evaluating the element of an entity.
+#[derive(Debug)]
+struct Thrown {
+    entity: specs::Entity,
+    error: error::Error,
+}
+
+impl Program {
+    /// Extracts a program from the specified IR, which should already have been type checked.
+    pub fn new(ir: &ir::Ir, codemap: &codespan::CodeMap) -> Self {
+        use specs::Join;
+
+        let entities = ir.world.entities();
+        let constexprs = ir.world.read_storage::<constexpr::Constexpr>();
+        let elements = ir.world.read_storage::<element::Element>();
+        let locations = ir.world.read_storage::<location::Location>();
+        let symbols = ir.world.read_storage::<symbol::Symbol>();
+
+        let constexprs = (&entities, &constexprs)
+            .join()
+            .map(|(entity, constexpr)| (entity, constexpr.value.clone()))
+            .collect();
+        let elements = (&entities, &elements)
+            .join()
+            .map(|(entity, element)| (entity, element.clone()))
+            .collect();
+        let locations = (&entities, &locations)
+            .join()
+            .filter_map(|(entity, location)| point(codemap, location.0).map(|p| (entity, p)))
+            .collect();
+        let symbols = (&entities, &symbols)
+            .join()
+            .map(|(entity, symbol)| (entity, symbol.clone()))
+            .collect();
+
+        Program {
+            constexprs,
+            elements,
+            locations,
+            symbols,
+        }
+    }
+
+    /// All of the top-level functions of this program, by name.
+    pub fn functions(&self) -> collections::HashMap<String, Closure> {
+        self.elements
+            .iter()
+            .filter_map(|(entity, element)| match *element {
+                element::Element::Closure(_) => self
+                    .symbols
+                    .get(entity)
+                    .filter(|symbol| symbol.is_top_level())
+                    .map(|symbol| (symbol.to_string(), Closure::new(*entity))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The number of parameters that the specified closure takes.
+    pub fn arity(&self, closure: &Closure) -> usize {
+        match self.elements.get(&closure.entity) {
+            Some(element::Element::Closure(definition)) => definition.parameters.len(),
+            _ => 0,
+        }
+    }
+
+    /// Calls the specified closure with the specified arguments.
+    ///
+    /// Fuel is consumed and interrupts are observed on every function entry, just like in compiled
+    /// code.
+    pub fn call(
+        &self,
+        state: &tin_runtime::State,
+        closure: &Closure,
+        arguments: Vec<Value>,
+    ) -> Result<Value, module::Error> {
+        let definition = match self.elements.get(&closure.entity) {
+            Some(element::Element::Closure(definition)) => definition,
+            other => {
+                let message = format!("not a function: {:?}", other);
+                return Err(self.unwind(closure.entity, Thrown::conflict(closure.entity, message)));
+            }
+        };
+
+        let bindings = definition
+            .parameters
+            .iter()
+            .cloned()
+            .zip(arguments)
+            .collect();
+        let parent = Some(closure.environment.clone());
+        let mut environment = Environment { bindings, parent };
+
+        self.run(state, closure.entity, definition, &mut environment)
+            .map_err(|thrown| self.unwind(closure.entity, thrown))
+    }
+
+    fn run(
+        &self,
+        state: &tin_runtime::State,
+        entity: specs::Entity,
+        definition: &element::Closure,
+        environment: &mut Environment,
+    ) -> Result<Value, Thrown> {
+        if state.take_interrupt() {
+            return Err(Thrown::new(entity, module::ErrorKind::Interrupted));
+        }
+        if !state.consume_fuel() {
+            return Err(Thrown::new(entity, module::ErrorKind::OutOfFuel));
+        }
+
+        for statement in &definition.statements {
+            let value = self.eval(state, *statement, environment)?;
+            environment.bindings.insert(*statement, value);
+        }
+
+        self.eval(state, definition.result, environment)
+    }
+
+    fn eval(
+        &self,
+        state: &tin_runtime::State,
+        entity: specs::Entity,
+        environment: &Environment,
+    ) -> Result<Value, Thrown> {
+        if let Some(value) = environment.lookup(entity) {
+            return Ok(value.clone());
+        }
+
+        if let Some(value) = self.constexprs.get(&entity) {
+            return Ok(Value::Data(value.clone()));
+        }
+
+        let element = self
+            .elements
+            .get(&entity)
+            .ok_or_else(|| Thrown::conflict(entity, "entity has no element".to_owned()))?;
+
+        match *element {
+            element::Element::Variable(ref variable) => {
+                if self.is_top_level(entity) {
+                    // Top-level definitions can not refer to any local bindings, so don't let
+                    // closures defined there hold on to the environment of the caller.
+                    self.eval(state, variable.initializer, &Environment::default())
+                } else {
+                    self.eval(state, variable.initializer, environment)
+                }
+            }
+            element::Element::Capture(ref capture) => {
+                self.eval(state, capture.captured, environment)
+            }
+            element::Element::Parameter(ref parameter) => Err(Thrown::conflict(
+                entity,
+                format!("unbound parameter: {}", parameter.name),
+            )),
+            element::Element::Closure(_) => Ok(Value::Closure(Closure {
+                entity,
+                environment: sync::Arc::new(environment.snapshot()),
+            })),
+            element::Element::Apply(ref apply) => {
+                self.eval_apply(state, entity, apply, environment)
+            }
+            element::Element::Assert(ref assert) => {
+                self.eval_assert(state, entity, element, assert, environment)
+            }
+            element::Element::Try(ref try_) => self.eval_try(state, try_, environment),
+            element::Element::CaughtError(ref caught_error) => Err(Thrown::conflict(
+                entity,
+                format!("unbound caught error: {}", caught_error.name),
+            )),
+            element::Element::Module(_) => Err(Thrown::conflict(
+                entity,
+                "a module is not a value".to_owned(),
+            )),
+            _ => self.eval_data(state, entity, element, environment),
+        }
+    }
+
+    fn eval_apply(
+        &self,
+        state: &tin_runtime::State,
+        entity: specs::Entity,
+        apply: &element::Apply,
+        environment: &Environment,
+    ) -> Result<Value, Thrown> {
+        let function = self.eval(state, apply.function, environment)?;
+        let arguments = apply
+            .parameters
+            .iter()
+            .map(|parameter| self.eval(state, *parameter, environment))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match function {
+            Value::Closure(ref closure) => {
+                self.call(state, closure, arguments)
+                    .map_err(|error| Thrown {
+                        entity,
+                        error: error::Error::EvaluationError(error),
+                    })
+            }
+            Value::Data(ref value) => Err(Thrown::conflict(
+                entity,
+                format!("not a function: {:?}", value),
+            )),
+        }
+    }
+
+    /// Evaluates the body of a `try` expression, or its handler with the caught error bound if
+    /// the body raises an error that can be recovered from.
+    fn eval_try(
+        &self,
+        state: &tin_runtime::State,
+        try_: &element::Try,
+        environment: &Environment,
+    ) -> Result<Value, Thrown> {
+        let error = match self.eval(state, try_.body, environment) {
+            Err(Thrown {
+                error: error::Error::EvaluationError(ref error),
+                ..
+            }) if error.kind().is_recoverable() => error.clone(),
+            result => return result,
+        };
+
+        let mut bindings = collections::HashMap::new();
+        bindings.insert(try_.error, Value::Data(interpreter::caught_error(&error)));
+        let parent = Some(sync::Arc::new(environment.snapshot()));
+
+        self.eval(state, try_.handler, &Environment { bindings, parent })
+    }
+
+    /// Evaluates an assertion.
+    ///
+    /// The operands of a comparison are bound before evaluating the condition, so that they are
+    /// only evaluated once even though their values are also used for the error message.
+    fn eval_assert(
+        &self,
+        state: &tin_runtime::State,
+        entity: specs::Entity,
+        element: &element::Element,
+        assert: &element::Assert,
+        environment: &Environment,
+    ) -> Result<Value, Thrown> {
+        let bindings = assert
+            .operands
+            .iter()
+            .map(|operand| Ok((*operand, self.eval(state, *operand, environment)?)))
+            .collect::<Result<_, Thrown>>()?;
+        let parent = Some(sync::Arc::new(environment.snapshot()));
+
+        self.eval_data(state, entity, element, &Environment { bindings, parent })
+    }
+
+    /// Evaluates elements that only operate on data, by first evaluating their operands.
+    fn eval_data(
+        &self,
+        state: &tin_runtime::State,
+        entity: specs::Entity,
+        element: &element::Element,
+        environment: &Environment,
+    ) -> Result<Value, Thrown> {
+        let operands = match *element {
+            element::Element::Tuple(ref tuple) => tuple.fields.clone(),
+            element::Element::Record(ref record) => record.fields.values().cloned().collect(),
+            element::Element::UnOp(ref un_op) => vec![un_op.operand],
+            element::Element::BiOp(ref bi_op) => vec![bi_op.lhs, bi_op.rhs],
+            element::Element::Select(ref select) => vec![select.record],
+            element::Element::Fail(ref fail) => vec![fail.message],
+            element::Element::Assert(ref assert) => assert
+                .operands
+                .iter()
+                .cloned()
+                .chain(vec![assert.condition, assert.message])
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut values = collections::HashMap::with_capacity(operands.len());
+        for operand in operands {
+            match self.eval(state, operand, environment)? {
+                Value::Data(value) => {
+                    values.insert(operand, value);
+                }
+                Value::Closure(_) => {
+                    return Err(Thrown::conflict(
+                        operand,
+                        "a function can not be used as data".to_owned(),
+                    ));
+                }
+            }
+        }
+
+        match interpreter::eval(element, |e| values.get(&e)) {
+            Ok(Some(value)) => Ok(Value::Data(value)),
+            Ok(None) => Err(Thrown::conflict(
+                entity,
+                format!("can not evaluate {:?}", element),
+            )),
+            Err(error) => Err(Thrown { entity, error }),
+        }
+    }
+
+    fn is_top_level(&self, entity: specs::Entity) -> bool {
+        self.symbols
+            .get(&entity)
+            .map_or(false, symbol::Symbol::is_top_level)
+    }
+
+    /// Adds a backtrace frame for the specified function to a thrown error.
+    fn unwind(&self, function: specs::Entity, thrown: Thrown) -> module::Error {
+        let mut error = match thrown.error {
+            error::Error::EvaluationError(error) => error,
+            error::Error::RuntimeTypeConflict(message) => {
+                error!("runtime type conflict: {}", message);
+                module::Error::new(module::ErrorKind::Unknown)
+            }
+        };
+
+        if let (Some(symbol), Some(location)) = (
+            self.symbols.get(&function),
+            self.locations.get(&thrown.entity),
+        ) {
+            error.push_frame(module::Frame::new(symbol.to_string(), location.clone()));
+        }
+
+        error
+    }
+}
+
+impl Closure {
+    fn new(entity: specs::Entity) -> Self {
+        let environment = sync::Arc::new(Environment::default());
+        Closure {
+            entity,
+            environment,
+        }
+    }
+}
+
+impl Environment {
+    fn lookup(&self, entity: specs::Entity) -> Option<&Value> {
+        self.bindings
+            .get(&entity)
+            .or_else(|| self.parent.as_ref().and_then(|p| p.lookup(entity)))
+    }
+
+    fn snapshot(&self) -> Self {
+        let bindings = self.bindings.clone();
+        let parent = self.parent.clone();
+        Environment { bindings, parent }
+    }
+}
+
+impl Thrown {
+    fn new(entity: specs::Entity, kind: module::ErrorKind) -> Self {
+        let error = error::Error::EvaluationError(module::Error::new(kind));
+        Thrown { entity, error }
+    }
+
+    fn conflict(entity: specs::Entity, message: String) -> Self {
+        let error = error::Error::RuntimeTypeConflict(message);
+        Thrown { entity, error }
+    }
+}
+
+fn point(codemap: &codespan::CodeMap, span: codespan::ByteSpan) -> Option<module::Point> {
+    let filemap = codemap.find_file(span.start())?;
+    let (line, column) = filemap.location(span.start()).ok()?;
+    Some(
+        module::Point::new(filemap.name().to_string(), line.0, column.0)
+            .with_span(span.start().0, span.end().0),
+    )
+}
@@ -0,0 +1,343 @@
+use env_logger;
+use failure;
+
+use crate::ast;
+use crate::interpreted;
+use crate::ir;
+use crate::module;
+use crate::test_util;
+use crate::value;
+
+#[test]
+fn immediate() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+main = || -> u32 { 42u32 };
+"#;
+
+    let module = interpret_module("immediate", source)?;
+
+    let main = module
+        .function::<interpreted::Function0<u32>>("main")
+        .unwrap();
+
+    let result = main.call();
+    assert_eq!(Ok(42), result);
+    Ok(())
+}
+
+#[test]
+fn parameter2() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+main = |a: u32, b: u32| -> u32 { b };
+"#;
+
+    let module = interpret_module("parameter2", source)?;
+
+    let main = module
+        .function::<interpreted::Function2<u32, u32, u32>>("main")
+        .unwrap();
+
+    let result = main.call(1, 43);
+    assert_eq!(Ok(43), result);
+    Ok(())
+}
+
+#[test]
+fn apply() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+other = |x: u32| -> u32 { x };
+main = |y: u32| -> u32 { a = other(y); other(other(a)) };
+"#;
+
+    let module = interpret_module("apply", source)?;
+
+    let main = module
+        .function::<interpreted::Function1<u32, u32>>("main")
+        .unwrap();
+
+    let result = main.call(43);
+    assert_eq!(Ok(43), result);
+    Ok(())
+}
+
+#[test]
+fn capture() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+pickFirst = |a: i32, b: i32| -> i32 {
+  capture = |x: i32| -> i32 { a + x };
+  capture(b)
+};
+main = |a: i32| -> i32 { pickFirst(a, 2i32) };
+"#;
+
+    let module = interpret_module("capture", source)?;
+
+    let main = module
+        .function::<interpreted::Function1<i32, i32>>("main")
+        .unwrap();
+
+    let result = main.call(40);
+    assert_eq!(Ok(42), result);
+    Ok(())
+}
+
+#[test]
+fn record() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+main = |x: u32| -> u32 { a = { x: x, y: x + 1u32, z: 3u32 }; a.y };
+"#;
+
+    let module = interpret_module("record", source)?;
+
+    let main = module
+        .function::<interpreted::Function1<u32, u32>>("main")
+        .unwrap();
+
+    let result = main.call(1);
+    assert_eq!(Ok(2), result);
+    Ok(())
+}
+
+#[test]
+fn tuple_value() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Pair = (0u32, 0u32);
+pair = |a: u32| -> Pair { (a, a + a) };
+"#;
+
+    let module = interpret_module("tuple_value", source)?;
+
+    let pair = module
+        .function::<interpreted::Function1<value::Value, u32>>("pair")
+        .unwrap();
+
+    let result = pair.call(21)?;
+    let expected = value::Value::tuple(value::Tuple {
+        fields: vec![value::Value::from(21u32), value::Value::from(42u32)],
+    });
+    assert_eq!(expected, result);
+    Ok(())
+}
+
+#[test]
+fn signature_mismatch() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+main = |a: u32| -> u32 { a };
+"#;
+
+    let module = interpret_module("signature_mismatch", source)?;
+
+    assert!(module
+        .function::<interpreted::Function0<u32>>("main")
+        .is_none());
+    assert!(module
+        .function::<interpreted::Function1<u32, u32>>("other")
+        .is_none());
+    Ok(())
+}
+
+#[test]
+fn division_by_zero() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+div = |a: u32, b: u32| -> u32 { a / b };
+main = |a: u32| -> u32 { div(a, 0u32) };
+"#;
+
+    let module = interpret_module("division_by_zero", source)?;
+
+    let main = module
+        .function::<interpreted::Function1<u32, u32>>("main")
+        .unwrap();
+
+    let error = main.call(1).unwrap_err();
+    assert_eq!(module::ErrorKind::IntegerDivisonByZero, error.kind());
+    let frames = error.backtrace().frames();
+    assert_eq!(2, frames.len());
+    assert_eq!("div", frames[0].name());
+    assert_eq!("main", frames[1].name());
+    Ok(())
+}
+
+#[test]
+fn user_error() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+fail_with = |reason: ""| -> u32 { fail(reason) };
+main = |a: u32| -> u32 { fail_with("boom") + a };
+"#;
+
+    let module = interpret_module("user_error", source)?;
+
+    let main = module
+        .function::<interpreted::Function1<u32, u32>>("main")
+        .unwrap();
+
+    let error = main.call(1).unwrap_err();
+    assert_eq!(module::ErrorKind::UserGenerated, error.kind());
+    assert_eq!(Some("boom"), error.message());
+    let frames = error.backtrace().frames();
+    assert_eq!(2, frames.len());
+    assert_eq!("fail_with", frames[0].name());
+    assert_eq!("main", frames[1].name());
+    Ok(())
+}
+
+#[test]
+fn assertions() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+check_port = |port: u32| -> :t { assert(port < 65536u32, "port out of range") };
+check_distinct = |a: u32, b: u32| -> :t { assert(!(a == b), "values must differ") };
+"#;
+
+    let module = interpret_module("assertions", source)?;
+
+    let check_port = module
+        .function::<interpreted::Function1<value::Value, u32>>("check_port")
+        .unwrap();
+    assert_eq!(Ok(value::Value::symbol("t")), check_port.call(80));
+
+    let error = check_port.call(70000).unwrap_err();
+    assert_eq!(module::ErrorKind::UserGenerated, error.kind());
+    assert_eq!(
+        Some("assertion `port < 65536u32` failed: port out of range (left: 70000u32, right: 65536u32)"),
+        error.message()
+    );
+    assert_eq!("check_port", error.backtrace().frames()[0].name());
+
+    let check_distinct = module
+        .function::<interpreted::Function2<value::Value, u32, u32>>("check_distinct")
+        .unwrap();
+    assert_eq!(Ok(value::Value::symbol("t")), check_distinct.call(1, 2));
+    assert_eq!(
+        Some("assertion `!(a == b)` failed: values must differ"),
+        check_distinct.call(1, 1).unwrap_err().message()
+    );
+    Ok(())
+}
+
+#[test]
+fn try_catch() -> Result<(), failure::Error> {
+    use std::collections;
+
+    let _ = env_logger::try_init();
+
+    let source = r#"
+div = |a: u32, b: u32| -> u32 { a / b };
+fail_with = |reason: ""| -> "" { fail(reason) };
+safe_div = |a: u32, b: u32| -> u32 { try { div(a, b) } catch |e| { 0u32 } };
+message = |a: u32| -> "" { try { fail_with("boom") } catch |e| { e.message } };
+recovered = try { 1u32 / 0u32 } catch |e| { e };
+"#;
+
+    let module = interpret_module("try_catch", source)?;
+
+    let safe_div = module
+        .function::<interpreted::Function2<u32, u32, u32>>("safe_div")
+        .unwrap();
+    assert_eq!(Ok(3), safe_div.call(6, 2));
+    assert_eq!(Ok(0), safe_div.call(6, 0));
+
+    let message = module
+        .function::<interpreted::Function1<value::Value, u32>>("message")
+        .unwrap();
+    assert_eq!(Ok(value::Value::string("boom")), message.call(1));
+
+    let mut fields = collections::HashMap::new();
+    fields.insert(
+        "kind".to_owned(),
+        value::Value::symbol("integer_division_by_zero"),
+    );
+    fields.insert(
+        "message".to_owned(),
+        value::Value::string("integer division by zero"),
+    );
+    assert_eq!(
+        Some(value::Value::record(value::Record { fields })),
+        module.constant("recovered")
+    );
+    Ok(())
+}
+
+#[test]
+fn out_of_fuel() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+forever = |a: u32| -> u32 { forever(a) };
+"#;
+
+    let module = interpret_module("out_of_fuel", source)?;
+    module.set_fuel(Some(100));
+
+    let forever = module
+        .function::<interpreted::Function1<u32, u32>>("forever")
+        .unwrap();
+
+    let error = forever.call(1).unwrap_err();
+    assert_eq!(module::ErrorKind::OutOfFuel, error.kind());
+    assert_eq!(Some(0), module.fuel());
+    Ok(())
+}
+
+#[test]
+fn interrupted() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+double = |a: u32| -> u32 { a + a };
+main = || -> u32 { double(21u32) };
+"#;
+
+    let module = interpret_module("interrupted", source)?;
+    let interrupt_handle = module.interrupt_handle();
+
+    let main = module
+        .function::<interpreted::Function0<u32>>("main")
+        .unwrap();
+
+    interrupt_handle.interrupt();
+    let error = main.call().unwrap_err();
+    assert_eq!(module::ErrorKind::Interrupted, error.kind());
+    assert_eq!(1, error.backtrace().frames().len());
+    assert_eq!("main", error.backtrace().frames()[0].name());
+
+    let result = main.call();
+    assert_eq!(Ok(42), result);
+    Ok(())
+}
+
+fn interpret_module(
+    name: &'static str,
+    source: &str,
+) -> Result<interpreted::Module, failure::Error> {
+    use crate::parser::Parse;
+
+    let mut codemap = codespan::CodeMap::new();
+    let file = codemap.add_filemap(codespan::FileName::Virtual(name.into()), source.to_owned());
+    let ast_module = ast::Module::parse(file.span(), source)?;
+    let mut ir = ir::Ir::new();
+    ir.load(&ast_module, &file)?;
+    ir.check_types()?;
+    test_util::render_graph(&format!(concat!(module_path!(), "::{}"), name), &ir)?;
+
+    Ok(interpreted::Module::new(&ir, &codemap))
+}
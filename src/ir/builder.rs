@@ -11,33 +11,56 @@ use crate::ir::component::symbol;
 use crate::ir::error;
 use crate::parser;
 
+/// The name of the built-in function that raises a user generated error.
+const FAIL: &str = "fail";
+/// The name of the built-in function that raises a user generated error if a condition is false.
+const ASSERT: &str = "assert";
+
 pub struct Builder<'a> {
     world: &'a mut specs::World,
+    file: &'a codespan::FileMap,
     symbol: Vec<symbol::Part>,
     current_scope: collections::HashMap<String, specs::Entity>,
     scopes: Vec<collections::HashMap<String, specs::Entity>>,
     current_captures: collections::HashMap<String, specs::Entity>,
     captures: Vec<collections::HashMap<String, specs::Entity>>,
+    references: Vec<(codespan::ByteSpan, specs::Entity)>,
 }
 
 impl<'a> Builder<'a> {
-    pub fn new(world: &'a mut specs::World) -> Builder<'a> {
+    /// Creates a builder where references are resolved against the specified (module) scope.
+    ///
+    /// The AST that is added must have been parsed from the specified file.
+    pub fn with_scope(
+        world: &'a mut specs::World,
+        file: &'a codespan::FileMap,
+        current_scope: collections::HashMap<String, specs::Entity>,
+    ) -> Builder<'a> {
         let symbol = Vec::new();
-        let current_scope = collections::HashMap::new();
         let scopes = Vec::new();
         let current_captures = collections::HashMap::new();
         let captures = Vec::new();
+        let references = Vec::new();
 
         Builder {
             world,
+            file,
             symbol,
             current_scope,
             scopes,
             current_captures,
             captures,
+            references,
         }
     }
 
+    /// The identifiers that have been resolved so far, along with the entities they refer to.
+    ///
+    /// References to variables from enclosing functions refer to the corresponding captures.
+    pub fn into_references(self) -> Vec<(codespan::ByteSpan, specs::Entity)> {
+        self.references
+    }
+
     pub fn add_module(
         &mut self,
         entity: specs::Entity,
@@ -81,6 +104,50 @@ impl<'a> Builder<'a> {
         Ok(())
     }
 
+    /// Adds an anonymous function without parameters, that evaluates the specified expression.
+    pub fn add_thunk(
+        &mut self,
+        entity: specs::Entity,
+        expression: &ast::Expression<parser::Context>,
+    ) -> Result<(), error::Error> {
+        use crate::ast::AstNode;
+        use specs::world::Builder;
+
+        // References to the module scope are resolved directly rather than captured, just like
+        // in the initializers of top-level definitions.
+        let result = self.world.create_entity().build();
+        self.add_expression(result, expression)?;
+
+        self.world
+            .write_storage()
+            .insert(
+                entity,
+                element::Element::Closure(element::Closure {
+                    captures: collections::HashMap::new(),
+                    parameters: Vec::new(),
+                    statements: Vec::new(),
+                    signature: result,
+                    result,
+                }),
+            )
+            .unwrap();
+
+        self.world
+            .write_storage()
+            .insert(
+                entity,
+                symbol::Symbol::new(vec![symbol::Part::Unnamed(u64::from(entity.id()))]),
+            )
+            .unwrap();
+
+        self.world
+            .write_storage()
+            .insert(entity, location::Location(expression.context().span))
+            .unwrap();
+
+        Ok(())
+    }
+
     fn add_identifier(
         &mut self,
         entity: specs::Entity,
@@ -125,6 +192,8 @@ impl<'a> Builder<'a> {
             location: identifier.context.span,
         })?;
 
+        self.references.push((identifier.context.span, definition));
+
         self.world
             .write_storage()
             .insert(entity, replacement::Replacement { to: definition })
@@ -155,6 +224,7 @@ impl<'a> Builder<'a> {
             ast::Expression::Lambda(ref v) => self.add_lambda(entity, v),
             ast::Expression::Select(ref v) => self.add_select(entity, v),
             ast::Expression::Apply(ref v) => self.add_apply(entity, v),
+            ast::Expression::Try(ref v) => self.add_try(entity, v),
             ast::Expression::Unknown => panic!("'unknown' AST nodes should not escape the parser"),
         }
     }
@@ -522,6 +592,15 @@ impl<'a> Builder<'a> {
     ) -> Result<(), error::Error> {
         use specs::world::Builder;
 
+        if let ast::Expression::Identifier(ref identifier) = *apply.function {
+            if identifier.value == FAIL && !self.is_defined(FAIL) {
+                return self.add_fail(entity, apply);
+            }
+            if identifier.value == ASSERT && !self.is_defined(ASSERT) {
+                return self.add_assert(entity, apply);
+            }
+        }
+
         let function = self.world.create_entity().build();
         self.add_expression(function, &*apply.function)?;
 
@@ -554,6 +633,159 @@ impl<'a> Builder<'a> {
         Ok(())
     }
 
+    /// Adds an application of the built-in `fail` function.
+    fn add_fail(
+        &mut self,
+        entity: specs::Entity,
+        apply: &ast::Apply<parser::Context>,
+    ) -> Result<(), error::Error> {
+        use specs::world::Builder;
+
+        if apply.parameters.len() != 1 {
+            return Err(error::Error::BuiltinArity {
+                name: FAIL.to_owned(),
+                expected: 1,
+                actual: apply.parameters.len(),
+                location: apply.context.span,
+            });
+        }
+
+        let message = self.world.create_entity().build();
+        self.add_expression(message, &apply.parameters[0])?;
+
+        self.world
+            .write_storage()
+            .insert(entity, element::Element::Fail(element::Fail { message }))
+            .unwrap();
+
+        self.world
+            .write_storage()
+            .insert(entity, location::Location(apply.context.span))
+            .unwrap();
+
+        Ok(())
+    }
+
+    /// Adds an application of the built-in `assert` function.
+    fn add_assert(
+        &mut self,
+        entity: specs::Entity,
+        apply: &ast::Apply<parser::Context>,
+    ) -> Result<(), error::Error> {
+        use crate::ast::AstNode;
+        use specs::world::Builder;
+
+        if apply.parameters.len() != 2 {
+            return Err(error::Error::BuiltinArity {
+                name: ASSERT.to_owned(),
+                expected: 2,
+                actual: apply.parameters.len(),
+                location: apply.context.span,
+            });
+        }
+
+        let condition = self.world.create_entity().build();
+        self.add_expression(condition, &apply.parameters[0])?;
+
+        let message = self.world.create_entity().build();
+        self.add_expression(message, &apply.parameters[1])?;
+
+        // The condition is shown as it was written
+        let source = self
+            .file
+            .src_slice(apply.parameters[0].context().span)
+            .map(str::to_owned)
+            .unwrap_or_else(|_| apply.parameters[0].to_string());
+        let operands = match self.world.read_storage::<element::Element>().get(condition) {
+            Some(element::Element::BiOp(element::BiOp { lhs, operator, rhs })) => match operator {
+                element::BiOperator::Eq
+                | element::BiOperator::Ne
+                | element::BiOperator::Lt
+                | element::BiOperator::Ge
+                | element::BiOperator::Gt
+                | element::BiOperator::Le => vec![*lhs, *rhs],
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+
+        self.world
+            .write_storage()
+            .insert(
+                entity,
+                element::Element::Assert(element::Assert {
+                    condition,
+                    message,
+                    source,
+                    operands,
+                }),
+            )
+            .unwrap();
+
+        self.world
+            .write_storage()
+            .insert(entity, location::Location(apply.context.span))
+            .unwrap();
+
+        Ok(())
+    }
+
+    fn add_try(
+        &mut self,
+        entity: specs::Entity,
+        try_: &ast::Try<parser::Context>,
+    ) -> Result<(), error::Error> {
+        use specs::world::Builder;
+
+        let body = self.world.create_entity().build();
+        self.add_expression(body, &*try_.body)?;
+
+        let name = try_.error.value.clone();
+        let error = self.world.create_entity().build();
+
+        self.world
+            .write_storage()
+            .insert(
+                error,
+                element::Element::CaughtError(element::CaughtError { name: name.clone() }),
+            )
+            .unwrap();
+
+        self.world
+            .write_storage()
+            .insert(error, location::Location(try_.error.context.span))
+            .unwrap();
+
+        // The error is only in scope within the handler, where it might shadow another definition
+        let shadowed = self.current_scope.insert(name.clone(), error);
+        let handler = self.world.create_entity().build();
+        let result = self.add_expression(handler, &*try_.handler);
+        match shadowed {
+            Some(shadowed) => self.current_scope.insert(name, shadowed),
+            None => self.current_scope.remove(&name),
+        };
+        result?;
+
+        self.world
+            .write_storage()
+            .insert(
+                entity,
+                element::Element::Try(element::Try {
+                    body,
+                    error,
+                    handler,
+                }),
+            )
+            .unwrap();
+
+        self.world
+            .write_storage()
+            .insert(entity, location::Location(try_.context.span))
+            .unwrap();
+
+        Ok(())
+    }
+
     fn add_parameter(
         &mut self,
         entity: specs::Entity,
@@ -581,6 +813,12 @@ impl<'a> Builder<'a> {
         Ok(())
     }
 
+    /// Whether the specified identifier refers to a definition in any enclosing scope.
+    fn is_defined(&self, name: &str) -> bool {
+        self.current_scope.contains_key(name)
+            || self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
     fn push_scope(&mut self, scope_size_hint: Option<usize>, captures_size_hint: Option<usize>) {
         self.scopes.push(mem::replace(
             &mut self.current_scope,
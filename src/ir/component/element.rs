@@ -6,7 +6,7 @@ use specs;
 use specs::Component;
 use specs::VecStorage;
 
-#[derive(Component, Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Component, Debug, VisitEntities, VisitEntitiesMut)]
 #[storage(VecStorage)]
 pub enum Element {
     Number(Number),
@@ -19,6 +19,10 @@ pub enum Element {
     Variable(Variable),
     Select(Select),
     Apply(Apply),
+    Fail(Fail),
+    Assert(Assert),
+    Try(Try),
+    CaughtError(CaughtError),
     Parameter(Parameter),
     Capture(Capture),
     Closure(Closure),
@@ -39,17 +43,17 @@ pub enum Number {
     F64(f64),
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Symbol {
     pub label: String,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Tuple {
     pub fields: Vec<specs::Entity>,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Record {
     pub fields: collections::HashMap<String, specs::Entity>,
 }
@@ -167,37 +171,81 @@ pub struct BiOp {
     pub rhs: specs::Entity,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Variable {
     pub name: String,
     pub initializer: specs::Entity,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Select {
     pub record: specs::Entity,
     pub field: String,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Apply {
     pub function: specs::Entity,
     pub parameters: Vec<specs::Entity>,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+/// An application of the built-in `fail` function, that raises an error with a message.
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
+pub struct Fail {
+    /// The string expression that describes the error.
+    pub message: specs::Entity,
+}
+
+/// An application of the built-in `assert` function, that raises an error with a message unless
+/// its condition holds.
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
+pub struct Assert {
+    /// The bool expression that must evaluate to `:t`.
+    pub condition: specs::Entity,
+    /// The string expression that describes the asserted invariant.
+    pub message: specs::Entity,
+    /// The source code of the condition, to include in the error.
+    pub source: String,
+    /// The operands of the condition if it is a comparison, whose values are included in the
+    /// error.
+    pub operands: Vec<specs::Entity>,
+}
+
+/// An expression that evaluates its handler instead of its body if the body raises an error.
+///
+/// Errors that enforce limits set by the host, like running out of fuel, are never caught.
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
+pub struct Try {
+    /// The expression to evaluate.
+    pub body: specs::Entity,
+    /// The `CaughtError` that the handler refers to the error through.
+    pub error: specs::Entity,
+    /// The expression to evaluate if the body raises an error.
+    pub handler: specs::Entity,
+}
+
+/// The error caught by a `try` expression, as seen from its handler.
+///
+/// It is a record with the kind of the error as a symbol and the message of the error.
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
+pub struct CaughtError {
+    /// The name that the error is bound to.
+    pub name: String,
+}
+
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Parameter {
     pub name: String,
     pub signature: specs::Entity,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Capture {
     pub name: String,
     pub captured: specs::Entity,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Closure {
     pub captures: collections::HashMap<String, specs::Entity>,
     pub parameters: Vec<specs::Entity>,
@@ -206,7 +254,7 @@ pub struct Closure {
     pub result: specs::Entity,
 }
 
-#[derive(Debug, VisitEntities, VisitEntitiesMut)]
+#[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Module {
     pub variables: collections::HashMap<String, specs::Entity>,
 }
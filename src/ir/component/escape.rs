@@ -0,0 +1,12 @@
+use specs::Component;
+use specs::VecStorage;
+
+/// Whether the value of an entity might outlive the function that creates it.
+#[derive(Clone, Copy, Component, Debug, Eq, PartialEq)]
+#[storage(VecStorage)]
+pub enum Escape {
+    /// The value might be returned, captured or passed to another function.
+    Escapes,
+    /// The value is only used within the function that creates it.
+    Local,
+}
@@ -1,30 +1,46 @@
+//! Memory layouts of Tin values.
+//!
+//! Layouts are computed for every typed expression in loaded code, and describe how values are
+//! represented in memory by compiled code.
 use std::fmt;
 
 use specs::Component;
 use specs::VecStorage;
 
+/// The memory layout of a Tin value.
 #[derive(Clone, Component, Debug, VisitEntities, VisitEntitiesMut)]
 #[storage(VecStorage)]
 pub struct Layout {
+    /// The size of the value in bytes.
     pub size: usize,
+    /// The required alignment of the value in bytes.
     pub alignment: usize,
+    /// The fields of a record value, sorted by name.
     pub named_fields: Vec<NamedField>,
+    /// The fields of a tuple value, in order.
     pub unnamed_fields: Vec<Offset>,
 }
 
+/// A named field of a record layout.
 #[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct NamedField {
+    /// The name of the field.
     pub field: String,
+    /// Where the field is stored, and its layout.
     pub offset_layout: Offset,
 }
 
+/// A layout stored at some offset within an enclosing layout.
 #[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub struct Offset {
+    /// The offset in bytes from the start of the enclosing value.
     pub offset: usize,
+    /// The layout of the value at the offset.
     pub layout: Layout,
 }
 
 impl Layout {
+    /// The layout of a zero-sized value.
     pub const fn zero() -> Layout {
         Layout {
             size: 0,
@@ -34,6 +50,7 @@ impl Layout {
         }
     }
 
+    /// The layout of a scalar value, that is aligned to its size.
     pub const fn scalar(size: usize) -> Layout {
         Layout {
             size,
@@ -43,6 +60,7 @@ impl Layout {
         }
     }
 
+    /// The layout of a record value with the specified fields.
     pub fn named_fields(size: usize, alignment: usize, named_fields: Vec<NamedField>) -> Layout {
         let unnamed_fields = Vec::new();
 
@@ -54,6 +72,7 @@ impl Layout {
         }
     }
 
+    /// The layout of a tuple value with the specified fields.
     pub fn unnamed_fields(size: usize, alignment: usize, unnamed_fields: Vec<Offset>) -> Layout {
         let named_fields = Vec::new();
 
@@ -67,6 +86,7 @@ impl Layout {
 }
 
 impl Offset {
+    /// A zero-sized layout at offset zero.
     pub const fn zero() -> Offset {
         Offset {
             offset: 0,
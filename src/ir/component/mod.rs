@@ -2,6 +2,7 @@ use specs;
 
 pub mod constexpr;
 pub mod element;
+pub mod escape;
 pub mod layout;
 pub mod location;
 pub mod replacement;
@@ -13,6 +14,7 @@ macro_rules! for_each_component {
         apply_component!(constexpr::Constexpr, $name, $body);
         apply_component!(constexpr::error::Error, $name, $body);
         apply_component!(element::Element, $name, $body);
+        apply_component!(escape::Escape, $name, $body);
         apply_component!(layout::Layout, $name, $body);
         apply_component!(location::Location, $name, $body);
         apply_component!(replacement::Replacement, $name, $body);
@@ -13,7 +13,6 @@ pub struct Symbol {
 #[derive(Clone, Debug, VisitEntities, VisitEntitiesMut)]
 pub enum Part {
     Named(String),
-    #[allow(unused)]
     Unnamed(u64),
 }
 
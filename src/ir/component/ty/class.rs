@@ -1,18 +1,29 @@
+//! Scalar classes of types.
 use std::fmt;
 
+/// A classification of types, describing which kinds of operators are applicable to them.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub enum Scalar {
+    /// The type is a symbol.
     Symbol,
+    /// The type is an integral number.
     Integral(IntegralScalar),
+    /// The type is a floating point number.
     Fractional,
+    /// The type is composed of other values (e.g. strings, tuples and records).
     Complex,
+    /// The type has no scalar semantics (e.g. functions and unions).
     Undefined,
 }
 
+/// The signedness of an integral scalar class.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub enum IntegralScalar {
+    /// The integer is unsigned.
     Unsigned,
+    /// The integer is signed.
     Signed,
+    /// The integer may have any signedness.
     Any,
 }
 
@@ -1,3 +1,4 @@
+//! Type errors.
 use std::fmt;
 
 use specs::Component;
@@ -6,29 +7,55 @@ use specs::VecStorage;
 use crate::ir::component::ty;
 use crate::ir::component::ty::class;
 
+/// A type error, where a value was expected to have some type but had another.
 #[derive(Component, Clone, Debug, Eq, Fail, PartialEq, VisitEntities, VisitEntitiesMut)]
 #[storage(VecStorage)]
 pub struct Error<E>
 where
     E: fmt::Debug + Send + Sync + 'static,
 {
+    /// The type that was expected.
     pub expected: ExpectedType,
+    /// The actual type that was encountered.
     pub actual: ty::Type,
+    /// The primary location of the error.
     pub main_entity: E,
+    /// Auxiliary locations that explain the error.
     pub aux_entities: Vec<AuxEntity<E>>,
 }
 
+/// An auxiliary location that helps explaining a type error.
 #[derive(Clone, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub struct AuxEntity<E> {
+    /// The auxiliary location.
     pub entity: E,
+    /// A description of what the auxiliary location contributes to the error.
     pub label: String,
 }
 
+/// A mismatch between a dynamic value and the type that it was expected to have.
+#[derive(Clone, Debug, Eq, Fail, PartialEq)]
+pub struct ValueError {
+    /// The path within the value where the mismatch was found, for example `.address.zip`.
+    ///
+    /// The path is empty if the mismatch concerns the value as a whole.
+    pub path: String,
+    /// The type that was expected, or `None` if nothing was expected at this path.
+    pub expected: Option<ExpectedType>,
+    /// The type of the value that was encountered, or `None` if there was no value at this path.
+    pub actual: Option<ty::Type>,
+}
+
+/// A description of an expected type.
 #[derive(Clone, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub enum ExpectedType {
+    /// A specific type was expected.
     Specific(ty::Type),
+    /// Any type of the specified scalar class was expected.
     ScalarClass(class::Scalar),
+    /// Any of the specified types was expected.
     AnyOf(Vec<ExpectedType>),
+    /// Any union type was expected.
     Union,
 }
 
@@ -67,3 +94,22 @@ impl fmt::Display for ExpectedType {
         }
     }
 }
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "expected ")?;
+        match self.expected {
+            Some(ref expected) => expected.fmt(f)?,
+            None => f.write_str("nothing")?,
+        }
+        write!(f, " but got ")?;
+        match self.actual {
+            Some(ref actual) => write!(f, "`{}`", actual)?,
+            None => f.write_str("nothing")?,
+        }
+        Ok(())
+    }
+}
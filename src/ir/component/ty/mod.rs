@@ -1,76 +1,216 @@
+//! Type descriptions for Tin values.
+//!
+//! Types are inferred for every expression in loaded code.  The types of the definitions of a
+//! compiled module can be inspected using `Module::exports`.
 use std::collections;
 use std::fmt;
 
 use specs::Component;
 use specs::VecStorage;
 
+use crate::value;
+
 pub mod class;
 pub mod error;
 
+/// The type of a Tin value.
 #[derive(Component, Clone, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 #[storage(VecStorage)]
 pub enum Type {
+    /// A numeric type.
     Number(Number),
+    /// The UTF-8 string type.
     String,
+    /// The type of a single specific symbol.
     Symbol(Symbol),
+    /// A tuple type.
     Tuple(Tuple),
+    /// A union of symbol types.
     Union(Union),
+    /// A record type.
     Record(Record),
+    /// A function type.
     Function(Function),
+    /// The type of expressions that never produce a value, like calls to `fail`.
+    ///
+    /// It is accepted wherever any other type is expected.
+    Never,
 }
 
+/// All of the numeric types.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub enum Number {
+    /// Unsigned 8-bit integer.
     U8,
+    /// Unsigned 16-bit integer.
     U16,
+    /// Unsigned 32-bit integer.
     U32,
+    /// Unsigned 64-bit integer.
     U64,
+    /// Signed 8-bit integer.
     I8,
+    /// Signed 16-bit integer.
     I16,
+    /// Signed 32-bit integer.
     I32,
+    /// Signed 64-bit integer.
     I64,
+    /// Floating point 32-bit number.
     F32,
+    /// Floating point 64-bit number.
     F64,
 }
 
+/// The type of a symbol.
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, VisitEntities, VisitEntitiesMut)]
 pub struct Symbol {
+    /// The label of the symbol, without the leading `:`.
     pub label: String,
 }
 
+/// A tuple type.
 #[derive(Clone, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub struct Tuple {
+    /// The types of the fields of the tuple, in declaration order.
     pub fields: Vec<Type>,
 }
 
+/// A union of several symbol types.
 #[derive(Clone, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub struct Union {
+    /// The symbols that are part of the union, in sorted order.
     pub alternatives: Vec<Symbol>,
 }
 
+/// A record type.
 #[derive(Clone, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub struct Record {
+    /// The types of the fields of the record, by field name.
     pub fields: collections::HashMap<String, Type>,
 }
 
+/// A function type.
 #[derive(Clone, Debug, Eq, PartialEq, VisitEntities, VisitEntitiesMut)]
 pub struct Function {
+    /// The types of the parameters of the function, in declaration order.
     pub parameters: Vec<Type>,
+    /// The result type of the function.
     pub result: Box<Type>,
 }
 
 impl Type {
+    /// The scalar class of this type, describing which operators are applicable to it.
     pub fn scalar_class(&self) -> class::Scalar {
         match *self {
             Type::Number(ref n) => n.scalar_class(),
             Type::Symbol(_) => class::Scalar::Symbol,
-            Type::Union(_) | Type::Function(_) => class::Scalar::Undefined,
+            Type::Union(_) | Type::Function(_) | Type::Never => class::Scalar::Undefined,
             Type::String | Type::Tuple(_) | Type::Record(_) => class::Scalar::Complex,
         }
     }
+
+    /// The most specific type of the specified value.
+    pub fn of_value(value: &value::Value) -> Type {
+        match *value.case() {
+            value::Case::Number(n) => Type::Number(Number::of_value(n)),
+            value::Case::String(_) => Type::String,
+            value::Case::Symbol(ref s) => Type::Symbol(Symbol {
+                label: s.label.clone(),
+            }),
+            value::Case::Tuple(ref t) => Type::Tuple(Tuple {
+                fields: t.fields.iter().map(Type::of_value).collect(),
+            }),
+            value::Case::Record(ref r) => Type::Record(Record {
+                fields: r
+                    .fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Type::of_value(v)))
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Checks whether the specified value conforms to this type.
+    ///
+    /// All structural mismatches are returned, each with the path within the value where it was
+    /// found.  An empty result means that the value conforms to this type.
+    pub fn check_value(&self, value: &value::Value) -> Vec<error::ValueError> {
+        let mut errors = Vec::new();
+        self.check_value_at(&mut String::new(), value, &mut errors);
+        errors
+    }
+
+    fn check_value_at(
+        &self,
+        path: &mut String,
+        value: &value::Value,
+        errors: &mut Vec<error::ValueError>,
+    ) {
+        match (self, value.case()) {
+            (Type::Number(n), value::Case::Number(v)) if *n == Number::of_value(*v) => {}
+            (Type::String, value::Case::String(_)) => {}
+            (Type::Symbol(s), value::Case::Symbol(v)) if s.label == v.label => {}
+            (Type::Union(u), value::Case::Symbol(v))
+                if u.alternatives.iter().any(|a| a.label == v.label) => {}
+            (Type::Tuple(t), value::Case::Tuple(v)) if t.fields.len() == v.fields.len() => {
+                for (i, (field_ty, field_value)) in t.fields.iter().zip(v.fields.iter()).enumerate()
+                {
+                    let len = path.len();
+                    path.push_str(&format!(".{}", i));
+                    field_ty.check_value_at(path, field_value, errors);
+                    path.truncate(len);
+                }
+            }
+            (Type::Record(r), value::Case::Record(v)) => {
+                let mut names = r.fields.keys().chain(v.fields.keys()).collect::<Vec<_>>();
+                names.sort_unstable();
+                names.dedup();
+
+                for name in names {
+                    let len = path.len();
+                    path.push('.');
+                    path.push_str(name);
+                    match (r.fields.get(name), v.fields.get(name)) {
+                        (Some(field_ty), Some(field_value)) => {
+                            field_ty.check_value_at(path, field_value, errors)
+                        }
+                        (expected, actual) => errors.push(error::ValueError {
+                            path: path.clone(),
+                            expected: expected.cloned().map(error::ExpectedType::Specific),
+                            actual: actual.map(Type::of_value),
+                        }),
+                    }
+                    path.truncate(len);
+                }
+            }
+            _ => errors.push(error::ValueError {
+                path: path.clone(),
+                expected: Some(error::ExpectedType::Specific(self.clone())),
+                actual: Some(Type::of_value(value)),
+            }),
+        }
+    }
 }
 
 impl Number {
+    /// The type of the specified number value.
+    pub fn of_value(number: value::Number) -> Number {
+        match number {
+            value::Number::U8(_) => Number::U8,
+            value::Number::U16(_) => Number::U16,
+            value::Number::U32(_) => Number::U32,
+            value::Number::U64(_) => Number::U64,
+            value::Number::I8(_) => Number::I8,
+            value::Number::I16(_) => Number::I16,
+            value::Number::I32(_) => Number::I32,
+            value::Number::I64(_) => Number::I64,
+            value::Number::F32(_) => Number::F32,
+            value::Number::F64(_) => Number::F64,
+        }
+    }
+
+    /// The scalar class of this numeric type.
     pub fn scalar_class(self) -> class::Scalar {
         match self {
             Number::U8 | Number::U16 | Number::U32 | Number::U64 => {
@@ -85,6 +225,7 @@ impl Number {
 }
 
 impl Union {
+    /// Adds the specified symbol to this union, keeping the alternatives sorted.
     pub fn with(mut self, symbol: &Symbol) -> Union {
         match self.alternatives.binary_search(symbol) {
             Ok(_) => {}
@@ -105,6 +246,7 @@ impl fmt::Display for Type {
             Type::Union(ref union) => union.fmt(f),
             Type::Record(ref record) => record.fmt(f),
             Type::Function(ref function) => function.fmt(f),
+            Type::Never => write!(f, "never"),
         }
     }
 }
@@ -14,6 +14,33 @@ pub enum Error {
         location: codespan::ByteSpan,
     },
 
+    /// A top-level definition has the same name as a definition that was loaded earlier.
+    #[fail(display = "`{}` is already defined", name)]
+    Redefinition {
+        /// The name of the definition.
+        name: String,
+        /// The location of the new definition.
+        location: codespan::ByteSpan,
+        /// The location of the earlier definition, if known.
+        previous: Option<codespan::ByteSpan>,
+    },
+
+    /// A built-in function is applied to the wrong number of arguments.
+    #[fail(
+        display = "built-in `{}` takes {} argument(s) but {} were supplied",
+        name, expected, actual
+    )]
+    BuiltinArity {
+        /// The name of the built-in function.
+        name: String,
+        /// The number of arguments that the function takes.
+        expected: usize,
+        /// The number of arguments that were supplied.
+        actual: usize,
+        /// The location of the application.
+        location: codespan::ByteSpan,
+    },
+
     /// The IR has a type error.
     #[fail(display = "type error")]
     Type(
@@ -25,6 +52,57 @@ pub enum Error {
     #[fail(display = "constexpr error")]
     Constexpr(codespan::ByteSpan, #[cause] constexpr::error::Error),
 
+    /// A definition required by an interface is missing.
+    #[fail(display = "missing definition `{}` of type `{}`", name, expected)]
+    MissingDefinition {
+        /// The name of the missing definition.
+        name: String,
+        /// The type that the definition was expected to have.
+        expected: ty::Type,
+    },
+
+    /// A definition does not have the type required by an interface.
+    #[fail(
+        display = "definition `{}` does not match the expected interface",
+        name
+    )]
+    InterfaceMismatch {
+        /// The name of the mismatching definition.
+        name: String,
+        /// The location of the definition.
+        location: codespan::ByteSpan,
+        /// The underlying type error.
+        #[cause]
+        error: ty::error::Error<codespan::ByteSpan>,
+    },
+
+    /// A definition required by an interface does not have a known type, so it can't be checked.
+    #[fail(display = "the type of definition `{}` is not known", name)]
+    UntypedDefinition {
+        /// The name of the definition.
+        name: String,
+        /// The location of the definition.
+        location: codespan::ByteSpan,
+        /// The type that the definition was expected to have.
+        expected: ty::Type,
+    },
+
+    /// There is no top-level definition with a known type with the specified name.
+    #[fail(display = "unknown definition `{}`", name)]
+    UnknownDefinition {
+        /// The name of the unknown definition.
+        name: String,
+    },
+
+    /// A value does not conform to the type of a definition.
+    #[fail(display = "value does not match the type of `{}`", name)]
+    Value {
+        /// The name of the definition whose type the value was checked against.
+        name: String,
+        /// The mismatches that were found, in the order they were encountered in the value.
+        errors: Vec<ty::error::ValueError>,
+    },
+
     /// There were multiple IR errors.
     #[fail(display = "multiple IR errors")]
     Multiple {
@@ -48,6 +126,94 @@ impl diagnostic::Diagnostics for Error {
                     &self.to_string(),
                 );
             }
+            Error::BuiltinArity { location, .. } => {
+                builder.add_label(codespan_reporting::Label {
+                    span: location,
+                    message: None,
+                    style: codespan_reporting::LabelStyle::Primary,
+                });
+                builder.add_diagnostic(
+                    codespan_reporting::Severity::Error,
+                    None,
+                    &self.to_string(),
+                );
+            }
+            Error::Redefinition {
+                location, previous, ..
+            } => {
+                builder.add_label(codespan_reporting::Label {
+                    span: location,
+                    message: Some("redefined here".to_owned()),
+                    style: codespan_reporting::LabelStyle::Primary,
+                });
+                if let Some(previous) = previous {
+                    builder.add_label(codespan_reporting::Label {
+                        span: previous,
+                        message: Some("previously defined here".to_owned()),
+                        style: codespan_reporting::LabelStyle::Secondary,
+                    });
+                }
+                builder.add_diagnostic(
+                    codespan_reporting::Severity::Error,
+                    None,
+                    &self.to_string(),
+                );
+            }
+            Error::MissingDefinition { .. } => {
+                builder.add_diagnostic(
+                    codespan_reporting::Severity::Error,
+                    None,
+                    &self.to_string(),
+                );
+            }
+            Error::UnknownDefinition { .. } => {
+                builder.add_diagnostic(
+                    codespan_reporting::Severity::Error,
+                    None,
+                    &self.to_string(),
+                );
+            }
+            Error::Value { ref errors, .. } => {
+                for error in errors {
+                    builder.add_diagnostic(
+                        codespan_reporting::Severity::Error,
+                        None,
+                        &format!("{}: {}", self, error),
+                    );
+                }
+            }
+            Error::InterfaceMismatch {
+                location,
+                ref error,
+                ..
+            } => {
+                builder.add_label(codespan_reporting::Label {
+                    span: location,
+                    message: Some(error.to_string()),
+                    style: codespan_reporting::LabelStyle::Primary,
+                });
+                builder.add_diagnostic(
+                    codespan_reporting::Severity::Error,
+                    None,
+                    &self.to_string(),
+                );
+            }
+            Error::UntypedDefinition {
+                location,
+                ref expected,
+                ..
+            } => {
+                builder.add_label(codespan_reporting::Label {
+                    span: location,
+                    message: Some(format!("expected `{}`", expected)),
+                    style: codespan_reporting::LabelStyle::Primary,
+                });
+                builder.add_diagnostic(
+                    codespan_reporting::Severity::Error,
+                    None,
+                    &self.to_string(),
+                );
+            }
             Error::Type(entity, ref type_error) => match type_error {
                 ty::error::Error {
                     ref main_entity,
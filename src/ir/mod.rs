@@ -1,10 +1,15 @@
 //! Intermediate representation variables for the compiler and interpreter.
+use std::collections;
 use std::fmt;
+use std::sync;
 
 use specs;
+use specs_visitor;
 
 use crate::ast;
+use crate::module;
 use crate::parser;
+use crate::value;
 
 pub mod builder;
 pub mod component;
@@ -13,9 +18,18 @@ mod system;
 #[cfg(test)]
 mod tests;
 
+struct EntityCollector {
+    entities: sync::Mutex<Vec<specs::Entity>>,
+}
+
+/// The pointer size that values are laid out for unless another one is requested.
+pub const NATIVE_PTR_SIZE: usize = 8;
+
 /// A separate universe of the Tin intermediate representation.
 pub struct Ir {
     pub(crate) world: specs::World,
+    ptr_size: usize,
+    references: Vec<(codespan::ByteSpan, specs::Entity)>,
 }
 
 impl Ir {
@@ -23,15 +37,45 @@ impl Ir {
     pub fn new() -> Ir {
         let mut world = specs::World::new();
         component::register_all(&mut world);
-        Ir { world }
+        let ptr_size = NATIVE_PTR_SIZE;
+        let references = Vec::new();
+        Ir {
+            world,
+            ptr_size,
+            references,
+        }
     }
 
     /// Adds the specified AST module to the IR world.
-    pub fn load(&mut self, module: &ast::Module<parser::Context>) -> Result<(), error::Error> {
+    ///
+    /// References in the module are resolved against its own definitions, and then against the
+    /// top-level definitions loaded so far.  Redefining an already loaded definition is an error.
+    /// The module must have been parsed from the specified file.
+    pub fn load(
+        &mut self,
+        module: &ast::Module<parser::Context>,
+        file: &codespan::FileMap,
+    ) -> Result<(), error::Error> {
         use specs::world::Builder;
 
+        let scope = self.definitions();
+        {
+            let locations = self.world.read_storage::<component::location::Location>();
+            for variable in &module.variables {
+                if let Some(&previous) = scope.get(&variable.name.value) {
+                    return Err(error::Error::Redefinition {
+                        name: variable.name.value.clone(),
+                        location: variable.context.span,
+                        previous: locations.get(previous).map(|l| l.0),
+                    });
+                }
+            }
+        }
+
         let entity = self.world.create_entity().build();
-        builder::Builder::new(&mut self.world).add_module(entity, module)?;
+        let mut builder = builder::Builder::with_scope(&mut self.world, file, scope);
+        builder.add_module(entity, module)?;
+        self.references.extend(builder.into_references());
 
         let mut dispatcher = specs::DispatcherBuilder::new()
             .with(
@@ -48,15 +92,124 @@ impl Ir {
         Ok(())
     }
 
+    /// Adds the specified AST expression to the IR world as an anonymous function without
+    /// parameters.
+    ///
+    /// References in the expression are resolved against the top-level definitions loaded so far.
+    /// The expression must have been parsed from the specified file.  Returns the entity of the
+    /// created function; its result can be found using `thunk_result`.
+    ///
+    /// If the expression can't be added, everything that was created for it is removed again.
+    pub fn add_thunk(
+        &mut self,
+        expression: &ast::Expression<parser::Context>,
+        file: &codespan::FileMap,
+    ) -> Result<specs::Entity, error::Error> {
+        use specs::Join;
+
+        let existing = self
+            .world
+            .entities()
+            .join()
+            .collect::<collections::HashSet<_>>();
+
+        let result = self.build_thunk(expression, file);
+
+        if result.is_err() {
+            let created = self
+                .world
+                .entities()
+                .join()
+                .filter(|entity| !existing.contains(entity))
+                .collect::<Vec<_>>();
+            self.delete(&created);
+        }
+
+        result
+    }
+
+    fn build_thunk(
+        &mut self,
+        expression: &ast::Expression<parser::Context>,
+        file: &codespan::FileMap,
+    ) -> Result<specs::Entity, error::Error> {
+        use specs::world::Builder;
+
+        let scope = self.definitions();
+        let entity = self.world.create_entity().build();
+        let mut builder = builder::Builder::with_scope(&mut self.world, file, scope);
+        builder.add_thunk(entity, expression)?;
+        self.references.extend(builder.into_references());
+
+        let mut dispatcher = specs::DispatcherBuilder::new()
+            .with(
+                system::apply_replacements::System,
+                "apply_replacements",
+                &[],
+            )
+            .build();
+
+        dispatcher.dispatch(&self.world.res);
+
+        self.maintain()?;
+
+        Ok(entity)
+    }
+
+    /// The entity holding the result of a function created using `add_thunk`.
+    pub fn thunk_result(&self, thunk: specs::Entity) -> Option<specs::Entity> {
+        match self
+            .world
+            .read_storage::<component::element::Element>()
+            .get(thunk)
+        {
+            Some(component::element::Element::Closure(closure)) => Some(closure.result),
+            _ => None,
+        }
+    }
+
+    /// Removes a function created using `add_thunk` along with everything it contains, so that it
+    /// will no longer be compiled and so that any errors within it are forgotten.
+    pub fn remove_thunk(&mut self, thunk: specs::Entity) {
+        let definitions = self
+            .definitions()
+            .values()
+            .cloned()
+            .collect::<collections::HashSet<_>>();
+        let visited = self.reachable(thunk, &definitions);
+
+        self.delete(&visited.into_iter().collect::<Vec<_>>());
+    }
+
     /// Checks and infers types for all known variables.
     ///
     /// `resolve_references` should be called before this; types will not be inferred for unresolved
     /// references.
     pub fn check_types(&mut self) -> Result<(), error::Error> {
+        self.check_types_for_ptr_size(NATIVE_PTR_SIZE)
+    }
+
+    /// Checks and infers types for all known variables, laying out values for a target with the
+    /// specified pointer size.
+    ///
+    /// Layouts that were inferred for a different pointer size are discarded first.
+    pub fn check_types_for_ptr_size(&mut self, ptr_size: usize) -> Result<(), error::Error> {
+        if self.ptr_size != ptr_size {
+            self.world
+                .write_storage::<component::layout::Layout>()
+                .clear();
+            self.ptr_size = ptr_size;
+        }
+
         let mut dispatcher = specs::DispatcherBuilder::new()
             .with(system::infer_types::System, "infer_types", &[])
             .with(system::infer_constexpr::System, "infer_constexpr", &[])
-            .with(system::infer_layouts::System::new(8), "infer_layouts", &[])
+            .with(system::infer_escapes::System, "infer_escapes", &[])
+            .with(
+                system::infer_layouts::System::new(ptr_size),
+                "infer_layouts",
+                &["infer_types"],
+            )
             .build();
 
         dispatcher.dispatch(&self.world.res);
@@ -66,6 +219,286 @@ impl Ir {
         Ok(())
     }
 
+    /// Checks that the top-level definitions conform to the specified interface.
+    ///
+    /// Every field of the interface record must correspond to a top-level definition with exactly
+    /// the same type.  `check_types` should be called before this; definitions without inferred
+    /// types will be reported as mismatches.
+    pub fn check_interface(&self, interface: &component::ty::Record) -> Result<(), error::Error> {
+        let locations = self.world.read_storage::<component::location::Location>();
+        let types = self.world.read_storage::<component::ty::Type>();
+
+        let definitions = self.definitions();
+
+        let mut names = interface.fields.keys().collect::<Vec<_>>();
+        names.sort_unstable();
+
+        let mut errors = Vec::new();
+
+        for name in names {
+            let expected = &interface.fields[name];
+            if let Some(entity) = definitions.get(name) {
+                let location = locations.get(*entity).unwrap().0;
+
+                match types.get(*entity) {
+                    Some(actual) if actual == expected => {}
+                    Some(actual) => errors.push(error::Error::InterfaceMismatch {
+                        name: name.clone(),
+                        location,
+                        error: component::ty::error::Error {
+                            expected: component::ty::error::ExpectedType::Specific(
+                                expected.clone(),
+                            ),
+                            actual: actual.clone(),
+                            main_entity: location,
+                            aux_entities: vec![],
+                        },
+                    }),
+                    None => errors.push(error::Error::UntypedDefinition {
+                        name: name.clone(),
+                        location,
+                        expected: expected.clone(),
+                    }),
+                }
+            } else {
+                errors.push(error::Error::MissingDefinition {
+                    name: name.clone(),
+                    expected: expected.clone(),
+                });
+            }
+        }
+
+        if errors.len() > 1 {
+            Err(error::Error::Multiple { errors })
+        } else if let Some(error) = errors.pop() {
+            Err(error)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that the specified value conforms to the type of the specified top-level definition.
+    ///
+    /// `check_types` should be called before this, so that the type of the definition is known.
+    pub fn check_value(&self, name: &str, value: &value::Value) -> Result<(), error::Error> {
+        let types = self.world.read_storage::<component::ty::Type>();
+
+        let ty = self
+            .definitions()
+            .get(name)
+            .and_then(|entity| types.get(*entity))
+            .ok_or_else(|| error::Error::UnknownDefinition {
+                name: name.to_owned(),
+            })?;
+
+        let errors = ty.check_value(value);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(error::Error::Value {
+                name: name.to_owned(),
+                errors,
+            })
+        }
+    }
+
+    /// All of the top-level definitions, sorted by name.
+    ///
+    /// `check_types` should be called before this, so that the types of the definitions are known.
+    pub fn exports(&self) -> Vec<module::Export> {
+        use specs::Join;
+
+        let elements = self.world.read_storage::<component::element::Element>();
+        let symbols = self.world.read_storage::<component::symbol::Symbol>();
+        let types = self.world.read_storage::<component::ty::Type>();
+
+        let mut exports = (&elements, &symbols, &types)
+            .join()
+            .filter_map(|(element, symbol, ty)| match *element {
+                component::element::Element::Variable(ref variable) if symbol.is_top_level() => {
+                    Some(module::Export::new(variable.name.clone(), ty.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        exports.sort_unstable_by(|a, b| a.name().cmp(b.name()));
+
+        exports
+    }
+
+    /// The layouts of all of the top-level definitions, sorted by name.
+    ///
+    /// `check_types` should be called before this, so that the layouts of the definitions are
+    /// known.
+    pub fn layouts(&self) -> Vec<(String, component::layout::Layout)> {
+        use specs::Join;
+
+        let elements = self.world.read_storage::<component::element::Element>();
+        let layouts = self.world.read_storage::<component::layout::Layout>();
+        let symbols = self.world.read_storage::<component::symbol::Symbol>();
+
+        let mut layouts = (&elements, &symbols, &layouts)
+            .join()
+            .filter_map(|(element, symbol, layout)| match *element {
+                component::element::Element::Variable(ref variable) if symbol.is_top_level() => {
+                    Some((variable.name.clone(), layout.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        layouts.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        layouts
+    }
+
+    /// The type of the innermost expression at the specified location, if it is known.
+    ///
+    /// For an identifier, this is the type of the definition that it refers to.  `check_types`
+    /// should be called before this, so that the types of the expressions are known.
+    pub fn type_at(&self, index: codespan::ByteIndex) -> Option<component::ty::Type> {
+        use specs::Join;
+
+        let types = self.world.read_storage::<component::ty::Type>();
+
+        if let Some(definition) = self.reference_at(index) {
+            return types.get(definition).cloned();
+        }
+
+        let locations = self.world.read_storage::<component::location::Location>();
+
+        (&locations, &types)
+            .join()
+            .filter(|(location, _)| span_contains(location.0, index))
+            .min_by_key(|(location, _)| location.0.end() - location.0.start())
+            .map(|(_, ty)| ty.clone())
+    }
+
+    /// The location of the definition that the identifier at the specified location refers to.
+    pub fn definition_at(&self, index: codespan::ByteIndex) -> Option<codespan::ByteSpan> {
+        let locations = self.world.read_storage::<component::location::Location>();
+
+        self.reference_at(index)
+            .and_then(|definition| locations.get(definition))
+            .map(|location| location.0)
+    }
+
+    /// The locations of all of the top-level definitions, sorted by name.
+    pub fn definition_locations(&self) -> Vec<(String, codespan::ByteSpan)> {
+        let locations = self.world.read_storage::<component::location::Location>();
+
+        let mut definitions = self
+            .definitions()
+            .into_iter()
+            .filter_map(|(name, entity)| locations.get(entity).map(|l| (name, l.0)))
+            .collect::<Vec<_>>();
+        definitions.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        definitions
+    }
+
+    /// The values of all of the top-level definitions that are known at compile time, by name.
+    pub fn constants(&self) -> collections::HashMap<String, value::Value> {
+        use specs::Join;
+
+        let constexprs = self.world.read_storage::<component::constexpr::Constexpr>();
+        let elements = self.world.read_storage::<component::element::Element>();
+        let symbols = self.world.read_storage::<component::symbol::Symbol>();
+
+        (&elements, &symbols, &constexprs)
+            .join()
+            .filter_map(|(element, symbol, constexpr)| match *element {
+                component::element::Element::Variable(ref variable) if symbol.is_top_level() => {
+                    Some((variable.name.clone(), constexpr.value.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The definition referred to by the innermost identifier at the specified location.
+    ///
+    /// Captures are followed to the variables or parameters that they capture.
+    fn reference_at(&self, index: codespan::ByteIndex) -> Option<specs::Entity> {
+        let elements = self.world.read_storage::<component::element::Element>();
+
+        let mut definition = self
+            .references
+            .iter()
+            .filter(|(span, _)| span_contains(*span, index))
+            .min_by_key(|(span, _)| span.end() - span.start())
+            .map(|(_, definition)| *definition)?;
+
+        while let Some(component::element::Element::Capture(capture)) = elements.get(definition) {
+            definition = capture.captured;
+        }
+
+        Some(definition)
+    }
+
+    /// The entities that make up the top-level definition with the specified name, including
+    /// everything that it refers to.
+    pub fn definition_entities(&self, name: &str) -> Option<collections::HashSet<specs::Entity>> {
+        let definition = *self.definitions().get(name)?;
+        Some(self.reachable(definition, &collections::HashSet::new()))
+    }
+
+    /// All entities that can be reached from `root` without passing through `boundary`.
+    fn reachable(
+        &self,
+        root: specs::Entity,
+        boundary: &collections::HashSet<specs::Entity>,
+    ) -> collections::HashSet<specs::Entity> {
+        use specs_visitor::VisitEntities;
+
+        let elements = self.world.read_storage::<component::element::Element>();
+        let mut visited = collections::HashSet::new();
+        let mut pending = vec![root];
+
+        while let Some(entity) = pending.pop() {
+            if boundary.contains(&entity) || !visited.insert(entity) {
+                continue;
+            }
+
+            if let Some(element) = elements.get(entity) {
+                let collector = EntityCollector {
+                    entities: sync::Mutex::new(Vec::new()),
+                };
+                element.accept(&collector);
+                pending.extend(collector.entities.into_inner().unwrap());
+            }
+        }
+
+        visited
+    }
+
+    /// Deletes the specified entities, along with the references to them.
+    fn delete(&mut self, entities: &[specs::Entity]) {
+        self.world.delete_entities(entities).unwrap();
+        self.world.maintain();
+
+        let alive = self.world.entities();
+        self.references
+            .retain(|&(_, definition)| alive.is_alive(definition));
+    }
+
+    /// All of the top-level definitions, by name.
+    fn definitions(&self) -> collections::HashMap<String, specs::Entity> {
+        use specs::Join;
+
+        let elements = self.world.read_storage::<component::element::Element>();
+        let symbols = self.world.read_storage::<component::symbol::Symbol>();
+
+        (&self.world.entities(), &elements, &symbols)
+            .join()
+            .filter_map(|(entity, element, symbol)| match *element {
+                component::element::Element::Variable(ref variable) if symbol.is_top_level() => {
+                    Some((variable.name.clone(), entity))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
     fn maintain(&mut self) -> Result<(), error::Error> {
         use specs::Join;
 
@@ -118,8 +551,18 @@ impl Ir {
     }
 }
 
+impl specs_visitor::EntityVisitor for EntityCollector {
+    fn visit_entity(&self, entity: specs::Entity) {
+        self.entities.lock().unwrap().push(entity);
+    }
+}
+
 impl fmt::Debug for Ir {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Ir").finish()
     }
 }
+
+fn span_contains(span: codespan::ByteSpan, index: codespan::ByteIndex) -> bool {
+    span.start() <= index && index < span.end()
+}
@@ -1,13 +1,33 @@
 use specs;
 
+use std::collections;
 use std::ops;
 
 use crate::interpreter;
 use crate::ir::component::constexpr;
 use crate::ir::component::element;
+use crate::module;
 
 pub struct System;
 
+#[derive(Clone, Debug)]
+enum Inference {
+    Constexpr(constexpr::Constexpr),
+    Error(constexpr::error::Error),
+    /// A recoverable error raised within a `try` expression; it might be caught, so it is only
+    /// reported if it reaches an entity outside of any `try` expression.
+    Failure(module::Error),
+}
+
+/// The entities that are evaluated as part of `try` expressions.
+#[derive(Debug, Default)]
+struct Tries {
+    /// Entities within the body or handler of some `try` expression.
+    guarded: collections::HashSet<specs::Entity>,
+    /// The body of the `try` expression that catches each error.
+    bodies: collections::HashMap<specs::Entity, specs::Entity>,
+}
+
 impl<'a> specs::System<'a> for System {
     type SystemData = (
         specs::Entities<'a>,
@@ -21,11 +41,19 @@ impl<'a> specs::System<'a> for System {
         use crate::best_iter::BestIteratorFlatMap;
         use crate::best_iter::BestJoin;
 
+        let tries = System::tries(&elements);
+        let mut failures = collections::HashMap::new();
+
         loop {
             let new_constexprs: Vec<_> = (&entities, &elements, !&constexprs, !&errors)
                 .best_join()
                 .best_flat_map(|(entity, element, _, _)| {
-                    transpose(System::infer_constexpr(element, &constexprs)).map(|r| (entity, r))
+                    if failures.contains_key(&entity) {
+                        None
+                    } else {
+                        System::infer(entity, element, &constexprs, &failures, &tries)
+                            .map(|i| (entity, i))
+                    }
                 })
                 .best_collect();
 
@@ -34,14 +62,17 @@ impl<'a> specs::System<'a> for System {
                 break;
             }
 
-            for (entity, constexpr) in new_constexprs {
-                match constexpr {
-                    Ok(constexpr) => {
+            for (entity, inference) in new_constexprs {
+                match inference {
+                    Inference::Constexpr(constexpr) => {
                         constexprs.insert(entity, constexpr).unwrap();
                     }
-                    Err(error) => {
+                    Inference::Error(error) => {
                         errors.insert(entity, error).unwrap();
                     }
+                    Inference::Failure(error) => {
+                        failures.insert(entity, error);
+                    }
                 }
             }
         }
@@ -49,6 +80,58 @@ impl<'a> specs::System<'a> for System {
 }
 
 impl System {
+    fn infer<D>(
+        entity: specs::Entity,
+        element: &element::Element,
+        constexprs: &specs::Storage<constexpr::Constexpr, D>,
+        failures: &collections::HashMap<specs::Entity, module::Error>,
+        tries: &Tries,
+    ) -> Option<Inference>
+    where
+        D: ops::Deref<Target = specs::storage::MaskedStorage<constexpr::Constexpr>>,
+    {
+        let inference = match *element {
+            element::Element::Try(element::Try { body, handler, .. }) => {
+                if failures.contains_key(&body) {
+                    // The body always raises an error that is caught, so the handler is the result
+                    if let Some(constexpr) = constexprs.get(handler) {
+                        Some(Inference::Constexpr(constexpr.clone()))
+                    } else {
+                        failures.get(&handler).cloned().map(Inference::Failure)
+                    }
+                } else {
+                    constexprs.get(body).cloned().map(Inference::Constexpr)
+                }
+            }
+            element::Element::CaughtError(_) => tries
+                .bodies
+                .get(&entity)
+                .and_then(|body| failures.get(body))
+                .map(|error| {
+                    Inference::Constexpr(constexpr::Constexpr {
+                        value: interpreter::caught_error(error),
+                    })
+                }),
+            _ => match System::failed_operand(element, constexprs, failures) {
+                Some(error) => Some(Inference::Failure(error.clone())),
+                None => match System::infer_constexpr(element, constexprs) {
+                    Ok(constexpr) => constexpr.map(Inference::Constexpr),
+                    Err(constexpr::error::Error::Evaluation(
+                        interpreter::error::Error::EvaluationError(ref error),
+                    )) if error.kind().is_recoverable() => Some(Inference::Failure(error.clone())),
+                    Err(error) => Some(Inference::Error(error)),
+                },
+            },
+        };
+
+        match inference {
+            Some(Inference::Failure(error)) if !tries.guarded.contains(&entity) => Some(
+                Inference::Error(interpreter::error::Error::EvaluationError(error).into()),
+            ),
+            other => other,
+        }
+    }
+
     fn infer_constexpr<D>(
         element: &element::Element,
         constexprs: &specs::Storage<constexpr::Constexpr, D>,
@@ -61,6 +144,92 @@ impl System {
                 .map(|value| constexpr::Constexpr { value }),
         )
     }
+
+    /// The error of the first operand of the specified element that failed, as long as all of
+    /// the operands before it are known at compile time.
+    fn failed_operand<'a, D>(
+        element: &element::Element,
+        constexprs: &specs::Storage<constexpr::Constexpr, D>,
+        failures: &'a collections::HashMap<specs::Entity, module::Error>,
+    ) -> Option<&'a module::Error>
+    where
+        D: ops::Deref<Target = specs::storage::MaskedStorage<constexpr::Constexpr>>,
+    {
+        for operand in System::operands(element) {
+            if let Some(error) = failures.get(&operand) {
+                return Some(error);
+            } else if constexprs.get(operand).is_none() {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Finds the entities that are evaluated as part of `try` expressions.
+    ///
+    /// Variables and closures are evaluated where they are defined, so they are not considered to
+    /// be part of the `try` expressions that refer to them.
+    fn tries<D>(elements: &specs::Storage<element::Element, D>) -> Tries
+    where
+        D: ops::Deref<Target = specs::storage::MaskedStorage<element::Element>>,
+    {
+        use specs::Join;
+
+        let mut tries = Tries::default();
+        let mut pending = Vec::new();
+
+        for element in elements.join() {
+            if let element::Element::Try(element::Try {
+                body,
+                error,
+                handler,
+            }) = *element
+            {
+                tries.bodies.insert(error, body);
+                pending.push(body);
+                pending.push(handler);
+            }
+        }
+
+        while let Some(entity) = pending.pop() {
+            if tries.guarded.insert(entity) {
+                if let Some(element) = elements.get(entity) {
+                    pending.extend(System::operands(element));
+                }
+            }
+        }
+
+        tries
+    }
+
+    /// The operands of the specified element, in the order that they are evaluated in.
+    fn operands(element: &element::Element) -> Vec<specs::Entity> {
+        match *element {
+            element::Element::Tuple(element::Tuple { ref fields }) => fields.clone(),
+            element::Element::Record(element::Record { ref fields }) => {
+                let mut names = fields.keys().collect::<Vec<_>>();
+                names.sort();
+                names.into_iter().map(|name| fields[name]).collect()
+            }
+            element::Element::UnOp(element::UnOp { operand, .. }) => vec![operand],
+            element::Element::BiOp(element::BiOp { lhs, rhs, .. }) => vec![lhs, rhs],
+            element::Element::Select(element::Select { record, .. }) => vec![record],
+            element::Element::Apply(element::Apply { ref parameters, .. }) => parameters.clone(),
+            element::Element::Fail(element::Fail { message }) => vec![message],
+            element::Element::Assert(element::Assert {
+                condition,
+                message,
+                ref operands,
+                ..
+            }) => operands
+                .iter()
+                .cloned()
+                .chain(vec![condition, message])
+                .collect(),
+            element::Element::Try(element::Try { body, handler, .. }) => vec![body, handler],
+            _ => Vec::new(),
+        }
+    }
 }
 
 impl From<interpreter::error::Error> for constexpr::error::Error {
@@ -68,12 +237,3 @@ impl From<interpreter::error::Error> for constexpr::error::Error {
         constexpr::error::Error::Evaluation(e)
     }
 }
-
-// TODO: awaits https://github.com/rust-lang/rust/issues/47338
-pub fn transpose<T, E>(result: Result<Option<T>, E>) -> Option<Result<T, E>> {
-    match result {
-        Ok(Some(x)) => Some(Ok(x)),
-        Ok(None) => None,
-        Err(e) => Some(Err(e)),
-    }
-}
@@ -0,0 +1,82 @@
+use std::collections;
+
+use specs;
+
+use crate::ir::component::element;
+use crate::ir::component::escape;
+
+pub struct System;
+
+impl<'a> specs::System<'a> for System {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::ReadStorage<'a, element::Element>,
+        specs::WriteStorage<'a, escape::Escape>,
+    );
+
+    fn run(&mut self, (entities, elements, mut escapes): Self::SystemData) {
+        use specs::Join;
+
+        let mut escaping = collections::HashSet::new();
+        let mut pending = (&entities, &elements)
+            .join()
+            .flat_map(|(_, element)| System::escape_roots(element))
+            .collect::<Vec<_>>();
+
+        while let Some(entity) = pending.pop() {
+            if escaping.insert(entity) {
+                if let Some(element) = elements.get(entity) {
+                    pending.extend(System::escape_dependencies(element));
+                }
+            }
+        }
+
+        debug!("inferred escaping entities: {:?}", escaping);
+
+        for (entity, _) in (&entities, &elements).join() {
+            let escape = if escaping.contains(&entity) {
+                escape::Escape::Escapes
+            } else {
+                escape::Escape::Local
+            };
+            escapes.insert(entity, escape).unwrap();
+        }
+    }
+}
+
+impl System {
+    /// The entities whose values escape because of how they are used by the specified element.
+    fn escape_roots(element: &element::Element) -> Vec<specs::Entity> {
+        match *element {
+            // Callees are not analyzed, so anything passed to them might be retained.
+            element::Element::Apply(element::Apply { ref parameters, .. }) => parameters.clone(),
+            element::Element::Capture(element::Capture { captured, .. }) => vec![captured],
+            element::Element::Closure(element::Closure {
+                ref captures,
+                result,
+                ..
+            }) => captures.values().cloned().chain(Some(result)).collect(),
+            element::Element::Module(element::Module { ref variables }) => {
+                variables.values().cloned().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The entities whose values escape if the value of the specified element escapes.
+    fn escape_dependencies(element: &element::Element) -> Vec<specs::Entity> {
+        match *element {
+            element::Element::Tuple(element::Tuple { ref fields }) => fields.clone(),
+            element::Element::Record(element::Record { ref fields }) => {
+                fields.values().cloned().collect()
+            }
+            element::Element::Variable(element::Variable { initializer, .. }) => {
+                vec![initializer]
+            }
+            element::Element::Select(element::Select { record, .. }) => vec![record],
+            element::Element::Try(element::Try { body, handler, .. }) => vec![body, handler],
+            element::Element::Capture(element::Capture { captured, .. }) => vec![captured],
+            _ => Vec::new(),
+        }
+    }
+}
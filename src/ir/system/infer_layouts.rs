@@ -5,6 +5,7 @@ use specs;
 
 use crate::ir::component::element;
 use crate::ir::component::layout;
+use crate::ir::component::ty;
 use std::ops;
 
 pub struct System {
@@ -17,10 +18,11 @@ impl<'a> specs::System<'a> for System {
     type SystemData = (
         specs::Entities<'a>,
         specs::ReadStorage<'a, element::Element>,
+        specs::ReadStorage<'a, ty::Type>,
         specs::WriteStorage<'a, layout::Layout>,
     );
 
-    fn run(&mut self, (entities, elements, mut layouts): Self::SystemData) {
+    fn run(&mut self, (entities, elements, types, mut layouts): Self::SystemData) {
         use crate::best_iter::BestIteratorCollect;
         use crate::best_iter::BestIteratorFlatMap;
         use crate::best_iter::BestJoin;
@@ -29,7 +31,7 @@ impl<'a> specs::System<'a> for System {
             let new_layouts: Vec<_> = (&entities, &elements, !&layouts)
                 .best_join()
                 .best_flat_map(|(entity, element, _)| {
-                    self.infer_layout(element, &elements, &layouts)
+                    self.infer_layout(entity, element, &elements, &types, &layouts)
                         .map(|layout| (entity, layout))
                 })
                 .best_collect();
@@ -45,19 +47,29 @@ impl<'a> specs::System<'a> for System {
     }
 }
 
+#[derive(Debug)]
+struct FieldLayout {
+    size: usize,
+    alignment: usize,
+    layout: layout::Layout,
+}
+
 impl System {
     pub fn new(ptr_size: usize) -> System {
         System { ptr_size }
     }
 
-    fn infer_layout<DE, DL>(
+    fn infer_layout<DE, DT, DL>(
         &self,
+        entity: specs::Entity,
         element: &element::Element,
         elements: &specs::Storage<element::Element, DE>,
+        types: &specs::Storage<ty::Type, DT>,
         layouts: &specs::Storage<layout::Layout, DL>,
     ) -> Option<layout::Layout>
     where
         DE: ops::Deref<Target = specs::storage::MaskedStorage<element::Element>>,
+        DT: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
         DL: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
     {
         match *element {
@@ -65,10 +77,10 @@ impl System {
             element::Element::String(_) => Some(layout::Layout::scalar(self.ptr_size)),
             element::Element::Symbol(_) => Some(layout::Layout::zero()),
             element::Element::Tuple(element::Tuple { ref fields }) => {
-                self.infer_tuple_layout(fields, layouts)
+                self.infer_tuple_layout(fields, types, layouts)
             }
             element::Element::Record(element::Record { ref fields }) => {
-                self.infer_record_layout(fields, layouts)
+                self.infer_record_layout(fields, types, layouts)
             }
             element::Element::UnOp(element::UnOp { operator, operand }) => {
                 self.infer_un_op_layout(operator, operand, layouts)
@@ -82,10 +94,13 @@ impl System {
             element::Element::Select(element::Select { record, ref field }) => {
                 self.infer_select_layout(record, field, elements, layouts)
             }
-            element::Element::Apply(element::Apply {
-                function,
-                ref parameters,
-            }) => self.infer_apply_layout(function, parameters, layouts),
+            element::Element::Apply(_) => self.infer_apply_layout(entity, types),
+            element::Element::Fail(_) => Some(layout::Layout::zero()),
+            element::Element::Assert(_)
+            | element::Element::Try(_)
+            | element::Element::CaughtError(_) => {
+                types.get(entity).and_then(|ty| self.type_layout(ty))
+            }
             element::Element::Parameter(element::Parameter { signature, .. }) => {
                 self.infer_parameter_layout(signature, layouts)
             }
@@ -93,7 +108,7 @@ impl System {
                 self.infer_capture_layout(captured, layouts)
             }
             element::Element::Closure(element::Closure { ref captures, .. }) => {
-                self.infer_closure_layout(captures, layouts)
+                self.infer_closure_layout(captures, types, layouts)
             }
             element::Element::Module(element::Module { ref variables }) => {
                 self.infer_module_layout(variables, layouts)
@@ -114,88 +129,39 @@ impl System {
         }
     }
 
-    fn infer_tuple_layout<D>(
+    fn infer_tuple_layout<DT, DL>(
         &self,
         fields: &[specs::Entity],
-        layouts: &specs::Storage<layout::Layout, D>,
+        types: &specs::Storage<ty::Type, DT>,
+        layouts: &specs::Storage<layout::Layout, DL>,
     ) -> Option<layout::Layout>
     where
-        D: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
+        DT: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
+        DL: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
     {
-        if let Some(mut layouts) = fields
+        fields
             .iter()
             .enumerate()
-            .map(|(i, f)| layouts.get(*f).map(|l| (i, l)))
+            .map(|(i, f)| self.infer_field_layout(*f, types, layouts).map(|l| (i, l)))
             .collect::<Option<Vec<_>>>()
-        {
-            if layouts.is_empty() {
-                Some(layout::Layout::zero())
-            } else {
-                layouts.sort_unstable_by_key(|(i, l)| (usize::max_value() - l.size, *i));
-                let alignment = layouts.iter().map(|(_, l)| l.alignment).max().unwrap();
-                let mut size = 0;
-
-                let mut unnamed_fields = vec![layout::Offset::zero(); layouts.len()];
-
-                for (i, layout) in layouts {
-                    let offset = align_up(size, layout.alignment);
-                    size = offset + layout.size;
-                    let layout = layout.clone();
-                    unnamed_fields[i] = layout::Offset { offset, layout };
-                }
-
-                Some(layout::Layout::unnamed_fields(
-                    size,
-                    alignment,
-                    unnamed_fields,
-                ))
-            }
-        } else {
-            None
-        }
+            .map(|layouts| self.tuple_layout(layouts))
     }
 
-    fn infer_record_layout<D>(
+    fn infer_record_layout<DT, DL>(
         &self,
         fields: &collections::HashMap<String, specs::Entity>,
-        layouts: &specs::Storage<layout::Layout, D>,
+        types: &specs::Storage<ty::Type, DT>,
+        layouts: &specs::Storage<layout::Layout, DL>,
     ) -> Option<layout::Layout>
     where
-        D: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
+        DT: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
+        DL: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
     {
-        if let Some(mut layouts) = fields
+        fields
             .iter()
-            .map(|(n, f)| layouts.get(*f).map(|l| (n, l)))
+            .map(|(n, f)| self.infer_field_layout(*f, types, layouts).map(|l| (n, l)))
             .collect::<Option<Vec<_>>>()
-        {
-            if layouts.is_empty() {
-                Some(layout::Layout::zero())
-            } else {
-                layouts.sort_unstable_by_key(|(n, l)| (usize::max_value() - l.size, n.as_str()));
-                let alignment = layouts.iter().map(|(_, l)| l.alignment).max().unwrap();
-                let mut size = 0;
-
-                let named_fields = layouts
-                    .into_iter()
-                    .map(|(field, layout)| {
-                        let offset = align_up(size, layout.alignment);
-                        size = offset + layout.size;
-                        let field = field.clone();
-                        let layout = layout.clone();
-                        let offset_layout = layout::Offset { offset, layout };
-
-                        layout::NamedField {
-                            field,
-                            offset_layout,
-                        }
-                    })
-                    .collect::<Vec<_>>();
-
-                Some(layout::Layout::named_fields(size, alignment, named_fields))
-            }
-        } else {
-            None
-        }
+            .map(|layouts| self.record_layout(layouts))
     }
 
     fn infer_un_op_layout<D>(
@@ -316,56 +282,15 @@ impl System {
 
     fn infer_apply_layout<D>(
         &self,
-        _function: specs::Entity,
-        _parameters: &[specs::Entity],
-        _layouts: &specs::Storage<layout::Layout, D>,
+        entity: specs::Entity,
+        types: &specs::Storage<ty::Type, D>,
     ) -> Option<layout::Layout>
     where
-        D: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
+        D: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
     {
-        // TODO
-        None
-        /*
-        match types.get(function) {
-            None => {
-                trace!("inference failure: missing function type for apply");
-                None
-            }
-            Some(f) => match f {
-                ty::Type::Function(ty::Function {
-                    parameters: ref formal_parameters,
-                    result,
-                }) => {
-                    if let Some(parameters) = parameters
-                        .iter()
-                        .map(|p| types.get(*p).cloned())
-                        .collect::<Option<Vec<_>>>()
-                    {
-                        if parameters == *formal_parameters {
-                            Some((**result).clone())
-                        } else {
-                            Some(ty::Type::Conflict(ty::Conflict {
-                                expected: Box::new(ty::Type::Function(ty::Function {
-                                    parameters,
-                                    result: Box::new(ty::Type::Any),
-                                })),
-                                actual: Box::new(f.clone()),
-                            }))
-                        }
-                    } else {
-                        None
-                    }
-                }
-                something => Some(ty::Type::Conflict(ty::Conflict {
-                    expected: Box::new(ty::Type::Function(ty::Function {
-                        parameters: vec![ty::Type::Any],
-                        result: Box::new(ty::Type::Any),
-                    })),
-                    actual: Box::new(something.clone()),
-                })),
-            },
-        }
-        */
+        // The layout of the result can't be derived from the function being applied, since it
+        // might not be known statically, so derive it from the result type instead.
+        types.get(entity).and_then(|ty| self.type_layout(ty))
     }
 
     fn infer_parameter_layout<D>(
@@ -390,17 +315,19 @@ impl System {
         layouts.get(capture).cloned()
     }
 
-    fn infer_closure_layout<D>(
+    fn infer_closure_layout<DT, DL>(
         &self,
         captures: &collections::HashMap<String, specs::Entity>,
-        layouts: &specs::Storage<layout::Layout, D>,
+        types: &specs::Storage<ty::Type, DT>,
+        layouts: &specs::Storage<layout::Layout, DL>,
     ) -> Option<layout::Layout>
     where
-        D: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
+        DT: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
+        DL: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
     {
         if let Some(mut capture_layouts) = captures
             .iter()
-            .map(|(n, f)| layouts.get(*f).map(|l| (n, l)))
+            .map(|(n, f)| self.infer_field_layout(*f, types, layouts).map(|l| (n, l)))
             .collect::<Option<Vec<_>>>()
         {
             let unnamed_fields = vec![layout::Offset {
@@ -419,11 +346,11 @@ impl System {
 
             let named_fields = capture_layouts
                 .into_iter()
-                .map(|(field, layout)| {
-                    let offset = align_up(size, layout.alignment);
-                    size = offset + layout.size;
+                .map(|(field, field_layout)| {
+                    let offset = align_up(size, field_layout.alignment);
+                    size = offset + field_layout.size;
                     let field = field.clone();
-                    let layout = layout.clone();
+                    let layout = field_layout.layout;
                     let offset_layout = layout::Offset { offset, layout };
 
                     layout::NamedField {
@@ -444,6 +371,23 @@ impl System {
         }
     }
 
+    /// Infers how the value of an entity is stored when it is embedded in some other value.
+    fn infer_field_layout<DT, DL>(
+        &self,
+        entity: specs::Entity,
+        types: &specs::Storage<ty::Type, DT>,
+        layouts: &specs::Storage<layout::Layout, DL>,
+    ) -> Option<FieldLayout>
+    where
+        DT: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
+        DL: ops::Deref<Target = specs::storage::MaskedStorage<layout::Layout>>,
+    {
+        let layout = layouts.get(entity)?.clone();
+        let ty = types.get(entity)?;
+
+        Some(self.field_layout(ty, layout))
+    }
+
     fn infer_module_layout<D>(
         &self,
         _variables: &collections::HashMap<String, specs::Entity>,
@@ -462,6 +406,115 @@ impl System {
             .map(|fields| ty::Type::Record(ty::Record { fields }))
             */
     }
+
+    /// Computes the layout of values of the specified type.
+    ///
+    /// Returns `None` for types whose layout depends on more than just the type, such as function
+    /// types whose layout depends on what the closure captures.
+    fn type_layout(&self, ty: &ty::Type) -> Option<layout::Layout> {
+        match *ty {
+            ty::Type::Number(ref n) => Some(layout::Layout::scalar(number_size(n))),
+            ty::Type::String => Some(layout::Layout::scalar(self.ptr_size)),
+            ty::Type::Symbol(_) | ty::Type::Union(_) | ty::Type::Never => {
+                Some(layout::Layout::zero())
+            }
+            ty::Type::Tuple(ty::Tuple { ref fields }) => fields
+                .iter()
+                .enumerate()
+                .map(|(i, f)| self.type_field_layout(f).map(|l| (i, l)))
+                .collect::<Option<Vec<_>>>()
+                .map(|layouts| self.tuple_layout(layouts)),
+            ty::Type::Record(ty::Record { ref fields }) => fields
+                .iter()
+                .map(|(n, f)| self.type_field_layout(f).map(|l| (n, l)))
+                .collect::<Option<Vec<_>>>()
+                .map(|layouts| self.record_layout(layouts)),
+            ty::Type::Function(_) => None,
+        }
+    }
+
+    fn type_field_layout(&self, ty: &ty::Type) -> Option<FieldLayout> {
+        self.type_layout(ty)
+            .map(|layout| self.field_layout(ty, layout))
+    }
+
+    /// Describes how a value of the specified type and layout is stored when it is embedded in
+    /// some other value.
+    ///
+    /// Values that are passed around as pointers (strings, tuples, records and closures) are
+    /// stored as pointers, so only take up the size of a pointer in the containing value.
+    fn field_layout(&self, ty: &ty::Type, layout: layout::Layout) -> FieldLayout {
+        let (size, alignment) = match *ty {
+            ty::Type::String | ty::Type::Tuple(_) | ty::Type::Record(_) | ty::Type::Function(_) => {
+                (self.ptr_size, self.ptr_size)
+            }
+            _ => (layout.size, layout.alignment),
+        };
+
+        FieldLayout {
+            size,
+            alignment,
+            layout,
+        }
+    }
+
+    fn tuple_layout(&self, mut layouts: Vec<(usize, FieldLayout)>) -> layout::Layout {
+        if layouts.is_empty() {
+            layout::Layout::zero()
+        } else {
+            layouts.sort_unstable_by_key(|(i, l)| (usize::max_value() - l.size, *i));
+            let alignment = layouts.iter().map(|(_, l)| l.alignment).max().unwrap();
+            let mut size = 0;
+
+            let mut unnamed_fields = vec![layout::Offset::zero(); layouts.len()];
+
+            for (i, field_layout) in layouts {
+                let offset = align_up(size, field_layout.alignment);
+                size = offset + field_layout.size;
+                let layout = field_layout.layout;
+                unnamed_fields[i] = layout::Offset { offset, layout };
+            }
+
+            layout::Layout::unnamed_fields(size, alignment, unnamed_fields)
+        }
+    }
+
+    fn record_layout(&self, mut layouts: Vec<(&String, FieldLayout)>) -> layout::Layout {
+        if layouts.is_empty() {
+            layout::Layout::zero()
+        } else {
+            layouts.sort_unstable_by_key(|(n, l)| (usize::max_value() - l.size, n.as_str()));
+            let alignment = layouts.iter().map(|(_, l)| l.alignment).max().unwrap();
+            let mut size = 0;
+
+            let named_fields = layouts
+                .into_iter()
+                .map(|(field, field_layout)| {
+                    let offset = align_up(size, field_layout.alignment);
+                    size = offset + field_layout.size;
+                    let field = field.clone();
+                    let layout = field_layout.layout;
+                    let offset_layout = layout::Offset { offset, layout };
+
+                    layout::NamedField {
+                        field,
+                        offset_layout,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            layout::Layout::named_fields(size, alignment, named_fields)
+        }
+    }
+}
+
+fn number_size(number: &ty::Number) -> usize {
+    match *number {
+        ty::Number::U8 | ty::Number::I8 => 1,
+        ty::Number::U16 | ty::Number::I16 => 2,
+        ty::Number::U32 | ty::Number::I32 | ty::Number::F32 => 4,
+        ty::Number::U64 | ty::Number::I64 | ty::Number::F64 => 8,
+    }
 }
 
 #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
@@ -4,6 +4,7 @@ use specs;
 
 use crate::ir::component::element;
 use crate::ir::component::ty;
+use crate::module;
 use std::ops;
 
 lazy_static! {
@@ -18,6 +19,23 @@ lazy_static! {
         ];
         ty::Type::Union(ty::Union { alternatives })
     };
+    static ref ERROR_TYPE: ty::Type = {
+        let mut alternatives = module::ErrorKind::RECOVERABLE
+            .iter()
+            .map(|kind| ty::Symbol {
+                label: kind.label().to_owned(),
+            })
+            .collect::<Vec<_>>();
+        alternatives.sort();
+
+        let mut fields = collections::HashMap::new();
+        fields.insert(
+            "kind".to_owned(),
+            ty::Type::Union(ty::Union { alternatives }),
+        );
+        fields.insert("message".to_owned(), ty::Type::String);
+        ty::Type::Record(ty::Record { fields })
+    };
 }
 
 pub struct System;
@@ -106,6 +124,14 @@ where
             function,
             ref parameters,
         }) => infer_apply_type(function, parameters, types),
+        element::Element::Fail(element::Fail { message }) => infer_fail_type(message, types),
+        element::Element::Assert(element::Assert {
+            condition, message, ..
+        }) => infer_assert_type(condition, message, types),
+        element::Element::Try(element::Try { body, handler, .. }) => {
+            infer_try_type(body, handler, types)
+        }
+        element::Element::CaughtError(_) => Some(Inference::Type(ERROR_TYPE.clone())),
         element::Element::Parameter(element::Parameter { signature, .. }) => {
             infer_parameter_type(signature, types)
         }
@@ -150,7 +176,13 @@ where
         .iter()
         .map(|f| types.get(*f).cloned())
         .collect::<Option<Vec<_>>>()
-        .map(|fields| Inference::Type(ty::Type::Tuple(ty::Tuple { fields })))
+        .map(|fields| {
+            if fields.contains(&ty::Type::Never) {
+                Inference::Type(ty::Type::Never)
+            } else {
+                Inference::Type(ty::Type::Tuple(ty::Tuple { fields }))
+            }
+        })
 }
 
 fn infer_record_type<D>(
@@ -164,7 +196,13 @@ where
         .iter()
         .map(|(k, v)| types.get(*v).map(|t| (k.clone(), t.clone())))
         .collect::<Option<collections::HashMap<_, _>>>()
-        .map(|fields| Inference::Type(ty::Type::Record(ty::Record { fields })))
+        .map(|fields| {
+            if fields.values().any(|t| *t == ty::Type::Never) {
+                Inference::Type(ty::Type::Never)
+            } else {
+                Inference::Type(ty::Type::Record(ty::Record { fields }))
+            }
+        })
 }
 
 fn infer_un_op_type<D>(
@@ -176,6 +214,7 @@ where
     D: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
 {
     types.get(operand).map(|ty| match operator {
+        _ if *ty == ty::Type::Never => Inference::Type(ty::Type::Never),
         element::UnOperator::Not => {
             if *ty == *BOOL_TYPE {
                 Inference::Type(BOOL_TYPE.clone())
@@ -213,6 +252,9 @@ where
 {
     // TODO: check scalar type semantics so e.g. records can't be divided.
     match (types.get(lhs), types.get(rhs)) {
+        (Some(ty::Type::Never), Some(_)) | (Some(_), Some(ty::Type::Never)) => {
+            Some(Inference::Type(ty::Type::Never))
+        }
         (Some(lhs_ty), Some(rhs_ty)) => {
             let result = match operator {
                 element::BiOperator::Eq
@@ -271,6 +313,7 @@ where
 {
     match types.get(record) {
         None => None,
+        Some(ty::Type::Never) => Some(Inference::Type(ty::Type::Never)),
         Some(t) => match t {
             ty::Type::Record(ty::Record { ref fields }) => {
                 if let Some(t) = fields.get(field) {
@@ -327,6 +370,7 @@ where
             trace!("inference failure: missing function type for apply");
             None
         }
+        Some(ty::Type::Never) => Some(Inference::Type(ty::Type::Never)),
         Some(f) => match f {
             ty::Type::Function(ty::Function {
                 parameters: ref formal_parameters,
@@ -337,7 +381,9 @@ where
                     .map(|p| types.get(*p).cloned())
                     .collect::<Option<Vec<_>>>()
                 {
-                    if parameters == *formal_parameters {
+                    if parameters.contains(&ty::Type::Never) {
+                        Some(Inference::Type(ty::Type::Never))
+                    } else if parameters == *formal_parameters {
                         Some(Inference::Type((**result).clone()))
                     } else {
                         // TODO: create a diff of expected and actual parameters
@@ -376,6 +422,86 @@ where
     }
 }
 
+fn infer_fail_type<D>(
+    message: specs::Entity,
+    types: &specs::Storage<ty::Type, D>,
+) -> InferenceResult<ty::Type>
+where
+    D: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
+{
+    types.get(message).map(|ty| match ty {
+        ty::Type::String | ty::Type::Never => Inference::Type(ty::Type::Never),
+        other => Inference::Error(ty::error::Error {
+            expected: ty::error::ExpectedType::Specific(ty::Type::String),
+            actual: other.clone(),
+            main_entity: message,
+            aux_entities: vec![],
+        }),
+    })
+}
+
+fn infer_assert_type<D>(
+    condition: specs::Entity,
+    message: specs::Entity,
+    types: &specs::Storage<ty::Type, D>,
+) -> InferenceResult<ty::Type>
+where
+    D: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
+{
+    let condition_ty = types.get(condition)?;
+    let message_ty = types.get(message)?;
+
+    if *condition_ty != *BOOL_TYPE && *condition_ty != ty::Type::Never {
+        Some(Inference::Error(ty::error::Error {
+            expected: ty::error::ExpectedType::Specific(BOOL_TYPE.clone()),
+            actual: condition_ty.clone(),
+            main_entity: condition,
+            aux_entities: vec![],
+        }))
+    } else if *message_ty != ty::Type::String && *message_ty != ty::Type::Never {
+        Some(Inference::Error(ty::error::Error {
+            expected: ty::error::ExpectedType::Specific(ty::Type::String),
+            actual: message_ty.clone(),
+            main_entity: message,
+            aux_entities: vec![],
+        }))
+    } else if *condition_ty == ty::Type::Never || *message_ty == ty::Type::Never {
+        Some(Inference::Type(ty::Type::Never))
+    } else {
+        Some(Inference::Type(ty::Type::Symbol(ty::Symbol {
+            label: "t".to_owned(),
+        })))
+    }
+}
+
+fn infer_try_type<D>(
+    body: specs::Entity,
+    handler: specs::Entity,
+    types: &specs::Storage<ty::Type, D>,
+) -> InferenceResult<ty::Type>
+where
+    D: ops::Deref<Target = specs::storage::MaskedStorage<ty::Type>>,
+{
+    let body_ty = types.get(body)?;
+    let handler_ty = types.get(handler)?;
+
+    if body_ty == handler_ty || *handler_ty == ty::Type::Never {
+        Some(Inference::Type(body_ty.clone()))
+    } else if *body_ty == ty::Type::Never {
+        Some(Inference::Type(handler_ty.clone()))
+    } else {
+        Some(Inference::Error(ty::error::Error {
+            expected: ty::error::ExpectedType::Specific(body_ty.clone()),
+            actual: handler_ty.clone(),
+            main_entity: handler,
+            aux_entities: vec![ty::error::AuxEntity {
+                entity: body,
+                label: format!("try body has type `{}`", body_ty),
+            }],
+        }))
+    }
+}
+
 fn infer_parameter_type<D>(
     signature: specs::Entity,
     types: &specs::Storage<ty::Type, D>,
@@ -412,7 +538,8 @@ where
     {
         if let Some(result_ty) = types.get(result) {
             if let Some(signature_ty) = types.get(signature) {
-                if signature_ty == result_ty {
+                // A result that never produces a value is fine for any signature
+                if signature_ty == result_ty || *result_ty == ty::Type::Never {
                     let result = Box::new(signature_ty.clone());
                     Some(Inference::Type(ty::Type::Function(ty::Function {
                         parameters,
@@ -1,4 +1,5 @@
 pub mod apply_replacements;
 pub mod infer_constexpr;
+pub mod infer_escapes;
 pub mod infer_layouts;
 pub mod infer_types;
@@ -122,18 +122,303 @@ a = || -> u32 {
     Ok(())
 }
 
+#[test]
+fn fail_in_any_position() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+a = |x: u32, reason: ""| -> u32 {
+  y = x + fail(reason);
+  y * 2u32
+};
+b = |reason: ""| -> (f32, :t) { (fail(reason), :t) };
+"#;
+    let expected = Ok(());
+    let actual = check_module("fail_in_any_position", source);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn fail_type_error() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+a = |x: u32| -> u32 {
+  fail(x)
+};
+"#;
+    let expected = Err(r#"error: type error
+- <fail_type_error>:3:3
+3 |   fail(x)
+  |   ^^^^^^^
+- <fail_type_error>:3:8
+3 |   fail(x)
+  |        ^ expected `str` but got `u32`
+"#
+    .to_owned());
+    let actual = check_module("fail_type_error", source);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn try_in_any_position() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+a = |x: u32| -> u32 { try { x / 2u32 } catch |e| { fail(e.message) } };
+b = |x: u32| -> "" { try { fail("nope") } catch |x| { x.message } };
+c = |x: u32| -> u32 { (try { x } catch |e| { 1u32 / 0u32 }) + 1u32 };
+"#;
+    let expected = Ok(());
+    let actual = check_module("try_in_any_position", source);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn try_type_error() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+a = |x: u32| -> u32 {
+  try { x + 1u32 } catch |e| { e.message }
+};
+"#;
+    let expected = Err(r#"error: type error
+- <try_type_error>:3:3
+3 |   try { x + 1u32 } catch |e| { e.message }
+  |   ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+- <try_type_error>:3:32
+3 |   try { x + 1u32 } catch |e| { e.message }
+  |                                ^^^^^^^^^ expected `u32` but got `str`
+- <try_type_error>:3:9
+3 |   try { x + 1u32 } catch |e| { e.message }
+  |         -------- try body has type `u32`
+"#
+    .to_owned());
+    let actual = check_module("try_type_error", source);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn fail_arity() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+a = || -> u32 { fail() };
+"#;
+    let expected = Err(
+        r#"error: built-in `fail` takes 1 argument(s) but 0 were supplied
+- <fail_arity>:2:17
+2 | a = || -> u32 { fail() };
+  |                 ^^^^^^
+"#
+        .to_owned(),
+    );
+    let actual = check_module("fail_arity", source);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn assert_type_error() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+a = |x: u32| -> :t {
+  assert(x, "x must be set")
+};
+"#;
+    let expected = Err(r#"error: type error
+- <assert_type_error>:3:3
+3 |   assert(x, "x must be set")
+  |   ^^^^^^^^^^^^^^^^^^^^^^^^^^
+- <assert_type_error>:3:10
+3 |   assert(x, "x must be set")
+  |          ^ expected `:f | :t` but got `u32`
+"#
+    .to_owned());
+    let actual = check_module("assert_type_error", source);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn assert_arity() -> Result<(), failure::Error> {
+    let _ = env_logger::try_init();
+
+    let source = r#"
+a = |x: u32| -> :t { assert(x < 2u32) };
+"#;
+    let expected = Err(
+        r#"error: built-in `assert` takes 2 argument(s) but 1 were supplied
+- <assert_arity>:2:22
+2 | a = |x: u32| -> :t { assert(x < 2u32) };
+  |                      ^^^^^^^^^^^^^^^^
+"#
+        .to_owned(),
+    );
+    let actual = check_module("assert_arity", source);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn interface_mismatch() -> Result<(), failure::Error> {
+    use std::collections;
+
+    let _ = env_logger::try_init();
+
+    let source = r#"
+init = || -> i32 { 0i32 };
+handle = |id: u64| -> u64 { id };
+"#;
+
+    let mut fields = collections::HashMap::new();
+    fields.insert(
+        "init".to_owned(),
+        component::ty::Type::Function(component::ty::Function {
+            parameters: vec![],
+            result: Box::new(component::ty::Type::Number(component::ty::Number::I32)),
+        }),
+    );
+    fields.insert(
+        "handle".to_owned(),
+        component::ty::Type::Function(component::ty::Function {
+            parameters: vec![component::ty::Type::Number(component::ty::Number::U64)],
+            result: Box::new(component::ty::Type::String),
+        }),
+    );
+    fields.insert("version".to_owned(), component::ty::Type::String);
+    let interface = component::ty::Record { fields };
+
+    let expected = Err(
+        r#"error: definition `handle` does not match the expected interface
+- <interface_mismatch>:3:1
+3 | handle = |id: u64| -> u64 { id };
+  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ expected `|u64| -> str` but got `|u64| -> u64`
+error: missing definition `version` of type `str`
+"#
+        .to_owned(),
+    );
+    let actual = check_module_interface("interface_mismatch", source, &interface);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn untyped_interface_definition() -> Result<(), failure::Error> {
+    use crate::parser::Parse;
+    use std::collections;
+
+    let _ = env_logger::try_init();
+
+    let source = r#"
+version = "1.0";
+"#;
+
+    let mut fields = collections::HashMap::new();
+    fields.insert("version".to_owned(), component::ty::Type::String);
+    let interface = component::ty::Record { fields };
+
+    let mut codemap = codespan::CodeMap::new();
+    let file = codemap.add_filemap(
+        codespan::FileName::Virtual("untyped_interface_definition".into()),
+        source.to_owned(),
+    );
+    let ast_module = ast::Module::parse(file.span(), source)?;
+    let mut ir = Ir::new();
+    ir.load(&ast_module, &file)?;
+
+    // Types have not been inferred yet
+    let expected = Err(r#"error: the type of definition `version` is not known
+- <untyped_interface_definition>:2:1
+2 | version = "1.0";
+  | ^^^^^^^^^^^^^^^ expected `str`
+"#
+    .to_owned());
+    let actual = ir
+        .check_interface(&interface)
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e));
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn value_mismatch() -> Result<(), failure::Error> {
+    use std::collections;
+
+    use crate::value;
+
+    let _ = env_logger::try_init();
+
+    let source = r#"
+Person = { name: "", address: { street: "", zip: 0u32 }, tags: ("", "") };
+"#;
+
+    let mut address = collections::HashMap::new();
+    address.insert("street".to_owned(), value::Value::string("Main Street"));
+    address.insert("zip".to_owned(), value::Value::string("12345"));
+    let mut fields = collections::HashMap::new();
+    fields.insert(
+        "address".to_owned(),
+        value::Value::record(value::Record { fields: address }),
+    );
+    fields.insert("age".to_owned(), value::Value::from(42u32));
+    fields.insert(
+        "tags".to_owned(),
+        value::Value::tuple(value::Tuple {
+            fields: vec![value::Value::string("a"), value::Value::from(1u8)],
+        }),
+    );
+    let person = value::Value::record(value::Record { fields });
+
+    let expected = Err(
+        r#"error: value does not match the type of `Person`: .address.zip: expected `u32` but got `str`
+error: value does not match the type of `Person`: .age: expected nothing but got `u32`
+error: value does not match the type of `Person`: .name: expected `str` but got nothing
+error: value does not match the type of `Person`: .tags.1: expected `str` but got `u8`
+"#
+        .to_owned(),
+    );
+    let actual = check_module_value("value_mismatch", source, "Person", &person);
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
 fn check_module(name: &'static str, source: &str) -> Result<(), String> {
     use crate::parser::Parse;
 
     let mut codemap = codespan::CodeMap::new();
-    let span = codemap
-        .add_filemap(codespan::FileName::Virtual(name.into()), source.to_owned())
-        .span();
-    let ast_module =
-        ast::Module::parse(span, source).map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+    let file = codemap.add_filemap(codespan::FileName::Virtual(name.into()), source.to_owned());
+    let ast_module = ast::Module::parse(file.span(), source)
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
 
     let mut ir = Ir::new();
-    ir.load(&ast_module)
+    ir.load(&ast_module, &file)
         .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
     ir.check_types()
         .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
@@ -142,3 +427,50 @@ fn check_module(name: &'static str, source: &str) -> Result<(), String> {
 
     Ok(())
 }
+
+fn check_module_interface(
+    name: &'static str,
+    source: &str,
+    interface: &component::ty::Record,
+) -> Result<(), String> {
+    use crate::parser::Parse;
+
+    let mut codemap = codespan::CodeMap::new();
+    let file = codemap.add_filemap(codespan::FileName::Virtual(name.into()), source.to_owned());
+    let ast_module = ast::Module::parse(file.span(), source)
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+
+    let mut ir = Ir::new();
+    ir.load(&ast_module, &file)
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+    ir.check_types()
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+    ir.check_interface(interface)
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+
+    Ok(())
+}
+
+fn check_module_value(
+    name: &'static str,
+    source: &str,
+    definition: &str,
+    value: &crate::value::Value,
+) -> Result<(), String> {
+    use crate::parser::Parse;
+
+    let mut codemap = codespan::CodeMap::new();
+    let file = codemap.add_filemap(codespan::FileName::Virtual(name.into()), source.to_owned());
+    let ast_module = ast::Module::parse(file.span(), source)
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+
+    let mut ir = Ir::new();
+    ir.load(&ast_module, &file)
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+    ir.check_types()
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+    ir.check_value(definition, value)
+        .map_err(|e| crate::diagnostic::to_string(&codemap, &e))?;
+
+    Ok(())
+}
@@ -55,6 +55,8 @@ extern crate lazy_static;
 #[macro_use]
 extern crate log;
 #[macro_use]
+extern crate serde_json;
+#[macro_use]
 extern crate specs_derive;
 #[macro_use]
 extern crate specs_visitor_derive;
@@ -63,6 +65,7 @@ extern crate specs_visitor_derive;
 extern crate pretty_assertions;
 
 use std::fmt;
+use std::sync;
 
 mod ast;
 mod best_iter;
@@ -70,18 +73,26 @@ mod codegen;
 mod interpreter;
 mod ir;
 mod parser;
-mod value;
 
 #[cfg(test)]
 mod test_util;
 
 pub mod diagnostic;
 pub mod error;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
 pub mod graph;
+pub mod interpreted;
+pub mod lsp;
 pub mod module;
+pub mod repl;
+pub mod testing;
+pub mod value;
 
 pub use crate::error::Error;
 pub use crate::error::Result;
+pub use crate::ir::component::layout;
+pub use crate::ir::component::ty;
 
 /// An instance of the Tin runtime.
 pub struct Tin {
@@ -115,7 +126,8 @@ impl Tin {
     ///
     /// Calling this function several times will load source code into the same module scope, but
     /// references in code from earlier calls will not be able to refer to definitions from code
-    /// from later calls.  Any references will eagerly be resolved and fail early.
+    /// from later calls.  Any references will eagerly be resolved and fail early.  A definition
+    /// that was loaded by an earlier call cannot be redefined.
     ///
     /// # Examples
     ///
@@ -131,6 +143,20 @@ impl Tin {
     /// # }
     /// ```
     ///
+    /// Later calls can refer to definitions from earlier calls:
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("answer.tn", "answer = 42i32;")?;
+    /// tin.load("main.tn", "main = || -> i32 { answer };")?;
+    /// assert!(tin.load("again.tn", "answer = 43i32;").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
     /// Unresolved references are not allowed:
     ///
     /// ```
@@ -147,16 +173,87 @@ impl Tin {
     where
         F: Into<codespan::FileName>,
     {
-        let span = self
-            .codemap
+        let file = self.add_file(file_name, source);
+        self.load_file(&file)
+    }
+
+    /// Adds the specified source code to the code map, returning the file that it was added as.
+    fn add_file<F>(&mut self, file_name: F, source: &str) -> sync::Arc<codespan::FileMap>
+    where
+        F: Into<codespan::FileName>,
+    {
+        self.codemap
             .add_filemap(file_name.into(), source.to_owned())
-            .span();
-        let module = parser::Parser::parse(&mut self.parser, span, source)?;
-        self.ir.load(&module)?;
+    }
+
+    /// Loads a file that has already been added to the code map as a module.
+    fn load_file(&mut self, file: &codespan::FileMap) -> Result<()> {
+        let module = parser::Parser::parse(&mut self.parser, file.span(), file.src())?;
+        self.ir.load(&module, file)?;
 
         Ok(())
     }
 
+    /// The inferred types of all of the top-level definitions loaded so far, sorted by name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", "main = || -> i32 { 42i32 };")?;
+    ///
+    /// let exports = tin.exports()?;
+    /// assert_eq!("main", exports[0].name());
+    /// assert_eq!("|| -> i32", exports[0].ty().to_string());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn exports(&mut self) -> Result<Vec<module::Export>> {
+        self.ir.check_types()?;
+        Ok(self.ir.exports())
+    }
+
+    /// The memory layouts of all of the top-level definitions loaded so far, sorted by name.
+    ///
+    /// The layouts are the ones used by native compiled code.
+    pub fn layouts(&mut self) -> Result<Vec<(String, layout::Layout)>> {
+        self.ir.check_types()?;
+        Ok(self.ir.layouts())
+    }
+
+    /// Formats the specified source code in the canonical Tin style.
+    ///
+    /// Comments and blank lines between definitions and statements are preserved, and long
+    /// records, tuples, applications and lambdas are broken over several lines.  The code is only
+    /// parsed, not loaded, but it is added to the code map so that parse errors can be reported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// let formatted = tin.format("main.tn", "/* Entry point */ main=||->i32{42i32};")?;
+    /// assert_eq!("/* Entry point */\nmain = || -> i32 { 42i32 };\n", formatted);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn format<F>(&mut self, file_name: F, source: &str) -> Result<String>
+    where
+        F: Into<codespan::FileName>,
+    {
+        let file = self
+            .codemap
+            .add_filemap(file_name.into(), source.to_owned());
+        let module = parser::Parser::parse(&mut self.parser, file.span(), source)?;
+
+        Ok(ast::format(&module, &file))
+    }
+
     /// Creates a graph representation of the current IR of this Tin instance.
     ///
     /// This can be used to for example visualize the code using GraphViz or other tools.
@@ -164,6 +261,14 @@ impl Tin {
         graph::Graph::new(&self.ir)
     }
 
+    /// Creates a graph representation of only the top-level definition with the specified name,
+    /// including everything that it refers to.
+    ///
+    /// Returns `None` if there is no such definition.
+    pub fn definition_graph(&self, name: &str) -> Option<graph::Graph> {
+        graph::Graph::of_definition(&self.ir, name)
+    }
+
     /// Compiles the code loaded so far into a stand-alone module.
     ///
     /// This module is detached from the runtime and can be used even after the runtime has been
@@ -191,10 +296,339 @@ impl Tin {
     /// ```
     pub fn compile(&mut self) -> Result<module::Module> {
         self.ir.check_types()?;
-        let module = codegen::Codegen::new(&self.ir, &self.codemap).compile();
+        codegen::Codegen::new(&self.ir, &self.codemap).compile()
+    }
+
+    /// Prepares the code loaded so far for interpretation, without generating any machine code.
+    ///
+    /// The returned module has the same API as a compiled module, and is likewise detached from
+    /// the runtime.  Interpreted code runs slower than compiled code, but starts up faster and
+    /// works on platforms where executable memory is forbidden.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", r#"
+    /// add = |a: i32, b: i32| -> i32 {
+    ///   capture = |x: i32| -> i32 { a + x };
+    ///   capture(b)
+    /// };
+    /// "#)?;
+    ///
+    /// let module = tin.interpret()?;
+    /// let add = module.function::<tin::interpreted::Function2<i32, i32, i32>>("add").unwrap();
+    ///
+    /// let result = add.call(40, 2)?;
+    /// assert_eq!(42, result);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn interpret(&mut self) -> Result<interpreted::Module> {
+        self.ir.check_types()?;
+        let module = interpreted::Module::new(&self.ir, &self.codemap);
         Ok(module)
     }
 
+    /// Compiles the code loaded so far into a relocatable object file for the host architecture.
+    ///
+    /// Every public top-level function `foo` is exported as a C-callable symbol `tin_foo`, which
+    /// takes the function's parameters followed by an out-parameter for errors.  The object has
+    /// to be linked together with the `tin-runtime` library, which provides the builtin functions
+    /// that compiled code relies on, but the compiler itself is not needed at runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", "main = || -> i32 { 42i32 };")?;
+    ///
+    /// let object = tin.compile_object("main.o")?;
+    /// assert!(!object.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compile_object(&mut self, name: &str) -> Result<Vec<u8>> {
+        self.ir.check_types()?;
+        codegen::Codegen::new(&self.ir, &self.codemap)
+            .compile_object(name)
+            .map_err(|e| {
+                e.downcast::<Error>()
+                    .unwrap_or_else(|e| Error::Codegen(e.to_string()))
+            })
+    }
+
+    /// Renders the Cranelift IR of every function in the code loaded so far.
+    ///
+    /// The IR is rendered as it would be compiled for the host architecture, before any
+    /// optimizations.  This is mostly useful for debugging the compiler.
+    pub fn compile_clif(&mut self) -> Result<String> {
+        self.ir.check_types()?;
+        codegen::Codegen::new(&self.ir, &self.codemap)
+            .compile_clif()
+            .map_err(|e| {
+                e.downcast::<Error>()
+                    .unwrap_or_else(|e| Error::Codegen(e.to_string()))
+            })
+    }
+
+    /// Compiles the code loaded so far into a standalone WebAssembly module.
+    ///
+    /// Values are laid out in linear memory for a 4-byte pointer size.  Every public top-level
+    /// function `foo` is exported as `tin_foo`, which takes and returns the same values as the
+    /// Tin function.  The builtin functions that compiled code relies on are imported from the
+    /// `env` module, and have to be provided by the host, which also owns all memory allocation
+    /// beyond the `tin_heap_base` global.
+    ///
+    /// The module also exports its `memory` and a `tin_state` global with the address of the
+    /// runtime state: a `u32` with the remaining fuel at offset 0, a `u8` interrupt flag at offset
+    /// 4, and a `u32` at offset 8 where every exported function stores the error returned by the
+    /// builtins, or zero if the call succeeded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", "main = || -> i32 { 42i32 };")?;
+    ///
+    /// let wasm = tin.compile_wasm()?;
+    /// assert_eq!(b"\0asm", &wasm[..4]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compile_wasm(&mut self) -> Result<Vec<u8>> {
+        self.ir.check_types_for_ptr_size(4)?;
+        codegen::Codegen::new(&self.ir, &self.codemap).compile_wasm()
+    }
+
+    /// Evaluates the specified expression in the scope of the code loaded so far.
+    ///
+    /// The expression can refer to any top-level definition that has been loaded.  Expressions
+    /// that can be evaluated at compile time are evaluated by the interpreter, and all other
+    /// expressions are compiled into a temporary function that is then called.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the expression contains a syntax error, semantic
+    /// errors or type errors, if evaluating it fails at runtime, or if its value is of a type that
+    /// cannot be represented as a `Value` (such as a function).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", "answer = 42u32; double = |a: u32| -> u32 { a + a };")?;
+    ///
+    /// assert_eq!(tin::value::Value::from(43u32), tin.eval("answer + 1u32")?);
+    /// assert_eq!(tin::value::Value::from(84u32), tin.eval("double(answer)")?);
+    /// assert_eq!(tin::value::Value::from(true), tin.eval("double(answer) == 84u32")?);
+    /// assert_eq!(tin::value::Value::from(false), tin.eval("double(answer) < 42u32")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval(&mut self, source: &str) -> Result<value::Value> {
+        let thunk = self.add_thunk(source)?;
+        let result = self.eval_thunk(thunk);
+        self.ir.remove_thunk(thunk);
+
+        result
+    }
+
+    /// Infers the type of the specified expression in the context of the code loaded so far,
+    /// without evaluating it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the expression contains a syntax error, refers to
+    /// something that is not defined, or if it or the loaded code has type errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", "double = |a: u32| -> u32 { a + a };")?;
+    ///
+    /// assert_eq!("u32", tin.type_of("double(21u32)")?.to_string());
+    /// assert_eq!("|u32| -> u32", tin.type_of("double")?.to_string());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn type_of(&mut self, source: &str) -> Result<ty::Type> {
+        let thunk = self.add_thunk(source)?;
+        let result = self.ir.check_types().map(|()| {
+            let result = self.ir.thunk_result(thunk).unwrap();
+            self.ir
+                .world
+                .read_storage::<ty::Type>()
+                .get(result)
+                .unwrap()
+                .clone()
+        });
+        self.ir.remove_thunk(thunk);
+
+        result.map_err(Error::Ir)
+    }
+
+    /// Parses the specified expression into the `eval` virtual file, and adds it as a thunk.
+    fn add_thunk(&mut self, source: &str) -> Result<specs::Entity> {
+        use crate::parser::Parse;
+
+        let file = self.codemap.add_filemap(
+            codespan::FileName::Virtual("eval".into()),
+            source.to_owned(),
+        );
+        let expression = ast::Expression::parse(file.span(), source)?;
+
+        Ok(self.ir.add_thunk(&expression, &file)?)
+    }
+
+    fn eval_thunk(&mut self, thunk: specs::Entity) -> Result<value::Value> {
+        self.ir.check_types()?;
+
+        let result = self.ir.thunk_result(thunk).unwrap();
+        let ty = {
+            let constexprs = self
+                .ir
+                .world
+                .read_storage::<ir::component::constexpr::Constexpr>();
+            if let Some(constexpr) = constexprs.get(result) {
+                return Ok(constexpr.value.clone());
+            }
+
+            self.ir
+                .world
+                .read_storage::<ty::Type>()
+                .get(result)
+                .unwrap()
+                .clone()
+        };
+        let representable = match ty {
+            ty::Type::Function(_) | ty::Type::Never => false,
+            _ => true,
+        };
+        if !representable {
+            return Err(Error::UnrepresentableValue(ty));
+        }
+
+        let layout = self
+            .ir
+            .world
+            .read_storage::<ir::component::layout::Layout>()
+            .get(result)
+            .cloned();
+
+        let mut module = codegen::Codegen::new(&self.ir, &self.codemap).compile()?;
+        module
+            .call_value(&thunk.id().to_string(), &ty, layout.as_ref())?
+            .ok_or_else(|| Error::UnrepresentableValue(ty))
+    }
+
+    /// Checks that the code loaded so far provides the specified interface.
+    ///
+    /// The interface is a record type, where each field describes a top-level definition that must
+    /// exist and have exactly the specified type.  This makes it possible to validate that a
+    /// script provides what a host application expects before fetching any functions from it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the loaded code has type errors, or if any definition
+    /// is missing or has a different type than what the interface requires.  The error can be
+    /// rendered as diagnostics that point at the offending definitions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # use std::collections;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// use tin::ty;
+    ///
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", "init = || -> i32 { 0i32 };")?;
+    ///
+    /// let mut fields = collections::HashMap::new();
+    /// fields.insert(
+    ///     "init".to_owned(),
+    ///     ty::Type::Function(ty::Function {
+    ///         parameters: vec![],
+    ///         result: Box::new(ty::Type::Number(ty::Number::I32)),
+    ///     }),
+    /// );
+    /// tin.check_interface(&ty::Record { fields })?;
+    ///
+    /// let mut fields = collections::HashMap::new();
+    /// fields.insert("handle".to_owned(), ty::Type::String);
+    /// assert!(tin.check_interface(&ty::Record { fields }).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn check_interface(&mut self, interface: &ty::Record) -> Result<()> {
+        self.ir.check_types()?;
+        self.ir.check_interface(interface)?;
+        Ok(())
+    }
+
+    /// Checks that a dynamic value conforms to the type of the specified top-level definition.
+    ///
+    /// This makes it possible to use definitions as schemas for data that comes from elsewhere,
+    /// for example configuration files.  The definition acts as an example of the expected
+    /// structure.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the loaded code has type errors, if there is no such
+    /// definition, or if the value does not match the type of the definition.  In the last case,
+    /// every mismatch is reported along with a path to where in the value it was found, for
+    /// example `.address.zip: expected `u32` but got `str``.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # use std::collections;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// use tin::value;
+    ///
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", r#"Person = { name: "", age: 0u32 };"#)?;
+    ///
+    /// let mut fields = collections::HashMap::new();
+    /// fields.insert("name".to_owned(), value::Value::string("Alice"));
+    /// fields.insert("age".to_owned(), value::Value::from(42u32));
+    /// let person = value::Value::record(value::Record { fields });
+    /// tin.check_value("Person", &person)?;
+    ///
+    /// let mut fields = collections::HashMap::new();
+    /// fields.insert("name".to_owned(), value::Value::string("Bob"));
+    /// fields.insert("age".to_owned(), value::Value::string("old"));
+    /// let person = value::Value::record(value::Record { fields });
+    /// assert!(tin.check_value("Person", &person).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn check_value(&mut self, name: &str, value: &value::Value) -> Result<()> {
+        self.ir.check_types()?;
+        self.ir.check_value(name, value)?;
+        Ok(())
+    }
+
     /// Returns a reference to the current code map, which contains location mapping for all source
     /// code loaded so far.
     pub fn codemap(&self) -> &codespan::CodeMap {
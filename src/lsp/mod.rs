@@ -0,0 +1,525 @@
+//! A language server for Tin source files, speaking the Language Server Protocol.
+//!
+//! The server keeps every open document in a separate Tin instance, and re-checks it whenever it
+//! changes.  It supports:
+//!
+//!   * Publishing diagnostics for parser, IR and type errors.
+//!   * Hovering over an expression to show its inferred type.
+//!   * Going to the definition of an identifier.
+//!   * Listing the top-level definitions of a document as symbols.
+//!
+//! Messages are exchanged as JSON-RPC, framed by `Content-Length` headers.  Use [`serve`] to run
+//! the server over a pair of streams, or [`Server::handle`] to drive it directly.
+use std::cmp;
+use std::collections;
+use std::io;
+
+use serde_json;
+
+use crate::diagnostic;
+use crate::ty;
+use crate::Error;
+use crate::Tin;
+
+#[cfg(test)]
+mod tests;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+const TEXT_DOCUMENT_SYNC_FULL: u64 = 1;
+
+const SEVERITY_ERROR: u64 = 1;
+const SEVERITY_WARNING: u64 = 2;
+const SEVERITY_INFORMATION: u64 = 3;
+const SEVERITY_HINT: u64 = 4;
+
+const SYMBOL_KIND_FUNCTION: u64 = 12;
+const SYMBOL_KIND_VARIABLE: u64 = 13;
+
+/// A language server, that tracks the state of all open documents.
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: collections::HashMap<String, Document>,
+    shut_down: bool,
+    exited: bool,
+}
+
+#[derive(Debug)]
+struct Document {
+    text: String,
+    base: codespan::ByteIndex,
+    tin: Tin,
+}
+
+#[derive(Debug)]
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+/// Serves the Language Server Protocol, reading messages from `input` and writing messages to
+/// `output` until the client asks the server to exit.
+///
+/// Returns the exit code that the server process should use; it is non-zero if the client did not
+/// shut the server down before exiting, or if `input` ended before that.
+pub fn serve<R, W>(mut input: R, mut output: W) -> io::Result<i32>
+where
+    R: io::BufRead,
+    W: io::Write,
+{
+    let mut server = Server::new();
+
+    while let Some(content) = read_message(&mut input)? {
+        let messages = match serde_json::from_slice(&content) {
+            Ok(message) => server.handle(&message),
+            Err(error) => vec![error_response(
+                serde_json::Value::Null,
+                &ResponseError {
+                    code: PARSE_ERROR,
+                    message: error.to_string(),
+                },
+            )],
+        };
+
+        for message in messages {
+            write_message(&mut output, &message)?;
+        }
+        output.flush()?;
+
+        if let Some(code) = server.exit_code() {
+            return Ok(code);
+        }
+    }
+
+    Ok(1)
+}
+
+/// Reads the content of the next message from the specified input.
+///
+/// Returns `None` if the input has ended.
+pub fn read_message<R>(input: &mut R) -> io::Result<Option<Vec<u8>>>
+where
+    R: io::BufRead,
+{
+    let mut content_length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        let mut parts = line.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                let length = value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                content_length = Some(length);
+            }
+        }
+    }
+
+    let content_length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+
+    let mut content = vec![0; content_length];
+    input.read_exact(&mut content)?;
+
+    Ok(Some(content))
+}
+
+/// Writes the specified message to the specified output.
+pub fn write_message<W>(output: &mut W, message: &serde_json::Value) -> io::Result<()>
+where
+    W: io::Write,
+{
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )
+}
+
+impl Server {
+    /// Creates a new server without any open documents.
+    pub fn new() -> Self {
+        let documents = collections::HashMap::new();
+        let shut_down = false;
+        let exited = false;
+
+        Server {
+            documents,
+            shut_down,
+            exited,
+        }
+    }
+
+    /// Handles a single request or notification from the client.
+    ///
+    /// Returns the messages to send back to the client, which include the response to a request
+    /// and any notifications that the message triggered.
+    pub fn handle(&mut self, message: &serde_json::Value) -> Vec<serde_json::Value> {
+        let params = &message["params"];
+
+        match (message["method"].as_str(), message.get("id")) {
+            (Some(method), Some(id)) => vec![match self.request(method, params) {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err(error) => error_response(id.clone(), &error),
+            }],
+            (Some(method), None) => self.notification(method, params),
+            // Responses to requests that were sent by the server; there are none.
+            (None, _) => Vec::new(),
+        }
+    }
+
+    /// The exit code of the server, if the client has asked it to exit.
+    pub fn exit_code(&self) -> Option<i32> {
+        if self.exited {
+            Some(if self.shut_down { 0 } else { 1 })
+        } else {
+            None
+        }
+    }
+
+    fn request(
+        &mut self,
+        method: &str,
+        params: &serde_json::Value,
+    ) -> Result<serde_json::Value, ResponseError> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": TEXT_DOCUMENT_SYNC_FULL,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {
+                    "name": "tin",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(serde_json::Value::Null)
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            _ => Err(ResponseError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unsupported method {:?}", method),
+            }),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &serde_json::Value) -> Vec<serde_json::Value> {
+        let uri = params["textDocument"]["uri"].as_str();
+
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                if let Some(text) = params["textDocument"]["text"].as_str() {
+                    vec![self.update(uri, text.to_owned())]
+                } else {
+                    Vec::new()
+                }
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // Only full document synchronization is supported, so the last change contains the
+                // whole new text.
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+
+                if let Some(text) = text {
+                    vec![self.update(uri, text.to_owned())]
+                } else {
+                    Vec::new()
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                vec![publish_diagnostics(uri, Vec::new())]
+            }
+            ("exit", _) => {
+                self.exited = true;
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> serde_json::Value {
+        let (document, diagnostics) = Document::analyze(uri, text);
+        let diagnostics = diagnostics
+            .iter()
+            .map(|d| document.diagnostic(uri, d))
+            .collect();
+        self.documents.insert(uri.to_owned(), document);
+
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    fn hover(&self, params: &serde_json::Value) -> Result<serde_json::Value, ResponseError> {
+        Ok(self
+            .document_position(params)?
+            .and_then(|(_, document, index)| document.tin.ir.type_at(index))
+            .map_or(
+                serde_json::Value::Null,
+                |ty| json!({ "contents": { "kind": "plaintext", "value": ty.to_string() } }),
+            ))
+    }
+
+    fn definition(&self, params: &serde_json::Value) -> Result<serde_json::Value, ResponseError> {
+        Ok(self
+            .document_position(params)?
+            .and_then(|(uri, document, index)| {
+                document
+                    .tin
+                    .ir
+                    .definition_at(index)
+                    .map(|span| json!({ "uri": uri, "range": document.range(span) }))
+            })
+            .unwrap_or(serde_json::Value::Null))
+    }
+
+    fn document_symbols(
+        &self,
+        params: &serde_json::Value,
+    ) -> Result<serde_json::Value, ResponseError> {
+        let uri = document_uri(params)?;
+        let document = match self.documents.get(uri) {
+            Some(document) => document,
+            None => return Ok(serde_json::Value::Null),
+        };
+
+        let types = document
+            .tin
+            .ir
+            .exports()
+            .into_iter()
+            .map(|export| (export.name().to_owned(), export.ty().clone()))
+            .collect::<collections::HashMap<_, _>>();
+
+        let symbols = document
+            .tin
+            .ir
+            .definition_locations()
+            .into_iter()
+            .map(|(name, span)| {
+                let kind = match types.get(&name) {
+                    Some(ty::Type::Function(_)) => SYMBOL_KIND_FUNCTION,
+                    _ => SYMBOL_KIND_VARIABLE,
+                };
+
+                json!({
+                    "name": name,
+                    "kind": kind,
+                    "location": { "uri": uri, "range": document.range(span) },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(serde_json::Value::Array(symbols))
+    }
+
+    /// The document and the location within it referred to by the parameters of a request.
+    ///
+    /// Returns `None` if the document is not open, or if the position is outside of the document.
+    fn document_position<'a>(
+        &'a self,
+        params: &'a serde_json::Value,
+    ) -> Result<Option<(&'a str, &'a Document, codespan::ByteIndex)>, ResponseError> {
+        let uri = document_uri(params)?;
+        let position = &params["position"];
+        if !position.is_object() {
+            return Err(ResponseError {
+                code: INVALID_PARAMS,
+                message: "missing position".to_owned(),
+            });
+        }
+
+        Ok(self
+            .documents
+            .get(uri)
+            .and_then(|document| document.index(position).map(|index| (uri, document, index))))
+    }
+}
+
+impl Document {
+    /// Loads and checks the specified document, returning any diagnostics that were found.
+    fn analyze(uri: &str, text: String) -> (Document, Vec<codespan_reporting::Diagnostic>) {
+        use crate::diagnostic::Diagnostics;
+
+        let mut tin = Tin::new();
+        let file = tin.add_file(codespan::FileName::Virtual(uri.to_owned().into()), &text);
+        let result = tin
+            .load_file(&file)
+            .and_then(|()| tin.ir.check_types().map_err(Error::from));
+
+        let mut builder = diagnostic::DiagnosticsBuilder::new();
+        if let Err(error) = result {
+            error.to_diagnostics(&mut builder);
+        }
+
+        let base = file.span().start();
+        let document = Document { text, base, tin };
+
+        (document, builder.build())
+    }
+
+    fn diagnostic(
+        &self,
+        uri: &str,
+        diagnostic: &codespan_reporting::Diagnostic,
+    ) -> serde_json::Value {
+        let primary = diagnostic
+            .labels
+            .iter()
+            .position(|label| match label.style {
+                codespan_reporting::LabelStyle::Primary => true,
+                codespan_reporting::LabelStyle::Secondary => false,
+            })
+            .or_else(|| {
+                if diagnostic.labels.is_empty() {
+                    None
+                } else {
+                    Some(0)
+                }
+            });
+
+        let range = match primary {
+            Some(idx) => self.range(diagnostic.labels[idx].span),
+            None => self.range(codespan::ByteSpan::new(self.base, self.base)),
+        };
+
+        let mut message = diagnostic.message.clone();
+        if let Some(label_message) = primary.and_then(|idx| diagnostic.labels[idx].message.as_ref())
+        {
+            message = format!("{}: {}", message, label_message);
+        }
+
+        let related_information = diagnostic
+            .labels
+            .iter()
+            .enumerate()
+            .filter(|&(idx, _)| Some(idx) != primary)
+            .map(|(_, label)| {
+                json!({
+                    "location": { "uri": uri, "range": self.range(label.span) },
+                    "message": label.message.as_ref().unwrap_or(&diagnostic.message),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let severity = match diagnostic.severity {
+            codespan_reporting::Severity::Bug | codespan_reporting::Severity::Error => {
+                SEVERITY_ERROR
+            }
+            codespan_reporting::Severity::Warning => SEVERITY_WARNING,
+            codespan_reporting::Severity::Note => SEVERITY_INFORMATION,
+            codespan_reporting::Severity::Help => SEVERITY_HINT,
+        };
+
+        let mut result = json!({
+            "range": range,
+            "severity": severity,
+            "source": "tin",
+            "message": message,
+            "relatedInformation": related_information,
+        });
+
+        if let Some(ref code) = diagnostic.code {
+            result["code"] = json!(code);
+        }
+
+        result
+    }
+
+    fn range(&self, span: codespan::ByteSpan) -> serde_json::Value {
+        json!({ "start": self.position(span.start()), "end": self.position(span.end()) })
+    }
+
+    /// Converts a byte index into a position, with characters counted in UTF-16 code units.
+    #[cfg_attr(
+        feature = "cargo-clippy",
+        allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)
+    )]
+    fn position(&self, index: codespan::ByteIndex) -> serde_json::Value {
+        let offset = cmp::min(cmp::max((index - self.base).0, 0) as usize, self.text.len());
+        let before = &self.text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        json!({
+            "line": before.matches('\n').count(),
+            "character": before[line_start..].encode_utf16().count(),
+        })
+    }
+
+    /// Converts a position into a byte index, with characters counted in UTF-16 code units.
+    #[cfg_attr(
+        feature = "cargo-clippy",
+        allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)
+    )]
+    fn index(&self, position: &serde_json::Value) -> Option<codespan::ByteIndex> {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+
+        let line_start = if line == 0 {
+            0
+        } else {
+            self.text.match_indices('\n').nth(line - 1)?.0 + 1
+        };
+        let line_text = self.text[line_start..].split('\n').next().unwrap_or("");
+
+        let mut units = 0;
+        let mut offset = line_text.len();
+        for (idx, c) in line_text.char_indices() {
+            if units >= character {
+                offset = idx;
+                break;
+            }
+            units += c.len_utf16();
+        }
+
+        Some(self.base + codespan::ByteOffset((line_start + offset) as codespan::RawOffset))
+    }
+}
+
+fn document_uri(params: &serde_json::Value) -> Result<&str, ResponseError> {
+    params["textDocument"]["uri"]
+        .as_str()
+        .ok_or_else(|| ResponseError {
+            code: INVALID_PARAMS,
+            message: "missing text document URI".to_owned(),
+        })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<serde_json::Value>) -> serde_json::Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+fn error_response(id: serde_json::Value, error: &ResponseError) -> serde_json::Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
@@ -0,0 +1,263 @@
+use std::io;
+
+use super::*;
+
+const URI: &str = "file:///main.tn";
+
+#[test]
+fn initialize() {
+    let mut server = Server::new();
+
+    let messages = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": { "capabilities": {} },
+    }));
+
+    assert_eq!(1, messages.len());
+    assert_eq!(json!(1), messages[0]["id"]);
+    let capabilities = &messages[0]["result"]["capabilities"];
+    assert_eq!(json!(1), capabilities["textDocumentSync"]);
+    assert_eq!(json!(true), capabilities["hoverProvider"]);
+    assert_eq!(json!(true), capabilities["definitionProvider"]);
+    assert_eq!(json!(true), capabilities["documentSymbolProvider"]);
+}
+
+#[test]
+fn diagnostics() {
+    let mut server = Server::new();
+
+    let messages = open(&mut server, "main = || -> i32 {\n  42f32 + 35f32\n};\n");
+
+    assert_eq!(1, messages.len());
+    assert_eq!(
+        json!("textDocument/publishDiagnostics"),
+        messages[0]["method"]
+    );
+    assert_eq!(json!(URI), messages[0]["params"]["uri"]);
+    let diagnostics = messages[0]["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(json!(1), diagnostics[0]["severity"]);
+    assert_eq!(json!("type error"), diagnostics[0]["message"]);
+    assert_eq!(
+        json!({ "start": { "line": 0, "character": 7 }, "end": { "line": 2, "character": 1 } }),
+        diagnostics[0]["range"]
+    );
+    let related = diagnostics[0]["relatedInformation"].as_array().unwrap();
+    assert_eq!(json!("expected `i32` but got `f32`"), related[0]["message"]);
+    assert_eq!(
+        json!({ "start": { "line": 1, "character": 2 }, "end": { "line": 1, "character": 15 } }),
+        related[0]["location"]["range"]
+    );
+
+    let messages = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "main = || -> i32 {\n  42i32\n};\n" }],
+        },
+    }));
+
+    assert_eq!(1, messages.len());
+    assert_eq!(json!([]), messages[0]["params"]["diagnostics"]);
+}
+
+#[test]
+fn diagnostics_undefined_reference() {
+    let mut server = Server::new();
+
+    let messages = open(&mut server, "main = || -> i32 { a };\n");
+
+    let diagnostics = messages[0]["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(
+        json!({ "start": { "line": 0, "character": 19 }, "end": { "line": 0, "character": 20 } }),
+        diagnostics[0]["range"]
+    );
+}
+
+#[test]
+fn hover() {
+    let mut server = Server::new();
+
+    open(
+        &mut server,
+        "Pair = { b: 0u32 };\nsecond = |r: Pair| -> u32 { r.b };\n",
+    );
+
+    assert_eq!(
+        json!({ "contents": { "kind": "plaintext", "value": "|{b: u32}| -> u32" } }),
+        request(&mut server, "textDocument/hover", 1, 1)
+    );
+    assert_eq!(
+        json!({ "contents": { "kind": "plaintext", "value": "{b: u32}" } }),
+        request(&mut server, "textDocument/hover", 1, 28)
+    );
+    assert_eq!(
+        json!({ "contents": { "kind": "plaintext", "value": "u32" } }),
+        request(&mut server, "textDocument/hover", 1, 30)
+    );
+}
+
+#[test]
+fn definition() {
+    let mut server = Server::new();
+
+    open(
+        &mut server,
+        "one = 1u32;\nmain = |a: u32| -> u32 {\n  f = || -> u32 { a + one };\n  f()\n};\n",
+    );
+
+    assert_eq!(
+        json!({
+            "uri": URI,
+            "range": { "start": { "line": 1, "character": 8 }, "end": { "line": 1, "character": 14 } },
+        }),
+        request(&mut server, "textDocument/definition", 2, 18)
+    );
+    assert_eq!(
+        json!({
+            "uri": URI,
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 10 } },
+        }),
+        request(&mut server, "textDocument/definition", 2, 22)
+    );
+    assert_eq!(
+        serde_json::Value::Null,
+        request(&mut server, "textDocument/definition", 3, 5)
+    );
+}
+
+#[test]
+fn document_symbols() {
+    let mut server = Server::new();
+
+    open(&mut server, "one = 1u32;\nmain = || -> u32 { one };\n");
+
+    let messages = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "textDocument/documentSymbol",
+        "params": { "textDocument": { "uri": URI } },
+    }));
+
+    let symbols = messages[0]["result"].as_array().unwrap();
+    assert_eq!(2, symbols.len());
+    assert_eq!(json!("main"), symbols[0]["name"]);
+    assert_eq!(json!(12), symbols[0]["kind"]);
+    assert_eq!(json!("one"), symbols[1]["name"]);
+    assert_eq!(json!(13), symbols[1]["kind"]);
+    assert_eq!(
+        json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 10 } }),
+        symbols[1]["location"]["range"]
+    );
+}
+
+#[test]
+fn unknown_method() {
+    let mut server = Server::new();
+
+    let messages = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "workspace/symbol",
+        "params": {},
+    }));
+
+    assert_eq!(json!(3), messages[0]["id"]);
+    assert_eq!(json!(-32601), messages[0]["error"]["code"]);
+}
+
+#[test]
+fn serve_session() {
+    let mut input = Vec::new();
+    for message in &[
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+        json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": URI,
+                    "languageId": "tin",
+                    "version": 1,
+                    "text": "main = || -> i32 { 42i32 };\n",
+                },
+            },
+        }),
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+        json!({ "jsonrpc": "2.0", "method": "exit" }),
+    ] {
+        write_message(&mut input, message).unwrap();
+    }
+
+    let mut output = Vec::new();
+    let code = serve(io::Cursor::new(input), &mut output).unwrap();
+    assert_eq!(0, code);
+
+    let mut output = io::Cursor::new(output);
+    let mut messages = Vec::new();
+    while let Some(content) = read_message(&mut output).unwrap() {
+        messages.push(serde_json::from_slice::<serde_json::Value>(&content).unwrap());
+    }
+
+    assert_eq!(3, messages.len());
+    assert_eq!(json!(1), messages[0]["id"]);
+    assert_eq!(
+        json!("textDocument/publishDiagnostics"),
+        messages[1]["method"]
+    );
+    assert_eq!(json!([]), messages[1]["params"]["diagnostics"]);
+    assert_eq!(json!(2), messages[2]["id"]);
+    assert_eq!(serde_json::Value::Null, messages[2]["result"]);
+}
+
+#[test]
+fn serve_without_shutdown() {
+    let mut input = Vec::new();
+    write_message(&mut input, &json!({ "jsonrpc": "2.0", "method": "exit" })).unwrap();
+
+    let code = serve(io::Cursor::new(input), io::sink()).unwrap();
+    assert_eq!(1, code);
+}
+
+#[test]
+fn serve_invalid_json() {
+    let input = b"Content-Length: 3\r\n\r\n{]}".to_vec();
+
+    let mut output = Vec::new();
+    let code = serve(io::Cursor::new(input), &mut output).unwrap();
+    assert_eq!(1, code);
+
+    let content = read_message(&mut io::Cursor::new(output)).unwrap().unwrap();
+    let message = serde_json::from_slice::<serde_json::Value>(&content).unwrap();
+    assert_eq!(json!(-32700), message["error"]["code"]);
+}
+
+fn open(server: &mut Server, text: &str) -> Vec<serde_json::Value> {
+    server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {
+            "textDocument": { "uri": URI, "languageId": "tin", "version": 1, "text": text },
+        },
+    }))
+}
+
+fn request(server: &mut Server, method: &str, line: u64, character: u64) -> serde_json::Value {
+    let mut messages = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": {
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        },
+    }));
+
+    assert_eq!(1, messages.len());
+    messages.remove(0)["result"].take()
+}
@@ -3,64 +3,70 @@ use std::collections;
 use std::fmt;
 use std::mem;
 use std::ptr;
+use std::slice;
+use std::sync;
 
 use cranelift_module;
 use cranelift_simplejit;
+use tin_runtime;
+
+use crate::diagnostic;
+use crate::ir::component::layout;
+use crate::ty;
+use crate::value;
+
+pub use tin_runtime::error::Error;
+pub use tin_runtime::error::ErrorKind;
+pub use tin_runtime::error::Frame;
+pub use tin_runtime::error::Point;
+pub use tin_runtime::error::Trace;
+pub use tin_runtime::state::MemoryStats;
 
 /// A compiled module, the result of an invocation of `Tin::compile`.
 pub struct Module {
     compiled: cranelift_module::Module<cranelift_simplejit::SimpleJITBackend>,
+    state: sync::Arc<tin_runtime::State>,
     function_ids: collections::HashMap<String, cranelift_module::FuncId>,
+    exports: Vec<Export>,
+    constants: collections::HashMap<String, value::Value>,
 }
 
-/// An error that may happen at runtime.
-#[derive(Clone, Debug, Fail, PartialEq)]
-#[fail(display = "{}{}", kind, backtrace)]
-pub struct Error {
-    kind: ErrorKind,
-    backtrace: Trace,
-}
-
-/// A trace, for example a backtrace for an error.
+/// A public top-level definition that is exported from a [`Module`].
 #[derive(Clone, Debug, PartialEq)]
-pub struct Trace {
-    frames: Vec<Frame>,
+pub struct Export {
+    name: String,
+    ty: ty::Type,
+    kind: ExportKind,
 }
 
-/// A call stack frame within a [`Trace`].
-#[derive(Clone, Debug, PartialEq)]
-pub struct Frame {
-    name: String,
-    location: Point,
+/// The various kinds of exported definitions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExportKind {
+    /// The definition is a function that can be fetched using `Module::function`.
+    Function,
+    /// The definition is a constant value.
+    Constant,
 }
 
-/// A specific code point, with a file, line and column.
-#[derive(Clone, Debug, PartialEq)]
-pub struct Point {
-    path: String,
-    line: u32,
-    column: u32,
+/// A handle that can be used to interrupt calls into a [`Module`], possibly from another thread.
+///
+/// Obtained through `Module::interrupt_handle`.
+#[derive(Clone, Debug)]
+pub struct InterruptHandle {
+    state: sync::Arc<tin_runtime::State>,
 }
 
-/// The various kinds of allowed errors.
-#[derive(Clone, Copy, Debug, PartialEq, Primitive)]
-pub enum ErrorKind {
-    /// An unknown error has occurred.
-    ///
-    /// If this happens, it is a bug.
-    Unknown = 1,
-    /// The stack has overflowed.
-    StackOverflow = 2,
-    /// There was an out-of-bounds array access.
-    OutOfBounds = 3,
-    /// An integer operation caused an overflow to happen.
-    IntegerOverflow = 4,
-    /// An integer operation caused a division-by-zero to happen.
-    IntegerDivisonByZero = 5,
-    /// A float-to-integer conversion failed.
-    BadConversionToInteger = 6,
-    /// User generated error.
-    UserGenerated = 7,
+/// Memory returned from a call into a [`Module`] that is owned by the host.
+///
+/// Calls release all of the memory that they allocated when they return, except when they return
+/// a pointer (for example to a tuple or record) to the host.  In that case, the memory that is
+/// reachable from that pointer is kept alive until the host takes ownership of it using
+/// `Module::take_ownership`, and then freed when the `Owned` handle is dropped.
+#[derive(Debug)]
+pub struct Owned {
+    state: sync::Arc<tin_runtime::State>,
+    ptr: *const u8,
+    allocations: Vec<tin_runtime::state::Allocation>,
 }
 
 /// A function that is exported from a [`Module`].
@@ -166,11 +172,208 @@ define_function!(
 impl Module {
     pub(crate) fn new(
         compiled: cranelift_module::Module<cranelift_simplejit::SimpleJITBackend>,
+        state: sync::Arc<tin_runtime::State>,
         function_ids: collections::HashMap<String, cranelift_module::FuncId>,
+        exports: Vec<Export>,
+        constants: collections::HashMap<String, value::Value>,
     ) -> Self {
         Module {
             compiled,
+            state,
             function_ids,
+            exports,
+            constants,
+        }
+    }
+
+    /// Returns all of the public top-level definitions of this module, sorted by name.
+    ///
+    /// This can be used to discover what a module provides, and to validate its structure before
+    /// fetching any functions.
+    pub fn exports(&self) -> &[Export] {
+        &self.exports
+    }
+
+    /// Fetches the value of the specified top-level constant.
+    ///
+    /// Returns `None` if there is no top-level definition with the specified name, or if the
+    /// definition does not have a value that is known at compile time.
+    pub fn constant(&self, name: &str) -> Option<value::Value> {
+        self.constants.get(name).cloned()
+    }
+
+    /// Sets the amount of fuel available to subsequent calls into this module.
+    ///
+    /// One unit of fuel is consumed every time a function is entered.  Since Tin has no loops, this
+    /// bounds the total amount of work that a call can perform.  When the fuel runs out, the call
+    /// fails with an `ErrorKind::OutOfFuel` error.  Passing `None` disables fuel metering, which
+    /// is the default.
+    ///
+    /// Fuel is not refilled automatically; call this method again before each call to give every
+    /// call the same budget, or only occasionally to share a budget between several calls.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", "forever = || -> i32 { forever() };")?;
+    ///
+    /// let mut module = tin.compile()?;
+    /// module.set_fuel(Some(1000));
+    ///
+    /// let forever = module.function::<tin::module::Function0<i32>>("forever").unwrap();
+    /// let error = forever.call().unwrap_err();
+    /// assert_eq!(tin::module::ErrorKind::OutOfFuel, error.kind());
+    /// assert_eq!(Some(0), module.fuel());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_fuel(&self, fuel: Option<usize>) {
+        self.state.set_fuel(fuel);
+    }
+
+    /// The amount of fuel that remains, or `None` if fuel metering is disabled.
+    ///
+    /// This can be inspected after a call to find out how much work the call performed.
+    pub fn fuel(&self) -> Option<usize> {
+        self.state.fuel()
+    }
+
+    /// Sets the maximum number of bytes that may be allocated at once by calls into this module.
+    ///
+    /// When an allocation would exceed the limit, the call fails with an
+    /// `ErrorKind::OutOfMemory` error.  Passing `None` removes the limit, which is the default.
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        self.state.set_memory_limit(limit);
+    }
+
+    /// The current memory limit, or `None` if memory use is unlimited.
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.state.memory_limit()
+    }
+
+    /// Statistics about the memory allocated by calls into this module.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.state.memory_stats()
+    }
+
+    /// Resets the cumulative memory statistics, so that the bytes allocated and peak usage of
+    /// subsequent calls can be observed in isolation.
+    ///
+    /// Live memory is not affected.
+    pub fn reset_memory_stats(&self) {
+        self.state.reset_memory_stats();
+    }
+
+    /// Takes ownership of the memory that is reachable from a pointer returned by a call into this
+    /// module.
+    ///
+    /// The pointer stays valid for as long as the returned handle is alive.  Memory that is never
+    /// claimed is freed when the module is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate failure;
+    /// # extern crate tin;
+    /// # fn main() -> Result<(), failure::Error> {
+    /// let mut tin = tin::Tin::new();
+    /// tin.load("main.tn", r#"
+    /// Pair = (0u32, 0u32);
+    /// pair = |a: u32| -> Pair { (a, a) };
+    /// "#)?;
+    ///
+    /// let mut module = tin.compile()?;
+    /// let pair = module.function::<tin::module::Function1<*const u8, u32>>("pair").unwrap();
+    ///
+    /// let owned = module.take_ownership(pair.call(1)?);
+    /// assert_eq!(1, module.memory_stats().live_allocations());
+    ///
+    /// drop(owned);
+    /// assert_eq!(0, module.memory_stats().live_allocations());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn take_ownership(&self, ptr: *const u8) -> Owned {
+        let state = self.state.clone();
+        let allocations = self.state.take_retained(ptr);
+        Owned {
+            state,
+            ptr,
+            allocations,
+        }
+    }
+
+    /// Returns a handle that can be used to interrupt a running call into this module.
+    ///
+    /// The handle can be sent to another thread, for example a watchdog that cancels calls that
+    /// take too long.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle::new(self.state.clone())
+    }
+
+    /// Calls the specified function, which takes no parameters, and converts its result into a
+    /// value of the specified type.
+    ///
+    /// Returns `Ok(None)` if there is no such function, or if values of the specified type cannot
+    /// be represented as a `Value`.
+    pub(crate) fn call_value(
+        &mut self,
+        name: &str,
+        ty: &ty::Type,
+        layout: Option<&layout::Layout>,
+    ) -> Result<Option<value::Value>, Error> {
+        let ptr = if let Some(id) = self.function_ids.get(name) {
+            self.compiled.get_finalized_function(*id)
+        } else {
+            return Ok(None);
+        };
+
+        match *ty {
+            ty::Type::Number(number) => {
+                let number = match number {
+                    ty::Number::U8 => value::Number::U8(call_ptr(ptr)?),
+                    ty::Number::U16 => value::Number::U16(call_ptr(ptr)?),
+                    ty::Number::U32 => value::Number::U32(call_ptr(ptr)?),
+                    ty::Number::U64 => value::Number::U64(call_ptr(ptr)?),
+                    ty::Number::I8 => value::Number::I8(call_ptr(ptr)?),
+                    ty::Number::I16 => value::Number::I16(call_ptr(ptr)?),
+                    ty::Number::I32 => value::Number::I32(call_ptr(ptr)?),
+                    ty::Number::I64 => value::Number::I64(call_ptr(ptr)?),
+                    ty::Number::F32 => value::Number::F32(call_ptr(ptr)?),
+                    ty::Number::F64 => value::Number::F64(call_ptr(ptr)?),
+                };
+                Ok(Some(value::Value::number(number)))
+            }
+            ty::Type::Symbol(ref symbol) => {
+                call_ptr::<u8>(ptr)?;
+                Ok(Some(value::Value::symbol(symbol.label.clone())))
+            }
+            ty::Type::Union(ref union) => {
+                // Unions are passed as the index of the alternative, in sorted order
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_possible_truncation))]
+                let index = match union.alternatives.len() {
+                    n if n <= usize::from(u8::max_value()) => usize::from(call_ptr::<u8>(ptr)?),
+                    n if n <= usize::from(u16::max_value()) => usize::from(call_ptr::<u16>(ptr)?),
+                    _ => call_ptr::<u32>(ptr)? as usize,
+                };
+                Ok(union
+                    .alternatives
+                    .get(index)
+                    .map(|symbol| value::Value::symbol(symbol.label.clone())))
+            }
+            ty::Type::String | ty::Type::Tuple(_) | ty::Type::Record(_) => {
+                if let Some(layout) = layout {
+                    let result = self.take_ownership(call_ptr::<*const u8>(ptr)?);
+                    Ok(unsafe { read_value(result.as_ptr(), ty, layout) })
+                } else {
+                    Ok(None)
+                }
+            }
+            ty::Type::Function(_) | ty::Type::Never => Ok(None),
         }
     }
 
@@ -190,124 +393,196 @@ impl Module {
     }
 }
 
-impl fmt::Debug for Module {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("Module").finish()
-    }
+fn call_ptr<R>(ptr: *const u8) -> Result<R, Error> {
+    unsafe { Function0::<R>::from_ptr(ptr) }.call()
 }
 
-impl Error {
-    /// Creates a new error of the specified kind.
-    pub(crate) fn new(kind: ErrorKind) -> Self {
-        let frames = Vec::new();
-        let backtrace = Trace { frames };
-        Error { kind, backtrace }
-    }
-
-    /// Pushes a new backtrace frame to the "bottom" of the stack.
-    pub(crate) fn push_frame(&mut self, frame: Frame) {
-        self.backtrace.frames.push(frame);
+/// Reads a value of the specified type and layout from memory managed by compiled code.
+#[allow(clippy::cast_ptr_alignment)]
+unsafe fn read_value(
+    ptr: *const u8,
+    ty: &ty::Type,
+    layout: &layout::Layout,
+) -> Option<value::Value> {
+    match *ty {
+        ty::Type::Number(number) => Some(value::Value::number(read_number(ptr, number))),
+        ty::Type::String => {
+            let len = ptr::read_unaligned(ptr as *const usize);
+            let bytes = slice::from_raw_parts(ptr.add(mem::size_of::<usize>()), len);
+            Some(value::Value::string(String::from_utf8_lossy(bytes)))
+        }
+        ty::Type::Symbol(ref symbol) => Some(value::Value::symbol(symbol.label.clone())),
+        ty::Type::Tuple(ref tuple) => {
+            let fields = tuple
+                .fields
+                .iter()
+                .zip(layout.unnamed_fields.iter())
+                .map(|(field_ty, offset_layout)| read_field(ptr, field_ty, offset_layout))
+                .collect::<Option<Vec<_>>>()?;
+            Some(value::Value::tuple(value::Tuple { fields }))
+        }
+        ty::Type::Record(ref record) => {
+            let fields = layout
+                .named_fields
+                .iter()
+                .map(|named_field| {
+                    let field_ty = &record.fields[&named_field.field];
+                    read_field(ptr, field_ty, &named_field.offset_layout)
+                        .map(|value| (named_field.field.clone(), value))
+                })
+                .collect::<Option<collections::HashMap<_, _>>>()?;
+            Some(value::Value::record(value::Record { fields }))
+        }
+        ty::Type::Union(_) | ty::Type::Function(_) | ty::Type::Never => None,
     }
+}
 
-    /// The kind of error.
-    pub fn kind(&self) -> ErrorKind {
-        self.kind
+/// Reads a field of a tuple or record; fields that are represented as pointers are followed.
+#[allow(clippy::cast_ptr_alignment)]
+unsafe fn read_field(
+    ptr: *const u8,
+    ty: &ty::Type,
+    offset_layout: &layout::Offset,
+) -> Option<value::Value> {
+    let field_ptr = ptr.add(offset_layout.offset);
+    match *ty {
+        ty::Type::String | ty::Type::Tuple(_) | ty::Type::Record(_) => read_value(
+            ptr::read_unaligned(field_ptr as *const *const u8),
+            ty,
+            &offset_layout.layout,
+        ),
+        _ => read_value(field_ptr, ty, &offset_layout.layout),
     }
+}
 
-    /// The backtrace of the error.
-    pub fn backtrace(&self) -> &Trace {
-        &self.backtrace
+#[allow(clippy::cast_ptr_alignment)]
+unsafe fn read_number(ptr: *const u8, number: ty::Number) -> value::Number {
+    match number {
+        ty::Number::U8 => value::Number::U8(ptr::read_unaligned(ptr)),
+        ty::Number::U16 => value::Number::U16(ptr::read_unaligned(ptr as *const u16)),
+        ty::Number::U32 => value::Number::U32(ptr::read_unaligned(ptr as *const u32)),
+        ty::Number::U64 => value::Number::U64(ptr::read_unaligned(ptr as *const u64)),
+        ty::Number::I8 => value::Number::I8(ptr::read_unaligned(ptr as *const i8)),
+        ty::Number::I16 => value::Number::I16(ptr::read_unaligned(ptr as *const i16)),
+        ty::Number::I32 => value::Number::I32(ptr::read_unaligned(ptr as *const i32)),
+        ty::Number::I64 => value::Number::I64(ptr::read_unaligned(ptr as *const i64)),
+        ty::Number::F32 => value::Number::F32(ptr::read_unaligned(ptr as *const f32)),
+        ty::Number::F64 => value::Number::F64(ptr::read_unaligned(ptr as *const f64)),
     }
 }
 
-impl Trace {
-    /// Returns all of the frames in the trace, most recently called function
-    /// first.
-    pub fn frames(&self) -> &[Frame] {
-        &self.frames
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Module").finish()
     }
 }
 
-impl Frame {
-    /// Creates a new frame.
-    pub fn new(name: String, location: Point) -> Self {
-        Frame { name, location }
+impl Export {
+    /// Creates a new export; the kind is derived from the type of the definition.
+    pub(crate) fn new(name: String, ty: ty::Type) -> Self {
+        let kind = if let ty::Type::Function(_) = ty {
+            ExportKind::Function
+        } else {
+            ExportKind::Constant
+        };
+        Export { name, ty, kind }
     }
 
-    /// Returns the corresponding function name for this frame.
+    /// The name of the exported definition.
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    /// Returns the location of the call that left this frame.
-    pub fn location(&self) -> &Point {
-        &self.location
+    /// The inferred type of the exported definition.
+    pub fn ty(&self) -> &ty::Type {
+        &self.ty
     }
-}
 
-impl Point {
-    /// Creates a new point.
-    pub fn new(path: String, line: u32, column: u32) -> Self {
-        Point { path, line, column }
+    /// The kind of the exported definition.
+    pub fn kind(&self) -> ExportKind {
+        self.kind
     }
+}
 
-    /// The file path of the point.
-    pub fn path(&self) -> &str {
-        &self.path
+impl InterruptHandle {
+    pub(crate) fn new(state: sync::Arc<tin_runtime::State>) -> Self {
+        InterruptHandle { state }
     }
 
-    /// The 0-based line number of the point.
-    pub fn line(&self) -> u32 {
-        self.line
+    /// Interrupts the currently running call into the module.
+    ///
+    /// The call fails with an `ErrorKind::Interrupted` error the next time it enters a function.
+    /// If no call is running, the next call is interrupted instead.  Once an interruption has been
+    /// delivered, later calls run normally again.
+    pub fn interrupt(&self) {
+        self.state.interrupt();
     }
+}
 
-    /// The 0-based column number of the point.
-    pub fn column(&self) -> u32 {
-        self.column
+impl Owned {
+    /// The pointer that was returned from the call.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
     }
 }
 
-impl fmt::Display for ErrorKind {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            ErrorKind::Unknown => f.write_str("unknown"),
-            ErrorKind::StackOverflow => f.write_str("stack overflow"),
-            ErrorKind::OutOfBounds => f.write_str("out of bounds"),
-            ErrorKind::IntegerOverflow => f.write_str("integer overflow"),
-            ErrorKind::IntegerDivisonByZero => f.write_str("integer division by zero"),
-            ErrorKind::BadConversionToInteger => f.write_str("bad conversion to integer"),
-            ErrorKind::UserGenerated => f.write_str("user generated"),
-        }
+impl Drop for Owned {
+    fn drop(&mut self) {
+        self.state
+            .free(mem::replace(&mut self.allocations, Vec::new()));
     }
 }
 
-impl fmt::Display for Trace {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if !self.frames.is_empty() {
-            writeln!(f)?;
-            writeln!(f, "trace:")?;
-
-            let mut needs_sep = false;
-            for frame in &self.frames {
-                if needs_sep {
-                    writeln!(f)?;
-                }
-                write!(f, "  {}", frame)?;
-                needs_sep = true;
+/// Runtime errors are rendered with a label for every frame of their trace, as long as the frames
+/// were produced by code compiled from the [`codespan::CodeMap`] that the diagnostics are emitted
+/// against.  Errors without any located frames fall back to their plain textual trace.
+impl diagnostic::Diagnostics for Error {
+    fn to_diagnostics(&self, builder: &mut diagnostic::DiagnosticsBuilder) {
+        let mut located = false;
+
+        for (i, frame) in self.backtrace().frames().iter().enumerate() {
+            if let Some((start, end)) = frame.location().span() {
+                let (message, style) = if i == 0 {
+                    (
+                        format!("raised in `{}`", frame.name()),
+                        codespan_reporting::LabelStyle::Primary,
+                    )
+                } else {
+                    (
+                        format!("called from `{}`", frame.name()),
+                        codespan_reporting::LabelStyle::Secondary,
+                    )
+                };
+
+                builder.add_label(codespan_reporting::Label {
+                    span: codespan::ByteSpan::new(
+                        codespan::ByteIndex(start),
+                        codespan::ByteIndex(end),
+                    ),
+                    message: Some(message),
+                    style,
+                });
+                located = true;
             }
         }
-        Ok(())
-    }
-}
 
-impl fmt::Display for Frame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}({})", self.name, self.location)
+        let message = if located {
+            match self.message() {
+                Some(message) => format!("{}: {}", self.kind(), message),
+                None => self.kind().to_string(),
+            }
+        } else {
+            self.to_string()
+        };
+        builder.add_diagnostic(codespan_reporting::Severity::Error, None, &message);
     }
 }
 
-impl fmt::Display for Point {
+impl fmt::Display for ExportKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}:{}:{}", self.path, self.line + 1, self.column + 1)
+        match *self {
+            ExportKind::Function => f.write_str("function"),
+            ExportKind::Constant => f.write_str("constant"),
+        }
     }
 }
@@ -181,7 +181,14 @@ macro_rules! parser_impl {
         impl Parser<$result> for &'static crate::parser::tin::$parser {
             fn parse(&mut self, span: codespan::ByteSpan, source: &str) -> Result<$result, Error> {
                 let mut errors = Vec::new();
-                let result = crate::parser::tin::$parser::parse(self, span, &mut errors, source);
+                let mut comments = Vec::new();
+                let result = crate::parser::tin::$parser::parse(
+                    self,
+                    span,
+                    &mut errors,
+                    &mut comments,
+                    source,
+                );
                 handle_parse_result(source, span, result, errors)
             }
         }
@@ -417,6 +424,23 @@ main = || -> i32 {
         assert!(actual.is_ok());
     }
 
+    #[test]
+    fn comments() {
+        let _ = env_logger::try_init();
+
+        let actual = parse_module(
+            "test",
+            r#"/* a */ a = |/* b */ b: u32| -> u32 { /* c */ c = b; /* d */ c };"#,
+        )
+        .unwrap();
+        let comments = actual
+            .comments
+            .into_iter()
+            .map(|c| c.value)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![" a ", " b ", " c ", " d "], comments);
+    }
+
     #[test]
     fn error_invalid_token() {
         let _ = env_logger::try_init();
@@ -1641,6 +1665,40 @@ help: valid tokens at this point: ["!", "#$0", "#$1", "#0", "#1", "#^-", "#^0",
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn try_catch() {
+        let _ = env_logger::try_init();
+
+        let expected = Ok(ast::Expression::Try(ast::Try {
+            context: (),
+            body: Box::new(ast::Expression::Apply(ast::Apply {
+                context: (),
+                function: Box::new(ast::Expression::Identifier(ast::Identifier {
+                    context: (),
+                    value: "a".to_owned(),
+                })),
+                parameters: vec![],
+            })),
+            error: ast::Identifier {
+                context: (),
+                value: "e".to_owned(),
+            },
+            handler: Box::new(ast::Expression::Select(ast::Select {
+                context: (),
+                record: Box::new(ast::Expression::Identifier(ast::Identifier {
+                    context: (),
+                    value: "e".to_owned(),
+                })),
+                field: ast::Identifier {
+                    context: (),
+                    value: "message".to_owned(),
+                },
+            })),
+        }));
+        let actual = parse_expression("test", r#"try { a() } catch |e| { e.message }"#);
+        assert_eq!(expected, actual);
+    }
+
     fn parse_module(name: &'static str, source: &str) -> Result<ast::Module<()>, String> {
         use crate::parser::Parse;
 
@@ -1650,7 +1708,9 @@ help: valid tokens at this point: ["!", "#$0", "#$1", "#0", "#1", "#^-", "#^0",
             .span();
 
         let mut errors = Vec::new();
-        let result = crate::ast::Module::new_parser().parse(span, &mut errors, source);
+        let mut comments = Vec::new();
+        let result =
+            crate::ast::Module::new_parser().parse(span, &mut errors, &mut comments, source);
         super::handle_parse_result(source, span, result, errors)
             .map(|r| r.map_context(&mut |_| ()))
             .map_err(|e| crate::diagnostic::to_string(&code_map, &e))
@@ -1665,7 +1725,9 @@ help: valid tokens at this point: ["!", "#$0", "#$1", "#0", "#1", "#^-", "#^0",
             .span();
 
         let mut errors = Vec::new();
-        let result = crate::ast::Expression::new_parser().parse(span, &mut errors, source);
+        let mut comments = Vec::new();
+        let result =
+            crate::ast::Expression::new_parser().parse(span, &mut errors, &mut comments, source);
         super::handle_parse_result(source, span, result, errors)
             .map(|r| r.map_context(&mut |_| ()))
             .map_err(|e| crate::diagnostic::to_string(&code_map, &e))
@@ -0,0 +1,305 @@
+//! An interactive read-eval-print loop for Tin code.
+//!
+//! Every input is either one or more definitions such as `double = |a: u32| -> u32 { a + a };`,
+//! which are added to the scope of the session, or an expression, which is evaluated and printed
+//! along with its type.  Input is read until all brackets, strings and comments are closed, so a
+//! definition can span several lines.  Input starting with a colon is a command:
+//!
+//!   * `:type EXPR` prints the type of an expression without evaluating it.
+//!   * `:graph NAME` prints the IR of a definition (and everything it refers to) as a GraphViz
+//!     graph.
+//!   * `:reset` forgets all definitions.
+//!   * `:help` lists the commands, and `:quit` ends the session.
+//!
+//! Errors are rendered as diagnostics pointing at the offending input.  Use [`run`] to drive a
+//! session over a pair of streams, or [`Repl::handle`] to feed it complete inputs directly.
+use std::collections;
+use std::io;
+
+use dot;
+
+use crate::diagnostic;
+use crate::ir;
+use crate::ty;
+use crate::Error;
+use crate::Tin;
+
+#[cfg(test)]
+mod tests;
+
+const PROMPT: &str = "tin> ";
+const CONTINUATION_PROMPT: &str = "...> ";
+
+const HELP: &str = "\
+Enter a definition such as `double = |a: u32| -> u32 { a + a };` or an expression to evaluate.
+
+Commands:
+  :type EXPR   Prints the type of an expression without evaluating it
+  :graph NAME  Prints the IR of a definition as a GraphViz graph
+  :reset       Forgets all definitions
+  :help        Prints this message
+  :quit        Ends the session
+";
+
+/// A REPL session, that keeps track of the definitions entered so far.
+#[derive(Debug)]
+pub struct Repl {
+    tin: Tin,
+    definitions: Vec<String>,
+    inputs: usize,
+}
+
+impl Repl {
+    /// Creates a new session without any definitions.
+    pub fn new() -> Repl {
+        let tin = Tin::new();
+        let definitions = Vec::new();
+        let inputs = 0;
+
+        Repl {
+            tin,
+            definitions,
+            inputs,
+        }
+    }
+
+    /// Handles a complete input, returning the output to print.
+    ///
+    /// Returns `None` if the input asks to end the session.
+    pub fn handle(&mut self, input: &str) -> Option<String> {
+        let input = input.trim();
+
+        if input.starts_with(':') {
+            let (command, argument) = match input.find(char::is_whitespace) {
+                Some(idx) => (&input[..idx], input[idx..].trim()),
+                None => (input, ""),
+            };
+
+            match command {
+                ":type" | ":t" => Some(self.type_of(argument)),
+                ":graph" | ":g" => Some(self.graph(argument)),
+                ":reset" => {
+                    *self = Repl::new();
+                    Some(String::new())
+                }
+                ":help" | ":h" => Some(HELP.to_owned()),
+                ":quit" | ":q" => None,
+                _ => Some(format!(
+                    "error: unknown command `{}`; type `:help` for a list of commands\n",
+                    command
+                )),
+            }
+        } else if input.is_empty() {
+            Some(String::new())
+        } else if is_definition(input) {
+            Some(self.define(input))
+        } else {
+            Some(self.eval(input.trim_end_matches(';')))
+        }
+    }
+
+    fn define(&mut self, input: &str) -> String {
+        let mut source = input.to_owned();
+        if !source.ends_with(';') {
+            source.push(';');
+        }
+
+        let file_name = codespan::FileName::Virtual(format!("repl:{}", self.inputs).into());
+        self.inputs += 1;
+
+        let previous = self
+            .tin
+            .exports()
+            .map(|exports| {
+                exports
+                    .into_iter()
+                    .map(|e| e.name().to_owned())
+                    .collect::<collections::HashSet<_>>()
+            })
+            .unwrap_or_default();
+        let result = self
+            .tin
+            .load(file_name, &source)
+            .and_then(|()| self.tin.exports());
+
+        match result {
+            Ok(exports) => {
+                self.definitions.push(source);
+
+                let mut output = String::new();
+                for export in exports {
+                    if !previous.contains(export.name()) {
+                        output.push_str(&format!("{} : {}\n", export.name(), export.ty()));
+                    }
+                }
+                output
+            }
+            Err(error) => {
+                let output = self.render(&error);
+                self.rebuild();
+                output
+            }
+        }
+    }
+
+    fn eval(&mut self, input: &str) -> String {
+        let ty = match self.tin.type_of(input) {
+            Ok(ty) => ty,
+            Err(error) => return self.render(&error),
+        };
+
+        if let ty::Type::Function(_) = ty {
+            return format!("<function> : {}\n", ty);
+        }
+
+        match self.tin.eval(input) {
+            Ok(value) => format!("{} : {}\n", value, ty),
+            Err(error) => self.render(&error),
+        }
+    }
+
+    fn type_of(&mut self, input: &str) -> String {
+        match self.tin.type_of(input) {
+            Ok(ty) => format!("{}\n", ty),
+            Err(error) => self.render(&error),
+        }
+    }
+
+    fn graph(&self, name: &str) -> String {
+        match self.tin.definition_graph(name) {
+            Some(graph) => {
+                let mut output = Vec::new();
+                dot::render(&graph, &mut output).unwrap();
+                String::from_utf8(output).unwrap()
+            }
+            None => self.render(&Error::Ir(ir::error::Error::UnknownDefinition {
+                name: name.to_owned(),
+            })),
+        }
+    }
+
+    fn render(&self, error: &Error) -> String {
+        diagnostic::to_string(self.tin.codemap(), error)
+    }
+
+    /// Recreates the Tin instance from the definitions that were accepted so far, to get rid of
+    /// anything that a failed input might have left behind.
+    fn rebuild(&mut self) {
+        self.tin = Tin::new();
+        for (i, source) in self.definitions.iter().enumerate() {
+            self.tin
+                .load(
+                    codespan::FileName::Virtual(format!("repl:{}", i).into()),
+                    source,
+                )
+                .unwrap();
+        }
+        self.inputs = self.definitions.len();
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+/// Runs a REPL session, reading input lines and writing prompts and results.
+///
+/// The session ends when the input ends or when `:quit` is entered.
+pub fn run<R, W>(mut input: R, mut output: W) -> io::Result<()>
+where
+    R: io::BufRead,
+    W: io::Write,
+{
+    let mut repl = Repl::new();
+    let mut buffer = String::new();
+
+    loop {
+        output.write_all(
+            if buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            }
+            .as_bytes(),
+        )?;
+        output.flush()?;
+
+        let read = input.read_line(&mut buffer)?;
+        let at_end = read == 0;
+
+        if at_end || is_complete(&buffer) {
+            match repl.handle(&buffer) {
+                Some(result) => output.write_all(result.as_bytes())?,
+                None => return Ok(()),
+            }
+            buffer.clear();
+        }
+
+        if at_end {
+            output.write_all(b"\n")?;
+            return Ok(());
+        }
+    }
+}
+
+/// Whether the specified input has no unclosed brackets, strings or comments.
+pub fn is_complete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => {
+                        chars.next();
+                    }
+                    Some(_) => {}
+                    None => return false,
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('*') if chars.peek() == Some(&'/') => {
+                            chars.next();
+                            break;
+                        }
+                        Some(_) => {}
+                        None => return false,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    depth <= 0
+}
+
+/// Whether the specified input starts with a definition, i.e. an identifier followed by `=`.
+fn is_definition(input: &str) -> bool {
+    let mut rest = input.trim_start();
+    while rest.starts_with("/*") {
+        match rest.find("*/") {
+            Some(idx) => rest = rest[idx + 2..].trim_start(),
+            None => return false,
+        }
+    }
+
+    let name_len = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or_else(|| rest.len());
+    if name_len == 0 || rest.starts_with(|c: char| c.is_numeric()) {
+        return false;
+    }
+
+    let rest = rest[name_len..].trim_start();
+    rest.starts_with('=') && !rest.starts_with("==")
+}
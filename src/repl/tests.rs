@@ -0,0 +1,190 @@
+use std::io;
+
+use super::*;
+
+#[test]
+fn definitions_and_expressions() {
+    let mut repl = Repl::new();
+
+    assert_eq!(
+        Some("answer : u32\n".to_owned()),
+        repl.handle("answer = 42u32")
+    );
+    assert_eq!(
+        Some("double : |u32| -> u32\n".to_owned()),
+        repl.handle("double = |a: u32| -> u32 { a + a };")
+    );
+    assert_eq!(
+        Some("84u32 : u32\n".to_owned()),
+        repl.handle("double(answer)")
+    );
+    assert_eq!(
+        Some("(\"x\", 1u8, :t) : (str, u8, :t)\n".to_owned()),
+        repl.handle("(\"x\", 1u8, :t)")
+    );
+    assert_eq!(
+        Some("<function> : |u32| -> u32\n".to_owned()),
+        repl.handle("double")
+    );
+}
+
+#[test]
+fn non_constant_comparisons() {
+    let mut repl = Repl::new();
+
+    repl.handle("double = |a: u32| -> u32 { a + a };");
+
+    assert_eq!(
+        Some(":t : :f | :t\n".to_owned()),
+        repl.handle("double(2u32) == 4u32")
+    );
+    assert_eq!(
+        Some(":f : :f | :t\n".to_owned()),
+        repl.handle("double(2u32) > 4u32")
+    );
+}
+
+#[test]
+fn type_command() {
+    let mut repl = Repl::new();
+
+    repl.handle("double = |a: u32| -> u32 { a + a };");
+
+    assert_eq!(Some("u32\n".to_owned()), repl.handle(":type double(1u32)"));
+    assert_eq!(Some("|u32| -> u32\n".to_owned()), repl.handle(":t double"));
+}
+
+#[test]
+fn failed_definitions_are_forgotten() {
+    let mut repl = Repl::new();
+
+    let output = repl.handle("main = || -> i32 { a }").unwrap();
+    assert!(output.contains("undefined reference to `a`"), "{}", output);
+    assert!(output.contains("<repl:0>:1:20"), "{}", output);
+
+    let output = repl.handle("bad = || -> i32 { 1u32 }").unwrap();
+    assert!(
+        output.contains("expected `i32` but got `u32`"),
+        "{}",
+        output
+    );
+
+    assert_eq!(Some("2u32 : u32\n".to_owned()), repl.handle("1u32 + 1u32"));
+    assert_eq!(
+        Some("main : || -> i32\n".to_owned()),
+        repl.handle("main = || -> i32 { 1i32 }")
+    );
+}
+
+#[test]
+fn failed_expressions_are_forgotten() {
+    let mut repl = Repl::new();
+
+    repl.handle("main = || -> i32 { 1i32 };");
+
+    // The left-hand side has a type error of its own, which must not linger either
+    let output = repl.handle("(1u32 + 1i32) + nope").unwrap();
+    assert!(
+        output.contains("undefined reference to `nope`"),
+        "{}",
+        output
+    );
+    assert!(repl.tin.eval("(1u32 + 1i32) + nope").is_err());
+
+    let mut module = repl.tin.compile().unwrap();
+    let main = module
+        .function::<crate::module::Function0<i32>>("main")
+        .unwrap();
+    assert_eq!(Ok(1), main.call());
+}
+
+#[test]
+fn redefinition() {
+    let mut repl = Repl::new();
+
+    repl.handle("answer = 42u32;");
+
+    let output = repl.handle("answer = 43u32;").unwrap();
+    assert!(output.contains("`answer` is already defined"), "{}", output);
+    assert_eq!(Some("42u32 : u32\n".to_owned()), repl.handle("answer"));
+}
+
+#[test]
+fn reset() {
+    let mut repl = Repl::new();
+
+    repl.handle("answer = 42u32;");
+    assert_eq!(Some(String::new()), repl.handle(":reset"));
+
+    let output = repl.handle("answer").unwrap();
+    assert!(
+        output.contains("undefined reference to `answer`"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn graph_command() {
+    let mut repl = Repl::new();
+
+    repl.handle("one = 1u32;");
+    repl.handle("two = || -> u32 { one + one };");
+    repl.handle("unrelated = 3u32;");
+
+    let output = repl.handle(":graph two").unwrap();
+    assert!(output.starts_with("digraph ir {"), "{}", output);
+    assert!(output.contains("one"), "{}", output);
+    assert!(!output.contains("unrelated"), "{}", output);
+
+    let output = repl.handle(":graph three").unwrap();
+    assert!(output.contains("unknown definition `three`"), "{}", output);
+}
+
+#[test]
+fn unknown_command() {
+    let mut repl = Repl::new();
+
+    let output = repl.handle(":frobnicate").unwrap();
+    assert!(
+        output.contains("unknown command `:frobnicate`"),
+        "{}",
+        output
+    );
+    assert_eq!(None, repl.handle(":quit"));
+}
+
+#[test]
+fn complete_inputs() {
+    assert!(is_complete("answer = 42u32;"));
+    assert!(is_complete("s = \"(\";"));
+    assert!(is_complete("/* { */ a"));
+    assert!(!is_complete("f = |a: u32| -> u32 {\n"));
+    assert!(is_complete("f = |a: u32| -> u32 {\n  a\n};\n"));
+    assert!(!is_complete("s = \"unterminated"));
+    assert!(!is_complete("/* unterminated ("));
+}
+
+#[test]
+fn definition_inputs() {
+    assert!(is_definition("a = 1u32"));
+    assert!(is_definition(
+        "  /* comment */ double=|a: u32| -> u32 { a }"
+    ));
+    assert!(!is_definition("a == b"));
+    assert!(!is_definition("double(1u32)"));
+    assert!(!is_definition("(a = 1u32)"));
+}
+
+#[test]
+fn run_session() {
+    let input = "answer = 42u32;\ndouble = |a: u32| -> u32 {\n  a + a\n};\ndouble(answer)\n:quit\n";
+
+    let mut output = Vec::new();
+    run(io::Cursor::new(input), &mut output).unwrap();
+
+    assert_eq!(
+        "tin> answer : u32\ntin> ...> ...> double : |u32| -> u32\ntin> 84u32 : u32\ntin> ",
+        String::from_utf8(output).unwrap()
+    );
+}
@@ -0,0 +1,213 @@
+//! Running tests that are written in Tin.
+//!
+//! A test is a top-level function whose name starts with `test_` and that takes no parameters.  It
+//! passes when it returns the `:t` symbol, for example as the result of a comparison, or a number
+//! that is zero.  It fails when it returns anything else or when it raises a runtime error.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate failure;
+//! # extern crate tin;
+//! # fn main() -> Result<(), failure::Error> {
+//! let source = r#"
+//! add = |a: i32, b: i32| -> i32 { a + b };
+//! test_add = || -> (0i32 == 0i32) { add(1i32, 2i32) == 3i32 };
+//! test_div = || -> i32 { 1i32 / (add(1i32, 2i32) - 3i32) };
+//! "#;
+//!
+//! let mut tin = tin::Tin::new();
+//! tin.load("main.tn", source)?;
+//! let mut module = tin.compile()?;
+//!
+//! let tests = tin::testing::discover(&module);
+//! assert_eq!(2, tests.len());
+//!
+//! assert!(tin::testing::run(&mut module, &tests[0]).is_ok());
+//! assert!(tin::testing::run(&mut module, &tests[1]).is_err());
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+
+use crate::module;
+use crate::ty;
+use crate::value;
+
+/// The prefix of the names of test functions.
+pub const PREFIX: &str = "test_";
+
+/// A test that was discovered in a compiled module.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Test {
+    name: String,
+    result: ty::Type,
+}
+
+/// The reason that a test failed.
+#[derive(Clone, Debug, Fail, PartialEq)]
+pub enum Failure {
+    /// The test returned something other than `:t` or zero.
+    #[fail(display = "test returned `{}`, expected `:t` or zero", _0)]
+    Returned(value::Value),
+    /// The test raised an error at runtime.
+    #[fail(display = "{}", _0)]
+    Runtime(#[cause] module::Error),
+    /// The test has a result type that can not be checked.
+    #[fail(display = "test has result type `{}`, expected `:t` or a number", _0)]
+    UnsupportedResult(ty::Type),
+}
+
+impl Test {
+    /// The name of the test function.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Finds all of the tests in the specified module, sorted by name.
+pub fn discover(module: &module::Module) -> Vec<Test> {
+    module
+        .exports()
+        .iter()
+        .filter(|export| export.name().starts_with(PREFIX))
+        .filter_map(|export| match *export.ty() {
+            ty::Type::Function(ref function) if function.parameters.is_empty() => Some(Test {
+                name: export.name().to_owned(),
+                result: (*function.result).clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Runs the specified test, which must have been discovered in the same module.
+///
+/// Every test is a separate call into the module, so a test that fails does not affect the tests
+/// that run after it.
+pub fn run(module: &mut module::Module, test: &Test) -> Result<(), Failure> {
+    let value = match test.result {
+        ty::Type::Number(_) | ty::Type::Symbol(_) | ty::Type::Union(_) => module
+            .call_value(&test.name, &test.result, None)
+            .map_err(Failure::Runtime)?,
+        _ => None,
+    };
+    let value = value.ok_or_else(|| Failure::UnsupportedResult(test.result.clone()))?;
+
+    let passed = match *value.case() {
+        value::Case::Symbol(ref symbol) => symbol.label == "t",
+        value::Case::Number(number) => is_zero(number),
+        _ => false,
+    };
+
+    if passed {
+        Ok(())
+    } else {
+        Err(Failure::Returned(value))
+    }
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::float_cmp))]
+fn is_zero(number: value::Number) -> bool {
+    match number {
+        value::Number::U8(n) => n == 0,
+        value::Number::U16(n) => n == 0,
+        value::Number::U32(n) => n == 0,
+        value::Number::U64(n) => n == 0,
+        value::Number::I8(n) => n == 0,
+        value::Number::I16(n) => n == 0,
+        value::Number::I32(n) => n == 0,
+        value::Number::I64(n) => n == 0,
+        value::Number::F32(n) => n == 0.0,
+        value::Number::F64(n) => n == 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections;
+
+    use super::*;
+    use crate::Tin;
+
+    #[test]
+    fn discovery() {
+        let module = compile(
+            r#"
+test_a = || -> i32 { 0i32 };
+test_b = |a: i32| -> i32 { a };
+test_c = 0i32;
+helper = || -> i32 { 0i32 };
+"#,
+        );
+
+        let names = discover(&module)
+            .into_iter()
+            .map(|t| t.name().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["test_a".to_owned()], names);
+    }
+
+    #[test]
+    fn outcomes() {
+        let mut module = compile(
+            r#"
+div = |a: u32, b: u32| -> u32 { a / b };
+test_comparison = || -> (0u32 == 0u32) { div(6u32, 2u32) == 3u32 };
+test_false_comparison = || -> (0u32 == 0u32) { div(6u32, 2u32) > 3u32 };
+test_number = || -> u32 { div(0u32, 1u32) };
+test_symbol = || -> :t { :t };
+test_nonzero = || -> f64 { 1.5f64 };
+test_other_symbol = || -> :nope { :nope };
+test_string = || -> "" { "t" };
+test_div_by_zero = || -> u32 { div(1u32, 0u32) };
+"#,
+        );
+        let mut results = discover(&module)
+            .into_iter()
+            .map(|test| (test.name().to_owned(), run(&mut module, &test)))
+            .collect::<collections::HashMap<_, _>>();
+
+        assert_eq!(Some(Ok(())), results.remove("test_comparison"));
+        assert_eq!(
+            Some(Err(Failure::Returned(value::Value::symbol("f")))),
+            results.remove("test_false_comparison")
+        );
+        assert_eq!(Some(Ok(())), results.remove("test_number"));
+        assert_eq!(Some(Ok(())), results.remove("test_symbol"));
+        assert_eq!(
+            Some(Err(Failure::Returned(value::Value::from(1.5f64)))),
+            results.remove("test_nonzero")
+        );
+        assert_eq!(
+            Some(Err(Failure::Returned(value::Value::symbol("nope")))),
+            results.remove("test_other_symbol")
+        );
+        assert_eq!(
+            Some(Err(Failure::UnsupportedResult(ty::Type::String))),
+            results.remove("test_string")
+        );
+
+        match results.remove("test_div_by_zero") {
+            Some(Err(Failure::Runtime(error))) => {
+                assert_eq!(module::ErrorKind::IntegerDivisonByZero, error.kind());
+                let frames = error.backtrace().frames();
+                assert_eq!("div", frames[0].name());
+                assert_eq!("test_div_by_zero", frames[1].name());
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    fn compile(source: &str) -> module::Module {
+        let mut tin = Tin::new();
+        tin.load("test.tn", source).unwrap();
+        tin.compile().unwrap()
+    }
+}
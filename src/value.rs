@@ -1,90 +1,134 @@
+//! Dynamically typed representations of Tin values.
+//!
+//! Values are produced by constant evaluation and are used to exchange data with the host.
 use std::cmp;
 use std::collections;
+use std::fmt;
 use std::sync;
 
+use crate::ast;
+
 lazy_static! {
-    pub static ref NIL: Value = {
+    pub(crate) static ref NIL: Value = {
         Value::new(Case::Symbol(Symbol {
             label: "nil".to_owned(),
         }))
     };
-    pub static ref FALSE: Value = {
+    pub(crate) static ref FALSE: Value = {
         Value::new(Case::Symbol(Symbol {
             label: "f".to_owned(),
         }))
     };
-    pub static ref TRUE: Value = {
+    pub(crate) static ref TRUE: Value = {
         Value::new(Case::Symbol(Symbol {
             label: "t".to_owned(),
         }))
     };
-    pub static ref LT: Value = {
+    pub(crate) static ref LT: Value = {
         Value::new(Case::Symbol(Symbol {
             label: "lt".to_owned(),
         }))
     };
-    pub static ref EQ: Value = {
+    pub(crate) static ref EQ: Value = {
         Value::new(Case::Symbol(Symbol {
             label: "eq".to_owned(),
         }))
     };
-    pub static ref GT: Value = {
+    pub(crate) static ref GT: Value = {
         Value::new(Case::Symbol(Symbol {
             label: "gt".to_owned(),
         }))
     };
 }
 
+/// A Tin value.
+///
+/// Values are immutable and cheap to clone.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Value(sync::Arc<Case>);
 
+/// The different cases that a value can take on.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Case {
+    /// A number value.
     Number(Number),
+    /// A string value.
     String(String),
+    /// A symbol value.
     Symbol(Symbol),
+    /// A tuple value.
     Tuple(Tuple),
+    /// A record value.
     Record(Record),
 }
 
+/// A number value.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 pub enum Number {
+    /// An unsigned 8-bit integer.
     U8(u8),
+    /// An unsigned 16-bit integer.
     U16(u16),
+    /// An unsigned 32-bit integer.
     U32(u32),
+    /// An unsigned 64-bit integer.
     U64(u64),
+    /// A signed 8-bit integer.
     I8(i8),
+    /// A signed 16-bit integer.
     I16(i16),
+    /// A signed 32-bit integer.
     I32(i32),
+    /// A signed 64-bit integer.
     I64(i64),
+    /// A 32-bit floating point number.
     F32(f32),
+    /// A 64-bit floating point number.
     F64(f64),
 }
 
+/// A symbol value.
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Symbol {
+    /// The label of the symbol, without the leading colon.
     pub label: String,
 }
 
+/// A tuple value.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Tuple {
+    /// The fields of the tuple, in order.
     pub fields: Vec<Value>,
 }
 
+/// A record value.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Record {
+    /// The fields of the record, by name.
     pub fields: collections::HashMap<String, Value>,
 }
 
+/// Conversion from a [`Value`] into a native Rust value.
+///
+/// This is the inverse of the `From` implementations for `Value`, and is used to convert the
+/// results of interpreted calls.
+pub trait FromValue: Sized {
+    /// Converts the specified value, or returns `None` if it is of the wrong type.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
 impl Value {
+    /// Creates a new value with the specified case.
     pub fn new(storage: Case) -> Self {
         Value(sync::Arc::new(storage))
     }
 
+    /// Creates a new number value.
     pub fn number(number: Number) -> Self {
         Value::new(Case::Number(number))
     }
 
+    /// Creates a new string value.
     pub fn string<S>(string: S) -> Self
     where
         S: Into<String>,
@@ -92,6 +136,7 @@ impl Value {
         Value(sync::Arc::new(Case::String(string.into())))
     }
 
+    /// Creates a new symbol value with the specified label.
     pub fn symbol<S>(label: S) -> Self
     where
         S: Into<String>,
@@ -100,19 +145,76 @@ impl Value {
         Value::new(Case::Symbol(Symbol { label }))
     }
 
+    /// Creates a new tuple value.
     pub fn tuple(tuple: Tuple) -> Self {
         Value::new(Case::Tuple(tuple))
     }
 
+    /// Creates a new record value.
     pub fn record(record: Record) -> Self {
         Value::new(Case::Record(record))
     }
 
+    /// The case of this value.
     pub fn case(&self) -> &Case {
         &self.0
     }
 }
 
+/// Values are displayed in Tin literal syntax, with record fields sorted by name.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.case() {
+            Case::Number(ref number) => number.fmt(f),
+            Case::String(ref string) => ast::StringValue::String(string.clone()).fmt(f),
+            Case::Symbol(ref symbol) => write!(f, ":{}", symbol.label),
+            Case::Tuple(ref tuple) => {
+                f.write_str("(")?;
+                for (i, field) in tuple.fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    field.fmt(f)?;
+                }
+                if tuple.fields.len() == 1 {
+                    f.write_str(",")?;
+                }
+                f.write_str(")")
+            }
+            Case::Record(ref record) => {
+                let mut fields = record.fields.iter().collect::<Vec<_>>();
+                fields.sort_by_key(|&(name, _)| name);
+
+                f.write_str("{")?;
+                for (i, (name, value)) in fields.into_iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Number::U8(n) => write!(f, "{}u8", n),
+            Number::U16(n) => write!(f, "{}u16", n),
+            Number::U32(n) => write!(f, "{}u32", n),
+            Number::U64(n) => write!(f, "{}u64", n),
+            Number::I8(n) => write!(f, "{}i8", n),
+            Number::I16(n) => write!(f, "{}i16", n),
+            Number::I32(n) => write!(f, "{}i32", n),
+            Number::I64(n) => write!(f, "{}i64", n),
+            Number::F32(n) => write!(f, "{}f32", n),
+            Number::F64(n) => write!(f, "{}f64", n),
+        }
+    }
+}
+
 impl From<bool> for Value {
     fn from(v: bool) -> Self {
         if v {
@@ -201,3 +303,54 @@ where
         v.map_or_else(|| NIL.clone(), Into::into)
     }
 }
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        if value == &*TRUE {
+            Some(true)
+        } else if value == &*FALSE {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match *value.case() {
+            Case::String(ref v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_from_value_for_number {
+    ($ty:ty, $case:ident) => {
+        impl FromValue for $ty {
+            fn from_value(value: &Value) -> Option<Self> {
+                match *value.case() {
+                    Case::Number(Number::$case(v)) => Some(v),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_from_value_for_number!(u8, U8);
+impl_from_value_for_number!(u16, U16);
+impl_from_value_for_number!(u32, U32);
+impl_from_value_for_number!(u64, U64);
+impl_from_value_for_number!(i8, I8);
+impl_from_value_for_number!(i16, I16);
+impl_from_value_for_number!(i32, I32);
+impl_from_value_for_number!(i64, I64);
+impl_from_value_for_number!(f32, F32);
+impl_from_value_for_number!(f64, F64);
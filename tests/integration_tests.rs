@@ -1,7 +1,6 @@
 #[macro_use]
 extern crate pretty_assertions;
 
-use std::fmt;
 use std::fs;
 use std::path;
 
@@ -33,12 +32,13 @@ fn test_ok(path: &str) -> Result<(), failure::Error> {
 
     assert_eq!("".to_owned(), String::from_utf8(err.clone()).unwrap());
 
-    let main = module
-        .unwrap()
-        .function::<tin::module::Function0<i32>>("main")
-        .unwrap();
+    let mut module = module.unwrap();
+    let tests = tin::testing::discover(&module);
+    assert!(!tests.is_empty(), "no tests found in {}", path.display());
 
-    assert_eq!(Ok(0), main.call());
+    for test in tests {
+        assert_eq!(Ok(()), tin::testing::run(&mut module, &test), "{}", test);
+    }
 
     Ok(())
 }
@@ -71,7 +71,7 @@ fn test_err(path: &str, err: &str) -> Result<(), failure::Error> {
                 .function::<tin::module::Function0<i32>>("main")
                 .unwrap()
                 .call()
-                .map_err(|e| report_error(e, &mut err_actual));
+                .map_err(|e| report_diagnostics(tin.codemap(), e.into(), &mut err_actual));
         }
     }
 
@@ -88,14 +88,6 @@ fn test_err(path: &str, err: &str) -> Result<(), failure::Error> {
     Ok(())
 }
 
-fn report_error<E>(error: E, out: &mut Vec<u8>)
-where
-    E: fmt::Display,
-{
-    out.extend_from_slice(error.to_string().as_bytes());
-    out.push(b'\n');
-}
-
 fn report_diagnostics(codemap: &codespan::CodeMap, error: tin::Error, mut out: &mut Vec<u8>) {
     use tin::diagnostic::Diagnostics;
 
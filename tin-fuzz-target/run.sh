@@ -2,12 +2,13 @@
 set -eux
 echo core | sudo tee /proc/sys/kernel/core_pattern 1>/dev/null
 echo performance | sudo tee /sys/devices/system/cpu/cpu*/cpufreq/scaling_governor 1>/dev/null
+target=${1:-tin-fuzz-target}
 cd "$(dirname "$0")"
 export RUSTFLAGS='-Clink-arg=-fuse-ld=gold'
 export RAYON_NUM_THREADS=1
 cargo afl build --release
 
-basedir=/tmp/tin-afl-release/
+basedir=/tmp/tin-afl-release/$target
 mkdir -p "$basedir"
 
 n=$(nproc)
@@ -15,13 +16,13 @@ sqrtn=$(echo "sqrt($n)" | bc)
 for i in $(seq $n)
 do
   if [[ "$i" -eq 1 ]]
-  then prefix="new-session -d -s tin-afl" flag=-M
+  then prefix="new-session -d -s $target" flag=-M
   elif [[ $(( "$i" % "$sqrtn" )) -eq 0 ]]
   then prefix="split-window -v" flag=-S
   else prefix="split-window -h" flag=-S
   fi
 
-  tmux $prefix "cargo afl fuzz -i ../testdata/ok -o $basedir/out $flag worker$i ../target/release/tin-fuzz-target"
+  tmux $prefix "cargo afl fuzz -i ../testdata/ok -o $basedir/out $flag worker$i ../target/release/$target"
   tmux select-layout tiled
 done
 
@@ -0,0 +1,19 @@
+#[macro_use]
+extern crate afl;
+
+fn run(data: &[u8]) {
+    let mut entropy = tin::fuzz::Entropy::from_bytes(data);
+    if let Some(disagreement) = tin::fuzz::differential::check(&mut entropy) {
+        panic!(
+            "constant evaluation and compiled code disagree:\n{}",
+            disagreement
+        );
+    }
+}
+
+fn main() {
+    fuzz!(|data: &[u8]| {
+        run(data);
+        ()
+    })
+}
@@ -0,0 +1,21 @@
+#[macro_use]
+extern crate afl;
+
+fn run(data: &[u8]) {
+    let source = tin::fuzz::program::generate(&mut tin::fuzz::Entropy::from_bytes(data));
+    let mut tin = tin::Tin::new();
+    if tin.load("fuzz", &source).is_ok() {
+        if let Ok(mut module) = tin.compile() {
+            if let Some(main) = module.function::<tin::module::Function0<i32>>("main") {
+                let _ = main.call();
+            }
+        }
+    }
+}
+
+fn main() {
+    fuzz!(|data: &[u8]| {
+        run(data);
+        ()
+    })
+}
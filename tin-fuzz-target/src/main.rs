@@ -6,8 +6,9 @@ fn run(data: &[u8]) {
         let mut tin = tin::Tin::new();
         if tin.load("fuzz", s).is_ok() {
             if let Ok(mut module) = tin.compile() {
-                if let Some(main) = module.function::<tin::module::Function0<i32>>("main") {
-                    let _ = main.call();
+                // The seed programs in `testdata/ok` define tests instead of a `main` function
+                for test in tin::testing::discover(&module) {
+                    let _ = tin::testing::run(&mut module, &test);
                 }
             }
         }
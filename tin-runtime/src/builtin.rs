@@ -0,0 +1,266 @@
+//! Builtin functions that generated code calls into.
+//!
+//! These are exported under the symbol names that generated code refers to, so that they can be
+//! resolved by a linker when linking ahead-of-time compiled code against this library.
+use std::mem;
+use std::num;
+use std::ptr;
+use std::slice;
+use std::str;
+
+use crate::error;
+use crate::state;
+
+/// Allocates memory for the running call; returns null if the memory limit would be exceeded.
+#[export_name = "tin_builtin_alloc"]
+pub unsafe extern "C" fn alloc(
+    state: *const state::State,
+    size: usize,
+    align: num::NonZeroUsize,
+) -> *mut u8 {
+    let state = state.as_ref().unwrap();
+    let ptr = state.allocate(size, align);
+    debug!("alloc size={:?} align={:?} ptr={:?}", size, align, ptr);
+    ptr
+}
+
+/// Frees memory that was allocated by the running call ahead of time.
+#[export_name = "tin_builtin_dealloc"]
+pub unsafe extern "C" fn dealloc(state: *const state::State, ptr: *mut u8) {
+    let state = state.as_ref().unwrap();
+    debug!("dealloc ptr={:?}", ptr);
+    state.deallocate(ptr);
+}
+
+/// Marks the start of a call from the host; returns the ID of the call to pass to `exit_call`.
+#[export_name = "tin_builtin_enter_call"]
+pub unsafe extern "C" fn enter_call(state: *const state::State) -> usize {
+    let state = state.as_ref().unwrap();
+    let frame = state.enter_call();
+    debug!("enter_call frame={:?}", frame);
+    frame
+}
+
+/// Marks the end of a call from the host, releasing the memory that the call allocated.
+#[export_name = "tin_builtin_exit_call"]
+pub unsafe extern "C" fn exit_call(state: *const state::State, frame: usize, retain: *const u8) {
+    let state = state.as_ref().unwrap();
+    debug!("exit_call frame={:?} retain={:?}", frame, retain);
+    state.exit_call(frame, retain);
+}
+
+/// Consumes one unit of fuel on function entry; returns 0 if there was no fuel left, and 1
+/// otherwise.
+#[export_name = "tin_builtin_consume_fuel"]
+pub unsafe extern "C" fn consume_fuel(state: *const state::State) -> u8 {
+    let state = state.as_ref().unwrap();
+    u8::from(state.consume_fuel())
+}
+
+/// Clears the interrupt flag on function entry; returns 1 if an interrupt had been requested, and
+/// 0 otherwise.
+#[export_name = "tin_builtin_take_interrupt"]
+pub unsafe extern "C" fn take_interrupt(state: *const state::State) -> u8 {
+    let state = state.as_ref().unwrap();
+    u8::from(state.take_interrupt())
+}
+
+/// Creates a new error of the specified kind.
+#[export_name = "tin_builtin_error"]
+pub unsafe extern "C" fn error(kind: u32) -> *mut error::Error {
+    use num_traits::cast::FromPrimitive;
+
+    let kind = error::ErrorKind::from_u32(kind).unwrap_or(error::ErrorKind::Unknown);
+
+    debug!("error kind={:?}", kind);
+
+    let error = error::Error::new(kind);
+
+    Box::into_raw(Box::new(error))
+}
+
+/// Creates a new user generated error, with the contents of a string value as its message.
+#[export_name = "tin_builtin_user_error"]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn user_error(message: *const u8) -> *mut error::Error {
+    let len = ptr::read_unaligned(message as *const usize);
+    let bytes = slice::from_raw_parts(message.add(mem::size_of::<usize>()), len);
+    let message = String::from_utf8_lossy(bytes).into_owned();
+
+    debug!("user_error message={:?}", message);
+
+    let error = error::Error::with_message(error::ErrorKind::UserGenerated, message);
+
+    Box::into_raw(Box::new(error))
+}
+
+/// The type of the operands of a failed comparison, as passed to `assertion_error`.
+///
+/// Integer operands are passed extended to 64 bits, and floating point operands as their bits.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Primitive)]
+pub enum OperandType {
+    /// The asserted condition is not a comparison, so there are no operands to show.
+    None = 0,
+    /// The operands are `u8`s.
+    U8 = 1,
+    /// The operands are `u16`s.
+    U16 = 2,
+    /// The operands are `u32`s.
+    U32 = 3,
+    /// The operands are `u64`s.
+    U64 = 4,
+    /// The operands are `i8`s.
+    I8 = 5,
+    /// The operands are `i16`s.
+    I16 = 6,
+    /// The operands are `i32`s.
+    I32 = 7,
+    /// The operands are `i64`s.
+    I64 = 8,
+    /// The operands are `f32`s.
+    F32 = 9,
+    /// The operands are `f64`s.
+    F64 = 10,
+}
+
+/// Creates a new user generated error for a failed assertion, with a message that includes the
+/// source code of the asserted condition as well as the message of the assertion.
+///
+/// If the condition is a comparison, the message also shows the values of its two operands.
+#[export_name = "tin_builtin_assertion_error"]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn assertion_error(
+    message: *const u8,
+    source_data: *const u8,
+    source_len: usize,
+    operand_type: u32,
+    lhs: u64,
+    rhs: u64,
+) -> *mut error::Error {
+    use num_traits::cast::FromPrimitive;
+
+    let len = ptr::read_unaligned(message as *const usize);
+    let bytes = slice::from_raw_parts(message.add(mem::size_of::<usize>()), len);
+    let message = String::from_utf8_lossy(bytes);
+    let source = str::from_utf8_unchecked(slice::from_raw_parts(source_data, source_len));
+    let operand_type = OperandType::from_u32(operand_type).unwrap_or(OperandType::None);
+
+    debug!(
+        "assertion_error message={:?} source={:?} operand_type={:?} lhs={:?} rhs={:?}",
+        message, source, operand_type, lhs, rhs
+    );
+
+    let message = match operand_type {
+        OperandType::None => format!("assertion `{}` failed: {}", source, message),
+        _ => format!(
+            "assertion `{}` failed: {} (left: {}, right: {})",
+            source,
+            message,
+            operand_type.format(lhs),
+            operand_type.format(rhs)
+        ),
+    };
+    let error = error::Error::with_message(error::ErrorKind::UserGenerated, message);
+
+    Box::into_raw(Box::new(error))
+}
+
+/// Computes the remainder of dividing two `f32`s, like the `%` operator does in Rust.
+#[export_name = "tin_builtin_rem_f32"]
+pub extern "C" fn rem_f32(lhs: f32, rhs: f32) -> f32 {
+    lhs % rhs
+}
+
+/// Computes the remainder of dividing two `f64`s, like the `%` operator does in Rust.
+#[export_name = "tin_builtin_rem_f64"]
+pub extern "C" fn rem_f64(lhs: f64, rhs: f64) -> f64 {
+    lhs % rhs
+}
+
+/// Whether an error can be caught by a `try` expression; returns 1 if so and 0 otherwise.
+#[export_name = "tin_builtin_recoverable"]
+pub unsafe extern "C" fn recoverable(error: *const error::Error) -> u8 {
+    let error = error.as_ref().unwrap();
+    debug!("recoverable error={:?}", error);
+    u8::from(error.kind().is_recoverable())
+}
+
+/// Catches an error, returning its message as a string value allocated for the running call.
+///
+/// The error is freed, even if allocating the message fails, in which case null is returned.
+/// Errors without a message get a description of their kind instead.
+#[export_name = "tin_builtin_catch_error"]
+#[allow(clippy::cast_ptr_alignment)]
+pub unsafe extern "C" fn catch_error(
+    state: *const state::State,
+    error: *mut error::Error,
+) -> *mut u8 {
+    let state = state.as_ref().unwrap();
+    let error = Box::from_raw(error);
+
+    debug!("catch_error error={:?}", error);
+
+    let message = match error.message() {
+        Some(message) => message.to_owned(),
+        None => error.kind().to_string(),
+    };
+
+    let header = mem::size_of::<usize>();
+    let align = num::NonZeroUsize::new(mem::align_of::<usize>()).unwrap();
+    let ptr = state.allocate(header + message.len(), align);
+    if !ptr.is_null() {
+        ptr::write(ptr as *mut usize, message.len());
+        ptr::copy_nonoverlapping(message.as_ptr(), ptr.add(header), message.len());
+    }
+    ptr
+}
+
+/// Adds a frame to the backtrace of an error that is being propagated.
+#[export_name = "tin_builtin_unwind_frame"]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn unwind_frame(
+    error: *mut error::Error,
+    name_data: *const u8,
+    name_len: usize,
+    path_data: *const u8,
+    path_len: usize,
+    line: u32,
+    col: u32,
+    start: u32,
+    end: u32,
+) {
+    let error = error.as_mut().unwrap();
+
+    let name = str::from_utf8_unchecked(slice::from_raw_parts(name_data, name_len));
+    let path = str::from_utf8_unchecked(slice::from_raw_parts(path_data, path_len));
+    let location = error::Point::new(path.to_owned(), line, col).with_span(start, end);
+
+    debug!(
+        "unwind_frame error={:?} name={:?} location={:?}",
+        error, name, location
+    );
+
+    let frame = error::Frame::new(name.to_owned(), location);
+
+    error.push_frame(frame);
+}
+
+impl OperandType {
+    /// Formats an operand of this type the same way that Tin displays numbers.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn format(self, bits: u64) -> String {
+        match self {
+            OperandType::None => String::new(),
+            OperandType::U8 => format!("{}u8", bits as u8),
+            OperandType::U16 => format!("{}u16", bits as u16),
+            OperandType::U32 => format!("{}u32", bits as u32),
+            OperandType::U64 => format!("{}u64", bits),
+            OperandType::I8 => format!("{}i8", bits as i8),
+            OperandType::I16 => format!("{}i16", bits as i16),
+            OperandType::I32 => format!("{}i32", bits as i32),
+            OperandType::I64 => format!("{}i64", bits as i64),
+            OperandType::F32 => format!("{}f32", f32::from_bits(bits as u32)),
+            OperandType::F64 => format!("{}f64", f64::from_bits(bits)),
+        }
+    }
+}
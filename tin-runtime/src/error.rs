@@ -0,0 +1,267 @@
+//! Errors that are raised by generated code.
+use std::fmt;
+
+/// An error that may happen at runtime.
+#[derive(Clone, Debug, Fail, PartialEq)]
+pub struct Error {
+    kind: ErrorKind,
+    message: Option<String>,
+    backtrace: Trace,
+}
+
+/// A trace, for example a backtrace for an error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    frames: Vec<Frame>,
+}
+
+/// A call stack frame within a [`Trace`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    name: String,
+    location: Point,
+}
+
+/// A specific code point, with a file, line and column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    path: String,
+    line: u32,
+    column: u32,
+    span: Option<(u32, u32)>,
+}
+
+/// The various kinds of allowed errors.
+#[derive(Clone, Copy, Debug, PartialEq, Primitive)]
+pub enum ErrorKind {
+    /// An unknown error has occurred.
+    ///
+    /// If this happens, it is a bug.
+    Unknown = 1,
+    /// The stack has overflowed.
+    StackOverflow = 2,
+    /// There was an out-of-bounds array access.
+    OutOfBounds = 3,
+    /// An integer operation caused an overflow to happen.
+    IntegerOverflow = 4,
+    /// An integer operation caused a division-by-zero to happen.
+    IntegerDivisonByZero = 5,
+    /// A float-to-integer conversion failed.
+    BadConversionToInteger = 6,
+    /// User generated error.
+    UserGenerated = 7,
+    /// The call ran out of fuel.
+    OutOfFuel = 8,
+    /// The call was interrupted through an `InterruptHandle`.
+    Interrupted = 9,
+    /// The call exceeded its memory limit.
+    OutOfMemory = 10,
+}
+
+impl Error {
+    /// Creates a new error of the specified kind.
+    pub fn new(kind: ErrorKind) -> Self {
+        let message = None;
+        let frames = Vec::new();
+        let backtrace = Trace { frames };
+        Error {
+            kind,
+            message,
+            backtrace,
+        }
+    }
+
+    /// Creates a new error of the specified kind, that carries a message describing it.
+    pub fn with_message(kind: ErrorKind, message: String) -> Self {
+        let message = Some(message);
+        let frames = Vec::new();
+        let backtrace = Trace { frames };
+        Error {
+            kind,
+            message,
+            backtrace,
+        }
+    }
+
+    /// Pushes a new backtrace frame to the "bottom" of the stack.
+    pub fn push_frame(&mut self, frame: Frame) {
+        self.backtrace.frames.push(frame);
+    }
+
+    /// The kind of error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The message of the error, for example the reason passed to `fail` for user generated
+    /// errors.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(String::as_str)
+    }
+
+    /// The backtrace of the error.
+    pub fn backtrace(&self) -> &Trace {
+        &self.backtrace
+    }
+}
+
+impl ErrorKind {
+    /// The kinds of errors that can be caught by `try` expressions.
+    ///
+    /// The other kinds of errors enforce limits that are set by the host, so they always propagate
+    /// all the way to the host.
+    pub const RECOVERABLE: &'static [ErrorKind] = &[
+        ErrorKind::Unknown,
+        ErrorKind::StackOverflow,
+        ErrorKind::OutOfBounds,
+        ErrorKind::IntegerOverflow,
+        ErrorKind::IntegerDivisonByZero,
+        ErrorKind::BadConversionToInteger,
+        ErrorKind::UserGenerated,
+    ];
+
+    /// Whether errors of this kind can be caught by `try` expressions.
+    pub fn is_recoverable(self) -> bool {
+        ErrorKind::RECOVERABLE.contains(&self)
+    }
+
+    /// The label of the symbol that represents this kind of error in Tin code, without the leading
+    /// `:`.
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorKind::Unknown => "unknown",
+            ErrorKind::StackOverflow => "stack_overflow",
+            ErrorKind::OutOfBounds => "out_of_bounds",
+            ErrorKind::IntegerOverflow => "integer_overflow",
+            ErrorKind::IntegerDivisonByZero => "integer_division_by_zero",
+            ErrorKind::BadConversionToInteger => "bad_conversion_to_integer",
+            ErrorKind::UserGenerated => "user_generated",
+            ErrorKind::OutOfFuel => "out_of_fuel",
+            ErrorKind::Interrupted => "interrupted",
+            ErrorKind::OutOfMemory => "out_of_memory",
+        }
+    }
+}
+
+impl Trace {
+    /// Returns all of the frames in the trace, most recently called function
+    /// first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+}
+
+impl Frame {
+    /// Creates a new frame.
+    pub fn new(name: String, location: Point) -> Self {
+        Frame { name, location }
+    }
+
+    /// Returns the corresponding function name for this frame.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the location of the call that left this frame.
+    pub fn location(&self) -> &Point {
+        &self.location
+    }
+}
+
+impl Point {
+    /// Creates a new point.
+    pub fn new(path: String, line: u32, column: u32) -> Self {
+        let span = None;
+        Point {
+            path,
+            line,
+            column,
+            span,
+        }
+    }
+
+    /// Attaches the span of the code at this point, as start and end byte indices into the code
+    /// map that the code was loaded from.
+    pub fn with_span(mut self, start: u32, end: u32) -> Self {
+        self.span = Some((start, end));
+        self
+    }
+
+    /// The file path of the point.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The 0-based line number of the point.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The 0-based column number of the point.
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    /// The start and end byte indices of the code at this point, if known.
+    pub fn span(&self) -> Option<(u32, u32)> {
+        self.span
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.message {
+            Some(ref message) => write!(f, "{}: {}", self.kind, message)?,
+            None => self.kind.fmt(f)?,
+        }
+        self.backtrace.fmt(f)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Unknown => f.write_str("unknown"),
+            ErrorKind::StackOverflow => f.write_str("stack overflow"),
+            ErrorKind::OutOfBounds => f.write_str("out of bounds"),
+            ErrorKind::IntegerOverflow => f.write_str("integer overflow"),
+            ErrorKind::IntegerDivisonByZero => f.write_str("integer division by zero"),
+            ErrorKind::BadConversionToInteger => f.write_str("bad conversion to integer"),
+            ErrorKind::UserGenerated => f.write_str("user generated"),
+            ErrorKind::OutOfFuel => f.write_str("out of fuel"),
+            ErrorKind::Interrupted => f.write_str("interrupted"),
+            ErrorKind::OutOfMemory => f.write_str("out of memory"),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.frames.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "trace:")?;
+
+            let mut needs_sep = false;
+            for frame in &self.frames {
+                if needs_sep {
+                    writeln!(f)?;
+                }
+                write!(f, "  {}", frame)?;
+                needs_sep = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({})", self.name, self.location)
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line + 1, self.column + 1)
+    }
+}
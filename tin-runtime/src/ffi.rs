@@ -0,0 +1,54 @@
+//! A C interface for programs that contain ahead-of-time compiled Tin code.
+//!
+//! Compiled functions are exported as `tin_<name>`.  They take their parameters followed by a
+//! `tin_error **` out-parameter, which is set to a non-null error if the call fails.  The
+//! functions in this module can be used to inspect and free such errors, and to manage the
+//! memory that calls return.
+use crate::error;
+use crate::state;
+
+/// The runtime state shared by all ahead-of-time compiled code in the program.
+#[export_name = "tin_runtime_state"]
+pub static STATE: state::State = state::State::new();
+
+/// Returns the kind of the specified error, as a numeric `ErrorKind` value.
+#[no_mangle]
+pub unsafe extern "C" fn tin_error_kind(error: *const error::Error) -> u32 {
+    error.as_ref().unwrap().kind() as u32
+}
+
+/// Frees an error that was returned from a call.
+#[no_mangle]
+pub unsafe extern "C" fn tin_error_free(error: *mut error::Error) {
+    if !error.is_null() {
+        drop(Box::from_raw(error));
+    }
+}
+
+/// Frees the memory that is reachable from a pointer returned from a call.
+#[no_mangle]
+pub extern "C" fn tin_release(ptr: *const u8) {
+    if !ptr.is_null() {
+        STATE.free(STATE.take_retained(ptr));
+    }
+}
+
+/// Interrupts the currently running call, which fails with an `Interrupted` error.
+///
+/// This may be called from any thread.
+#[no_mangle]
+pub extern "C" fn tin_interrupt() {
+    STATE.interrupt();
+}
+
+/// Sets the amount of fuel available to calls; a value of zero disables fuel metering.
+#[no_mangle]
+pub extern "C" fn tin_set_fuel(fuel: usize) {
+    STATE.set_fuel(if fuel == 0 { None } else { Some(fuel) });
+}
+
+/// Sets the maximum number of bytes that calls may allocate; a value of zero removes the limit.
+#[no_mangle]
+pub extern "C" fn tin_set_memory_limit(limit: usize) {
+    STATE.set_memory_limit(if limit == 0 { None } else { Some(limit) });
+}
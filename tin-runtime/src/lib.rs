@@ -0,0 +1,34 @@
+//! The runtime support library for Tin.
+//!
+//! Code generated by the Tin compiler calls into this library for memory management, error
+//! reporting and other services that are not emitted inline.  The JIT compiler in the `tin` crate
+//! uses it directly, and it can be linked on its own (for example as a static library) into
+//! programs that contain ahead-of-time compiled Tin code, without shipping the compiler.
+#![deny(nonstandard_style, warnings, unused)]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_import_braces,
+    unused_qualifications
+)]
+#![cfg_attr(feature = "cargo-clippy", deny(clippy::all, clippy::pedantic))]
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::use_self))]
+
+#[macro_use]
+extern crate enum_primitive_derive;
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate log;
+
+pub mod builtin;
+pub mod error;
+pub mod ffi;
+pub mod state;
+
+pub use crate::error::Error;
+pub use crate::error::ErrorKind;
+pub use crate::state::State;
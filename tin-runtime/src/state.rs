@@ -0,0 +1,420 @@
+//! Runtime state that is shared between the host and generated code.
+use std::alloc;
+use std::cell;
+use std::collections;
+use std::mem;
+use std::num;
+use std::ptr;
+use std::sync;
+use std::sync::atomic;
+use std::usize;
+
+/// The symbol under which the runtime state is made available to generated code.
+pub const STATE_SYMBOL: &str = "tin_runtime_state";
+
+/// The runtime state of a compiled module.
+///
+/// Generated code only accesses the state through builtin functions, since the host may access it
+/// from other threads at the same time.
+#[derive(Debug)]
+pub struct State {
+    /// The remaining fuel; one unit is consumed on every function entry while fuel is limited.
+    fuel: atomic::AtomicUsize,
+    /// Whether the running call should be interrupted at the next function entry.
+    ///
+    /// Generated code clears this flag when it observes it, so that later calls are unaffected.
+    interrupted: atomic::AtomicBool,
+    /// Whether fuel is being metered at all.
+    fuel_limited: atomic::AtomicBool,
+    /// Bookkeeping for all memory allocated by generated code.
+    ///
+    /// This is created on first use, so that a `State` can be constructed in a `static`.
+    memory: atomic::AtomicPtr<sync::Mutex<Memory>>,
+}
+
+/// A block of memory allocated on behalf of generated code.
+#[allow(missing_copy_implementations)]
+#[derive(Debug)]
+pub struct Allocation {
+    address: usize,
+    layout: alloc::Layout,
+}
+
+/// Statistics about the memory allocated by generated code.
+///
+/// Obtained through `Module::memory_stats`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MemoryStats {
+    bytes_allocated: usize,
+    live_bytes: usize,
+    peak_bytes: usize,
+    live_allocations: usize,
+}
+
+#[derive(Debug, Default)]
+struct Memory {
+    limit: Option<usize>,
+    bytes_allocated: usize,
+    live_bytes: usize,
+    peak_bytes: usize,
+    live_allocations: usize,
+    /// The ID to give to the next call that starts.
+    next_frame: usize,
+    /// Allocations made by each of the calls that are currently running, by the ID of the call.
+    ///
+    /// Calls might run concurrently on different threads, so each call has its own frame, which
+    /// is released when that call returns.
+    frames: collections::HashMap<usize, Vec<Allocation>>,
+    /// Allocations made outside of any call, which are only released when the state is dropped.
+    unscoped: Vec<Allocation>,
+    /// Allocations made by calls that returned a pointer to the host, keyed by that pointer.
+    ///
+    /// They are kept alive until the host takes ownership of them or the state is dropped.
+    retained: collections::HashMap<usize, Vec<Allocation>>,
+}
+
+thread_local! {
+    /// The calls that are running on this thread, innermost last, as pairs of the address of
+    /// their state and the ID of their frame.
+    static CALLS: cell::RefCell<Vec<(usize, usize)>> = cell::RefCell::new(Vec::new());
+}
+
+impl State {
+    /// Creates a new runtime state, without any fuel or memory limits.
+    pub const fn new() -> Self {
+        State {
+            fuel: atomic::AtomicUsize::new(usize::MAX),
+            interrupted: atomic::AtomicBool::new(false),
+            fuel_limited: atomic::AtomicBool::new(false),
+            memory: atomic::AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// A pointer to this state, as seen by generated code.
+    pub fn as_ptr(&self) -> *const u8 {
+        let ptr: *const State = self;
+        ptr as *const u8
+    }
+
+    /// Sets the remaining fuel, or disables fuel metering if `None`.
+    pub fn set_fuel(&self, fuel: Option<usize>) {
+        self.fuel_limited
+            .store(fuel.is_some(), atomic::Ordering::SeqCst);
+        self.fuel
+            .store(fuel.unwrap_or(usize::MAX), atomic::Ordering::SeqCst);
+    }
+
+    /// The remaining fuel, or `None` if fuel metering is disabled.
+    pub fn fuel(&self) -> Option<usize> {
+        if self.fuel_limited.load(atomic::Ordering::SeqCst) {
+            Some(self.fuel.load(atomic::Ordering::SeqCst))
+        } else {
+            None
+        }
+    }
+
+    /// Consumes one unit of fuel, returning `false` if there was no fuel left.
+    ///
+    /// Nothing is consumed if fuel metering is disabled.  The host may set the fuel from another
+    /// thread at any time, so the fuel is only decremented if it did not change in the meantime.
+    pub fn consume_fuel(&self) -> bool {
+        if !self.fuel_limited.load(atomic::Ordering::SeqCst) {
+            return true;
+        }
+
+        let mut fuel = self.fuel.load(atomic::Ordering::SeqCst);
+        loop {
+            if fuel == 0 {
+                return false;
+            }
+            match self.fuel.compare_exchange_weak(
+                fuel,
+                fuel - 1,
+                atomic::Ordering::SeqCst,
+                atomic::Ordering::SeqCst,
+            ) {
+                Ok(_) => return true,
+                Err(current) => fuel = current,
+            }
+        }
+    }
+
+    /// Requests that the running call is interrupted at the next function entry.
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Clears the interrupt flag, returning whether an interrupt had been requested.
+    pub fn take_interrupt(&self) -> bool {
+        self.interrupted.swap(false, atomic::Ordering::SeqCst)
+    }
+
+    /// Sets the maximum number of live bytes, or removes the limit if `None`.
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        self.memory().lock().unwrap().limit = limit;
+    }
+
+    /// The maximum number of live bytes, or `None` if there is no limit.
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory().lock().unwrap().limit
+    }
+
+    /// Statistics about the memory allocated so far.
+    pub fn memory_stats(&self) -> MemoryStats {
+        let memory = self.memory().lock().unwrap();
+        MemoryStats {
+            bytes_allocated: memory.bytes_allocated,
+            live_bytes: memory.live_bytes,
+            peak_bytes: memory.peak_bytes,
+            live_allocations: memory.live_allocations,
+        }
+    }
+
+    /// Resets the cumulative memory statistics.
+    pub fn reset_memory_stats(&self) {
+        let mut memory = self.memory().lock().unwrap();
+        memory.bytes_allocated = 0;
+        memory.peak_bytes = memory.live_bytes;
+    }
+
+    /// Allocates memory for the currently running call.
+    ///
+    /// Returns a null pointer if the allocation would exceed the memory limit, or if the system
+    /// allocator fails.
+    pub fn allocate(&self, size: usize, align: num::NonZeroUsize) -> *mut u8 {
+        let mut memory = self.memory().lock().unwrap();
+        let live_bytes = match memory.live_bytes.checked_add(size) {
+            Some(live_bytes) => live_bytes,
+            None => return ptr::null_mut(),
+        };
+
+        if memory.limit.map_or(false, |limit| live_bytes > limit) {
+            return ptr::null_mut();
+        }
+
+        let layout = unsafe { alloc::Layout::from_size_align_unchecked(size, align.get()) };
+        let ptr = unsafe { alloc::alloc(layout) };
+        if ptr.is_null() {
+            return ptr;
+        }
+
+        memory.bytes_allocated = memory.bytes_allocated.saturating_add(size);
+        memory.live_bytes = live_bytes;
+        memory.peak_bytes = memory.peak_bytes.max(live_bytes);
+        memory.live_allocations += 1;
+
+        let allocation = Allocation {
+            address: ptr as usize,
+            layout,
+        };
+        match self.current_frame() {
+            Some(frame) => memory.frames.get_mut(&frame).unwrap().push(allocation),
+            None => memory.unscoped.push(allocation),
+        }
+
+        ptr
+    }
+
+    /// Frees memory that was allocated by the currently running call ahead of time.
+    pub fn deallocate(&self, ptr: *mut u8) {
+        let frame = match self.current_frame() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let mut memory = self.memory().lock().unwrap();
+        let address = ptr as usize;
+        let allocations = memory.frames.get_mut(&frame).unwrap();
+        if let Some(idx) = allocations.iter().rposition(|a| a.address == address) {
+            let allocation = allocations.remove(idx);
+            memory.free(vec![allocation]);
+        }
+    }
+
+    /// Marks the start of a call from the host on the current thread; returns the ID of the call
+    /// to pass to `exit_call`.
+    pub fn enter_call(&self) -> usize {
+        let mut memory = self.memory().lock().unwrap();
+        let frame = memory.next_frame;
+        memory.next_frame = memory.next_frame.wrapping_add(1);
+        memory.frames.insert(frame, Vec::new());
+
+        CALLS.with(|calls| calls.borrow_mut().push((self.address(), frame)));
+        frame
+    }
+
+    /// Marks the end of a call from the host, releasing everything that the call allocated.
+    ///
+    /// If `retain` is non-null, the call returned a pointer to the host.  The allocations that
+    /// are reachable from that pointer are then retained until the host takes ownership of them
+    /// using `take_retained`, and everything else is released.
+    pub fn exit_call(&self, frame: usize, retain: *const u8) {
+        let address = self.address();
+        CALLS.with(|calls| {
+            let mut calls = calls.borrow_mut();
+            if let Some(idx) = calls.iter().rposition(|&call| call == (address, frame)) {
+                calls.remove(idx);
+            }
+        });
+
+        let mut memory = self.memory().lock().unwrap();
+        let allocations = memory.frames.remove(&frame).unwrap_or_default();
+
+        if retain.is_null() {
+            memory.free(allocations);
+        } else {
+            let (reachable, unreachable) = reachable_from(retain as usize, allocations);
+            memory.free(unreachable);
+            if !reachable.is_empty() {
+                memory
+                    .retained
+                    .entry(retain as usize)
+                    .or_insert_with(Vec::new)
+                    .extend(reachable);
+            }
+        }
+    }
+
+    /// Takes ownership of the allocations that were retained for the specified returned pointer.
+    pub fn take_retained(&self, ptr: *const u8) -> Vec<Allocation> {
+        let mut memory = self.memory().lock().unwrap();
+        memory.retained.remove(&(ptr as usize)).unwrap_or_default()
+    }
+
+    /// Frees allocations that were previously taken using `take_retained`.
+    pub fn free(&self, allocations: Vec<Allocation>) {
+        self.memory().lock().unwrap().free(allocations);
+    }
+
+    /// The ID of the innermost call into this state that is running on the current thread.
+    fn current_frame(&self) -> Option<usize> {
+        let address = self.address();
+        CALLS.with(|calls| {
+            calls
+                .borrow()
+                .iter()
+                .rev()
+                .find(|&&(state, _)| state == address)
+                .map(|&(_, frame)| frame)
+        })
+    }
+
+    fn address(&self) -> usize {
+        self.as_ptr() as usize
+    }
+
+    fn memory(&self) -> &sync::Mutex<Memory> {
+        let mut memory = self.memory.load(atomic::Ordering::Acquire);
+
+        if memory.is_null() {
+            let new_memory = Box::into_raw(Box::new(sync::Mutex::new(Memory::default())));
+            memory =
+                self.memory
+                    .compare_and_swap(ptr::null_mut(), new_memory, atomic::Ordering::AcqRel);
+
+            if memory.is_null() {
+                memory = new_memory;
+            } else {
+                // Another thread got there first.
+                drop(unsafe { Box::from_raw(new_memory) });
+            }
+        }
+
+        unsafe { &*memory }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::new()
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        let memory = *self.memory.get_mut();
+        if memory.is_null() {
+            return;
+        }
+
+        let memory = unsafe { Box::from_raw(memory) };
+        if let Ok(mut memory) = memory.into_inner() {
+            let unscoped = mem::replace(&mut memory.unscoped, Vec::new());
+            memory.free(unscoped);
+
+            for (_, allocations) in mem::replace(&mut memory.frames, collections::HashMap::new()) {
+                memory.free(allocations);
+            }
+
+            for (_, allocations) in mem::replace(&mut memory.retained, collections::HashMap::new())
+            {
+                memory.free(allocations);
+            }
+        }
+    }
+}
+
+impl MemoryStats {
+    /// The total number of bytes that have been allocated, including memory that has since been
+    /// freed.
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    /// The number of bytes that are currently allocated.
+    pub fn live_bytes(&self) -> usize {
+        self.live_bytes
+    }
+
+    /// The highest number of bytes that have been allocated at once.
+    pub fn peak_bytes(&self) -> usize {
+        self.peak_bytes
+    }
+
+    /// The number of allocations that have not yet been freed.
+    pub fn live_allocations(&self) -> usize {
+        self.live_allocations
+    }
+}
+
+impl Memory {
+    fn free(&mut self, allocations: Vec<Allocation>) {
+        for allocation in allocations {
+            unsafe { alloc::dealloc(allocation.address as *mut u8, allocation.layout) };
+            self.live_bytes -= allocation.layout.size();
+            self.live_allocations -= 1;
+        }
+    }
+}
+
+/// Splits the specified allocations into the ones that are reachable from the specified address,
+/// and the ones that are not.
+///
+/// Generated code stores pointers to other values at pointer-aligned offsets, so every
+/// pointer-sized word of a reachable allocation that points into another allocation is treated
+/// as a reference to it.  This might retain more than necessary, but never too little.
+fn reachable_from(root: usize, allocations: Vec<Allocation>) -> (Vec<Allocation>, Vec<Allocation>) {
+    let word = mem::size_of::<usize>();
+    let mut unvisited = allocations;
+    let mut reachable = Vec::new();
+    let mut pending = vec![root];
+
+    while let Some(address) = pending.pop() {
+        let idx = unvisited
+            .iter()
+            .position(|a| a.address <= address && address < a.address + a.layout.size().max(1));
+
+        if let Some(idx) = idx {
+            let allocation = unvisited.swap_remove(idx);
+            let mut offset = 0;
+            while offset + word <= allocation.layout.size() {
+                let ptr = (allocation.address + offset) as *const usize;
+                pending.push(unsafe { ptr::read_unaligned(ptr) });
+                offset += word;
+            }
+            reachable.push(allocation);
+        }
+    }
+
+    (reachable, unvisited)
+}